@@ -0,0 +1,109 @@
+//! Benchmarks running representative Rurtle scripts. They need nightly Rust,
+//! run them with `cargo bench --features nightly`.
+//!
+//! The interpreter benchmarks draw on a `NullBackend` so that only the
+//! evaluator is measured, the rendering benchmarks use the `SoftwareBackend`.
+#![feature(test)]
+extern crate rurtle;
+extern crate test;
+
+use rurtle::backend::NullBackend;
+use rurtle::{RenderBackend, Rurtle, SoftwareBackend};
+use test::Bencher;
+
+const SIZE: (u32, u32) = (640, 640);
+
+/// Recursive function calls and arithmetic
+const FIBONACCI: &'static str = "
+learn fib :n do
+    if :n < 2 do return :n end
+    return (fib :n - 1) + (fib :n - 2)
+end
+fib 15
+";
+
+/// Many short segments
+const SPIRAL: &'static str = "
+for [i 1 2000] do
+    forward :i / 100
+    right 5
+end
+";
+
+/// Recursive drawing
+const TREE: &'static str = "
+learn tree :size :depth do
+    if :depth = 0 do return nothing end
+    forward :size
+    left 30
+    tree :size * 0.7 :depth - 1
+    right 60
+    tree :size * 0.7 :depth - 1
+    left 30
+    backward :size
+end
+tree 100 9
+";
+
+/// List and string handling
+const LISTS: &'static str = "
+make \"numbers\" []
+for [i 1 300] do
+    make \"numbers\" :numbers + [:i]
+end
+make \"text\" \"\"
+for [i 1 300] do
+    make \"text\" :text + tostring getindex :numbers :i - 1
+end
+";
+
+/// Run the script once on a new interpreter
+fn run<B: RenderBackend + 'static>(screen: B, script: &str) {
+    let mut rurtle = Rurtle::with_screen(screen);
+    rurtle.turtle().set_auto_update(false);
+    rurtle.eval(script).unwrap();
+}
+
+#[bench]
+fn eval_fibonacci(b: &mut Bencher) {
+    b.iter(|| run(NullBackend::new(SIZE), FIBONACCI));
+}
+
+#[bench]
+fn eval_spiral(b: &mut Bencher) {
+    b.iter(|| run(NullBackend::new(SIZE), SPIRAL));
+}
+
+#[bench]
+fn eval_tree(b: &mut Bencher) {
+    b.iter(|| run(NullBackend::new(SIZE), TREE));
+}
+
+#[bench]
+fn eval_lists(b: &mut Bencher) {
+    b.iter(|| run(NullBackend::new(SIZE), LISTS));
+}
+
+#[bench]
+fn parse_tree(b: &mut Bencher) {
+    let mut rurtle = Rurtle::with_screen(NullBackend::new(SIZE));
+    // LEARN only defines the function, so this measures lexing and parsing
+    let source = TREE.replace("tree 100 9", "");
+    b.iter(|| rurtle.eval(&source).unwrap());
+}
+
+#[bench]
+fn render_spiral(b: &mut Bencher) {
+    let mut rurtle = Rurtle::with_screen(SoftwareBackend::new(SIZE));
+    rurtle.turtle().set_auto_update(false);
+    rurtle.eval(SPIRAL).unwrap();
+    b.iter(|| rurtle.turtle().get_screen().render_image(1.0));
+}
+
+#[bench]
+fn render_tree(b: &mut Bencher) {
+    let mut rurtle = Rurtle::with_screen(SoftwareBackend::new(SIZE));
+    rurtle.turtle().set_auto_update(false);
+    rurtle.eval(TREE).unwrap();
+    b.iter(|| rurtle.turtle().get_screen().render_image(1.0));
+}
@@ -0,0 +1,553 @@
+//! The interface between turtles and whatever they draw on.
+//!
+//! A `Turtle` doesn't draw by itself, it tells a `RenderBackend` which shapes
+//! to add and where the turtle cursor is. `graphic::TurtleScreen` is the
+//! default backend which draws into a window using OpenGL. `NullBackend`
+//! discards everything, which is useful for tests.
+use super::filter::Filter;
+use super::graphic::color::{self, Color};
+use image::{self, DynamicImage};
+use std::io;
+
+/// The state of the turtle cursor that is drawn on top of the drawing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cursor {
+    /// Position in turtle coordinates
+    pub position: (f32, f32),
+    /// Orientation in degrees, 0° is north and positive degrees count
+    /// counter-clockwise
+    pub orientation: f32,
+    pub color: Color,
+    pub hidden: bool,
+}
+
+/// The result of a backend operation that can fail because of the script's
+/// input, e.g. an image that is too large for the graphics card. The error is
+/// a message for the user.
+pub type RenderResult<T> = Result<T, String>;
+
+/// The largest width and height of images that backends support unless they
+/// know better, see `RenderBackend::max_image_size`
+pub const MAX_IMAGE_SIZE: u32 = 16384;
+
+/// Color of the grid lines of the `Overlay`
+pub const GRID_COLOR: Color = (0.85, 0.85, 0.85, 1.0);
+/// Color of the axes, ticks and labels of the `Overlay`
+pub const AXES_COLOR: Color = (0.45, 0.45, 0.45, 1.0);
+/// Color of the rectangle that marks the visible part in the minimap
+pub const MINIMAP_FRAME_COLOR: Color = (0.85, 0.2, 0.2, 1.0);
+/// Size of the minimap relative to the window
+const MINIMAP_SCALE: f32 = 0.25;
+/// Width of a character in texts, the font is monospaced
+pub const GLYPH_WIDTH: f32 = 12. * 0.6;
+/// Background color of `Annotation` boxes
+pub const ANNOTATION_COLOR: Color = (1.0, 0.98, 0.8, 1.0);
+/// Space between the text of an `Annotation` and its border
+const ANNOTATION_PADDING: f32 = 4.;
+
+/// A coordinate grid and axes that are drawn beneath the drawing, and a
+/// minimap on top of it. They are not part of the drawing itself, e.g. they
+/// don't stop a floodfill.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Overlay {
+    /// Distance between two grid lines, no grid if `None`
+    pub grid: Option<f32>,
+    /// Show the x and y axis with ticks and labels
+    pub axes: bool,
+    /// Show the whole drawing scaled down in a corner of the window. Only
+    /// windows show the minimap, screenshots don't contain it.
+    pub minimap: bool,
+}
+
+impl Overlay {
+    /// Return the size of the minimap in a window of the given size
+    pub fn minimap_size((width, height): (f32, f32)) -> (f32, f32) {
+        (width * MINIMAP_SCALE, height * MINIMAP_SCALE)
+    }
+
+    /// Distance between two labels on the axes
+    fn label_spacing(&self) -> f32 {
+        self.grid.unwrap_or(100.)
+    }
+
+    /// Return the multiples of `spacing` between `-limit` and `limit`
+    fn steps(spacing: f32, limit: f32) -> Vec<f32> {
+        let count = (limit / spacing).floor() as i32;
+        (-count..count + 1).map(|i| i as f32 * spacing).collect()
+    }
+
+    /// Return the lines to draw on a canvas of the given size
+    pub fn lines(&self, size: (f32, f32)) -> Vec<((f32, f32), (f32, f32), Color)> {
+        let (w, h) = (size.0 / 2., size.1 / 2.);
+        let mut lines = Vec::new();
+        if let Some(spacing) = self.grid {
+            for x in Overlay::steps(spacing, w) {
+                lines.push(((x, -h), (x, h), GRID_COLOR));
+            }
+            for y in Overlay::steps(spacing, h) {
+                lines.push(((-w, y), (w, y), GRID_COLOR));
+            }
+        }
+        if self.axes {
+            lines.push(((-w, 0.), (w, 0.), AXES_COLOR));
+            lines.push(((0., -h), (0., h), AXES_COLOR));
+            let spacing = self.label_spacing();
+            for x in Overlay::steps(spacing, w) {
+                lines.push(((x, -4.), (x, 4.), AXES_COLOR));
+            }
+            for y in Overlay::steps(spacing, h) {
+                lines.push(((-4., y), (4., y), AXES_COLOR));
+            }
+        }
+        lines
+    }
+
+    /// Return the labels of the axes (anchor and text) on a canvas of the
+    /// given size
+    pub fn labels(&self, size: (f32, f32)) -> Vec<((f32, f32), String)> {
+        if !self.axes {
+            return Vec::new();
+        }
+        let (w, h) = (size.0 / 2., size.1 / 2.);
+        let spacing = self.label_spacing();
+        let mut labels = Vec::new();
+        for x in Overlay::steps(spacing, w).into_iter().filter(|&x| x != 0.) {
+            labels.push(((x + 3., 5.), x.to_string()));
+        }
+        for y in Overlay::steps(spacing, h).into_iter().filter(|&y| y != 0.) {
+            labels.push(((5., y + 3.), y.to_string()));
+        }
+        labels
+    }
+}
+
+/// A text in a box with a pointer to a point of the drawing, like a speech
+/// bubble. Annotations are drawn on top of the drawing but don't belong to
+/// it, e.g. they don't stop a floodfill.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    /// The point the pointer points at
+    pub target: (f32, f32),
+    pub text: String,
+}
+
+impl Annotation {
+    /// Return the box around the text, which lies above and to the right of
+    /// the target
+    pub fn frame(&self) -> Bounds {
+        let (x, y) = (self.target.0 + 12., self.target.1 + 20.);
+        let width = self.text.chars().count() as f32 * GLYPH_WIDTH + 2. * ANNOTATION_PADDING;
+        Bounds { min: (x, y), max: (x + width, y + 12. + 2. * ANNOTATION_PADDING) }
+    }
+
+    /// Return the lower-left corner of the text
+    pub fn anchor(&self) -> (f32, f32) {
+        let frame = self.frame();
+        // Leave room for the descenders
+        (frame.min.0 + ANNOTATION_PADDING, frame.min.1 + ANNOTATION_PADDING + 3.)
+    }
+
+    /// Return the triangle between the lower edge of the box and the target
+    pub fn pointer(&self) -> [(f32, f32); 3] {
+        let Bounds { min: (x, y), .. } = self.frame();
+        [(x + 4., y), self.target, (x + 14., y)]
+    }
+
+    /// Return the border of box and pointer as closed polygon
+    pub fn outline(&self) -> Vec<(f32, f32)> {
+        let Bounds { min, max } = self.frame();
+        let pointer = self.pointer();
+        vec![min, pointer[0], pointer[1], pointer[2], (max.0, min.1), max, (min.0, max.1)]
+    }
+
+    /// Return the corners of the box
+    pub fn corners(&self) -> [(f32, f32); 4] {
+        let Bounds { min, max } = self.frame();
+        [min, (max.0, min.1), max, (min.0, max.1)]
+    }
+}
+
+/// The part of the drawing that is visible, like a camera: `center` is shown
+/// in the middle of the canvas and everything is enlarged by `zoom`. The
+/// default view shows the origin in the middle without zoom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct View {
+    pub center: (f32, f32),
+    pub zoom: f32,
+}
+
+impl Default for View {
+    fn default() -> View {
+        View { center: (0., 0.), zoom: 1. }
+    }
+}
+
+impl View {
+    /// Return the view that shows `bounds` as large as possible on a canvas
+    /// of the given size, leaving `margin` units free at each side
+    pub fn fit(bounds: Bounds, size: (f32, f32), margin: f32) -> View {
+        let (width, height) = bounds.size();
+        let available = ((size.0 - 2. * margin).max(1.), (size.1 - 2. * margin).max(1.));
+        let zoom = match (width > 0., height > 0.) {
+            (true, true) => (available.0 / width).min(available.1 / height),
+            (true, false) => available.0 / width,
+            (false, true) => available.1 / height,
+            // A single point can't be enlarged
+            (false, false) => 1.,
+        };
+        View { center: bounds.center(), zoom: zoom }
+    }
+
+    /// Map a point from turtle coordinates to the canvas, relative to its
+    /// middle
+    pub fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        ((x - self.center.0) * self.zoom, (y - self.center.1) * self.zoom)
+    }
+
+    /// Map a point on the canvas (relative to its middle) back to turtle
+    /// coordinates
+    pub fn invert(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (x / self.zoom + self.center.0, y / self.zoom + self.center.1)
+    }
+
+    /// Return the part of the drawing that is visible on a canvas of the
+    /// given size
+    pub fn visible_bounds(&self, (width, height): (f32, f32)) -> Bounds {
+        let (w, h) = (width / 2. / self.zoom, height / 2. / self.zoom);
+        let (x, y) = self.center;
+        Bounds { min: (x - w, y - h), max: (x + w, y + h) }
+    }
+
+    /// Return the view of a minimap of the given size. It shows the whole
+    /// drawing and the part of it that is visible on a canvas of the size
+    /// `visible`.
+    pub fn minimap(&self, drawing: Option<Bounds>, visible: (f32, f32), size: (f32, f32))
+                   -> View {
+        const MARGIN: f32 = 4.;
+        let mut bounds = Some(self.visible_bounds(visible));
+        if let Some(drawing) = drawing {
+            Bounds::extend(&mut bounds, drawing.min, 0.);
+            Bounds::extend(&mut bounds, drawing.max, 0.);
+        }
+        View::fit(bounds.unwrap(), size, MARGIN)
+    }
+
+    /// Return the size of an `Overlay` centered at the origin that covers
+    /// everything visible on a canvas of the given size
+    pub fn overlay_size(&self, (width, height): (f32, f32)) -> (f32, f32) {
+        (2. * self.center.0.abs() + width / self.zoom,
+         2. * self.center.1.abs() + height / self.zoom)
+    }
+}
+
+/// An axis-aligned rectangle that contains a drawing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    /// The lower-left corner
+    pub min: (f32, f32),
+    /// The upper-right corner
+    pub max: (f32, f32),
+}
+
+impl Bounds {
+    pub fn size(&self) -> (f32, f32) {
+        (self.max.0 - self.min.0, self.max.1 - self.min.1)
+    }
+
+    pub fn center(&self) -> (f32, f32) {
+        ((self.min.0 + self.max.0) / 2., (self.min.1 + self.max.1) / 2.)
+    }
+
+    /// Grow `bounds` so that it contains a square with sides of `size`
+    /// centered at `point`. `None` is an empty drawing.
+    pub fn extend(bounds: &mut Option<Bounds>, (x, y): (f32, f32), size: f32) {
+        let r = size / 2.;
+        *bounds = Some(match *bounds {
+            Some(b) => Bounds {
+                min: (b.min.0.min(x - r), b.min.1.min(y - r)),
+                max: (b.max.0.max(x + r), b.max.1.max(y + r)),
+            },
+            None => Bounds { min: (x - r, y - r), max: (x + r, y + r) },
+        });
+    }
+
+    /// Grow `bounds` so that it contains the rectangle of the given size
+    /// centered at `center` and rotated by `angle` degrees
+    pub fn extend_rotated(bounds: &mut Option<Bounds>, center: (f32, f32), size: (f32, f32),
+                          angle: f32) {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let (w, h) = (size.0 / 2., size.1 / 2.);
+        for &(dx, dy) in &[(-w, -h), (w, -h), (w, h), (-w, h)] {
+            let corner = (center.0 + dx * cos - dy * sin, center.1 + dx * sin + dy * cos);
+            Bounds::extend(bounds, corner, 0.);
+        }
+    }
+
+    /// Grow `bounds` so that it (roughly) contains the text, assuming that
+    /// the characters are about as wide as they are high in the default font
+    pub fn extend_text(bounds: &mut Option<Bounds>, anchor: (f32, f32), angle: f32,
+                       text: &str) {
+        const FONT_SIZE: f32 = 12.;
+        let size = (text.chars().count() as f32 * GLYPH_WIDTH, FONT_SIZE);
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let (dx, dy) = (size.0 / 2., size.1 / 2.);
+        let center = (anchor.0 + dx * cos - dy * sin, anchor.1 + dx * sin + dy * cos);
+        Bounds::extend_rotated(bounds, center, size, angle);
+    }
+}
+
+/// Return the color of a line in a fading trail of the given length. The
+/// newest line (`age` 0) keeps its color, older lines get more and more
+/// transparent.
+pub fn fade(color: Color, age: usize, length: usize) -> Color {
+    let (r, g, b, a) = color;
+    let visibility = 1. - age as f32 / length.max(1) as f32;
+    (r, g, b, a * visibility.max(0.))
+}
+
+/// Remove the shapes for which `remove` returns true from a display list of
+/// `(owner, shape)` pairs, keeping the order of the others. Returns how many
+/// of the removed shapes came before `foreground_start`, the foreground moves
+/// to the front by that much.
+pub fn remove_shapes<S, F>(shapes: &mut Vec<(usize, S)>, foreground_start: usize, mut remove: F)
+                           -> usize
+    where F: FnMut(&(usize, S)) -> bool
+{
+    let (mut index, mut removed) = (0, 0);
+    shapes.retain(|item| {
+        let keep = !remove(item);
+        if !keep && index < foreground_start {
+            removed += 1;
+        }
+        index += 1;
+        keep
+    });
+    removed
+}
+
+/// Symmetry mode: every line is repeated `count` times rotated around the
+/// origin, and if `reflect` is set, also mirrored at the rotated y-axes. The
+/// default of one copy without reflection draws each line once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Symmetry {
+    pub count: u32,
+    pub reflect: bool,
+}
+
+impl Default for Symmetry {
+    fn default() -> Symmetry {
+        Symmetry { count: 1, reflect: false }
+    }
+}
+
+impl Symmetry {
+    /// Return all copies of the line from `start` to `end`, including the
+    /// line itself
+    pub fn lines(&self, start: (f32, f32), end: (f32, f32)) -> Vec<((f32, f32), (f32, f32))> {
+        let count = self.count.max(1);
+        let mut lines = Vec::new();
+        for i in 0..count {
+            let angle = (i as f32 * 360. / count as f32).to_radians();
+            let (sin, cos) = angle.sin_cos();
+            let rotate = |(x, y): (f32, f32)| (x * cos - y * sin, x * sin + y * cos);
+            lines.push((rotate(start), rotate(end)));
+            if self.reflect {
+                let mirror = |(x, y): (f32, f32)| (-x, y);
+                lines.push((rotate(mirror(start)), rotate(mirror(end))));
+            }
+        }
+        lines
+    }
+}
+
+/// A surface that turtles can draw on. Coordinates are turtle coordinates with
+/// the origin in the middle of the canvas and the y-axis pointing up.
+pub trait RenderBackend {
+    /// Add a line from `start` to `end`
+    fn add_line(&mut self, start: (f32, f32), end: (f32, f32), color: Color);
+    /// Add single points with the size of the pen. Backends that can't draw
+    /// points draw a short line for each point instead.
+    fn plot_points(&mut self, points: &[(f32, f32)], color: Color) {
+        for &(x, y) in points {
+            self.add_line((x, y), (x + 1., y), color);
+        }
+    }
+    /// Add a text whose lower-left corner is at `anchor`, rotated by `angle`
+    /// degrees
+    fn add_text(&mut self, anchor: (f32, f32), angle: f32, color: Color, text: &str)
+                -> RenderResult<()>;
+    /// Use the font in the file at `path` for characters that the other fonts
+    /// lack in texts added afterwards
+    fn add_fallback_font(&mut self, _path: &str) -> io::Result<()> {
+        Ok(())
+    }
+    /// Floodfill the area around `point`
+    fn fill(&mut self, point: (f32, f32), color: Color) -> RenderResult<()>;
+    /// Add the image from `path`, centered at `position`
+    fn stamp_image(&mut self, path: &str, position: (f32, f32), rotation: f32, scale: f32)
+                   -> RenderResult<()>;
+    /// Remove everything that has been drawn
+    fn clear(&mut self);
+    /// Start the foreground: everything added from now on can be removed
+    /// with `clear_foreground`, the shapes drawn before stay
+    fn begin_foreground(&mut self) {}
+    /// Remove everything that has been added since `begin_foreground`
+    fn clear_foreground(&mut self) {}
+    /// Set the turtle that the shapes added from now on belong to. 0 is the
+    /// local turtle, the server uses the client numbers.
+    fn set_owner(&mut self, _owner: usize) {}
+    /// Remove everything the given turtle has drawn
+    fn clear_owner(&mut self, _owner: usize) {}
+    /// Remove the last shape the given turtle has drawn. Returns false if
+    /// there is none or the backend doesn't keep track of the owners.
+    fn undo_owner(&mut self, _owner: usize) -> bool { false }
+    /// Add an annotation on top of the drawing, if the backend supports it
+    fn add_annotation(&mut self, _annotation: Annotation) -> RenderResult<()> {
+        Ok(())
+    }
+    /// Remove all annotations. `clear` removes them too.
+    fn clear_annotations(&mut self) {}
+    /// Replace the drawing with an image of it, run through the filter. The
+    /// overlay and the annotations are not part of the image.
+    fn apply_filter(&mut self, _filter: Filter) -> RenderResult<()> {
+        Ok(())
+    }
+
+    /// Update the turtle cursor
+    fn set_cursor(&mut self, cursor: Cursor);
+    fn set_background_color(&mut self, color: Color);
+    /// Enable or disable anti-aliasing of lines, if the backend supports it
+    fn set_smooth_lines(&mut self, smooth: bool);
+    /// Return whether lines are anti-aliased
+    fn smooth_lines(&self) -> bool { false }
+    /// Set the width of lines that are added from now on, in turtle units
+    fn set_pen_size(&mut self, _size: f32) {}
+    /// Switch to or from fullscreen mode, if the backend supports it
+    fn set_fullscreen(&mut self, _fullscreen: bool) -> RenderResult<()> {
+        Ok(())
+    }
+    /// Set the grid and axes drawn beneath the drawing, if the backend
+    /// supports it
+    fn set_overlay(&mut self, _overlay: Overlay) {}
+    /// Set the symmetry that is applied to lines added from now on, if the
+    /// backend supports it
+    fn set_symmetry(&mut self, _symmetry: Symmetry) {}
+    /// Only keep the last `length` lines and let them fade out, if the
+    /// backend supports it. `None` keeps all lines.
+    fn set_trail(&mut self, _length: Option<usize>) {}
+    /// Only show the first `count` lines and hide everything drawn after
+    /// them, if the backend supports it. `None` shows everything.
+    fn set_visible_lines(&mut self, _count: Option<usize>) {}
+    /// Return the number of lines in the drawing
+    fn line_count(&self) -> usize { 0 }
+    /// Return the bounding box of everything drawn, `None` if nothing has
+    /// been drawn or the backend doesn't keep track of its shapes
+    fn drawing_bounds(&self) -> Option<Bounds> { None }
+    /// Return the drawn lines as start and end point in the order they were
+    /// drawn, points as lines of length 0. Texts, fills and images are left
+    /// out. Backends that don't keep track of their shapes return nothing.
+    fn line_segments(&self) -> Vec<((f32, f32), (f32, f32))> { Vec::new() }
+    /// Show the given part of the drawing, if the backend supports it
+    fn set_view(&mut self, _view: View) {}
+    /// Return the part of the drawing that is shown
+    fn view(&self) -> View { View::default() }
+
+    /// Show the current state of the drawing
+    fn draw_and_update(&mut self);
+    /// Handle pending input events, e.g. mouse clicks
+    fn pump_events(&mut self);
+    /// Returns true if the user closed the output
+    fn is_closed(&self) -> bool;
+    /// Return the size of the whole canvas in turtle units
+    fn canvas_size(&self) -> (f32, f32);
+    /// Return the size of the part of the canvas that fits into the window.
+    /// This is smaller than `canvas_size` if the canvas can be scrolled.
+    fn visible_size(&self) -> (f32, f32) { self.canvas_size() }
+
+    /// Return the last known mouse position in turtle coordinates
+    fn mouse_position(&self) -> (f32, f32) { (0., 0.) }
+    /// Returns true if the left mouse button is pressed
+    fn is_mouse_down(&self) -> bool { false }
+    /// Returns true if the key with the given name (e.g. "left", "space" or
+    /// "a") is pressed
+    fn is_key_down(&self, _key: &str) -> bool { false }
+    /// Return the positions of all clicks since the last call
+    fn take_clicks(&mut self) -> Vec<(f32, f32)> { Vec::new() }
+    /// Returns true if `point` lies on the turtle cursor
+    fn hits_turtle(&self, _point: (f32, f32)) -> bool { false }
+
+    /// Return the largest width and height of the images the backend can
+    /// create, see `render_image`
+    fn max_image_size(&self) -> u32 { MAX_IMAGE_SIZE }
+    /// Return the current drawing (including the cursor) as image with one
+    /// pixel per unit
+    fn screenshot(&self) -> RenderResult<DynamicImage>;
+    /// Return the current drawing at the device's native resolution
+    fn native_screenshot(&self) -> RenderResult<DynamicImage> {
+        self.screenshot()
+    }
+    /// Render the drawing without cursor with `scale` pixels per unit. The
+    /// caller makes sure that the image is not larger than `max_image_size`.
+    fn render_image(&self, scale: f32) -> RenderResult<DynamicImage>;
+    /// Export the drawing as PostScript file
+    fn export_ps(&self, _path: &str) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Other, "PostScript export is not supported"))
+    }
+}
+
+/// A backend that doesn't draw anything. Screenshots are empty images with
+/// the background color.
+pub struct NullBackend {
+    size: (u32, u32),
+    background_color: Color,
+}
+
+impl NullBackend {
+    /// Construct a new `NullBackend`. `size` is the size of the screenshots.
+    pub fn new(size: (u32, u32)) -> NullBackend {
+        NullBackend {
+            size: size,
+            background_color: color::WHITE,
+        }
+    }
+
+    fn blank_image(&self, scale: f32) -> DynamicImage {
+        let (r, g, b, a) = self.background_color;
+        let pixel = image::Rgba([(r * 255.) as u8, (g * 255.) as u8,
+                                 (b * 255.) as u8, (a * 255.) as u8]);
+        let (width, height) = self.size;
+        DynamicImage::ImageRgba8(image::ImageBuffer::from_pixel(
+            (width as f32 * scale) as u32, (height as f32 * scale) as u32, pixel))
+    }
+}
+
+impl RenderBackend for NullBackend {
+    fn add_line(&mut self, _: (f32, f32), _: (f32, f32), _: Color) {}
+    fn add_text(&mut self, _: (f32, f32), _: f32, _: Color, _: &str) -> RenderResult<()> {
+        Ok(())
+    }
+    fn fill(&mut self, _: (f32, f32), _: Color) -> RenderResult<()> {
+        Ok(())
+    }
+    fn stamp_image(&mut self, _: &str, _: (f32, f32), _: f32, _: f32) -> RenderResult<()> {
+        Ok(())
+    }
+    fn clear(&mut self) {}
+    fn set_cursor(&mut self, _: Cursor) {}
+    fn set_background_color(&mut self, color: Color) {
+        self.background_color = color;
+    }
+    fn set_smooth_lines(&mut self, _: bool) {}
+    fn draw_and_update(&mut self) {}
+    fn pump_events(&mut self) {}
+    fn is_closed(&self) -> bool { false }
+    fn canvas_size(&self) -> (f32, f32) {
+        (self.size.0 as f32, self.size.1 as f32)
+    }
+    fn screenshot(&self) -> RenderResult<DynamicImage> {
+        Ok(self.blank_image(1.))
+    }
+    fn render_image(&self, scale: f32) -> RenderResult<DynamicImage> {
+        Ok(self.blank_image(scale))
+    }
+}
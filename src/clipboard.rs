@@ -0,0 +1,115 @@
+//! Copying text and images to the system clipboard.
+//!
+//! There is no portable clipboard API, so this module calls the usual command
+//! line tools: `pbcopy` and `osascript` on macOS, `clip` and PowerShell on
+//! Windows and `wl-copy`, `xclip` or `xsel` everywhere else. If none of them
+//! is installed, an error is returned.
+use image::{self, DynamicImage};
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// A program together with its arguments
+type Tool = (&'static str, &'static [&'static str]);
+
+#[cfg(target_os = "macos")]
+const TEXT_TOOLS: &'static [Tool] = &[("pbcopy", &[])];
+#[cfg(windows)]
+const TEXT_TOOLS: &'static [Tool] = &[("clip", &[])];
+#[cfg(not(any(target_os = "macos", windows)))]
+const TEXT_TOOLS: &'static [Tool] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+];
+
+#[cfg(not(any(target_os = "macos", windows)))]
+const IMAGE_TOOLS: &'static [Tool] = &[
+    ("wl-copy", &["--type", "image/png"]),
+    ("xclip", &["-selection", "clipboard", "-t", "image/png"]),
+];
+
+fn other_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, message)
+}
+
+/// Run the program and write `input` to its standard input
+fn pipe_to(program: &str, args: &[&str], input: &[u8]) -> io::Result<()> {
+    let mut child = try!(Command::new(program)
+                         .args(args)
+                         .stdin(Stdio::piped())
+                         .stdout(Stdio::null())
+                         .stderr(Stdio::null())
+                         .spawn());
+    // The pipe is closed when stdin is dropped, so the program sees the end
+    try!(child.stdin.take().unwrap().write_all(input));
+    let status = try!(child.wait());
+    if status.success() {
+        Ok(())
+    } else {
+        Err(other_error(format!("{} failed", program)))
+    }
+}
+
+/// Try the tools in order until one of them succeeds
+fn pipe_to_any(tools: &[Tool], input: &[u8]) -> io::Result<()> {
+    for &(program, args) in tools {
+        if pipe_to(program, args, input).is_ok() {
+            return Ok(());
+        }
+    }
+    let names: Vec<_> = tools.iter().map(|&(program, _)| program).collect();
+    Err(other_error(format!("could not copy to the clipboard (tried {})", names.join(", "))))
+}
+
+/// Copy the text to the clipboard
+pub fn copy_text(text: &str) -> io::Result<()> {
+    pipe_to_any(TEXT_TOOLS, text.as_bytes())
+}
+
+/// Copy the image to the clipboard
+pub fn copy_image(image: &DynamicImage) -> io::Result<()> {
+    let mut png = Vec::new();
+    try!(image.save(&mut png, image::ImageFormat::PNG)
+         .map_err(|e| other_error(format!("{}", e))));
+    copy_png(&png)
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn copy_png(png: &[u8]) -> io::Result<()> {
+    pipe_to_any(IMAGE_TOOLS, png)
+}
+
+/// macOS and Windows can't read images from a pipe, so the image is written
+/// to a temporary file that the script loads
+#[cfg(any(target_os = "macos", windows))]
+fn copy_png(png: &[u8]) -> io::Result<()> {
+    use std::{env, fs};
+    let path = env::temp_dir().join("rurtle-clipboard.png");
+    try!(try!(fs::File::create(&path)).write_all(png));
+    let (program, args) = image_script(&path.to_string_lossy());
+    let status = try!(Command::new(program).args(&args).status());
+    if status.success() {
+        Ok(())
+    } else {
+        Err(other_error(format!("{} failed", program)))
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn image_script(path: &str) -> (&'static str, Vec<String>) {
+    ("osascript", vec![
+        "-e".to_owned(),
+        format!("set the clipboard to (read (POSIX file \"{}\") as «class PNGf»)", path),
+    ])
+}
+
+#[cfg(windows)]
+fn image_script(path: &str) -> (&'static str, Vec<String>) {
+    ("powershell", vec![
+        "-STA".to_owned(),
+        "-NoProfile".to_owned(),
+        "-Command".to_owned(),
+        format!("Add-Type -AssemblyName System.Windows.Forms, System.Drawing; \
+                 [Windows.Forms.Clipboard]::SetImage([Drawing.Image]::FromFile('{}'))", path),
+    ])
+}
@@ -0,0 +1,122 @@
+//! Comparison of two drawings, e.g. a submission with a reference drawing.
+//!
+//! A drawing is given either as a command log saved with `SAVELOG` (see the
+//! `replay` module) or as a PNG image. Logs are replayed on a
+//! `SoftwareBackend` of the given size, so both drawings have to be rendered
+//! at the same size to be compared:
+//!
+//! ```no_run
+//! use rurtle::diff;
+//! let reference = diff::load_drawing("reference.log", (640, 640)).unwrap();
+//! let submission = diff::load_drawing("submission.log", (640, 640)).unwrap();
+//! let comparison = diff::compare(&reference, &submission).unwrap();
+//! println!("{:.1}% similar", comparison.similarity() * 100.);
+//! comparison.image.save("diff.png").unwrap();
+//! ```
+use super::replay;
+use super::software::SoftwareBackend;
+use super::turtle::Turtle;
+use image::{self, Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::error::Error;
+use std::{io, time};
+
+/// The color of the pixels that differ in the visualization
+pub const DIFFERENCE_COLOR: Rgba<u8> = Rgba { data: [230, 20, 20, 255] };
+
+/// Two channel values are considered different if they differ by more than
+/// this amount. This hides small differences in antialiasing.
+pub const CHANNEL_THRESHOLD: i32 = 32;
+
+/// How much the matching parts are faded towards white in the visualization,
+/// between 0 (not at all) and 1 (invisible)
+const FADE: f32 = 0.75;
+
+/// The result of `compare`
+pub struct Comparison {
+    /// Number of pixels that belong to at least one of the drawings, i.e.
+    /// differ from its background
+    pub drawn: usize,
+    /// Number of pixels that differ between the drawings
+    pub differing: usize,
+    /// The reference drawing, faded, with the differing pixels in
+    /// `DIFFERENCE_COLOR`
+    pub image: RgbaImage,
+}
+
+impl Comparison {
+    /// Return the share of the drawn pixels that are the same in both
+    /// drawings, between 0 and 1. Two empty drawings are the same.
+    pub fn similarity(&self) -> f32 {
+        if self.drawn == 0 {
+            return 1.;
+        }
+        1. - self.differing.min(self.drawn) as f32 / self.drawn as f32
+    }
+}
+
+/// Load the drawing at `path`. Files ending in `.png` are read as images,
+/// everything else as command log that is replayed on a canvas of the given
+/// size.
+pub fn load_drawing(path: &str, size: (u32, u32)) -> Result<RgbaImage, Box<Error>> {
+    if path.to_lowercase().ends_with(".png") {
+        return Ok(try!(image::open(path)).to_rgba());
+    }
+    let commands = try!(replay::load_log(path));
+    let mut turtle = Turtle::new(SoftwareBackend::new(size));
+    try!(turtle.replay(&commands, time::Duration::from_millis(0)));
+    Ok(try!(turtle.render_image(1.0)).to_rgba())
+}
+
+/// Return true if the two pixels differ noticeably, i.e. one of the channels
+/// by more than `CHANNEL_THRESHOLD`
+pub fn differ(a: &Rgba<u8>, b: &Rgba<u8>) -> bool {
+    (0..4).any(|i| (a.data[i] as i32 - b.data[i] as i32).abs() > CHANNEL_THRESHOLD)
+}
+
+/// Return the most common color of the image, which is taken as its
+/// background
+fn background(image: &RgbaImage) -> Rgba<u8> {
+    let mut counts = HashMap::new();
+    for pixel in image.pixels() {
+        *counts.entry(pixel.data).or_insert(0) += 1;
+    }
+    let color = counts.into_iter().max_by_key(|&(_, count)| count).map(|(color, _)| color);
+    Rgba { data: color.unwrap_or([255, 255, 255, 255]) }
+}
+
+/// Compare the drawing with the reference pixel by pixel. Returns an error if
+/// the images have different sizes.
+pub fn compare(reference: &RgbaImage, other: &RgbaImage) -> Result<Comparison, Box<Error>> {
+    if reference.dimensions() != other.dimensions() {
+        let ((w1, h1), (w2, h2)) = (reference.dimensions(), other.dimensions());
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the drawings have different sizes: {}x{} and {}x{}", w1, h1, w2, h2))));
+    }
+    let (reference_background, other_background) = (background(reference), background(other));
+    let (width, height) = reference.dimensions();
+    let mut image = RgbaImage::new(width, height);
+    let (mut drawn, mut differing) = (0, 0);
+    for y in 0..height {
+        for x in 0..width {
+            let (a, b) = (reference.get_pixel(x, y), other.get_pixel(x, y));
+            if differ(a, &reference_background) || differ(b, &other_background) {
+                drawn += 1;
+            }
+            let pixel = if differ(a, b) {
+                differing += 1;
+                DIFFERENCE_COLOR
+            } else {
+                let fade = |c: u8| c + ((255 - c) as f32 * FADE) as u8;
+                Rgba { data: [fade(a.data[0]), fade(a.data[1]), fade(a.data[2]), 255] }
+            };
+            image.put_pixel(x, y, pixel);
+        }
+    }
+    Ok(Comparison {
+        drawn: drawn,
+        differing: differing,
+        image: image,
+    })
+}
@@ -0,0 +1,145 @@
+//! A simple interface to embed the Rurtle interpreter in other programs.
+//!
+//! `Rurtle` bundles a turtle screen, a turtle and the environment in which
+//! Rurtle code is run. Host programs can define their own native functions
+//! which are then callable from Rurtle code just like the built-in ones.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rurtle::{Rurtle, ScreenOptions, Value};
+//! use rurtle::environ::{Environment, ResultType};
+//!
+//! fn answer(_: &mut Environment, _: &[Value]) -> ResultType {
+//!     Ok(Value::Number(42.))
+//! }
+//!
+//! let mut rurtle = Rurtle::new(ScreenOptions::default());
+//! rurtle.define_native("ANSWER", 0, answer);
+//! rurtle.eval("forward answer").unwrap();
+//! ```
+//!
+//! Long running code can be watched and cancelled with an instruction hook,
+//! e.g. to give each script at most one second:
+//!
+//! ```no_run
+//! use rurtle::{Rurtle, RuntimeError, ScreenOptions};
+//! use std::time::{Duration, Instant};
+//!
+//! let mut rurtle = Rurtle::new(ScreenOptions::default());
+//! let start = Instant::now();
+//! rurtle.set_instruction_hook(1000, move |stats| {
+//!     if start.elapsed() > Duration::from_secs(1) {
+//!         Err(RuntimeError::new(format!("cancelled after {} statements", stats.statements)))
+//!     } else {
+//!         Ok(())
+//!     }
+//! });
+//! ```
+use super::backend::RenderBackend;
+use super::environ::{Environment, FuncType, Function, RuntimeError};
+use super::environ::value::Value;
+#[cfg(feature = "native")]
+use super::graphic::{ScreenOptions, TurtleScreen};
+use super::plugin::{self, Plugin};
+use super::stats::Stats;
+use super::turtle::Turtle;
+
+/// Errors that can occur while evaluating source code: lexing, parsing and
+/// runtime errors
+pub type Error = Box<::std::error::Error>;
+
+/// Size of the window created by `Rurtle::new`
+#[cfg(feature = "native")]
+const DEFAULT_SIZE: (u32, u32) = (640, 640);
+
+/// An embedded Rurtle interpreter
+pub struct Rurtle {
+    environment: Environment,
+    plugins: Vec<String>,
+}
+
+impl Rurtle {
+    /// Create a new interpreter with a 640x640 window titled "Rurtle"
+    ///
+    /// # Panics
+    ///
+    /// Panics if the window can't be created, see `TurtleScreen::with_options`
+    #[cfg(feature = "native")]
+    pub fn new(options: ScreenOptions) -> Rurtle {
+        Rurtle::with_screen(TurtleScreen::with_options(DEFAULT_SIZE, "Rurtle", options))
+    }
+
+    /// Create a new interpreter that draws on the given backend, e.g. a
+    /// `TurtleScreen`
+    pub fn with_screen<B: RenderBackend + 'static>(screen: B) -> Rurtle {
+        Rurtle {
+            environment: Environment::new(Turtle::new(screen)),
+            plugins: Vec::new(),
+        }
+    }
+
+    /// Run the given source code and return the value of the last statement
+    pub fn eval(&mut self, source: &str) -> Result<Value, Error> {
+        self.environment.eval_source(source)
+    }
+
+    /// Define a native function that can be called from Rurtle code. `arity`
+    /// is the number of arguments the function takes. Existing functions with
+    /// the same name (including built-in ones) are replaced.
+    pub fn define_native(&mut self, name: &str, arity: i32, function: FuncType) {
+        self.environment.define_function(name, Function::Native(arity, function));
+    }
+
+    /// Register the functions of the given plugin
+    pub fn load_plugin(&mut self, plugin: &Plugin) -> Result<(), Error> {
+        try!(plugin::load(&mut self.environment, plugin));
+        self.plugins.push(plugin.name().to_owned());
+        Ok(())
+    }
+
+    /// Return the names of the loaded plugins
+    pub fn plugins(&self) -> &[String] {
+        &self.plugins
+    }
+
+    /// Return the value of the global variable `name`
+    pub fn get_global(&mut self, name: &str) -> Option<Value> {
+        self.environment.global_frame().locals.get(name).cloned()
+    }
+
+    /// Set the global variable `name`
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.environment.global_frame().locals.insert(name.to_owned(), value);
+    }
+
+    /// Handle the window's events and run the click handler if necessary. This
+    /// should be called regularly, e.g. in the host's main loop.
+    pub fn handle_events(&mut self) -> Result<(), Error> {
+        try!(self.environment.handle_events());
+        Ok(())
+    }
+
+    /// Call `hook` every `interval` statements while Rurtle code runs, see
+    /// `Environment::set_instruction_hook`
+    pub fn set_instruction_hook<F>(&mut self, interval: u64, hook: F)
+        where F: FnMut(&Stats) -> Result<(), RuntimeError> + 'static
+    {
+        self.environment.set_instruction_hook(interval, hook)
+    }
+
+    /// Returns true if the window has been closed
+    pub fn is_closed(&mut self) -> bool {
+        self.turtle().get_screen().is_closed()
+    }
+
+    /// Return the turtle to control it directly from Rust
+    pub fn turtle(&mut self) -> &mut Turtle {
+        self.environment.get_turtle()
+    }
+
+    /// Return the underlying environment for advanced uses
+    pub fn environment(&mut self) -> &mut Environment {
+        &mut self.environment
+    }
+}
@@ -0,0 +1,45 @@
+//! Arbitrary precision integers for the `bignum` feature
+//!
+//! Integer arithmetic that overflows an `i64` continues with a `BigInt` instead
+//! of falling back to a float. Results that fit into an `i64` again are always
+//! turned back into a normal `Value::Integer`, so a `Value::BigInteger` is
+//! guaranteed to be outside of the `i64` range.
+use super::value::Value;
+use num::BigInt;
+use num::traits::ToPrimitive;
+use std::cmp::Ordering;
+
+/// Convert an integer value of any size to a `BigInt`. Returns `None` for
+/// floats and all other values.
+pub fn to_big(value: &Value) -> Option<BigInt> {
+    match *value {
+        Value::Integer(n) => Some(BigInt::from(n)),
+        Value::BigInteger(ref n) => Some(n.clone()),
+        _ => None,
+    }
+}
+
+/// Turn a `BigInt` into a `Value`, using `Value::Integer` if it fits
+pub fn to_value(number: BigInt) -> Value {
+    match number.to_i64() {
+        Some(n) => Value::Integer(n),
+        None => Value::BigInteger(number),
+    }
+}
+
+/// Convert a `BigInt` to the nearest float, infinity if it's too big
+pub fn to_float(number: &BigInt) -> f32 {
+    // Parsing saturates to infinity instead of failing
+    number.to_string().parse().unwrap()
+}
+
+/// Compare two numbers of which at least one is a `BigInteger`
+pub fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (to_big(a), to_big(b)) {
+        (Some(a), Some(b)) => Some(a.cmp(&b)),
+        _ => match (a.as_number(), b.as_number()) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => None,
+        },
+    }
+}
@@ -0,0 +1,198 @@
+use super::{Environment, ResultType, RuntimeError, Value};
+use image::{self, RgbaImage};
+use rustc_serialize::json::{self, Json};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+
+/// Convert a JSON value to a Rurtle value. Objects become lists of
+/// `[key value]` pairs, `null` becomes `Nothing` and booleans become 1 or 0.
+fn from_json(json: Json) -> Value {
+    match json {
+        Json::I64(n) => Value::Integer(n),
+        Json::U64(n) if n <= ::std::i64::MAX as u64 => Value::Integer(n as i64),
+        Json::U64(n) => Value::Number(n as f32),
+        Json::F64(n) => Value::Number(n as f32),
+        Json::String(s) => Value::String(s),
+        Json::Boolean(b) => Value::Number(if b { 1. } else { 0. }),
+        Json::Array(a) => Value::List(a.into_iter().map(from_json).collect()),
+        Json::Object(o) => Value::List(o.into_iter().map(|(key, value)| {
+            Value::List(vec![Value::String(key), from_json(value)])
+        }).collect()),
+        Json::Null => Value::Nothing,
+    }
+}
+
+/// Convert a Rurtle value to JSON, see `Value::to_json`
+fn to_json(value: &Value) -> Result<Json, RuntimeError> {
+    match value.to_json() {
+        Some(json) => Ok(json),
+        None => Err(RuntimeError("can't convert procedure to JSON".to_owned())),
+    }
+}
+
+pub fn parsejson(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref string), => {
+        match Json::from_str(string) {
+            Ok(json) => Ok(from_json(json)),
+            Err(e) => Err(RuntimeError(format!("invalid JSON: {}", e))),
+        }
+    })
+}
+
+pub fn tojson(_: &mut Environment, args: &[Value]) -> ResultType {
+    let json = try!(to_json(&args[0]));
+    Ok(Value::String(json::encode(&json).unwrap()))
+}
+
+/// Convert a list of `[key value]` pairs to a JSON object
+pub fn tojsonobject(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::List(ref pairs), => {
+        let mut object = BTreeMap::new();
+        for pair in pairs {
+            match *pair {
+                Value::List(ref p) if p.len() == 2 => {
+                    let key = match p[0] {
+                        Value::String(ref k) => k.clone(),
+                        ref k => format!("{}", k),
+                    };
+                    object.insert(key, try!(to_json(&p[1])));
+                },
+                ref val => return Err(RuntimeError(format!("not a key-value pair: {}", val))),
+            }
+        }
+        Ok(Value::String(json::encode(&Json::Object(object)).unwrap()))
+    })
+}
+
+/// Split CSV data into rows of fields. Fields may be quoted with `"`, in which
+/// case they can contain commas, line breaks and `""` as escaped quote.
+fn parse_csv(data: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = data.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(::std::mem::replace(&mut field, String::new())),
+            '\r' if !quoted => (),
+            '\n' if !quoted => {
+                row.push(::std::mem::replace(&mut field, String::new()));
+                rows.push(::std::mem::replace(&mut row, Vec::new()));
+            },
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+pub fn readcsv(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        let parse_numbers = args[1].boolean();
+        let mut data = String::new();
+        if let Err(e) = File::open(name).and_then(|mut f| f.read_to_string(&mut data)) {
+            return Err(RuntimeError(format!("{}", e)));
+        }
+        let rows = parse_csv(&data).into_iter().map(|row| {
+            Value::List(row.into_iter().map(|field| {
+                if !parse_numbers {
+                    return Value::String(field);
+                }
+                match (field.trim().parse::<i64>(), field.trim().parse::<f32>()) {
+                    (Ok(n), _) => Value::Integer(n),
+                    (_, Ok(x)) => Value::Number(x),
+                    _ => Value::String(field),
+                }
+            }).collect())
+        }).collect();
+        Ok(Value::List(rows))
+    })
+}
+
+pub fn loadimage(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        match image::open(name) {
+            Ok(image) => {
+                env.images.push(image.to_rgba());
+                Ok(Value::Integer(env.images.len() as i64 - 1))
+            },
+            Err(e) => Err(RuntimeError(format!("{}: {}", name, e))),
+        }
+    })
+}
+
+/// Return the image with the given handle, see `loadimage`
+fn get_image(env: &Environment, handle: i64) -> Result<&RgbaImage, RuntimeError> {
+    if handle < 0 || handle as usize >= env.images.len() {
+        return Err(RuntimeError(format!("invalid image handle: {}", handle)));
+    }
+    Ok(&env.images[handle as usize])
+}
+
+pub fn imagepixel(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Integer(handle),
+              arg Value::Number(x),
+              arg Value::Number(y), =>
+    {
+        let image = try!(get_image(env, handle));
+        let (width, height) = image.dimensions();
+        let (column, row) = (x.floor(), y.floor());
+        if !(column >= 0. && row >= 0. && column < width as f32 && row < height as f32) {
+            return Err(RuntimeError(format!("pixel {} {} is outside of the {}x{} image",
+                                            x, y, width, height)));
+        }
+        let data = image.get_pixel(column as u32, row as u32).data;
+        let channel = |c: u8| Value::Number(c as f32 / 255.);
+        Ok(Value::List(vec![channel(data[0]), channel(data[1]), channel(data[2])]))
+    })
+}
+
+pub fn imagesize(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(handle), => {
+        let (width, height) = try!(get_image(env, handle)).dimensions();
+        Ok(Value::List(vec![Value::Integer(width as i64), Value::Integer(height as i64)]))
+    })
+}
+
+/// How long FETCH waits for the server before giving up
+#[cfg(feature = "fetch")]
+const FETCH_TIMEOUT_SECS: u64 = 10;
+
+#[cfg(feature = "fetch")]
+pub fn fetch(_: &mut Environment, args: &[Value]) -> ResultType {
+    use hyper::Client;
+    use std::time::Duration;
+    get_args!(args, arg Value::String(ref url), => {
+        let mut client = Client::new();
+        client.set_read_timeout(Some(Duration::from_secs(FETCH_TIMEOUT_SECS)));
+        client.set_write_timeout(Some(Duration::from_secs(FETCH_TIMEOUT_SECS)));
+        let mut response = match client.get(&url[..]).send() {
+            Ok(r) => r,
+            Err(e) => return Err(RuntimeError(format!("{}", e))),
+        };
+        if !response.status.is_success() {
+            return Err(RuntimeError(format!("{}: {}", url, response.status)));
+        }
+        let mut body = String::new();
+        match response.read_to_string(&mut body) {
+            Ok(_) => Ok(Value::String(body)),
+            Err(e) => Err(RuntimeError(format!("{}", e))),
+        }
+    })
+}
+
+#[cfg(not(feature = "fetch"))]
+pub fn fetch(_: &mut Environment, _: &[Value]) -> ResultType {
+    Err(RuntimeError("FETCH is not available, rebuild rurtle with --features fetch".to_owned()))
+}
@@ -1,17 +1,52 @@
 use super::{Environment, ResultType, RuntimeError, Value};
-use std::fs;
+use super::super::super::parse::ast::Node;
+use super::super::super::clipboard;
+use super::super::super::plotter::{Format, PlotterOptions};
+use super::super::super::replay;
+use super::super::super::stats::millis;
+use super::super::workspace;
+use std::{env as stdenv, fs, time};
 
 pub fn make(env: &mut Environment, args: &[Value]) -> ResultType {
     if let Value::String(ref name) = args[0] {
-        env.current_frame().locals.insert(name.clone(), args[1].clone());
+        try!(env.set_variable(name, args[1].clone()));
         Ok(Value::Nothing)
     } else {
         Err(RuntimeError(format!("invalid argument: {:?}", args[1])))
     }
 }
 
+pub fn local(env: &mut Environment, args: &[Value]) -> ResultType {
+    // Either a single name or a list of names
+    let names = match args[0] {
+        Value::String(_) => vec![args[0].clone()],
+        Value::List(ref names) => names.clone(),
+        ref val => return Err(RuntimeError(format!("invalid argument: {:?}", val))),
+    };
+    for name in names {
+        if let Value::String(name) = name {
+            try!(env.check_mutable(&name));
+            env.current_frame().locals.insert(name, Value::Nothing);
+        } else {
+            return Err(RuntimeError(format!("invalid argument: {:?}", name)));
+        }
+    }
+    Ok(Value::Nothing)
+}
+
+pub fn localmake(env: &mut Environment, args: &[Value]) -> ResultType {
+    if let Value::String(ref name) = args[0] {
+        try!(env.check_mutable(name));
+        env.current_frame().locals.insert(name.clone(), args[1].clone());
+        Ok(Value::Nothing)
+    } else {
+        Err(RuntimeError(format!("invalid argument: {:?}", args[0])))
+    }
+}
+
 pub fn global(env: &mut Environment, args: &[Value]) -> ResultType {
     if let Value::String(ref name) = args[0] {
+        try!(env.check_mutable(name));
         env.global_frame().locals.insert(name.clone(), args[1].clone());
         Ok(Value::Nothing)
     } else {
@@ -19,20 +54,293 @@ pub fn global(env: &mut Environment, args: &[Value]) -> ResultType {
     }
 }
 
-pub fn screenshot(env: &mut Environment, args: &[Value]) -> ResultType {
+pub fn constant(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        env.define_constant(name, args[1].clone())
+    })
+}
+
+/// Save the given image as PNG file with the given name
+fn save_png(image: ::image::DynamicImage, name: &str) -> ResultType {
+    let mut file = match fs::File::create(name) {
+        Ok(f) => f,
+        Err(e) => return Err(RuntimeError(format!("{}", e))),
+    };
+    match image.save(&mut file, ::image::ImageFormat::PNG) {
+        Ok(()) => Ok(Value::Nothing),
+        Err(e) => Err(RuntimeError(format!("{}", e))),
+    }
+}
+
+/// Replace the placeholders `{}` and `{:0N}` (padded with zeros to N digits)
+/// in the filename with `number`
+fn fill_template(template: &str, number: u32) -> Result<String, RuntimeError> {
+    let invalid = || RuntimeError(format!("invalid filename template: {}", template));
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(length) => start + length,
+            None => return Err(invalid()),
+        };
+        let spec = &rest[start + 1..end];
+        let width = if spec.is_empty() {
+            0
+        } else if spec.starts_with(':') {
+            try!(spec[1..].parse::<usize>().map_err(|_| invalid()))
+        } else {
+            return Err(invalid());
+        };
+        result.push_str(&rest[..start]);
+        result.push_str(&format!("{:0width$}", number, width = width));
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Return the filename for a screenshot. A name with a placeholder like
+/// `frame_{:04}.png` gets the next number of the screenshot counter.
+fn screenshot_name(env: &mut Environment, name: &str) -> Result<String, RuntimeError> {
+    // Names without a placeholder don't count
+    if !name.contains('{') {
+        return Ok(name.to_owned());
+    }
+    let number = env.next_screenshot_number();
+    fill_template(name, number)
+}
+
+/// Takes an optional scale. With a scale, the drawing is rendered again at
+/// that many times the window size instead of saving the window's content.
+pub fn screenshot(env: &mut Environment, args: &[Node]) -> ResultType {
+    let args: Vec<Value> = try!(args.iter().map(|a| env.eval(a)).collect());
+    if args.len() == 1 {
+        get_args!(args, arg Value::String(ref name), => {
+            let name = try!(screenshot_name(env, name));
+            let shot = try!(env.get_turtle().screenshot());
+            save_png(shot, &name)
+        })
+    } else {
+        get_args!(args, arg Value::String(ref name), arg Value::Number(scale), => {
+            if scale <= 0. {
+                return Err(RuntimeError(format!("invalid scale: {}", scale)));
+            }
+            let name = try!(screenshot_name(env, name));
+            let shot = try!(env.get_turtle().render_image(scale));
+            save_png(shot, &name)
+        })
+    }
+}
+
+pub fn nativescreenshot(env: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::String(ref name), => {
-        let shot = env.get_turtle().get_screen().screenshot();
-        let mut file = match fs::File::create(name) {
-            Ok(f) => f,
+        let name = try!(screenshot_name(env, name));
+        let shot = try!(env.get_turtle().native_screenshot());
+        save_png(shot, &name)
+    })
+}
+
+pub fn recordvideo(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::String(ref name),
+              arg Value::Number(fps), =>
+    {
+        try!(env.start_video(name, fps));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn stopvideo(env: &mut Environment, _: &[Value]) -> ResultType {
+    try!(env.stop_video());
+    Ok(Value::Nothing)
+}
+
+pub fn setscreenshotcounter(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(number), => {
+        if number < 0. || number.fract() != 0. || number > ::std::u32::MAX as f32 {
+            return Err(RuntimeError(format!("invalid screenshot number: {}", number)));
+        }
+        env.set_screenshot_counter(number as u32);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn clipboardcopy(_: &mut Environment, args: &[Value]) -> ResultType {
+    // Strings are copied as they are, everything else like PRINT shows it
+    let text = args[0].to_string();
+    match clipboard::copy_text(&text) {
+        Ok(()) => Ok(Value::Nothing),
+        Err(e) => Err(RuntimeError(format!("{}", e))),
+    }
+}
+
+pub fn screenshottoclipboard(env: &mut Environment, _: &[Value]) -> ResultType {
+    let shot = try!(env.get_turtle().screenshot());
+    match clipboard::copy_image(&shot) {
+        Ok(()) => Ok(Value::Nothing),
+        Err(e) => Err(RuntimeError(format!("{}", e))),
+    }
+}
+
+pub fn saveps(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        match env.get_turtle().export_ps(name) {
+            Ok(()) => Ok(Value::Nothing),
+            Err(e) => Err(RuntimeError(format!("{}", e))),
+        }
+    })
+}
+
+pub fn setplotter(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Number(scale),
+              arg Value::String(ref pen_down),
+              arg Value::String(ref pen_up), =>
+    {
+        if scale <= 0. || !scale.is_finite() {
+            return Err(RuntimeError(format!("invalid plotter scale: {}", scale)));
+        }
+        env.plotter.scale = scale;
+        env.plotter.pen_down = pen_down.clone();
+        env.plotter.pen_up = pen_up.clone();
+        Ok(Value::Nothing)
+    })
+}
+
+/// Export the drawing for a plotter in the given format, see `SETPLOTTER`
+fn save_plotter(env: &mut Environment, args: &[Value], format: Format) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        let options = PlotterOptions { format: format, .. env.plotter.clone() };
+        match env.get_turtle().export_plotter(name, &options) {
+            Ok(()) => Ok(Value::Nothing),
+            Err(e) => Err(RuntimeError(format!("{}", e))),
+        }
+    })
+}
+
+pub fn savegcode(env: &mut Environment, args: &[Value]) -> ResultType {
+    save_plotter(env, args, Format::Gcode)
+}
+
+pub fn savehpgl(env: &mut Environment, args: &[Value]) -> ResultType {
+    save_plotter(env, args, Format::Hpgl)
+}
+
+pub fn savelog(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        match replay::save_log(name, &env.get_turtle().get_log()) {
+            Ok(()) => Ok(Value::Nothing),
+            Err(e) => Err(RuntimeError(format!("{}", e))),
+        }
+    })
+}
+
+pub fn replaylog(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::String(ref name),
+              arg Value::Number(delay), =>
+    {
+        let commands = match replay::load_log(name) {
+            Ok(c) => c,
             Err(e) => return Err(RuntimeError(format!("{}", e))),
         };
-        match shot.save(&mut file, ::image::ImageFormat::PNG) {
+        let delay = time::Duration::from_millis(if delay > 0. { delay as u64 } else { 0 });
+        try!(env.get_turtle().replay(&commands, delay));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn saveworkspace(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        match workspace::save(env, name) {
             Ok(()) => Ok(Value::Nothing),
             Err(e) => Err(RuntimeError(format!("{}", e))),
         }
     })
 }
 
+pub fn loadworkspace(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        match workspace::load(env, name) {
+            Ok(()) => Ok(Value::Nothing),
+            Err(e) => Err(RuntimeError(format!("{}: {}", name, e))),
+        }
+    })
+}
+
+pub fn repcount(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(Value::Integer(env.repcount().unwrap_or(-1) as i64))
+}
+
+pub fn stats(env: &mut Environment, _: &[Value]) -> ResultType {
+    let stats = env.stats();
+    let pair = |name: &str, value: f32| {
+        Value::List(vec![Value::String(name.to_owned()), Value::Number(value)])
+    };
+    // Times are given in milliseconds
+    Ok(Value::List(vec![
+        pair("statements", stats.statements as f32),
+        pair("segments", stats.segments as f32),
+        pair("frames", stats.frames as f32),
+        pair("lextime", millis(stats.lex_time)),
+        pair("parsetime", millis(stats.parse_time)),
+        pair("evaltime", millis(stats.eval_time)),
+        pair("rendertime", millis(stats.render_time)),
+    ]))
+}
+
+pub fn resetstats(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.reset_stats();
+    Ok(Value::Nothing)
+}
+
+pub fn args(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(Value::List(env.get_args().iter().map(|a| Value::String(a.clone())).collect()))
+}
+
+pub fn getenv(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        match stdenv::var(name) {
+            Ok(value) => Ok(Value::String(value)),
+            Err(_) => Ok(Value::Nothing),
+        }
+    })
+}
+
+pub fn cwd(_: &mut Environment, _: &[Value]) -> ResultType {
+    match stdenv::current_dir() {
+        Ok(path) => Ok(Value::String(path.to_string_lossy().into_owned())),
+        Err(e) => Err(RuntimeError(format!("{}", e))),
+    }
+}
+
+pub fn setcwd(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref path), => {
+        match stdenv::set_current_dir(path) {
+            Ok(()) => Ok(Value::Nothing),
+            Err(e) => Err(RuntimeError(format!("{}: {}", path, e))),
+        }
+    })
+}
+
+pub fn listdir(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref path), => {
+        let entries = match fs::read_dir(path) {
+            Ok(e) => e,
+            Err(e) => return Err(RuntimeError(format!("{}: {}", path, e))),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            match entry {
+                Ok(entry) => names.push(entry.file_name().to_string_lossy().into_owned()),
+                Err(e) => return Err(RuntimeError(format!("{}", e))),
+            }
+        }
+        names.sort();
+        Ok(Value::List(names.into_iter().map(Value::String).collect()))
+    })
+}
+
 pub fn prompt(_: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::String(ref prompt_string), => {
         // What?
@@ -49,3 +357,17 @@ pub fn throw(_: &mut Environment, args: &[Value]) -> ResultType {
         Err(RuntimeError(error_desc.clone()))
     })
 }
+
+pub fn gameloop(env: &mut Environment, args: &[Node]) -> ResultType {
+    match try!(env.eval(&args[0])) {
+        ref value if value.as_number().map_or(false, |fps| fps > 0.) => {
+            env.run_game_loop(value.as_number().unwrap(), &args[1])
+        },
+        value => Err(RuntimeError(format!("invalid frame rate: {}", value))),
+    }
+}
+
+pub fn stoploop(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.stop_game_loop();
+    Ok(Value::Nothing)
+}
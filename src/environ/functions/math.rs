@@ -0,0 +1,316 @@
+use super::{Environment, ResultType, RuntimeError, Value};
+
+pub fn bitand(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(a), arg Value::Integer(b), => {
+        Ok(Value::Integer(a & b))
+    })
+}
+
+pub fn bitor(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(a), arg Value::Integer(b), => {
+        Ok(Value::Integer(a | b))
+    })
+}
+
+pub fn bitxor(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(a), arg Value::Integer(b), => {
+        Ok(Value::Integer(a ^ b))
+    })
+}
+
+/// Shift `n` to the left by `count` bits, or to the right if `count` is
+/// negative. Right shifts fill in the sign bit if `arithmetic` is true and
+/// zeros otherwise. Bits shifted out are lost.
+fn shift(n: i64, count: i64, arithmetic: bool) -> i64 {
+    if count >= 64 {
+        0
+    } else if count >= 0 {
+        n << count
+    } else if count <= -64 {
+        if arithmetic && n < 0 { -1 } else { 0 }
+    } else if arithmetic {
+        n >> -count
+    } else {
+        ((n as u64) >> -count) as i64
+    }
+}
+
+pub fn lshift(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(n), arg Value::Integer(count), => {
+        Ok(Value::Integer(shift(n, count, false)))
+    })
+}
+
+pub fn ashift(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(n), arg Value::Integer(count), => {
+        Ok(Value::Integer(shift(n, count, true)))
+    })
+}
+
+pub fn quotient(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(a), arg Value::Integer(b), => {
+        if b == 0 {
+            return Err(RuntimeError("division by zero".to_owned()));
+        }
+        match a.checked_div(b) {
+            Some(n) => Ok(Value::Integer(n)),
+            None => Err(RuntimeError("integer overflow".to_owned())),
+        }
+    })
+}
+
+pub fn remainder(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(a), arg Value::Integer(b), => {
+        if b == 0 {
+            return Err(RuntimeError("division by zero".to_owned()));
+        }
+        // i64::MIN % -1 overflows, but the remainder is 0 anyway
+        Ok(Value::Integer(a.checked_rem(b).unwrap_or(0)))
+    })
+}
+
+/// Greatest common divisor of the absolute values, `None` if it doesn't fit
+/// into an `i64` (only possible for `gcd(i64::MIN, 0)`)
+fn gcd_of(a: i64, b: i64) -> Option<i64> {
+    let (mut a, mut b) = (a.wrapping_abs() as u64, b.wrapping_abs() as u64);
+    while b != 0 {
+        let rest = a % b;
+        a = b;
+        b = rest;
+    }
+    if a <= ::std::i64::MAX as u64 { Some(a as i64) } else { None }
+}
+
+/// Return the smallest factor of `n` greater than 1, `n` itself if it is prime.
+/// `n` has to be at least 2.
+fn smallest_factor(n: u64) -> u64 {
+    if n % 2 == 0 {
+        return 2;
+    }
+    let mut divisor = 3;
+    while divisor <= n / divisor {
+        if n % divisor == 0 {
+            return divisor;
+        }
+        divisor += 2;
+    }
+    n
+}
+
+pub fn gcd(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(a), arg Value::Integer(b), => {
+        match gcd_of(a, b) {
+            Some(n) => Ok(Value::Integer(n)),
+            None => Err(RuntimeError("integer overflow".to_owned())),
+        }
+    })
+}
+
+pub fn lcm(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(a), arg Value::Integer(b), => {
+        if a == 0 || b == 0 {
+            return Ok(Value::Integer(0));
+        }
+        let lcm = gcd_of(a, b).and_then(|d| (a / d).checked_mul(b)).and_then(i64::checked_abs);
+        match lcm {
+            Some(n) => Ok(Value::Integer(n)),
+            None => Err(RuntimeError("integer overflow".to_owned())),
+        }
+    })
+}
+
+pub fn primep(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(n), => {
+        let prime = n >= 2 && smallest_factor(n as u64) == n as u64;
+        Ok(Value::Number(if prime { 1. } else { 0. }))
+    })
+}
+
+pub fn factorize(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(n), => {
+        if n < 1 {
+            return Err(RuntimeError(format!("can't factorize {}", n)));
+        }
+        let mut n = n as u64;
+        let mut factors = Vec::new();
+        while n > 1 {
+            let factor = smallest_factor(n);
+            factors.push(Value::Integer(factor as i64));
+            n /= factor;
+        }
+        Ok(Value::List(factors))
+    })
+}
+
+// 2D vectors are lists of two numbers
+
+fn vector(value: &Value) -> Result<(f32, f32), RuntimeError> {
+    if let Value::List(ref items) = *value {
+        if let &[ref x, ref y] = &items[..] {
+            if let (Some(x), Some(y)) = (x.as_number(), y.as_number()) {
+                return Ok((x, y));
+            }
+        }
+    }
+    Err(RuntimeError(format!("expected a vector [x y], got {}", value)))
+}
+
+fn vector_value((x, y): (f32, f32)) -> Value {
+    Value::List(vec![Value::Number(x), Value::Number(y)])
+}
+
+pub fn vecadd(_: &mut Environment, args: &[Value]) -> ResultType {
+    let (a, b) = (try!(vector(&args[0])), try!(vector(&args[1])));
+    Ok(vector_value((a.0 + b.0, a.1 + b.1)))
+}
+
+pub fn vecsub(_: &mut Environment, args: &[Value]) -> ResultType {
+    let (a, b) = (try!(vector(&args[0])), try!(vector(&args[1])));
+    Ok(vector_value((a.0 - b.0, a.1 - b.1)))
+}
+
+pub fn vecscale(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::List(_), arg Value::Number(factor), => {
+        let (x, y) = try!(vector(&args[0]));
+        Ok(vector_value((x * factor, y * factor)))
+    })
+}
+
+pub fn dot(_: &mut Environment, args: &[Value]) -> ResultType {
+    let (a, b) = (try!(vector(&args[0])), try!(vector(&args[1])));
+    Ok(Value::Number(a.0 * b.0 + a.1 * b.1))
+}
+
+pub fn cross2d(_: &mut Environment, args: &[Value]) -> ResultType {
+    let (a, b) = (try!(vector(&args[0])), try!(vector(&args[1])));
+    Ok(Value::Number(a.0 * b.1 - a.1 * b.0))
+}
+
+pub fn norm(_: &mut Environment, args: &[Value]) -> ResultType {
+    let (x, y) = try!(vector(&args[0]));
+    Ok(Value::Number(x.hypot(y)))
+}
+
+pub fn rotatevec(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::List(_), arg Value::Number(angle), => {
+        let (x, y) = try!(vector(&args[0]));
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Ok(vector_value((x * cos - y * sin, x * sin + y * cos)))
+    })
+}
+
+// Complex numbers are vectors [re im] as well
+
+pub fn cmul(_: &mut Environment, args: &[Value]) -> ResultType {
+    let ((a, b), (c, d)) = (try!(vector(&args[0])), try!(vector(&args[1])));
+    Ok(vector_value((a * c - b * d, a * d + b * c)))
+}
+
+pub fn complexiter(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Number(cx),
+              arg Value::Number(cy),
+              arg Value::Integer(max), =>
+    {
+        // Compute with double precision so zooming in works a bit longer
+        let (cx, cy) = (cx as f64, cy as f64);
+        let (mut x, mut y) = (0f64, 0f64);
+        let mut count = 0;
+        while count < max && x * x + y * y <= 4. {
+            let temp = x * x - y * y + cx;
+            y = 2. * x * y + cy;
+            x = temp;
+            count += 1;
+        }
+        Ok(Value::Integer(count))
+    })
+}
+
+// Matrices are lists of rows, each row being a list of numbers
+
+fn numbers(value: &Value) -> Option<Vec<f32>> {
+    match *value {
+        Value::List(ref items) => items.iter().map(Value::as_number).collect(),
+        _ => None,
+    }
+}
+
+fn numbers_value(numbers: Vec<f32>) -> Value {
+    Value::List(numbers.into_iter().map(Value::Number).collect())
+}
+
+fn matrix(value: &Value) -> Result<Vec<Vec<f32>>, RuntimeError> {
+    let rows = match *value {
+        Value::List(ref rows) => rows.iter().map(numbers).collect::<Option<Vec<_>>>(),
+        _ => None,
+    };
+    if let Some(rows) = rows {
+        let width = rows.first().map_or(0, Vec::len);
+        if width > 0 && rows.iter().all(|r| r.len() == width) {
+            return Ok(rows);
+        }
+    }
+    Err(RuntimeError(format!("expected a matrix, got {}", value)))
+}
+
+fn matrix_value(rows: Vec<Vec<f32>>) -> Value {
+    Value::List(rows.into_iter().map(numbers_value).collect())
+}
+
+/// Multiply a row with a column
+fn dot_product<'a, I: Iterator<Item = &'a f32>>(row: &[f32], column: I) -> f32 {
+    row.iter().zip(column).map(|(a, b)| a * b).sum()
+}
+
+pub fn matmul(_: &mut Environment, args: &[Value]) -> ResultType {
+    let (a, b) = (try!(matrix(&args[0])), try!(matrix(&args[1])));
+    if a[0].len() != b.len() {
+        return Err(RuntimeError(format!("can't multiply a {}x{} by a {}x{} matrix",
+                                        a.len(), a[0].len(), b.len(), b[0].len())));
+    }
+    let product = a.iter().map(|row| {
+        (0..b[0].len()).map(|j| dot_product(row, b.iter().map(|r| &r[j]))).collect()
+    }).collect();
+    Ok(matrix_value(product))
+}
+
+pub fn transpose(_: &mut Environment, args: &[Value]) -> ResultType {
+    let rows = try!(matrix(&args[0]));
+    let columns = (0..rows[0].len()).map(|j| rows.iter().map(|row| row[j]).collect()).collect();
+    Ok(matrix_value(columns))
+}
+
+pub fn identity(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(size), => {
+        if size < 1 {
+            return Err(RuntimeError(format!("invalid matrix size: {}", size)));
+        }
+        let size = size as usize;
+        Ok(matrix_value((0..size).map(|i| {
+            (0..size).map(|j| if i == j { 1. } else { 0. }).collect()
+        }).collect()))
+    })
+}
+
+pub fn matapply(_: &mut Environment, args: &[Value]) -> ResultType {
+    let rows = try!(matrix(&args[0]));
+    let mut vector = match numbers(&args[1]) {
+        Some(v) => v,
+        None => return Err(RuntimeError(format!("expected a vector, got {}", args[1]))),
+    };
+    let size = vector.len();
+    // A vector with one component less is extended to homogeneous coordinates,
+    // so affine transformations can be written as one matrix
+    let homogeneous = size + 1 == rows[0].len();
+    if homogeneous {
+        vector.push(1.);
+    } else if size != rows[0].len() {
+        return Err(RuntimeError(format!("can't apply a {}x{} matrix to a vector of length {}",
+                                        rows.len(), rows[0].len(), size)));
+    }
+    let mut result: Vec<f32> = rows.iter().map(|row| dot_product(row, vector.iter())).collect();
+    if homogeneous {
+        result.truncate(size);
+    }
+    Ok(numbers_value(result))
+}
@@ -7,9 +7,10 @@
 // at some point and clippy doesn't like that
 #![cfg_attr(feature = "linted", allow(identity_op))]
 
-pub use super::{Environment, Function, ResultType, RuntimeError};
+pub use super::{Environment, FuncType, Function, ResultType, RuntimeError};
 pub use super::value::Value;
-use super::Function::Native;
+use super::Function::{Native, Special};
+use super::super::parse::Arity;
 use std::collections::HashMap;
 
 // Example function.
@@ -28,7 +29,9 @@ fn print(_: &mut Environment, args: &[Value]) -> ResultType {
 /// the => expr part which specifies what should happen with the arguments.
 ///
 /// If a argument can't be matched with the given pattern, a
-/// `Err(RuntimeError(..))` is returned.
+/// `Err(RuntimeError(..))` is returned. Integers (of any size) are promoted to
+/// floats if they don't match the pattern themselves, so `Value::Number(x)` accepts
+/// both kinds of numbers while `Value::Integer(n)` only accepts integers.
 ///
 /// # Example
 ///
@@ -52,6 +55,16 @@ macro_rules! get_args {
     (index $ind:expr, $args:expr, arg $p:pat, $(arg $ps:pat,)* => $b:expr) => {
         {
             let arg = &$args[$ind];
+            #[allow(unused_variables)]
+            let matches = match *arg { $p => true, _ => false };
+            let promoted;
+            let arg = match arg.as_number() {
+                Some(x) if !matches => {
+                    promoted = Value::Number(x);
+                    &promoted
+                },
+                _ => arg,
+            };
             if let $p = *arg {
                 get_args!(index $ind+1, $args, $(arg $ps,)* => $b)
             } else {
@@ -66,22 +79,68 @@ macro_rules! get_args {
 mod turtle;
 mod env;
 mod types;
+mod math;
 mod string;
+mod sound;
+mod procedure;
+mod data;
+mod testing;
+mod plot;
 
-/// A helpful macro to construct a `HashMap`
+/// A helpful macro to construct a `HashMap`. Each key may only appear once,
+/// a later entry would silently replace the earlier one.
 macro_rules! map {
     ($($k:expr => $v:expr,) *) => {
         {
             let mut result = HashMap::new();
-            $(result.insert($k.to_owned(), $v);)*
+            $(
+                let previous = result.insert($k.to_owned(), $v);
+                assert!(previous.is_none(), "{} is defined twice", $k);
+            )*
             result
         }
     }
 }
 
+/// The built-in functions that access files, the network, the clipboard or
+/// the terminal. These functions are disabled in sandbox mode, see
+/// `Environment::set_sandbox`. Add new functions of that kind here as well
+/// when registering them in `default_functions`.
+pub const RESTRICTED: &'static [&'static str] = &[
+    "SCREENSHOT",
+    "NATIVESCREENSHOT",
+    "RECORDVIDEO",
+    "SCREENSHOTTOCLIPBOARD",
+    "CLIPBOARDCOPY",
+    "SAVEPS",
+    "SAVEGCODE",
+    "SAVEHPGL",
+    "SAVELOG",
+    "REPLAYLOG",
+    "SAVEWORKSPACE",
+    "LOADWORKSPACE",
+    "PROMPT",
+    "GETENV",
+    "CWD",
+    "SETCWD",
+    "LISTDIR",
+    "STAMPIMAGE",
+    "ADDFALLBACKFONT",
+    "EXPORT3D",
+    "READCSV",
+    "FETCH",
+    "LOADIMAGE",
+];
+
+/// Return true if the built-in function with the given (uppercase) name is
+/// disabled in sandbox mode, see `RESTRICTED`
+pub fn is_restricted(name: &str) -> bool {
+    RESTRICTED.contains(&name)
+}
+
 /// Return a HashMap of the built-in functions
 pub fn default_functions() -> HashMap<String, Function> {
-    map!{
+    let functions = map!{
         "PRINT" => Native(1, print),
 
         // Turtle control/draw functions
@@ -91,23 +150,128 @@ pub fn default_functions() -> HashMap<String, Function> {
         "RIGHT" => Native(1, turtle::right),
         "COLOR" => Native(3, turtle::color),
         "BGCOLOR" => Native(3, turtle::bgcolor),
-        "CLEAR" => Native(0, turtle::clear),
+        "PENCOLOR" => Native(0, turtle::pencolor),
+        "BACKGROUND" => Native(0, turtle::background),
+        "PENDOWNP" => Native(0, turtle::pendownp),
+        "PENSIZE" => Native(0, turtle::pensize),
+        "SETPENSIZE" => Native(1, turtle::setpensize),
+        "PEN" => Native(0, turtle::pen),
+        "SETPEN" => Native(1, turtle::setpen),
+        "TURTLESTATE" => Native(0, turtle::turtlestate),
+        "SETTURTLESTATE" => Native(1, turtle::setturtlestate),
+        "WITHPENUP" => Special(Arity::fixed(1), turtle::withpenup),
+        "WITHCOLOR" => Special(Arity::fixed(2), turtle::withcolor),
+        "WITHSTATE" => Special(Arity::fixed(1), turtle::withstate),
+        "SETPALETTE" => Native(1, turtle::setpalette),
+        "PALETTECOLOR" => Native(1, turtle::palettecolor),
+        "COLORAT" => Native(1, turtle::colorat),
+        "CLEAN" => Special(Arity { default: 0, maximum: Some(1) }, turtle::clean),
+        "CLEAR" => Special(Arity { default: 0, maximum: Some(1) }, turtle::clean),
+        "CLEARSCREEN" => Native(0, turtle::clearscreen),
         "PENDOWN" => Native(0, turtle::pendown),
         "PENUP" => Native(0, turtle::penup),
         "HOME" => Native(0, turtle::home),
+        "SETHOME" => Native(3, turtle::sethome),
         "REALIGN" => Native(1, turtle::realign),
+        "HEADING" => Native(0, turtle::heading),
+        "SETHEADINGTOWARDS" => Native(2, turtle::setheadingtowards),
+        "POLARGOTO" => Native(2, turtle::polargoto),
         "HIDE" => Native(0, turtle::hide),
         "SHOW" => Native(0, turtle::show),
-        "WRITE" => Native(1, turtle::write),
+        "WRITE" => Special(Arity { default: 1, maximum: Some(2) }, turtle::write),
+        "HORIZONTALTEXT" => Native(1, turtle::horizontaltext),
+        "WRITEALONG" => Native(1, turtle::writealong),
+        "WRITEARC" => Native(2, turtle::writearc),
+        "ADDFALLBACKFONT" => Native(1, turtle::addfallbackfont),
         "FLOOD" => Native(0, turtle::flood),
+        "STAMPIMAGE" => Native(2, turtle::stampimage),
+        "IMAGEFILTER" => Native(1, turtle::imagefilter),
+        "BLUR" => Native(1, turtle::blur),
+        "SMOOTHLINES" => Native(1, turtle::smoothlines),
+        "FULLSCREEN" => Native(1, turtle::fullscreen),
+        "GRID" => Native(1, turtle::grid),
+        "AXES" => Native(1, turtle::axes),
+        "MINIMAP" => Native(1, turtle::minimap),
+        "DRAWINGBOUNDS" => Native(0, turtle::drawingbounds),
+        "FITVIEW" => Native(1, turtle::fitview),
+        "NEWSCREEN" => Native(3, turtle::newscreen),
+        "SETSCREEN" => Native(1, turtle::setscreen),
+        "SCREEN" => Native(0, turtle::screen),
+        "SCREENS" => Native(0, turtle::screens),
+        "MIRROR" => Native(1, turtle::mirror),
+        "KALEIDOSCOPE" => Native(1, turtle::kaleidoscope),
+        "SETTRAIL" => Native(1, turtle::settrail),
+        "SNAP" => Native(1, turtle::snap),
+        "SNAPGRID" => Native(1, turtle::snapgrid),
+        "ISOMETRIC" => Native(1, turtle::isometric),
+        "PLAYBACK" => Native(1, turtle::playback),
+        "BEGINFOREGROUND" => Native(0, turtle::beginforeground),
+        "CLEARFOREGROUND" => Native(0, turtle::clearforeground),
+        "ANNOTATE" => Native(1, turtle::annotate),
+        "CLEARANNOTATIONS" => Native(0, turtle::clearannotations),
+        "CLEARMINE" => Native(0, turtle::clearmine),
+        "UNDO" => Native(0, turtle::undo),
+        "CHECKPOINT" => Native(1, turtle::checkpoint),
+        "ROLLBACK" => Special(Arity { default: 1, maximum: Some(2) }, turtle::rollback),
+        // Transforms
+        "PUSHTRANSFORM" => Native(0, turtle::pushtransform),
+        "POPTRANSFORM" => Native(0, turtle::poptransform),
+        "SCALE" => Native(2, turtle::scale),
+        "ROTATE" => Native(1, turtle::rotate),
+        "TRANSLATE" => Native(2, turtle::translate),
+        // Physics simulation
+        "SETVELOCITY" => Native(2, turtle::setvelocity),
+        "VELOCITY" => Native(0, turtle::velocity),
+        "GRAVITY" => Native(1, turtle::gravity),
+        "BOUNCE" => Native(1, turtle::bounce),
+        "UPDATE" => Native(1, turtle::update),
+        // 3D mode
+        "MODE3D" => Native(1, turtle::mode3d),
+        "PITCHUP" => Native(1, turtle::pitchup),
+        "PITCHDOWN" => Native(1, turtle::pitchdown),
+        "ROLLLEFT" => Native(1, turtle::rollleft),
+        "ROLLRIGHT" => Native(1, turtle::rollright),
+        "ORBIT" => Native(2, turtle::orbit),
+        "EXPORT3D" => Native(1, turtle::export3d),
+        "MOUSEX" => Native(0, turtle::mousex),
+        "MOUSEY" => Native(0, turtle::mousey),
+        "MOUSEDOWN?" => Native(0, turtle::mousedown),
+        "KEYDOWN?" => Native(1, turtle::keydown),
 
         // Environment functions to set variables
         "MAKE" => Native(2, env::make),
         "GLOBAL" => Native(2, env::global),
+        "LOCAL" => Native(1, env::local),
+        "LOCALMAKE" => Native(2, env::localmake),
+        "CONSTANT" => Native(2, env::constant),
         // Other environment functions
-        "SCREENSHOT" => Native(1, env::screenshot),
+        "SCREENSHOT" => Special(Arity { default: 1, maximum: Some(2) }, env::screenshot),
+        "NATIVESCREENSHOT" => Native(1, env::nativescreenshot),
+        "SETSCREENSHOTCOUNTER" => Native(1, env::setscreenshotcounter),
+        "RECORDVIDEO" => Native(2, env::recordvideo),
+        "STOPVIDEO" => Native(0, env::stopvideo),
+        "SCREENSHOTTOCLIPBOARD" => Native(0, env::screenshottoclipboard),
+        "CLIPBOARDCOPY" => Native(1, env::clipboardcopy),
+        "SAVEPS" => Native(1, env::saveps),
+        "SAVEGCODE" => Native(1, env::savegcode),
+        "SAVEHPGL" => Native(1, env::savehpgl),
+        "SETPLOTTER" => Native(3, env::setplotter),
+        "SAVELOG" => Native(1, env::savelog),
+        "REPLAYLOG" => Native(2, env::replaylog),
+        "SAVEWORKSPACE" => Native(1, env::saveworkspace),
+        "LOADWORKSPACE" => Native(1, env::loadworkspace),
         "PROMPT" => Native(1, env::prompt),
+        "ARGS" => Native(0, env::args),
+        "GETENV" => Native(1, env::getenv),
+        "CWD" => Native(0, env::cwd),
+        "SETCWD" => Native(1, env::setcwd),
+        "LISTDIR" => Native(1, env::listdir),
+        "REPCOUNT" => Native(0, env::repcount),
+        "STATS" => Native(0, env::stats),
+        "RESETSTATS" => Native(0, env::resetstats),
         "THROW" => Native(1, env::throw),
+        "GAMELOOP" => Special(Arity { default: 2, maximum: Some(2) }, env::gameloop),
+        "STOPLOOP" => Native(0, env::stoploop),
 
         // Haskellesque names
         "HEAD" => Native(1, types::head),
@@ -119,17 +283,110 @@ pub fn default_functions() -> HashMap<String, Function> {
         "LENGTH" => Native(1, types::length), // also works for strings
         "ISEMPTY" => Native(1, types::isempty),
         "GETINDEX" => Native(2, types::getindex),
-        "FIND" => Native(2, types::find),
+        "FIND" => Native(2, types::find), // also works for strings
+        "MEMBERP" => Native(2, types::memberp),
+        "COUNT" => Native(2, types::count),
         // conversion
         "NOT" => Native(1, types::not),
+        "EQUALP" => Native(2, types::equalp),
+        "BEFOREP" => Native(2, types::beforep),
+        "AND" => Special(Arity { default: 2, maximum: None }, types::and),
+        "OR" => Special(Arity { default: 2, maximum: None }, types::or),
+        "NUMBERP" => Native(1, types::numberp),
+        "STRINGP" => Native(1, types::stringp),
+        "LISTP" => Native(1, types::listp),
+        "NOTHINGP" => Native(1, types::nothingp),
+        "PROCEDUREP" => Native(1, types::procedurep),
+        "INTEGERP" => Native(1, types::integerp),
+        "ISNAN" => Native(1, types::isnan),
+        "TYPEOF" => Native(1, types::typeof_),
         "TONUMBER" => Native(1, types::tonumber),
         "TOSTRING" => Native(1, types::tostring),
         "NOTHING" => Native(0, types::nothing),
 
+        // Integer functions
+        "BITAND" => Native(2, math::bitand),
+        "BITOR" => Native(2, math::bitor),
+        "BITXOR" => Native(2, math::bitxor),
+        "LSHIFT" => Native(2, math::lshift),
+        "ASHIFT" => Native(2, math::ashift),
+        "QUOTIENT" => Native(2, math::quotient),
+        "REMAINDER" => Native(2, math::remainder),
+        "GCD" => Native(2, math::gcd),
+        "LCM" => Native(2, math::lcm),
+        "PRIMEP" => Native(1, math::primep),
+        "FACTORIZE" => Native(1, math::factorize),
+        // Vector functions
+        "VECADD" => Native(2, math::vecadd),
+        "VECSUB" => Native(2, math::vecsub),
+        "VECSCALE" => Native(2, math::vecscale),
+        "DOT" => Native(2, math::dot),
+        "CROSS2D" => Native(2, math::cross2d),
+        "NORM" => Native(1, math::norm),
+        "ROTATEVEC" => Native(2, math::rotatevec),
+        // Complex numbers
+        "CADD" => Native(2, math::vecadd),
+        "CMUL" => Native(2, math::cmul),
+        "CABS" => Native(1, math::norm),
+        "COMPLEXITER" => Native(3, math::complexiter),
+        // Matrix functions
+        "MATMUL" => Native(2, math::matmul),
+        "TRANSPOSE" => Native(1, math::transpose),
+        "IDENTITY" => Native(1, math::identity),
+        "MATAPPLY" => Native(2, math::matapply),
+
+        // Functions for first-class procedures
+        "FUNC" => Native(1, procedure::func),
+        "DEFINE" => Native(2, procedure::define),
+        "TEXT" => Native(1, procedure::text),
+        "CALL" => Native(2, procedure::call),
+        "MAP" => Native(2, procedure::map),
+        "FILTER" => Native(2, procedure::filter),
+
         // String manipulating functions
         "REPLACE" => Native(3, string::replace),
         "CONTAINS" => Native(2, string::contains),
         "CHARS" => Native(1, string::chars),
+        "TOCHARLIST" => Native(1, string::chars),
+        "CHAR" => Native(1, string::char),
+        "ASCII" => Native(1, string::ascii),
         "SPLIT" => Native(2, string::split),
+        "FORM" => Native(3, string::form),
+        "FORMAT" => Native(2, string::format),
+
+        // Data exchange functions
+        "PARSEJSON" => Native(1, data::parsejson),
+        "TOJSON" => Native(1, data::tojson),
+        "TOJSONOBJECT" => Native(1, data::tojsonobject),
+        "READCSV" => Native(2, data::readcsv),
+        "FETCH" => Native(1, data::fetch),
+        "LOADIMAGE" => Native(1, data::loadimage),
+        "IMAGEPIXEL" => Native(3, data::imagepixel),
+        "IMAGESIZE" => Native(1, data::imagesize),
+
+        // Plotting functions
+        "PLOTXY" => Native(2, plot::plotxy),
+        "PLOTFN" => Native(3, plot::plotfn),
+        "PLOTPOINTS" => Native(1, plot::plotpoints),
+        "BEGINPATH" => Native(0, plot::beginpath),
+        "PATH" => Native(0, plot::path),
+        "SIMPLIFY" => Native(1, plot::simplify),
+        "IFS" => Native(2, plot::ifs),
+
+        // Testing functions
+        "ASSERT" => Native(1, testing::assert),
+        "ASSERTEQUAL" => Native(2, testing::assertequal),
+        "TESTCASE" => Special(Arity { default: 2, maximum: Some(2) }, testing::testcase),
+
+        // Sound functions
+        "TOOT" => Native(2, sound::toot),
+        "PLAYNOTES" => Native(1, sound::playnotes),
+        "NOTEON" => Special(Arity { default: 2, maximum: Some(3) }, sound::noteon),
+        "NOTEOFF" => Special(Arity { default: 1, maximum: Some(2) }, sound::noteoff),
+        "PROGRAM" => Special(Arity { default: 1, maximum: Some(2) }, sound::program),
+    };
+    for name in RESTRICTED {
+        assert!(functions.contains_key(*name), "restricted function {} is not defined", name);
     }
+    functions
 }
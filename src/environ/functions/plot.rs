@@ -0,0 +1,131 @@
+use super::{Environment, ResultType, RuntimeError, Value};
+use super::super::super::path;
+use super::super::super::plot;
+use super::super::super::ifs::{self, AffineMap};
+
+/// Number of points at which `plotfn` evaluates the function
+const SAMPLES: usize = 200;
+
+/// Convert a list of numbers
+fn numbers(values: &[Value]) -> Result<Vec<f32>, RuntimeError> {
+    values.iter().map(|value| match value.as_number() {
+        Some(x) => Ok(x),
+        None => Err(RuntimeError(format!("not a number: {}", value))),
+    }).collect()
+}
+
+pub fn plotxy(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::List(ref xs),
+              arg Value::List(ref ys), =>
+    {
+        if xs.len() != ys.len() {
+            return Err(RuntimeError(format!("lists have different lengths: {} and {}",
+                                            xs.len(), ys.len())));
+        }
+        let points = try!(numbers(xs)).into_iter().zip(try!(numbers(ys))).collect();
+        try!(plot::plot(env.get_turtle(), &[points]));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn plotfn(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Procedure(ref procedure),
+              arg Value::Number(min),
+              arg Value::Number(max), =>
+    {
+        if !(min < max) {
+            return Err(RuntimeError(format!("invalid range: {} to {}", min, max)));
+        }
+        let mut points = Vec::with_capacity(SAMPLES + 1);
+        for i in 0..SAMPLES + 1 {
+            let x = min + (max - min) * i as f32 / SAMPLES as f32;
+            let value = try!(env.call_procedure(procedure, vec![Value::Number(x)]));
+            match value.as_number() {
+                Some(y) => points.push((x, y)),
+                None => return Err(RuntimeError(format!("not a number: {}", value))),
+            }
+        }
+        try!(plot::plot(env.get_turtle(), &[points]));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn plotpoints(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::List(ref list), => {
+        let mut points = Vec::with_capacity(list.len());
+        for item in list {
+            match *item {
+                Value::List(ref pair) if pair.len() == 2 => {
+                    let coordinates = try!(numbers(pair));
+                    points.push((coordinates[0], coordinates[1]));
+                },
+                _ => return Err(RuntimeError(format!("not a point [x y]: {}", item))),
+            }
+        }
+        try!(env.get_turtle().plot_points(&points));
+        Ok(Value::Nothing)
+    })
+}
+
+/// Convert points to a list of `[x y]` lists
+fn point_list(points: &[(f32, f32)]) -> Value {
+    Value::List(points.iter()
+                .map(|&(x, y)| Value::List(vec![Value::Number(x), Value::Number(y)]))
+                .collect())
+}
+
+pub fn beginpath(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.get_turtle().begin_path();
+    Ok(Value::Nothing)
+}
+
+pub fn path(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(point_list(env.get_turtle().path()))
+}
+
+pub fn simplify(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(tolerance), => {
+        if tolerance < 0. || !tolerance.is_finite() {
+            return Err(RuntimeError(format!("invalid tolerance: {}", tolerance)));
+        }
+        Ok(point_list(&path::simplify(env.get_turtle().path(), tolerance)))
+    })
+}
+
+/// Convert a list of `[a b c d e f weight]` lists to affine maps
+fn affine_maps(values: &[Value]) -> Result<Vec<AffineMap>, RuntimeError> {
+    let mut maps = Vec::new();
+    for value in values {
+        let numbers = match *value {
+            Value::List(ref items) => try!(numbers(items)),
+            _ => Vec::new(),
+        };
+        match &numbers[..] {
+            &[a, b, c, d, e, f, weight] if weight >= 0. && weight.is_finite() => {
+                maps.push(AffineMap { a: a, b: b, c: c, d: d, e: e, f: f, weight: weight });
+            },
+            _ => return Err(RuntimeError(format!("invalid transform: {}", value))),
+        }
+    }
+    if !maps.iter().any(|map| map.weight > 0.) {
+        return Err(RuntimeError("the weights of the transforms add up to 0".to_owned()));
+    }
+    Ok(maps)
+}
+
+pub fn ifs(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::List(ref transforms),
+              arg Value::Integer(count), =>
+    {
+        if count < 0 || count as u64 > ifs::MAX_POINTS as u64 {
+            return Err(RuntimeError(format!("invalid point count: {}", count)));
+        }
+        let maps = try!(affine_maps(transforms));
+        try!(env.check_segments(count as u64));
+        try!(ifs::draw(env.get_turtle(), &maps, count as usize));
+        Ok(Value::Nothing)
+    })
+}
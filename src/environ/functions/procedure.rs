@@ -0,0 +1,76 @@
+use super::{Environment, ResultType, RuntimeError, Value};
+
+pub fn func(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        env.make_procedure(name)
+    })
+}
+
+pub fn define(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), arg Value::List(ref definition), => {
+        let (params, body) = match definition.len() {
+            2 => (&definition[0], &definition[1]),
+            _ => return Err(RuntimeError("definition must be a list of parameters and body"
+                                         .to_owned())),
+        };
+        let params = match *params {
+            Value::List(ref params) => params.iter().map(|p| p.to_string()).collect::<Vec<_>>(),
+            _ => return Err(RuntimeError(format!("invalid parameter list: {}", params))),
+        };
+        // The body is either source code or a list of lines
+        let body = match *body {
+            Value::String(ref body) => body.clone(),
+            Value::List(ref lines) => {
+                lines.iter().map(|l| l.to_string()).collect::<Vec<_>>().join("\n")
+            },
+            _ => return Err(RuntimeError(format!("invalid body: {}", body))),
+        };
+        env.define_procedure(name, &params, &body)
+    })
+}
+
+pub fn text(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        let (params, body) = try!(env.procedure_text(name));
+        let params = params.into_iter().map(Value::String).collect();
+        let lines = body.lines().map(|l| Value::String(l.to_owned())).collect();
+        Ok(Value::List(vec![Value::List(params), Value::List(lines)]))
+    })
+}
+
+pub fn call(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Procedure(ref procedure),
+              arg Value::List(ref arguments), =>
+    {
+        env.call_procedure(procedure, arguments.clone())
+    })
+}
+
+pub fn map(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Procedure(ref procedure),
+              arg Value::List(ref values), =>
+    {
+        let mut result = Vec::new();
+        for value in values {
+            result.push(try!(env.call_procedure(procedure, vec![value.clone()])));
+        }
+        Ok(Value::List(result))
+    })
+}
+
+pub fn filter(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Procedure(ref procedure),
+              arg Value::List(ref values), =>
+    {
+        let mut result = Vec::new();
+        for value in values {
+            if try!(env.call_procedure(procedure, vec![value.clone()])).boolean() {
+                result.push(value.clone());
+            }
+        }
+        Ok(Value::List(result))
+    })
+}
@@ -0,0 +1,75 @@
+use super::{Environment, ResultType, RuntimeError, Value};
+use super::super::super::parse::ast::Node;
+
+pub fn toot(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Number(frequency),
+              arg Value::Number(duration), => {
+                  let speaker = try!(env.get_speaker());
+                  speaker.toot(frequency as u32, duration as u64);
+                  Ok(Value::Nothing)
+              })
+}
+
+pub fn playnotes(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::List(ref notes), => {
+        let mut parsed = Vec::new();
+        for note in notes {
+            match *note {
+                Value::List(ref pair) if pair.len() == 2 => {
+                    match (pair[0].as_number(), pair[1].as_number()) {
+                        (Some(f), Some(d)) => parsed.push((f as u32, d as u64)),
+                        _ => return Err(RuntimeError(format!("invalid note: {}", note))),
+                    }
+                },
+                _ => return Err(RuntimeError(format!("invalid note: {}", note))),
+            }
+        }
+        let speaker = try!(env.get_speaker());
+        speaker.play_notes(&parsed);
+        Ok(Value::Nothing)
+    })
+}
+
+/// Return the value as MIDI data byte between `min` and `max`, e.g. a note
+/// number. The first value in the range is sent as 0, so channels and
+/// programs can be counted from 1 as on most instruments.
+fn midi_byte(value: &Value, what: &str, min: i64, max: i64) -> Result<u8, RuntimeError> {
+    match value.as_number() {
+        Some(x) if x.floor() >= min as f32 && x.floor() <= max as f32 => {
+            Ok((x.floor() as i64 - min) as u8)
+        },
+        _ => Err(RuntimeError(format!("invalid {}: {}, expected {} to {}", what, value, min,
+                                      max))),
+    }
+}
+
+/// Return the channel given as optional argument, channel 1 by default
+fn midi_channel(args: &[Value], index: usize) -> Result<u8, RuntimeError> {
+    args.get(index).map_or(Ok(0), |channel| midi_byte(channel, "channel", 1, 16))
+}
+
+pub fn noteon(env: &mut Environment, args: &[Node]) -> ResultType {
+    let args: Vec<Value> = try!(args.iter().map(|a| env.eval(a)).collect());
+    let note = try!(midi_byte(&args[0], "note", 0, 127));
+    let velocity = try!(midi_byte(&args[1], "velocity", 0, 127));
+    let channel = try!(midi_channel(&args, 2));
+    try!(try!(env.get_midi()).note_on(channel, note, velocity).map_err(RuntimeError));
+    Ok(Value::Nothing)
+}
+
+pub fn noteoff(env: &mut Environment, args: &[Node]) -> ResultType {
+    let args: Vec<Value> = try!(args.iter().map(|a| env.eval(a)).collect());
+    let note = try!(midi_byte(&args[0], "note", 0, 127));
+    let channel = try!(midi_channel(&args, 1));
+    try!(try!(env.get_midi()).note_off(channel, note).map_err(RuntimeError));
+    Ok(Value::Nothing)
+}
+
+pub fn program(env: &mut Environment, args: &[Node]) -> ResultType {
+    let args: Vec<Value> = try!(args.iter().map(|a| env.eval(a)).collect());
+    let program = try!(midi_byte(&args[0], "program", 1, 128));
+    let channel = try!(midi_channel(&args, 1));
+    try!(try!(env.get_midi()).program_change(channel, program).map_err(RuntimeError));
+    Ok(Value::Nothing)
+}
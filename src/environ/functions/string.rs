@@ -25,6 +25,24 @@ pub fn chars(_: &mut Environment, args: &[Value]) -> ResultType {
     })
 }
 
+pub fn char(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Integer(code), => {
+        match ::std::char::from_u32(code as u32) {
+            Some(c) if code >= 0 && code as u32 as i64 == code => Ok(Value::String(c.to_string())),
+            _ => Err(RuntimeError(format!("invalid character code: {}", code))),
+        }
+    })
+}
+
+pub fn ascii(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref string), => {
+        match string.chars().next() {
+            Some(c) => Ok(Value::Integer(c as u32 as i64)),
+            None => Err(RuntimeError("empty string has no character code".to_owned())),
+        }
+    })
+}
+
 pub fn split(_: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args,
               arg Value::String(ref string),
@@ -33,3 +51,91 @@ pub fn split(_: &mut Environment, args: &[Value]) -> ResultType {
         Ok(Value::List(string.split(pattern).map(|s| Value::String(s.to_owned())).collect()))
     })
 }
+
+pub fn form(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Number(number),
+              arg Value::Integer(width),
+              arg Value::Integer(precision), =>
+    {
+        if width < 0 || precision < 0 {
+            return Err(RuntimeError(format!("invalid width/precision: {} {}", width, precision)));
+        }
+        Ok(Value::String(format!("{:>width$.prec$}", number,
+                                 width = width as usize, prec = precision as usize)))
+    })
+}
+
+pub fn format(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::String(ref template),
+              arg Value::List(ref values), =>
+    {
+        format_template(template, values).map(Value::String)
+    })
+}
+
+/// Fill the placeholders in `template` with the given values. Supported are
+/// `%s` (any value), `%d` (number without fraction), `%f` (number) and `%%`.
+/// Between `%` and the conversion character, a width and a precision may be
+/// given like in C's `printf`, e.g. `%8.2f`.
+fn format_template(template: &str, values: &[Value]) -> Result<String, RuntimeError> {
+    let mut result = String::new();
+    let mut values = values.iter();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        let mut width = String::new();
+        while let Some(&d) = chars.peek() {
+            if !d.is_digit(10) { break; }
+            width.push(d);
+            chars.next();
+        }
+        let mut precision = None;
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            let mut digits = String::new();
+            while let Some(&d) = chars.peek() {
+                if !d.is_digit(10) { break; }
+                digits.push(d);
+                chars.next();
+            }
+            precision = Some(digits.parse::<usize>().unwrap_or(0));
+        }
+        let width = width.parse::<usize>().unwrap_or(0);
+        let conversion = match chars.next() {
+            Some('%') => { result.push('%'); continue; },
+            Some(c) => c,
+            None => return Err(RuntimeError("incomplete format specifier".to_owned())),
+        };
+        let value = match values.next() {
+            Some(v) => v,
+            None => return Err(RuntimeError("not enough values for format".to_owned())),
+        };
+        let formatted = match (conversion, value) {
+            ('s', value) => {
+                let text = format!("{}", value);
+                match precision {
+                    Some(p) => text.chars().take(p).collect(),
+                    None => text,
+                }
+            },
+            ('d', &Value::Integer(n)) => format!("{}", n),
+            #[cfg(feature = "bignum")]
+            ('d', &Value::BigInteger(ref n)) => format!("{}", n),
+            ('d', &Value::Number(n)) => format!("{}", n.round() as i64),
+            ('f', value) if value.as_number().is_some() => {
+                format!("{:.prec$}", value.as_number().unwrap(), prec = precision.unwrap_or(6))
+            },
+            ('d', val) | ('f', val) => {
+                return Err(RuntimeError(format!("expected number, got {}", val.type_string())));
+            },
+            (c, _) => return Err(RuntimeError(format!("unknown format specifier: %{}", c))),
+        };
+        result.push_str(&format!("{:>width$}", formatted, width = width));
+    }
+    Ok(result)
+}
@@ -0,0 +1,38 @@
+use super::{Environment, ResultType, RuntimeError, Value};
+use super::super::super::parse::ast::Node;
+
+pub fn assert(_: &mut Environment, args: &[Value]) -> ResultType {
+    if args[0].boolean() {
+        Ok(Value::Nothing)
+    } else {
+        Err(RuntimeError("assertion failed".to_owned()))
+    }
+}
+
+pub fn assertequal(_: &mut Environment, args: &[Value]) -> ResultType {
+    if args[0] == args[1] {
+        Ok(Value::Nothing)
+    } else {
+        Err(RuntimeError(format!("assertion failed: expected {}, got {}",
+                                 args[0].to_source(), args[1].to_source())))
+    }
+}
+
+pub fn testcase(env: &mut Environment, args: &[Node]) -> ResultType {
+    let name = match try!(env.eval(&args[0])) {
+        Value::String(name) => name,
+        value => return Err(RuntimeError(format!("invalid argument: {:?}", value))),
+    };
+    // A failing test case is reported but doesn't stop the test file
+    match env.run_test_case(&args[1]) {
+        Ok(_) => {
+            println!("[pass] {}", name);
+            env.test_results().passed += 1;
+        },
+        Err(e) => {
+            println!("[FAIL] {}: {}", name, e);
+            env.test_results().failed.push((name, e.to_string()));
+        },
+    }
+    Ok(Value::Nothing)
+}
@@ -1,29 +1,33 @@
 use super::{Environment, ResultType, RuntimeError, Value};
+use super::super::super::filter::{Filter, FILTER_NAMES};
+use super::super::super::graphic::color::{Color, Palette, PALETTE_NAMES};
+use super::super::super::parse::ast::Node;
+use super::super::super::turtle::TurtleState;
 
 pub fn forward(env: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::Number(x), => {
-        env.turtle.forward(x);
+        try!(env.turtle.forward(x));
         Ok(Value::Nothing)
     })
 }
 
 pub fn backward(env: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::Number(x), => {
-        env.turtle.backward(x);
+        try!(env.turtle.backward(x));
         Ok(Value::Nothing)
     })
 }
 
 pub fn left(env: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::Number(x), => {
-        env.turtle.left(x);
+        try!(env.turtle.left(x));
         Ok(Value::Nothing)
     })
 }
 
 pub fn right(env: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::Number(x), => {
-        env.turtle.right(x);
+        try!(env.turtle.right(x));
         Ok(Value::Nothing)
     })
 }
@@ -33,7 +37,7 @@ pub fn color(env: &mut Environment, args: &[Value]) -> ResultType {
               arg Value::Number(a),
               arg Value::Number(b),
               arg Value::Number(c), => {
-                  env.turtle.set_color(a, b, c);
+                  try!(env.turtle.set_color(a, b, c));
                   Ok(Value::Nothing)
               })
 }
@@ -43,13 +47,60 @@ pub fn bgcolor(env: &mut Environment, args: &[Value]) -> ResultType {
               arg Value::Number(r),
               arg Value::Number(g),
               arg Value::Number(b), => {
-                  env.turtle.set_background_color(r, g, b);
+                  try!(env.turtle.set_background_color(r, g, b));
                   Ok(Value::Nothing)
               })
 }
 
-pub fn clear(env: &mut Environment, _: &[Value]) -> ResultType {
-    env.turtle.clear();
+/// Convert a list of numbers to floats, `None` if an element is no number
+fn floats(values: &[Value]) -> Option<Vec<f32>> {
+    values.iter().map(Value::as_number).collect()
+}
+
+/// Return the color as `[r g b]` list
+fn color_list(color: Color) -> Value {
+    let (r, g, b, _) = color;
+    Value::List(vec![Value::Number(r), Value::Number(g), Value::Number(b)])
+}
+
+pub fn pencolor(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(color_list(env.turtle.get_color()))
+}
+
+pub fn background(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(color_list(env.turtle.get_background_color()))
+}
+
+pub fn pendownp(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(Value::Number(if env.turtle.is_pen_down() { 1. } else { 0. }))
+}
+
+pub fn pensize(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(Value::Number(env.turtle.get_pen_size()))
+}
+
+pub fn setpensize(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(size), => {
+        try!(env.turtle.set_pen_size(size));
+        Ok(Value::Nothing)
+    })
+}
+
+/// Takes an optional flag whether the home is reset to the origin as well
+pub fn clean(env: &mut Environment, args: &[Node]) -> ResultType {
+    let reset_home = match args.get(0) {
+        Some(arg) => try!(env.eval(arg)).boolean(),
+        None => false,
+    };
+    env.turtle.clean();
+    if reset_home {
+        try!(env.turtle.set_home(0., 0., 0.));
+    }
+    Ok(Value::Nothing)
+}
+
+pub fn clearscreen(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.turtle.clear_screen();
     Ok(Value::Nothing)
 }
 
@@ -68,9 +119,30 @@ pub fn home(env: &mut Environment, _: &[Value]) -> ResultType {
     Ok(Value::Nothing)
 }
 
+pub fn sethome(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Number(x),
+              arg Value::Number(y),
+              arg Value::Number(heading), => {
+                  try!(env.turtle.set_home(x, y, heading));
+                  Ok(Value::Nothing)
+              })
+}
+
 pub fn realign(env: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::Number(x), => {
-        env.turtle.set_orientation(x);
+        try!(env.turtle.set_orientation(x));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn heading(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(Value::Number(env.turtle.get_orientation()))
+}
+
+pub fn setheadingtowards(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(x), arg Value::Number(y), => {
+        try!(env.turtle.set_heading_towards(x, y));
         Ok(Value::Nothing)
     })
 }
@@ -85,14 +157,622 @@ pub fn show(env: &mut Environment, _: &[Value]) -> ResultType {
     Ok(Value::Nothing)
 }
 
-pub fn write(env: &mut Environment, args: &[Value]) -> ResultType {
+/// Takes the text and optionally the heading to write it at
+pub fn write(env: &mut Environment, args: &[Node]) -> ResultType {
+    let args: Vec<Value> = try!(args.iter().map(|a| env.eval(a)).collect());
+    if args.len() == 1 {
+        get_args!(args, arg Value::String(ref s), => {
+            try!(env.turtle.write(s));
+            Ok(Value::Nothing)
+        })
+    } else {
+        get_args!(args, arg Value::String(ref s), arg Value::Number(heading), => {
+            try!(env.turtle.write_rotated(s, heading));
+            Ok(Value::Nothing)
+        })
+    }
+}
+
+pub fn horizontaltext(env: &mut Environment, args: &[Value]) -> ResultType {
+    env.turtle.set_horizontal_text(args[0].boolean());
+    Ok(Value::Nothing)
+}
+
+pub fn writealong(env: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::String(ref s), => {
-        env.turtle.write(s);
+        env.turtle.write_along(s);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn writearc(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref s), arg Value::Number(radius), => {
+        try!(env.turtle.write_arc(s, radius));
         Ok(Value::Nothing)
     })
 }
 
+pub fn addfallbackfont(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref path), => {
+        match env.turtle.add_fallback_font(path) {
+            Ok(()) => Ok(Value::Nothing),
+            Err(e) => Err(RuntimeError(format!("{}", e))),
+        }
+    })
+}
+
 pub fn flood(env: &mut Environment, _: &[Value]) -> ResultType {
-    env.turtle.flood();
+    try!(env.turtle.flood());
+    Ok(Value::Nothing)
+}
+
+pub fn imagefilter(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        match Filter::from_name(name) {
+            Some(filter) => {
+                try!(env.turtle.apply_filter(filter));
+                Ok(Value::Nothing)
+            },
+            None => Err(RuntimeError(format!("unknown filter: {}, known filters: {}",
+                                             name, FILTER_NAMES.join(", ")))),
+        }
+    })
+}
+
+pub fn blur(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(radius), => {
+        if radius < 0. || !radius.is_finite() {
+            return Err(RuntimeError(format!("invalid blur radius: {}", radius)));
+        }
+        try!(env.turtle.apply_filter(Filter::Blur(radius.round() as u32)));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn smoothlines(env: &mut Environment, args: &[Value]) -> ResultType {
+    env.turtle.set_smooth_lines(args[0].boolean());
+    Ok(Value::Nothing)
+}
+
+pub fn fullscreen(env: &mut Environment, args: &[Value]) -> ResultType {
+    try!(env.turtle.set_fullscreen(args[0].boolean()));
+    Ok(Value::Nothing)
+}
+
+pub fn mousex(env: &mut Environment, _: &[Value]) -> ResultType {
+    let screen = env.turtle.get_screen();
+    screen.pump_events();
+    Ok(Value::Number(screen.mouse_position().0))
+}
+
+pub fn mousey(env: &mut Environment, _: &[Value]) -> ResultType {
+    let screen = env.turtle.get_screen();
+    screen.pump_events();
+    Ok(Value::Number(screen.mouse_position().1))
+}
+
+pub fn mousedown(env: &mut Environment, _: &[Value]) -> ResultType {
+    let screen = env.turtle.get_screen();
+    screen.pump_events();
+    Ok(Value::Number(if screen.is_mouse_down() { 1. } else { 0. }))
+}
+
+pub fn keydown(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref key), => {
+        let screen = env.turtle.get_screen();
+        screen.pump_events();
+        Ok(Value::Number(if screen.is_key_down(key) { 1. } else { 0. }))
+    })
+}
+
+pub fn stampimage(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::String(ref path),
+              arg Value::Number(scale), => {
+                  match env.turtle.stamp_image(path, scale) {
+                      Ok(()) => Ok(Value::Nothing),
+                      Err(e) => Err(RuntimeError(format!("{}", e))),
+                  }
+              })
+}
+
+pub fn grid(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(spacing), => {
+        // Too many lines would make drawing unbearably slow
+        if spacing != 0. && spacing < 5. {
+            return Err(RuntimeError(format!("grid spacing too small: {}", spacing)));
+        }
+        env.turtle.show_grid(spacing);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn axes(env: &mut Environment, args: &[Value]) -> ResultType {
+    env.turtle.show_axes(args[0].boolean());
+    Ok(Value::Nothing)
+}
+
+pub fn minimap(env: &mut Environment, args: &[Value]) -> ResultType {
+    env.turtle.show_minimap(args[0].boolean());
+    Ok(Value::Nothing)
+}
+
+pub fn drawingbounds(env: &mut Environment, _: &[Value]) -> ResultType {
+    match env.turtle.drawing_bounds() {
+        Some(bounds) => Ok(Value::List(vec![Value::Number(bounds.min.0),
+                                            Value::Number(bounds.min.1),
+                                            Value::Number(bounds.max.0),
+                                            Value::Number(bounds.max.1)])),
+        None => Ok(Value::Nothing),
+    }
+}
+
+pub fn fitview(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(margin), => {
+        try!(env.turtle.fit_view(margin));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn newscreen(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(width),
+                    arg Value::Number(height),
+                    arg Value::String(ref name), => {
+        if !(width >= 1. && height >= 1. && width <= 16384. && height <= 16384.) {
+            return Err(RuntimeError(format!("invalid screen size: {}x{}", width, height)));
+        }
+        try!(env.new_screen((width as u32, height as u32), name));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn setscreen(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        try!(env.select_screen(name));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn screen(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(Value::String(env.screen_name().to_owned()))
+}
+
+pub fn screens(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(Value::List(env.screen_names().into_iter().map(Value::String).collect()))
+}
+
+/// Return the number of copies for `MIRROR` and `KALEIDOSCOPE`
+fn mirror_count(args: &[Value]) -> Result<u32, RuntimeError> {
+    match args[0].as_number() {
+        Some(n) if n >= 0. && n <= 360. && n.fract() == 0. => Ok(n as u32),
+        _ => Err(RuntimeError(format!("invalid number of copies: {}", args[0]))),
+    }
+}
+
+pub fn mirror(env: &mut Environment, args: &[Value]) -> ResultType {
+    let count = try!(mirror_count(args));
+    env.turtle.set_mirror(count, false);
+    Ok(Value::Nothing)
+}
+
+pub fn kaleidoscope(env: &mut Environment, args: &[Value]) -> ResultType {
+    let count = try!(mirror_count(args));
+    env.turtle.set_mirror(count, true);
+    Ok(Value::Nothing)
+}
+
+pub fn settrail(env: &mut Environment, args: &[Value]) -> ResultType {
+    match args[0].as_number() {
+        Some(n) if n >= 0. && n.fract() == 0. => {
+            env.turtle.set_trail(n as u32);
+            Ok(Value::Nothing)
+        },
+        _ => Err(RuntimeError(format!("invalid trail length: {}", args[0]))),
+    }
+}
+
+pub fn polargoto(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Number(radius),
+              arg Value::Number(angle), => {
+                  try!(env.turtle.polar_goto(radius, angle));
+                  Ok(Value::Nothing)
+              })
+}
+
+pub fn isometric(env: &mut Environment, args: &[Value]) -> ResultType {
+    env.turtle.set_isometric(args[0].boolean());
+    Ok(Value::Nothing)
+}
+
+pub fn snap(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(angle), => {
+        try!(env.turtle.set_snap(angle));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn snapgrid(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(size), => {
+        try!(env.turtle.set_snap_grid(size));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn playback(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(rate), => {
+        if rate < 0. {
+            return Err(RuntimeError(format!("invalid playback rate: {}", rate)));
+        }
+        env.turtle.set_playback(rate);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn beginforeground(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.turtle.begin_foreground();
+    Ok(Value::Nothing)
+}
+
+pub fn clearforeground(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.turtle.clear_foreground();
+    Ok(Value::Nothing)
+}
+
+pub fn annotate(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref s), => {
+        try!(env.turtle.annotate(s));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn clearannotations(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.turtle.clear_annotations();
+    Ok(Value::Nothing)
+}
+
+pub fn clearmine(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.turtle.clear_mine();
+    Ok(Value::Nothing)
+}
+
+pub fn undo(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(Value::Number(if env.turtle.undo() { 1. } else { 0. }))
+}
+
+pub fn checkpoint(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::String(ref name), => {
+        env.turtle.checkpoint(name);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn rollback(env: &mut Environment, args: &[Node]) -> ResultType {
+    let args: Vec<Value> = try!(args.iter().map(|a| env.eval(a)).collect());
+    let keep_state = args.get(1).map_or(false, Value::boolean);
+    get_args!(args, arg Value::String(ref name), => {
+        try!(env.turtle.rollback(name, keep_state));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn pushtransform(env: &mut Environment, _: &[Value]) -> ResultType {
+    env.turtle.push_transform();
+    Ok(Value::Nothing)
+}
+
+pub fn poptransform(env: &mut Environment, _: &[Value]) -> ResultType {
+    if env.turtle.pop_transform() {
+        Ok(Value::Nothing)
+    } else {
+        Err(RuntimeError("no transform to pop".to_owned()))
+    }
+}
+
+pub fn scale(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(sx), arg Value::Number(sy), => {
+        try!(env.turtle.scale(sx, sy));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn rotate(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(deg), => {
+        try!(env.turtle.rotate(deg));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn translate(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(dx), arg Value::Number(dy), => {
+        try!(env.turtle.translate(dx, dy));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn setvelocity(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::Number(x),
+              arg Value::Number(y), =>
+    {
+        env.turtle.physics().velocity = (x, y);
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn velocity(env: &mut Environment, _: &[Value]) -> ResultType {
+    let (x, y) = env.turtle.physics().velocity;
+    Ok(Value::List(vec![Value::Number(x), Value::Number(y)]))
+}
+
+pub fn gravity(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(g), => {
+        env.turtle.physics().gravity = g;
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn bounce(env: &mut Environment, args: &[Value]) -> ResultType {
+    env.turtle.physics().bounce = args[0].boolean();
+    Ok(Value::Nothing)
+}
+
+pub fn update(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(time), => {
+        if time < 0. {
+            return Err(RuntimeError(format!("invalid time step: {}", time)));
+        }
+        try!(env.turtle.step(time));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn mode3d(env: &mut Environment, args: &[Value]) -> ResultType {
+    env.turtle.set_3d(args[0].boolean());
+    Ok(Value::Nothing)
+}
+
+/// Return an error unless the turtle is in 3D mode
+fn require_3d(env: &mut Environment) -> Result<(), RuntimeError> {
+    if env.turtle.is_3d() {
+        Ok(())
+    } else {
+        Err(RuntimeError("only available in 3D mode, use mode3d 1".to_owned()))
+    }
+}
+
+pub fn pitchup(env: &mut Environment, args: &[Value]) -> ResultType {
+    try!(require_3d(env));
+    get_args!(args, arg Value::Number(x), => {
+        try!(env.turtle.pitch_up(x));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn pitchdown(env: &mut Environment, args: &[Value]) -> ResultType {
+    try!(require_3d(env));
+    get_args!(args, arg Value::Number(x), => {
+        try!(env.turtle.pitch_down(x));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn rollleft(env: &mut Environment, args: &[Value]) -> ResultType {
+    try!(require_3d(env));
+    get_args!(args, arg Value::Number(x), => {
+        try!(env.turtle.roll_left(x));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn rollright(env: &mut Environment, args: &[Value]) -> ResultType {
+    try!(require_3d(env));
+    get_args!(args, arg Value::Number(x), => {
+        try!(env.turtle.roll_right(x));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn export3d(env: &mut Environment, args: &[Value]) -> ResultType {
+    try!(require_3d(env));
+    get_args!(args, arg Value::String(ref name), => {
+        let space = env.turtle.get_space().unwrap();
+        let result = ::std::fs::File::create(name).and_then(|mut file| {
+            if name.to_lowercase().ends_with(".ply") {
+                space.write_ply(&mut file)
+            } else {
+                space.write_obj(&mut file)
+            }
+        });
+        match result {
+            Ok(()) => Ok(Value::Nothing),
+            Err(e) => Err(RuntimeError(format!("{}: {}", name, e))),
+        }
+    })
+}
+
+pub fn orbit(env: &mut Environment, args: &[Value]) -> ResultType {
+    try!(require_3d(env));
+    get_args!(args,
+              arg Value::Number(yaw),
+              arg Value::Number(pitch), =>
+    {
+        try!(env.turtle.orbit(yaw, pitch));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn setpalette(env: &mut Environment, args: &[Value]) -> ResultType {
+    let palette = match args[0] {
+        Value::String(ref name) => match Palette::named(name) {
+            Some(palette) => palette,
+            None => return Err(RuntimeError(format!("unknown palette: {} (available: {})",
+                                                    name, PALETTE_NAMES.join(", ")))),
+        },
+        // A list of [r g b] colors
+        Value::List(ref colors) => {
+            let mut result = Vec::new();
+            for color in colors {
+                match *color {
+                    Value::List(ref rgb) => match floats(rgb).as_ref().map(|c| &c[..]) {
+                        Some(&[r, g, b]) => result.push((r, g, b, 1.0)),
+                        _ => return Err(RuntimeError(format!("invalid color: {}", color))),
+                    },
+                    _ => return Err(RuntimeError(format!("invalid color: {}", color))),
+                }
+            }
+            match Palette::new(result) {
+                Some(palette) => palette,
+                None => return Err(RuntimeError("palette without colors".to_owned())),
+            }
+        },
+        ref value => return Err(RuntimeError(format!("invalid argument: {:?}", value))),
+    };
+    *env.palette() = palette;
     Ok(Value::Nothing)
 }
+
+pub fn palettecolor(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(t), => {
+        let (r, g, b, _) = env.palette().color_at(t);
+        Ok(Value::List(vec![Value::Number(r), Value::Number(g), Value::Number(b)]))
+    })
+}
+
+pub fn colorat(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(t), => {
+        let (r, g, b, _) = env.palette().color_at(t);
+        try!(env.turtle.set_color(r, g, b));
+        Ok(Value::Nothing)
+    })
+}
+
+/// Return a `[name value]` pair
+fn pair(name: &str, value: Value) -> Value {
+    Value::List(vec![Value::String(name.to_owned()), value])
+}
+
+/// Return the pen part of the state as list of `[name value]` pairs
+fn pen_pairs(state: &TurtleState) -> Vec<Value> {
+    vec![
+        pair("down", Value::Number(if state.pen_down { 1. } else { 0. })),
+        pair("color", color_list(state.color)),
+        pair("size", Value::Number(state.pen_size)),
+    ]
+}
+
+/// Change the state according to the `[name value]` pairs. Only the names in
+/// `allowed` may occur.
+fn apply_pairs(state: &mut TurtleState, pairs: &[Value], allowed: &[&str])
+               -> Result<(), RuntimeError>
+{
+    let invalid = |value: &Value| RuntimeError(format!("invalid state entry: {}", value));
+    for entry in pairs {
+        let (name, value) = match *entry {
+            Value::List(ref p) if p.len() == 2 => match p[0] {
+                Value::String(ref name) if allowed.contains(&&name[..]) => (name, &p[1]),
+                _ => return Err(invalid(entry)),
+            },
+            _ => return Err(invalid(entry)),
+        };
+        match (&name[..], value) {
+            ("down", value) => state.pen_down = value.boolean(),
+            ("hidden", value) => state.hidden = value.boolean(),
+            ("heading", value) if value.as_number().is_some() => {
+                state.orientation = value.as_number().unwrap()
+            },
+            ("size", value) if value.as_number().is_some() => {
+                state.pen_size = value.as_number().unwrap()
+            },
+            ("position", &Value::List(ref p)) => match floats(p).as_ref().map(|p| &p[..]) {
+                Some(&[x, y]) => state.position = (x, y),
+                _ => return Err(invalid(entry)),
+            },
+            ("color", &Value::List(ref c)) => match floats(c).as_ref().map(|c| &c[..]) {
+                Some(&[r, g, b]) => state.color = (r, g, b, 1.0),
+                _ => return Err(invalid(entry)),
+            },
+            _ => return Err(invalid(entry)),
+        }
+    }
+    Ok(())
+}
+
+/// Run the block and restore parts of the `saved` turtle state afterwards,
+/// even if the block fails. `restore` copies them from the saved state into
+/// the current one.
+fn run_and_restore<F>(env: &mut Environment, block: &Node, saved: TurtleState, restore: F)
+                      -> ResultType
+    where F: Fn(&mut TurtleState, &TurtleState)
+{
+    let result = env.run_block(block);
+    let mut state = env.turtle.state();
+    restore(&mut state, &saved);
+    try!(env.turtle.set_state(&state));
+    result
+}
+
+pub fn withpenup(env: &mut Environment, args: &[Node]) -> ResultType {
+    let saved = env.turtle.state();
+    env.turtle.pen_up();
+    run_and_restore(env, &args[0], saved, |state, saved| state.pen_down = saved.pen_down)
+}
+
+pub fn withcolor(env: &mut Environment, args: &[Node]) -> ResultType {
+    let color = match try!(env.eval(&args[0])) {
+        Value::List(ref c) => floats(c),
+        _ => None,
+    };
+    let (r, g, b) = match color.as_ref().map(|c| &c[..]) {
+        Some(&[r, g, b]) => (r, g, b),
+        _ => return Err(RuntimeError("the color must be a list [r g b]".to_owned())),
+    };
+    let saved = env.turtle.state();
+    try!(env.turtle.set_color(r, g, b));
+    run_and_restore(env, &args[1], saved, |state, saved| state.color = saved.color)
+}
+
+pub fn withstate(env: &mut Environment, args: &[Node]) -> ResultType {
+    let saved = env.turtle.state();
+    run_and_restore(env, &args[0], saved, |state, saved| *state = *saved)
+}
+
+/// Names of the entries returned by `PEN`
+const PEN_ENTRIES: [&'static str; 3] = ["down", "color", "size"];
+/// Names of the entries returned by `TURTLESTATE`
+const STATE_ENTRIES: [&'static str; 6] = ["position", "heading", "down", "color", "size",
+                                          "hidden"];
+
+pub fn pen(env: &mut Environment, _: &[Value]) -> ResultType {
+    Ok(Value::List(pen_pairs(&env.turtle.state())))
+}
+
+pub fn setpen(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::List(ref pairs), => {
+        let mut state = env.turtle.state();
+        try!(apply_pairs(&mut state, pairs, &PEN_ENTRIES));
+        try!(env.turtle.set_state(&state));
+        Ok(Value::Nothing)
+    })
+}
+
+pub fn turtlestate(env: &mut Environment, _: &[Value]) -> ResultType {
+    let state = env.turtle.state();
+    let (x, y) = state.position;
+    let mut pairs = vec![
+        pair("position", Value::List(vec![Value::Number(x), Value::Number(y)])),
+        pair("heading", Value::Number(state.orientation)),
+    ];
+    pairs.extend(pen_pairs(&state));
+    pairs.push(pair("hidden", Value::Number(if state.hidden { 1. } else { 0. })));
+    Ok(Value::List(pairs))
+}
+
+pub fn setturtlestate(env: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::List(ref pairs), => {
+        let mut state = env.turtle.state();
+        try!(apply_pairs(&mut state, pairs, &STATE_ENTRIES));
+        try!(env.turtle.set_state(&state));
+        Ok(Value::Nothing)
+    })
+}
@@ -1,4 +1,5 @@
 use super::{Environment, ResultType, RuntimeError, Value};
+use super::super::super::parse::ast::Node;
 
 pub fn head(_: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::List(ref values), => {
@@ -22,8 +23,8 @@ pub fn tail(_: &mut Environment, args: &[Value]) -> ResultType {
 
 pub fn length(_: &mut Environment, args: &[Value]) -> ResultType {
     match args[0] {
-        Value::List(ref l) => Ok(Value::Number(l.len() as f32)),
-        Value::String(ref s) => Ok(Value::Number(s.len() as f32)),
+        Value::List(ref l) => Ok(Value::Integer(l.len() as i64)),
+        Value::String(ref s) => Ok(Value::Integer(s.len() as i64)),
         ref val => Err(RuntimeError(format!("Invalid argument: {}", val))),
     }
 }
@@ -37,28 +38,51 @@ pub fn isempty(_: &mut Environment, args: &[Value]) -> ResultType {
 pub fn getindex(_: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args,
               arg Value::List(ref values),
-              arg Value::Number(n), =>
+              arg Value::Integer(idx), =>
     {
-        let idx = n as usize;
-        if idx >= values.len() {
+        if idx < 0 || idx as usize >= values.len() {
             Err(RuntimeError(format!("Index out of bounds: {} >= {}", idx, values.len())))
         } else {
-            Ok(values[idx].clone())
+            Ok(values[idx as usize].clone())
         }
     })
 }
 
+/// Return the index of the first occurence of `needle` in `haystack`. For
+/// strings, this is the index of the first character of the substring.
+fn position(haystack: &Value, needle: &Value) -> Result<Option<usize>, RuntimeError> {
+    match (haystack, needle) {
+        (&Value::List(ref values), needle) => Ok(values.iter().position(|v| v == needle)),
+        (&Value::String(ref string), &Value::String(ref pattern)) => {
+            Ok(string.find(&pattern[..]).map(|i| string[..i].chars().count()))
+        },
+        (&Value::String(_), val) => Err(RuntimeError(format!("Invalid argument: {}", val))),
+        (val, _) => Err(RuntimeError(format!("Invalid argument: {}", val))),
+    }
+}
+
 pub fn find(_: &mut Environment, args: &[Value]) -> ResultType {
-    if let Value::List(ref values) = args[0] {
-        let needle = &args[1];
-        for (i, hay) in values.iter().enumerate() {
-            if hay == needle {
-                return Ok(Value::Number(i as f32))
-            }
-        }
-        Ok(Value::Number(-1.))
-    } else {
-        Err(RuntimeError(format!("Invalid argument: {}", args[0])))
+    match try!(position(&args[0], &args[1])) {
+        Some(i) => Ok(Value::Integer(i as i64)),
+        None => Ok(Value::Integer(-1)),
+    }
+}
+
+pub fn memberp(_: &mut Environment, args: &[Value]) -> ResultType {
+    let found = try!(position(&args[0], &args[1])).is_some();
+    Ok(Value::Number(if found { 1. } else { 0. }))
+}
+
+pub fn count(_: &mut Environment, args: &[Value]) -> ResultType {
+    match (&args[0], &args[1]) {
+        (&Value::List(ref values), needle) => {
+            Ok(Value::Integer(values.iter().filter(|&v| v == needle).count() as i64))
+        },
+        (&Value::String(ref string), &Value::String(ref pattern)) if !pattern.is_empty() => {
+            Ok(Value::Integer(string.matches(&pattern[..]).count() as i64))
+        },
+        (&Value::String(_), val) => Err(RuntimeError(format!("Invalid argument: {}", val))),
+        (val, _) => Err(RuntimeError(format!("Invalid argument: {}", val))),
     }
 }
 
@@ -67,10 +91,101 @@ pub fn not(_: &mut Environment, args: &[Value]) -> ResultType {
     Ok(Value::Number(if as_boolean { 0. } else { 1. }))
 }
 
+pub fn equalp(_: &mut Environment, args: &[Value]) -> ResultType {
+    // Unlike =, this never fails: lists are compared element by element and
+    // values of different types are simply unequal
+    Ok(Value::Number(if args[0] == args[1] { 1. } else { 0. }))
+}
+
+pub fn beforep(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args,
+              arg Value::String(ref a),
+              arg Value::String(ref b), =>
+    {
+        Ok(Value::Number(if a < b { 1. } else { 0. }))
+    })
+}
+
+// and/or are special forms, they only evaluate as many arguments as needed
+
+pub fn and(env: &mut Environment, args: &[Node]) -> ResultType {
+    for arg in args {
+        if !try!(env.eval(arg)).boolean() {
+            return Ok(Value::Number(0.));
+        }
+    }
+    Ok(Value::Number(1.))
+}
+
+pub fn or(env: &mut Environment, args: &[Node]) -> ResultType {
+    for arg in args {
+        if try!(env.eval(arg)).boolean() {
+            return Ok(Value::Number(1.));
+        }
+    }
+    Ok(Value::Number(0.))
+}
+
+// Type predicates
+
+/// Return 1 if the value is of the given type, 0 otherwise
+fn is_type(value: &Value, type_name: &str) -> ResultType {
+    Ok(Value::Number(if value.type_string() == type_name { 1. } else { 0. }))
+}
+
+pub fn numberp(_: &mut Environment, args: &[Value]) -> ResultType {
+    is_type(&args[0], "number")
+}
+
+pub fn stringp(_: &mut Environment, args: &[Value]) -> ResultType {
+    is_type(&args[0], "string")
+}
+
+pub fn listp(_: &mut Environment, args: &[Value]) -> ResultType {
+    is_type(&args[0], "list")
+}
+
+pub fn nothingp(_: &mut Environment, args: &[Value]) -> ResultType {
+    is_type(&args[0], "nothing")
+}
+
+pub fn procedurep(_: &mut Environment, args: &[Value]) -> ResultType {
+    is_type(&args[0], "procedure")
+}
+
+pub fn integerp(_: &mut Environment, args: &[Value]) -> ResultType {
+    let integer = match args[0] {
+        Value::Integer(_) => true,
+        #[cfg(feature = "bignum")]
+        Value::BigInteger(_) => true,
+        _ => false,
+    };
+    Ok(Value::Number(if integer { 1. } else { 0. }))
+}
+
+pub fn isnan(_: &mut Environment, args: &[Value]) -> ResultType {
+    get_args!(args, arg Value::Number(x), => {
+        Ok(Value::Number(if x.is_nan() { 1. } else { 0. }))
+    })
+}
+
+pub fn typeof_(_: &mut Environment, args: &[Value]) -> ResultType {
+    Ok(Value::String(args[0].type_string().to_owned()))
+}
+
 // Type conversion functions
 
 pub fn tonumber(_: &mut Environment, args: &[Value]) -> ResultType {
     get_args!(args, arg Value::String(ref string), => {
+        if let Ok(num) = string.parse::<i64>() {
+            return Ok(Value::Integer(num));
+        }
+        #[cfg(feature = "bignum")]
+        {
+            if let Ok(num) = string.parse::<::num::BigInt>() {
+                return Ok(Value::BigInteger(num));
+            }
+        }
         match string.parse::<f32>() {
             Ok(num) => Ok(Value::Number(num)),
             Err(e) => Err(RuntimeError(format!("{}", e))),
@@ -4,18 +4,38 @@
 //! execute the parsed tree. This module contains `Environment`, the execution
 //! environment for Rurtle code. It also defines the "prelude", the built-in
 //! functions in the Rurtle language.
+#[macro_use]
 pub mod functions;
 pub mod value;
 pub mod stack;
-use self::value::Value;
-use super::parse::ast::{Node, AddOp, MulOp, CompOp};
+pub mod workspace;
+#[cfg(feature = "bignum")]
+mod bignum;
+use self::value::{Value, Procedure};
+use super::parse::ast::{Node, AddOp, MulOp, CompOp, Parameter};
+use super::parse::{Arity, FuncMap};
 use super::turtle;
-use std::collections::HashMap;
-use std::fmt;
+use super::sound;
+use super::midi;
+use super::video;
+use super::stats::{millis, Instant, Stats};
+use super::graphic::color::Palette;
+use super::plotter::PlotterOptions;
+use image::{GenericImage, RgbaImage};
+use std::collections::{HashMap, HashSet};
+use std::{fmt, thread, time};
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub struct RuntimeError(String);
 
+impl RuntimeError {
+    /// Construct a new `RuntimeError` with the given message
+    pub fn new<S: Into<String>>(message: S) -> RuntimeError {
+        RuntimeError(message.into())
+    }
+}
+
 impl ::std::fmt::Display for RuntimeError {
     fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
         fmt.pad(&self.0)
@@ -28,6 +48,12 @@ impl ::std::error::Error for RuntimeError {
     }
 }
 
+impl From<turtle::TurtleError> for RuntimeError {
+    fn from(error: turtle::TurtleError) -> RuntimeError {
+        RuntimeError(error.to_string())
+    }
+}
+
 /// The type returned by Rurtle functions
 pub type ResultType = Result<Value, RuntimeError>;
 /// The type that functions called in Rurtle must have.
@@ -35,6 +61,11 @@ pub type ResultType = Result<Value, RuntimeError>;
 /// The first parameter is the Environment in which the function is executed and
 /// the second argument are the function's parameters.
 pub type FuncType = fn(&mut Environment, &[Value]) -> ResultType;
+/// The type that special forms must have.
+///
+/// Special forms get their arguments as unevaluated AST nodes and may decide
+/// themselves if and when to evaluate them (see `Environment::eval`).
+pub type SpecialFuncType = fn(&mut Environment, &[Node]) -> ResultType;
 
 /// A function available to Rurtle programs can either be a function defined in
 /// a Rurtle program or a native function of FuncType
@@ -47,6 +78,30 @@ pub enum Function {
     /// be extracted from the function pointer. The second argument is a
     /// function of `FuncType`
     Native(i32, FuncType),
+    /// This variant holds a native Rust function that gets its arguments
+    /// unevaluated, e.g. to implement short-circuiting operators. The first
+    /// parameter is the number of arguments the function accepts.
+    Special(Arity, SpecialFuncType),
+    /// This variant holds a macro that was defined in Rurtle via the MACRO
+    /// statement. It gets the source of its arguments and returns code that
+    /// is run in place of the call. The node is a `LearnStatement` as well.
+    Macro(Node),
+}
+
+impl Function {
+    /// Return the number of arguments that the function takes
+    pub fn arity(&self) -> Arity {
+        match *self {
+            Function::Native(i, _) => Arity::fixed(i),
+            Function::Special(arity, _) => arity,
+            Function::Defined(ref node) | Function::Macro(ref node) => {
+                match *node {
+                    Node::LearnStatement(_, ref params, _) => Arity::of_parameters(params),
+                    _ => panic!("Function node is not a LearnStatement"),
+                }
+            },
+        }
+    }
 }
 
 impl Clone for Function {
@@ -55,6 +110,8 @@ impl Clone for Function {
         match *self {
             Defined(ref node) => Defined(node.clone()),
             Native(arg_count, function) => Native(arg_count, function),
+            Special(arity, function) => Special(arity, function),
+            Macro(ref node) => Macro(node.clone()),
         }
     }
 }
@@ -62,6 +119,17 @@ impl Clone for Function {
 /// Helper function to get a pointer without needing to type the type
 fn pointer<T>(x: &T) -> *const T { x as *const T }
 
+/// Return the source of a macro argument. The elements of a list literal are
+/// put on separate lines, this way a list can be passed as block of code.
+fn macro_argument(node: &Node) -> String {
+    match *node {
+        Node::List(ref elements) => {
+            elements.iter().map(|e| e.to_source()).collect::<Vec<_>>().join("\n")
+        },
+        ref node => node.to_source(),
+    }
+}
+
 impl fmt::Debug for Function {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         use self::Function::*;
@@ -72,6 +140,12 @@ impl fmt::Debug for Function {
             Native(count, function) => {
                 write!(fmt, "Native({:?}, {:?})", count, pointer(&function))
             },
+            Special(arity, function) => {
+                write!(fmt, "Special({:?}, {:?})", arity, pointer(&function))
+            },
+            Macro(ref node) => {
+                write!(fmt, "Macro({:?})", node)
+            },
         }
     }
 }
@@ -87,27 +161,538 @@ macro_rules! framed {
     }
 }
 
+/// Constants that are defined in every environment
+pub const PREDEFINED_CONSTANTS: [(&'static str, f32); 3] = [
+    ("PI", ::std::f32::consts::PI),
+    ("E", ::std::f32::consts::E),
+    ("TAU", 2. * ::std::f32::consts::PI),
+];
+
+/// Results of the test cases run with `TESTCASE`
+#[derive(Debug, Default, Clone)]
+pub struct TestResults {
+    /// Number of test cases that passed
+    pub passed: u32,
+    /// Names of the failed test cases together with the error message
+    pub failed: Vec<(String, String)>,
+}
+
+/// Limits for running untrusted code, see `Environment::set_limits`. `None`
+/// means unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Limits {
+    /// Maximum number of evaluated statements, see `Stats::statements`
+    pub statements: Option<u64>,
+    /// Maximum depth of nested function calls
+    pub call_depth: Option<usize>,
+    /// Maximum number of line segments drawn by the turtle
+    pub segments: Option<u64>,
+    /// Maximum wall time
+    pub time: Option<time::Duration>,
+}
+
+/// What has been used of the `Limits` since the evaluation started
+#[derive(Debug, Clone, Copy)]
+struct LimitUsage {
+    statements: u64,
+    /// Number of segments the turtle had drawn at the start. This is adjusted
+    /// when the counters are reset, so `RESETSTATS` can't be used to draw
+    /// more.
+    segments_base: i64,
+    start: Instant,
+}
+
+/// A function that is called regularly while Rurtle code runs, see
+/// `Environment::set_instruction_hook`. It gets the current counters, an
+/// error cancels the execution.
+pub type InstructionHook = Box<FnMut(&Stats) -> Result<(), RuntimeError>>;
+
+/// A function that opens a new window with the given size and title and
+/// returns a turtle that draws in it, see `Environment::set_screen_factory`
+pub type ScreenFactory = Box<Fn((u32, u32), &str) -> turtle::Turtle>;
+
+/// Name of the screen the environment was created with
+pub const MAIN_SCREEN: &'static str = "main";
+
 pub struct Environment {
     stack: Vec<stack::Frame>,
     turtle: turtle::Turtle,
+    /// Name of the screen `turtle` draws on
+    screen: String,
+    /// The turtles of all other screens together with the screen names, see
+    /// `new_screen`
+    screens: Vec<(String, turtle::Turtle)>,
+    screen_factory: Option<ScreenFactory>,
+    click_handler: Option<Node>,
+    speaker: Option<sound::Speaker>,
+    midi: Option<midi::MidiOut>,
+    repcounts: Vec<i32>,
+    constants: HashSet<String>,
+    args: Vec<String>,
+    test_results: TestResults,
+    stats: Stats,
+    palette: Palette,
+    /// Set by `STOPLOOP` to end the running `GAMELOOP`
+    loop_stopped: bool,
+    /// Number of the next screenshot with a numbered filename
+    screenshot_counter: u32,
+    /// The running video recording, see `start_video`
+    video: Option<video::Recorder>,
+    /// The hook and the number of statements between two calls
+    instruction_hook: Option<(u64, InstructionHook)>,
+    limits: Limits,
+    /// Set while code is evaluated, see `begin_limited`
+    limit_usage: Option<LimitUsage>,
+    sandbox: bool,
+    /// The images loaded with `LOADIMAGE`, their handles are the indices
+    images: Vec<RgbaImage>,
+    /// The settings of `SAVEGCODE` and `SAVEHPGL`, see `SETPLOTTER`
+    plotter: PlotterOptions,
 }
 
 impl Environment {
     /// Construct a new `Environment` with default values
     pub fn new(turtle: turtle::Turtle) -> Environment {
-        Environment {
+        let mut environ = Environment {
             stack: stack::new_stack(),
             turtle: turtle,
+            screen: MAIN_SCREEN.to_owned(),
+            screens: Vec::new(),
+            screen_factory: None,
+            click_handler: None,
+            speaker: None,
+            midi: None,
+            repcounts: Vec::new(),
+            constants: HashSet::new(),
+            args: Vec::new(),
+            test_results: TestResults::default(),
+            stats: Stats::default(),
+            palette: Palette::default(),
+            loop_stopped: false,
+            screenshot_counter: 0,
+            video: None,
+            instruction_hook: None,
+            limits: Limits::default(),
+            limit_usage: None,
+            sandbox: false,
+            images: Vec::new(),
+            plotter: PlotterOptions::default(),
+        };
+        for &(name, value) in &PREDEFINED_CONSTANTS {
+            environ.define_constant(name, Value::Number(value)).unwrap();
+        }
+        environ
+    }
+
+    /// Define a global variable that can't be changed afterwards
+    pub fn define_constant(&mut self, name: &str, value: Value) -> ResultType {
+        try!(self.check_mutable(name));
+        self.global_frame().locals.insert(name.to_owned(), value);
+        self.constants.insert(name.to_owned());
+        Ok(Value::Nothing)
+    }
+
+    /// Return an error if the variable with the given name is a constant
+    pub fn check_mutable(&self, name: &str) -> Result<(), RuntimeError> {
+        if self.constants.contains(name) {
+            Err(RuntimeError(format!("{} is a constant", name)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Set the command line arguments that are passed to the script
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Return the command line arguments that are passed to the script
+    pub fn get_args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Return the results of the test cases run so far
+    pub fn test_results(&mut self) -> &mut TestResults {
+        &mut self.test_results
+    }
+
+    /// Run the body of a test case, see `run_block`
+    pub fn run_test_case(&mut self, body: &Node) -> ResultType {
+        self.run_block(body)
+    }
+
+    /// Run a block that was passed to a function. The block is either a list
+    /// of statements (which are evaluated in order) or a procedure without
+    /// arguments.
+    pub fn run_block(&mut self, body: &Node) -> ResultType {
+        framed!(self, self.eval_block(body).and_then(|value| match value {
+            Value::Procedure(ref procedure) => self.call_procedure(procedure, Vec::new()),
+            value => Ok(value),
+        }))
+    }
+
+    /// Run `body` `fps` times per second until `stop_game_loop` is called,
+    /// the loop is left with BREAK or the window is closed. Before each
+    /// frame, everything drawn by the last frame is removed, see
+    /// `Turtle::begin_foreground`. The screen is only redrawn after each
+    /// frame. In the browser (the `web` feature), threads can't wait between
+    /// frames, so this returns an error.
+    pub fn run_game_loop(&mut self, fps: f32, body: &Node) -> ResultType {
+        if cfg!(feature = "web") {
+            return Err(RuntimeError("GAMELOOP is not available in the browser".to_owned()));
+        }
+        let frame_time = time::Duration::from_millis((1000. / fps) as u64);
+        let auto_update = self.turtle.get_auto_update();
+        self.turtle.set_auto_update(false);
+        self.turtle.begin_foreground();
+        self.loop_stopped = false;
+        self.repcounts.push(0);
+        let mut result = Ok(Value::Nothing);
+        let mut next_frame = Instant::now();
+        while !self.loop_stopped && !self.turtle.get_screen().is_closed() {
+            *self.repcounts.last_mut().unwrap() += 1;
+            self.turtle.clear_foreground();
+            match self.eval_loop_body(body) {
+                Ok(false) => (),
+                Ok(true) => break,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                },
+            }
+            self.turtle.render();
+            if let Err(e) = self.handle_events() {
+                result = Err(e);
+                break;
+            }
+            // If a frame took too long, the next one starts right away
+            // instead of trying to catch up
+            next_frame = next_frame + frame_time;
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
+            }
+        }
+        self.repcounts.pop();
+        self.loop_stopped = false;
+        self.turtle.set_auto_update(auto_update);
+        try!(result);
+        Ok(Value::Nothing)
+    }
+
+    /// Return the number for the next screenshot with a numbered filename
+    /// and count it, see `SCREENSHOT`
+    pub fn next_screenshot_number(&mut self) -> u32 {
+        self.screenshot_counter += 1;
+        self.screenshot_counter - 1
+    }
+
+    /// Return the plotter settings made with `SETPLOTTER`
+    pub fn plotter_options(&self) -> &PlotterOptions {
+        &self.plotter
+    }
+
+    /// Set the number of the next numbered screenshot
+    pub fn set_screenshot_counter(&mut self, number: u32) {
+        self.screenshot_counter = number;
+    }
+
+    /// Start recording a video of the current screen into the file at `path`
+    /// with `fps` frames per second, see `video::Recorder`. A running
+    /// recording is finished first.
+    pub fn start_video(&mut self, path: &str, fps: f32) -> Result<(), RuntimeError> {
+        try!(self.stop_video());
+        let size = try!(self.turtle.screenshot()).dimensions();
+        match video::Recorder::start(path, fps, size) {
+            Ok(recorder) => self.video = Some(recorder),
+            Err(e) => return Err(RuntimeError(format!("{}", e))),
+        }
+        self.record_video_frame()
+    }
+
+    /// Finish the running video recording, if there is one
+    pub fn stop_video(&mut self) -> Result<(), RuntimeError> {
+        match self.video.take() {
+            Some(recorder) => recorder.finish().map_err(|e| RuntimeError(format!("{}", e))),
+            None => Ok(()),
+        }
+    }
+
+    /// Add a screenshot to the running video if the next frame is due
+    fn record_video_frame(&mut self) -> Result<(), RuntimeError> {
+        if !self.video.as_ref().map_or(false, video::Recorder::is_due) {
+            return Ok(());
+        }
+        let image = try!(self.turtle.screenshot());
+        let result = self.video.as_mut().unwrap().add_frame(&image);
+        if let Err(e) = result {
+            // The encoder won't recover, so the recording ends here
+            self.video = None;
+            return Err(RuntimeError(format!("video recording failed: {}", e)));
+        }
+        Ok(())
+    }
+
+    /// End the running game loop after the current frame
+    pub fn stop_game_loop(&mut self) {
+        self.loop_stopped = true;
+    }
+
+    /// Call `hook` every `interval` evaluated statements (see
+    /// `Stats::statements`) with the counters of `stats()`. Hosts can use
+    /// this to show the progress or to enforce a time budget: if the hook
+    /// returns an error, the running code is cancelled with that error.
+    /// Replaces the previous hook.
+    pub fn set_instruction_hook<F>(&mut self, interval: u64, hook: F)
+        where F: FnMut(&Stats) -> Result<(), RuntimeError> + 'static
+    {
+        self.instruction_hook = Some((interval.max(1), Box::new(hook)));
+    }
+
+    /// Remove the hook set with `set_instruction_hook`
+    pub fn clear_instruction_hook(&mut self) {
+        self.instruction_hook = None;
+    }
+
+    /// Restrict the resources that Rurtle code may use. The limits apply to
+    /// each call of `eval_source` (e.g. each line entered at the prompt) and
+    /// each run of the click handler. When a limit is exceeded, a runtime
+    /// error is thrown.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Enable or disable sandbox mode. In sandbox mode, built-in functions
+    /// that access files, the network, the clipboard or the terminal throw
+    /// an error instead, see `functions::RESTRICTED`. Use
+    /// this together with `set_limits` to run untrusted code.
+    pub fn set_sandbox(&mut self, sandbox: bool) {
+        self.sandbox = sandbox;
+    }
+
+    /// Returns true if sandbox mode is enabled
+    pub fn is_sandboxed(&self) -> bool {
+        self.sandbox
+    }
+
+    /// Return the limits set with `set_limits`
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Start counting the usage of the limits, unless an evaluation is
+    /// already running (e.g. `LOADWORKSPACE` evaluates the loaded file).
+    /// Returns true if counting started, in which case `end_limited` has to
+    /// be called afterwards.
+    fn begin_limited(&mut self) -> bool {
+        if self.limit_usage.is_some() {
+            return false;
+        }
+        self.limit_usage = Some(LimitUsage {
+            statements: 0,
+            segments_base: self.turtle.stats().segments as i64,
+            start: Instant::now(),
+        });
+        true
+    }
+
+    fn end_limited(&mut self, started: bool) {
+        if started {
+            self.limit_usage = None;
+        }
+    }
+
+    /// Count the statement and return an error if a limit is exceeded
+    fn check_limits(&mut self) -> Result<(), RuntimeError> {
+        let segments = self.turtle.stats().segments as i64;
+        let usage = match self.limit_usage {
+            Some(ref mut usage) => {
+                usage.statements += 1;
+                *usage
+            },
+            None => return Ok(()),
+        };
+        if let Some(max) = self.limits.statements {
+            if usage.statements > max {
+                return Err(RuntimeError(format!("statement limit exceeded ({})", max)));
+            }
+        }
+        if let Some(max) = self.limits.segments {
+            if segments - usage.segments_base > max as i64 {
+                return Err(RuntimeError(format!("segment limit exceeded ({})", max)));
+            }
         }
+        if let Some(max) = self.limits.time {
+            if usage.start.elapsed() > max {
+                return Err(RuntimeError(format!("time limit exceeded ({} ms)", millis(max))));
+            }
+        }
+        Ok(())
+    }
+
+    /// Return an error if drawing `count` more segments would exceed the
+    /// segment limit. Builtins that draw many segments at once call this
+    /// before doing the work.
+    pub fn check_segments(&mut self, count: u64) -> Result<(), RuntimeError> {
+        let (usage, max) = match (self.limit_usage, self.limits.segments) {
+            (Some(usage), Some(max)) => (usage, max),
+            _ => return Ok(()),
+        };
+        let segments = self.turtle.stats().segments as i64 + count as i64;
+        if segments - usage.segments_base > max as i64 {
+            return Err(RuntimeError(format!("segment limit exceeded ({})", max)));
+        }
+        Ok(())
+    }
+
+    /// Call the instruction hook if it's due
+    fn run_instruction_hook(&mut self) -> Result<(), RuntimeError> {
+        let due = match self.instruction_hook {
+            Some((interval, _)) => self.stats.statements % interval == 0,
+            None => false,
+        };
+        if !due {
+            return Ok(());
+        }
+        let stats = self.stats();
+        match self.instruction_hook {
+            Some((_, ref mut hook)) => hook(&stats),
+            None => Ok(()),
+        }
+    }
+
+    /// Return the palette used by `PALETTECOLOR`
+    pub fn palette(&mut self) -> &mut Palette {
+        &mut self.palette
+    }
+
+    /// Return the performance counters of the interpreter and the turtle
+    pub fn stats(&mut self) -> Stats {
+        self.stats.merge(self.turtle.stats())
+    }
+
+    /// Reset all performance counters to zero
+    pub fn reset_stats(&mut self) {
+        if let Some(ref mut usage) = self.limit_usage {
+            usage.segments_base -= self.turtle.stats().segments as i64;
+        }
+        self.stats = Stats::default();
+        *self.turtle.stats() = Stats::default();
     }
 
     pub fn get_turtle(&mut self) -> &mut turtle::Turtle {
         &mut self.turtle
     }
 
+    /// Set the function that `new_screen` uses to open windows. Without a
+    /// factory, the environment can't open more screens.
+    pub fn set_screen_factory<F>(&mut self, factory: F)
+        where F: Fn((u32, u32), &str) -> turtle::Turtle + 'static
+    {
+        self.screen_factory = Some(Box::new(factory));
+    }
+
+    /// Open a new window with the given size and name and make it the
+    /// current screen, so the turtle commands draw in it from now on. The
+    /// other screens keep their turtles and drawings.
+    pub fn new_screen(&mut self, size: (u32, u32), name: &str) -> Result<(), RuntimeError> {
+        if name == self.screen || self.screens.iter().any(|&(ref n, _)| n == name) {
+            return Err(RuntimeError(format!("screen {} exists already", name)));
+        }
+        let turtle = match self.screen_factory {
+            Some(ref factory) => factory(size, name),
+            None => return Err(RuntimeError("can't open more screens here".to_owned())),
+        };
+        self.screens.push((name.to_owned(), turtle));
+        self.select_screen(name)
+    }
+
+    /// Make the screen with the given name the current one, see
+    /// `new_screen`. The screen the environment was created with is called
+    /// `MAIN_SCREEN`.
+    pub fn select_screen(&mut self, name: &str) -> Result<(), RuntimeError> {
+        if name == self.screen {
+            return Ok(());
+        }
+        let index = match self.screens.iter().position(|&(ref n, _)| n == name) {
+            Some(index) => index,
+            None => return Err(RuntimeError(format!("no screen named {}", name))),
+        };
+        let old_segments = self.turtle.stats().segments as i64;
+        {
+            let (ref mut screen, ref mut turtle) = self.screens[index];
+            ::std::mem::swap(&mut self.screen, screen);
+            ::std::mem::swap(&mut self.turtle, turtle);
+        }
+        // The segment limit counts the segments of all screens together
+        if let Some(ref mut usage) = self.limit_usage {
+            usage.segments_base += self.turtle.stats().segments as i64 - old_segments;
+        }
+        Ok(())
+    }
+
+    /// Return the name of the current screen
+    pub fn screen_name(&self) -> &str {
+        &self.screen
+    }
+
+    /// Return the names of all screens, the current one included. The main
+    /// screen comes first, the others are sorted alphabetically.
+    pub fn screen_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.screens.iter().map(|&(ref n, _)| n.clone()).collect();
+        names.push(self.screen.clone());
+        names.sort_by(|a, b| (a != MAIN_SCREEN, a).cmp(&(b != MAIN_SCREEN, b)));
+        names
+    }
+
+    /// Draw everything and update the windows of all screens
+    pub fn update_screens(&mut self) {
+        for &mut (_, ref mut turtle) in &mut self.screens {
+            turtle.get_screen().draw_and_update();
+        }
+        self.turtle.get_screen().draw_and_update();
+    }
+
+    /// Returns true if the window of the main screen has been closed
+    pub fn is_main_screen_closed(&mut self) -> bool {
+        match self.screens.iter().position(|&(ref n, _)| n == MAIN_SCREEN) {
+            Some(index) => self.screens[index].1.get_screen().is_closed(),
+            None => self.turtle.get_screen().is_closed(),
+        }
+    }
+
+    /// Return the `Speaker` used for sound output. The audio device is only
+    /// opened when sound is used for the first time.
+    pub fn get_speaker(&mut self) -> Result<&sound::Speaker, RuntimeError> {
+        if self.speaker.is_none() {
+            self.speaker = sound::Speaker::new();
+        }
+        match self.speaker {
+            Some(ref speaker) => Ok(speaker),
+            None => Err(RuntimeError("No audio device available".to_owned())),
+        }
+    }
+
+    /// Return the connection used for MIDI output. Like the speaker, it is
+    /// only opened when it is used for the first time.
+    pub fn get_midi(&mut self) -> Result<&mut midi::MidiOut, RuntimeError> {
+        if self.midi.is_none() {
+            self.midi = Some(try!(midi::MidiOut::new().map_err(RuntimeError)));
+        }
+        Ok(self.midi.as_mut().unwrap())
+    }
+
+    /// Define a global function with the given name, replacing any existing
+    /// function of the same name. Names are case insensitive.
+    pub fn define_function(&mut self, name: &str, function: Function) {
+        self.global_frame().functions[0].insert(name.to_uppercase(), function);
+    }
+
     /// Return a map mapping the function name to the argument count. Useful for
     /// passing it to `Parser::parse`
-    pub fn function_arg_count(&self) -> HashMap<String, i32> {
+    pub fn function_arg_count(&self) -> FuncMap {
         let mut result = HashMap::new();
         // We walk the stack and insert the functions of each frame into the
         // "global stack" of the parser. If a function is redefined in a "tighter"
@@ -115,16 +700,7 @@ impl Environment {
         for stack_frame in &self.stack {
             for mini_frame in &stack_frame.functions {
                 for (name, function) in mini_frame {
-                    let count = match *function {
-                        Function::Native(i, _) => i,
-                        Function::Defined(ref node) => {
-                            match *node {
-                                Node::LearnStatement(_, ref args, _) => args.len() as i32,
-                                _ => panic!("Function node is not a LearnStatement"),
-                            }
-                        },
-                    };
-                    result.insert(name.clone(), count);
+                    result.insert(name.clone(), function.arity());
                 }
             }
         }
@@ -143,20 +719,61 @@ impl Environment {
         None
     }
 
+    /// Handle the window's events and run the ONTURTLECLICK block for every
+    /// click that hit the turtle
+    pub fn handle_events(&mut self) -> ResultType {
+        // The other screens only need to stay responsive
+        for &mut (_, ref mut turtle) in &mut self.screens {
+            turtle.get_screen().pump_events();
+        }
+        self.turtle.get_screen().pump_events();
+        try!(self.record_video_frame());
+        self.turtle.handle_orbit();
+        self.turtle.advance_playback();
+        let hits = self.turtle.handle_clicks();
+        // Take the handler out while it's running, this way clicks that happen
+        // inside of the handler don't call it recursively
+        if let Some(handler) = self.click_handler.take() {
+            let mut result = Ok(Value::Nothing);
+            for _ in 0..hits {
+                let started = self.begin_limited();
+                result = framed!(self, self.eval_block(&handler));
+                self.end_limited(started);
+                if result.is_err() {
+                    break;
+                }
+            }
+            if self.click_handler.is_none() {
+                self.click_handler = Some(handler);
+            }
+            try!(result);
+        }
+        Ok(Value::Nothing)
+    }
+
     /// Tokenize, parse and evaluate the given source
     pub fn eval_source(&mut self, source: &str) -> Result<Value, Box<::std::error::Error>> {
         use super::lex;
         use super::parse;
+        let start = Instant::now();
         let tokens = match lex::tokenize(source) {
             Ok(t) => t,
             Err(e) => return Err(Box::new(e)),
         };
+        self.stats.lex_time += start.elapsed();
+        let start = Instant::now();
         let mut parser = parse::Parser::new(tokens, self.function_arg_count());
         let tree = match parser.parse() {
             Ok(n) => n.flatten(),
             Err(e) => return Err(Box::new(e)),
         };
-        match self.eval(&tree) {
+        self.stats.parse_time += start.elapsed();
+        let start = Instant::now();
+        let started = self.begin_limited();
+        let result = self.eval_block(&tree);
+        self.end_limited(started);
+        self.stats.eval_time += start.elapsed();
+        match result {
             Ok(v) => return Ok(v),
             Err(e) => return Err(Box::new(e)),
         };
@@ -165,7 +782,7 @@ impl Environment {
     /// Evaluate the given AST node
     pub fn eval(&mut self, node: &Node) -> ResultType {
         use super::parse::ast::Node::*;
-        if self.current_frame().should_return {
+        if self.current_frame().should_return || self.current_frame().loop_signal.is_some() {
             return Ok(Value::Nothing);
         }
         match *node {
@@ -177,8 +794,12 @@ impl Environment {
                 self.eval_repeat_statement(num, body),
             WhileStatement(ref condition, ref body) =>
                 self.eval_while_statement(condition, body),
+            ForStatement(ref name, ref start, ref end, ref step, ref body) =>
+                self.eval_for_statement(name, start, end, step, body),
             ref learn_statement @ LearnStatement(..) =>
                 self.eval_learn_statement(learn_statement),
+            MacroStatement(ref name, ref params, ref body) =>
+                self.eval_macro_statement(name, params, body),
             Comparison(ref a, op, ref b) =>
                 self.eval_comparison(a, op, b),
             Addition(ref start, ref values) =>
@@ -189,8 +810,16 @@ impl Environment {
                 self.eval_func_call(name, args),
             ReturnStatement(ref value) =>
                 self.eval_return_statement(value),
+            BreakStatement =>
+                self.eval_loop_signal(stack::LoopSignal::Break),
+            ContinueStatement =>
+                self.eval_loop_signal(stack::LoopSignal::Continue),
             TryStatement(ref normal, ref exception) =>
                 self.eval_try_statement(normal, exception),
+            Lambda(ref params, ref body) =>
+                self.eval_lambda(params, body),
+            OnClickStatement(ref body) =>
+                self.eval_onclick_statement(body),
             Assignment(ref name, ref value) =>
                 self.eval_assignment(name, value),
             List(ref elements) =>
@@ -199,16 +828,43 @@ impl Environment {
                 Ok(Value::String(string.clone())),
             Number(num) =>
                 Ok(Value::Number(num)),
+            Integer(num) =>
+                Ok(Value::Integer(num)),
             Variable(ref name) =>
                 self.eval_variable(name),
         }
     }
 
+    /// Evaluate the statements and return the value of the last one
     fn eval_statement_list(&mut self, statements: &[Node]) -> ResultType {
+        let mut value = Value::Nothing;
         for statement in statements {
-            try!(self.eval(statement));
+            value = try!(self.eval_statement(statement));
         }
-        Ok(Value::Nothing)
+        Ok(value)
+    }
+
+    /// Evaluate a body, e.g. of a loop or a function. The parser flattens a
+    /// body with a single statement to that statement, so it is counted
+    /// here.
+    fn eval_block(&mut self, body: &Node) -> ResultType {
+        match *body {
+            Node::StatementList(ref statements) => self.eval_statement_list(statements),
+            _ => self.eval_statement(body),
+        }
+    }
+
+    /// Count the statement for the stats and the limits, then evaluate it.
+    /// Expressions inside of the statement don't count separately.
+    fn eval_statement(&mut self, statement: &Node) -> ResultType {
+        if self.current_frame().should_return || self.current_frame().loop_signal.is_some() {
+            return Ok(Value::Nothing);
+        }
+        self.stats.statements += 1;
+        try!(self.check_limits());
+        try!(self.run_instruction_hook());
+        try!(self.record_video_frame());
+        self.eval(statement)
     }
 
     fn eval_if_statement(&mut self, condition: &Node, true_body: &Node,
@@ -217,28 +873,108 @@ impl Environment {
     {
         let value = try!(self.eval(condition));
         if value.boolean() {
-            try!(framed!(self, self.eval(true_body)));
+            framed!(self, self.eval_block(true_body))
         } else if let Some(ref false_body) = *false_body {
-            try!(framed!(self, self.eval(false_body)));
+            framed!(self, self.eval_block(false_body))
+        } else {
+            Ok(Value::Nothing)
         }
-        Ok(Value::Nothing)
     }
 
     fn eval_repeat_statement(&mut self, num: &Node, body: &Node) -> ResultType {
-        let num = try!(self.eval(num));
-        if let Value::Number(num) = num {
-            for _ in 0..num as i32 {
-                try!(framed!(self, self.eval(body)));
+        let num = match try!(self.eval(num)) {
+            Value::Integer(n) => Some(n),
+            Value::Number(x) => Some(x as i64),
+            _ => None,
+        };
+        if let Some(num) = num {
+            self.repcounts.push(0);
+            let mut result = Ok(Value::Nothing);
+            for i in 0..num {
+                *self.repcounts.last_mut().unwrap() = (i + 1) as i32;
+                match self.eval_loop_body(body) {
+                    Ok(false) => (),
+                    Ok(true) => break,
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    },
+                }
             }
+            self.repcounts.pop();
+            try!(result);
             Ok(Value::Nothing)
         } else {
             Err(RuntimeError("repeat count has to be a number".to_owned()))
         }
     }
 
+    /// Evaluate the body of a loop and handle BREAK and CONTINUE. Returns true
+    /// if the loop should be left.
+    fn eval_loop_body(&mut self, body: &Node) -> Result<bool, RuntimeError> {
+        self.current_frame().loop_depth += 1;
+        let result = framed!(self, self.eval_block(body));
+        self.current_frame().loop_depth -= 1;
+        try!(result);
+        let signal = self.current_frame().loop_signal.take();
+        Ok(signal == Some(stack::LoopSignal::Break) || self.current_frame().should_return)
+    }
+
+    fn eval_loop_signal(&mut self, signal: stack::LoopSignal) -> ResultType {
+        if self.current_frame().loop_depth == 0 {
+            return Err(RuntimeError(format!("{:?} not in a loop", signal)));
+        }
+        self.current_frame().loop_signal = Some(signal);
+        Ok(Value::Nothing)
+    }
+
     fn eval_while_statement(&mut self, condition: &Node, body: &Node) -> ResultType {
         while try!(self.eval(condition)).boolean() {
-            try!(framed!(self, self.eval(body)));
+            if try!(self.eval_loop_body(body)) {
+                break;
+            }
+            // Interactive programs usually run in a while loop, so we need to
+            // dispatch the events here too
+            try!(self.handle_events());
+        }
+        Ok(Value::Nothing)
+    }
+
+    fn eval_for_statement(&mut self, name: &str, start: &Node, end: &Node,
+                          step: &Option<Box<Node>>, body: &Node)
+                          -> ResultType
+    {
+        // The counter is computed with the usual arithmetic, so it stays an
+        // integer if start and step are integers
+        let start = try!(self.eval(start));
+        if start.as_number().is_none() {
+            return Err(RuntimeError("for start has to be a number".to_owned()));
+        }
+        let end = try!(self.eval(end));
+        if end.as_number().is_none() {
+            return Err(RuntimeError("for end has to be a number".to_owned()));
+        }
+        let step = match *step {
+            Some(ref step) => match try!(self.eval(step)) {
+                ref n if n.as_number().map_or(false, |n| n != 0.) => n.clone(),
+                _ => return Err(RuntimeError("for step has to be a nonzero number".to_owned())),
+            },
+            // Count downwards if end is smaller than start
+            None => Value::Integer(if end < start { -1 } else { 1 }),
+        };
+        let upwards = step > Value::Integer(0);
+        // The counter is computed from the number of iterations instead of
+        // adding the step each time, so fractional steps don't add up errors
+        let mut index = 0;
+        let mut counter = start.clone();
+        while (upwards && counter <= end) || (!upwards && counter >= end) {
+            try!(self.check_mutable(name));
+            self.current_frame().locals.insert(name.into(), counter.clone());
+            if try!(self.eval_loop_body(body)) {
+                break;
+            }
+            index += 1;
+            counter = (&Value::Integer(index) * &step).and_then(|n| &start + &n).unwrap();
         }
         Ok(Value::Nothing)
     }
@@ -253,12 +989,136 @@ impl Environment {
         }
     }
 
+    fn eval_macro_statement(&mut self, name: &str, params: &[Parameter], body: &Node)
+                            -> ResultType {
+        let node = Node::LearnStatement(name.to_owned(), params.to_vec(), Box::new(body.clone()));
+        self.current_frame().functions.last_mut().unwrap()
+            .insert(name.to_owned(), Function::Macro(node));
+        Ok(Value::Nothing)
+    }
+
+    fn eval_lambda(&mut self, params: &[Parameter], body: &Node) -> ResultType {
+        let node = Node::LearnStatement("LAMBDA".to_owned(), params.to_vec(),
+                                        Box::new(body.clone()));
+        Ok(Value::Procedure(Rc::new(Procedure {
+            name: "LAMBDA".to_owned(),
+            function: Function::Defined(node),
+            captured: self.capture_locals(),
+        })))
+    }
+
+    /// Return a copy of the current function's local variables. Global
+    /// variables are not captured since they are always reachable.
+    fn capture_locals(&mut self) -> HashMap<String, Value> {
+        if self.current_frame().is_global {
+            HashMap::new()
+        } else {
+            self.current_frame().locals.clone()
+        }
+    }
+
+    /// Return a `Procedure` value for the function with the given name. The
+    /// procedure captures the current local variables.
+    pub fn make_procedure(&mut self, name: &str) -> ResultType {
+        let name = name.to_uppercase();
+        let function = match self.find_function(&name) {
+            Some(f) => f.clone(),
+            None => return Err(RuntimeError(format!("function {} not found", name))),
+        };
+        Ok(Value::Procedure(Rc::new(Procedure {
+            name: name,
+            function: function,
+            captured: self.capture_locals(),
+        })))
+    }
+
+    /// Define a global function from source code. Each parameter is written
+    /// like in a LEARN statement, the colon may be omitted. The body is
+    /// Rurtle source.
+    pub fn define_procedure(&mut self, name: &str, params: &[String], body: &str)
+                            -> ResultType {
+        let params: Vec<String> = params.iter().map(|p| {
+            if p.starts_with(':') || p.starts_with('[') { p.clone() } else { format!(":{}", p) }
+        }).collect();
+        let source = format!("LEARN {} {} DO\n{}\nEND", name, params.join(" "), body);
+        let tree = try!(self.parse_code(&source).map_err(|e| {
+            RuntimeError(format!("invalid definition of {}: {}", name, e))
+        }));
+        let node = match tree {
+            Node::StatementList(mut statements) => {
+                if statements.len() == 1 { statements.pop() } else { None }
+            },
+            node => Some(node),
+        };
+        match node {
+            Some(node @ Node::LearnStatement(..)) => {
+                self.define_function(name, Function::Defined(node));
+                Ok(Value::Nothing)
+            },
+            _ => Err(RuntimeError(format!("invalid definition of {}", name))),
+        }
+    }
+
+    /// Tokenize and parse the given source with the functions that are
+    /// currently defined
+    fn parse_code(&self, source: &str) -> Result<Node, Box<::std::error::Error>> {
+        use super::lex;
+        use super::parse;
+        let tokens = try!(lex::tokenize(source));
+        let mut parser = parse::Parser::new(tokens, self.function_arg_count());
+        Ok(try!(parser.parse()).flatten())
+    }
+
+    /// Return the parameters and the body of the function with the given
+    /// name, in the form `define_procedure` takes
+    pub fn procedure_text(&self, name: &str) -> Result<(Vec<String>, String), RuntimeError> {
+        match self.find_function(&name.to_uppercase()) {
+            Some(&Function::Defined(Node::LearnStatement(_, ref params, ref body))) |
+            Some(&Function::Macro(Node::LearnStatement(_, ref params, ref body))) => {
+                Ok((params.iter().map(|p| p.to_source()).collect(), body.to_source()))
+            },
+            Some(_) => Err(RuntimeError(format!("{} is a built-in function", name))),
+            None => Err(RuntimeError(format!("function {} not found", name))),
+        }
+    }
+
+    /// Call the given procedure with the given (already evaluated) arguments
+    pub fn call_procedure(&mut self, procedure: &Procedure, args: Vec<Value>) -> ResultType {
+        if !procedure.function.arity().accepts(args.len() as i32) {
+            return Err(RuntimeError(format!("wrong number of arguments for {}: {}",
+                                            procedure.name, args.len())));
+        }
+        match procedure.function {
+            Function::Native(_, f) => {
+                try!(self.check_sandbox(&procedure.name, &procedure.function));
+                f(self, &args)
+            },
+            Function::Special(..) | Function::Macro(_) => {
+                Err(RuntimeError(format!("{} can't be called as procedure", procedure.name)))
+            },
+            Function::Defined(ref node) => {
+                match *node {
+                    Node::LearnStatement(ref name, ref params, ref body) => {
+                        self.call_function_with_locals(name, params, args, body,
+                                                       procedure.captured.clone())
+                    },
+                    _ => panic!("Defined function is no LearnStatement"),
+                }
+            },
+        }
+    }
+
+    fn eval_onclick_statement(&mut self, body: &Node) -> ResultType {
+        self.click_handler = Some(body.clone());
+        Ok(Value::Nothing)
+    }
+
     fn eval_try_statement(&mut self, normal: &Node, exception: &Node) -> ResultType {
-        let result = framed!(self, self.eval(normal));
+        let result = framed!(self, self.eval_block(normal));
         match result {
             Ok(_) => Ok(Value::Nothing),
             Err(_) => {
-                framed!(self, self.eval(exception))
+                framed!(self, self.eval_block(exception))
             },
         }
     }
@@ -317,11 +1177,24 @@ impl Environment {
             Some(f) => f.clone(),
             None => return Err(RuntimeError(format!("function {} not found", name))),
         };
+        if !function.arity().accepts(arg_nodes.len() as i32) {
+            return Err(RuntimeError(format!("wrong number of arguments for {}: {}",
+                                            name, arg_nodes.len())));
+        }
+        if let Function::Special(_, f) = function {
+            try!(self.check_sandbox(name, &function));
+            return f(self, arg_nodes);
+        }
+        if let Function::Macro(ref node) = function {
+            return self.expand_macro(node, arg_nodes);
+        }
         let args: Vec<Value> = try!(arg_nodes.iter().map(|a| self.eval(a)).collect());
         match function {
-            Function::Native(_, ref f) => {
+            Function::Native(_, f) => {
+                try!(self.check_sandbox(name, &function));
                 f(self, &args)
             },
+            Function::Special(..) | Function::Macro(_) => unreachable!(),
             Function::Defined(ref node) => {
                 match *node {
                     Node::LearnStatement(ref name, ref arg_names, ref body) =>
@@ -332,17 +1205,73 @@ impl Environment {
         }
     }
 
-    fn call_defined_function(&mut self, name: &str, arg_names: &[String],
+    /// Call the macro with the source of the arguments and run the code it
+    /// returns in place of the call, i.e. in the current frame
+    fn expand_macro(&mut self, node: &Node, arg_nodes: &[Node]) -> ResultType {
+        let (name, params, body) = match *node {
+            Node::LearnStatement(ref name, ref params, ref body) => (name, params, body),
+            _ => panic!("Macro is no LearnStatement"),
+        };
+        let args = arg_nodes.iter().map(|arg| Value::String(macro_argument(arg))).collect();
+        let source = match try!(self.call_defined_function(name, params, args, body)) {
+            Value::String(source) => source,
+            Value::List(lines) => {
+                lines.iter().map(|l| l.to_string()).collect::<Vec<_>>().join("\n")
+            },
+            Value::Nothing => return Ok(Value::Nothing),
+            other => return Err(RuntimeError(format!("macro {} must return code, not {}",
+                                                     name, other.type_string()))),
+        };
+        let tree = try!(self.parse_code(&source).map_err(|e| {
+            RuntimeError(format!("invalid code from macro {}: {}", name, e))
+        }));
+        self.eval_block(&tree)
+    }
+
+    /// Return an error if the built-in function is forbidden in sandbox mode.
+    /// Functions defined in Rurtle are always allowed, they can only call
+    /// the built-in functions, which are checked themselves.
+    fn check_sandbox(&self, name: &str, function: &Function) -> Result<(), RuntimeError> {
+        let name = name.to_uppercase();
+        match *function {
+            Function::Native(..) | Function::Special(..) => {
+                if self.sandbox && functions::is_restricted(&name) {
+                    return Err(RuntimeError(format!("{} is not allowed in sandbox mode", name)));
+                }
+                Ok(())
+            },
+            Function::Defined(_) | Function::Macro(_) => Ok(()),
+        }
+    }
+
+    fn call_defined_function(&mut self, name: &str, params: &[Parameter],
                              args: Vec<Value>, body: &Node)
                              -> ResultType
     {
+        self.call_function_with_locals(name, params, args, body, HashMap::new())
+    }
+
+    /// Call a defined function in a new frame which starts with the given
+    /// local variables
+    fn call_function_with_locals(&mut self, name: &str, params: &[Parameter],
+                                 args: Vec<Value>, body: &Node,
+                                 locals: HashMap<String, Value>)
+                                 -> ResultType
+    {
+        if let Some(max) = self.limits.call_depth {
+            // The global frame doesn't count
+            if self.stack.len() > max {
+                return Err(RuntimeError(format!("call depth limit exceeded ({})", max)));
+            }
+        }
         let mut frame = stack::Frame::default();
         frame.fn_name = name.into();
-        for (name, value) in arg_names.iter().zip(args) {
-            frame.locals.insert(name.clone(), value);
-        }
+        frame.locals = locals;
         self.stack.push(frame);
-        let result = self.eval(body);
+        let result = match self.bind_parameters(params, args) {
+            Ok(_) => self.eval_block(body),
+            Err(e) => Err(e),
+        };
         frame = self.stack.pop().unwrap();
         try!(result);
         match frame.return_value {
@@ -351,6 +1280,27 @@ impl Environment {
         }
     }
 
+    /// Bind the given arguments to the parameters in the current frame.
+    /// Default values are evaluated in the current frame too, so they may
+    /// refer to earlier parameters.
+    fn bind_parameters(&mut self, params: &[Parameter], args: Vec<Value>) -> ResultType {
+        let mut args = args.into_iter();
+        for param in params {
+            let value = match *param {
+                Parameter::Required(_) => args.next().expect("arity was checked before"),
+                Parameter::Optional(_, ref default) => {
+                    match args.next() {
+                        Some(value) => value,
+                        None => try!(self.eval(default)),
+                    }
+                },
+                Parameter::Rest(_) => Value::List(args.by_ref().collect()),
+            };
+            self.current_frame().locals.insert(param.name().to_owned(), value);
+        }
+        Ok(Value::Nothing)
+    }
+
     fn eval_return_statement(&mut self, value: &Node) -> ResultType {
         if self.current_frame().is_global {
             return Err(RuntimeError("Return not in a function".to_owned()));
@@ -363,7 +1313,7 @@ impl Environment {
 
     fn eval_assignment(&mut self, name: &str, value: &Node) -> ResultType {
         let value = try!(self.eval(value));
-        self.current_frame().locals.insert(name.into(), value.clone());
+        try!(self.set_variable(name, value.clone()));
         Ok(value)
     }
 
@@ -382,6 +1332,12 @@ impl Environment {
         }
     }
 
+    /// Return the number of the current iteration of the innermost running
+    /// `repeat`-loop, starting at 1. Returns `None` if no loop is running.
+    pub fn repcount(&self) -> Option<i32> {
+        self.repcounts.last().cloned()
+    }
+
     /// Return the current stack frame or the global frame if the code is not
     /// executing in a function
     pub fn current_frame(&mut self) -> &mut stack::Frame {
@@ -404,6 +1360,23 @@ impl Environment {
         self.current_frame().functions.pop().unwrap();
     }
 
+    /// Set the variable with the given name to the given value
+    ///
+    /// If the variable exists in the current function, it is changed there.
+    /// Otherwise, if a global variable with that name exists, the global
+    /// variable is changed. If the variable doesn't exist at all, it is created
+    /// in the current function. Constants can't be changed.
+    pub fn set_variable(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+        try!(self.check_mutable(name));
+        if !self.current_frame().locals.contains_key(name) &&
+            self.global_frame().locals.contains_key(name) {
+            self.global_frame().locals.insert(name.to_owned(), value);
+        } else {
+            self.current_frame().locals.insert(name.to_owned(), value);
+        }
+        Ok(())
+    }
+
     /// Retrieve the value for the variable with the given name
     ///
     /// The variable is searched in the current function's local variables. If
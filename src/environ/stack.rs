@@ -4,6 +4,15 @@ use super::functions;
 use std::collections::HashMap;
 use std::default::Default;
 
+/// Signals to leave a loop early
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopSignal {
+    /// Leave the innermost loop
+    Break,
+    /// Skip the rest of the current iteration of the innermost loop
+    Continue,
+}
+
 /// A `Frame` contains information about the current function.
 ///
 /// A new `Frame` is constructed each time you enter a function
@@ -22,6 +31,10 @@ pub struct Frame {
     pub should_return: bool,
     /// Value that the current function should return (if any)
     pub return_value: Option<Value>,
+    /// If this is set, the innermost loop should be left or continued
+    pub loop_signal: Option<LoopSignal>,
+    /// Number of loops that are currently running in this function
+    pub loop_depth: u32,
     /// Name of the function
     pub fn_name: String,
     /// Flag indicating if this frame is the global frame
@@ -35,6 +48,8 @@ impl Default for Frame {
             functions: vec![HashMap::new()],
             should_return: false,
             return_value: None,
+            loop_signal: None,
+            loop_depth: 0,
             fn_name: String::new(),
             is_global: false,
         }
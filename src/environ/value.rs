@@ -1,9 +1,25 @@
 //! The Rurtle type/value system
 //!
-//! Rurtle is dynamically typed and has 4 different types of values:
+//! Rurtle is dynamically typed and has 5 different types of values:
 //!
-//! `Number`: Rurtle doesn't differentiate between integers and floats, there is
-//! just a single number type. Internally numbers are represented by floats.
+//! `Number`: Numbers are either integers (`Integer`, written without decimal
+//! point like `3`) or floats (`Number`, like `3.5`). Both have the type
+//! "number" and can be mixed freely: integers are promoted to floats when they
+//! meet a float, when a division has a remainder and when an operation
+//! overflows. Everything else stays exact, so loop counters and indices don't
+//! suffer from rounding errors. Built-in functions that expect a float accept
+//! integers too, functions that expect an index or count only accept integers.
+//!
+//! Float arithmetic follows the float rules: `1 / 0` is infinity and `0 / 0` is
+//! NaN ("not a number"), which is unequal to everything including itself, see
+//! `ISNAN`. Such values can be computed and printed, but the turtle refuses to
+//! move to a non-finite coordinate and strings and lists can't be repeated a
+//! non-finite number of times.
+//!
+//! With the `bignum` feature, integer operations that overflow continue with
+//! arbitrary precision integers instead, so `FACT 30` stays exact. Integer
+//! literals are still limited to 64 bit, use `TONUMBER "<digits>"` for bigger
+//! ones.
 //!
 //! `String`: A chain of characters, also known as a text. Like Python, Rurtle
 //! has no special datatype for a single character. A `String` of length 1 may
@@ -15,15 +31,64 @@
 //!
 //! `Nothing`: Something like Python's `None`, this is the default value for
 //! everything that doesn't explicitely return something else.
+//!
+//! `Procedure`: A function that can be passed around like any other value, e.g.
+//! created by `LAMBDA`. A procedure remembers the local variables of the
+//! function in which it was created.
+use super::Function;
+#[cfg(feature = "bignum")]
+use super::bignum;
+use super::super::lex::{quote, number_literal, integer_literal};
+use super::super::parse::ast::Node;
+#[cfg(feature = "bignum")]
+use num::BigInt;
+#[cfg(feature = "bignum")]
+use num::traits::Zero;
+use rustc_serialize::json::Json;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::ops;
 use std::fmt;
+use std::rc::Rc;
 /// Enum combining the possible Rurtle value types
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Nothing,
     Number(f32),
+    /// A number without fraction, see the module documentation
+    Integer(i64),
+    /// An integer outside of the `i64` range, only with the `bignum` feature
+    #[cfg(feature = "bignum")]
+    BigInteger(BigInt),
     String(String),
     List(Vec<Value>),
+    Procedure(Rc<Procedure>),
+}
+
+/// A first-class function together with the variables it captured
+#[derive(Debug)]
+pub struct Procedure {
+    /// Name of the procedure, used for display purposes
+    pub name: String,
+    /// The function that is called
+    pub function: Function,
+    /// Local variables of the frame in which the procedure was created. They
+    /// are copied when the procedure is created.
+    pub captured: HashMap<String, Value>,
+}
+
+/// Procedures are only equal to themselves
+impl PartialEq for Procedure {
+    fn eq(&self, other: &Procedure) -> bool {
+        self as *const Procedure == other as *const Procedure
+    }
+}
+
+/// Procedures can't be ordered
+impl PartialOrd for Procedure {
+    fn partial_cmp(&self, other: &Procedure) -> Option<Ordering> {
+        if self == other { Some(Ordering::Equal) } else { None }
+    }
 }
 
 impl Value {
@@ -36,6 +101,9 @@ impl Value {
     pub fn boolean(&self) -> bool {
         match *self {
             Value::Number(f) => f != 0.0,
+            Value::Integer(n) => n != 0,
+            #[cfg(feature = "bignum")]
+            Value::BigInteger(_) => true,
             Value::String(ref s) => !s.is_empty(),
             Value::List(ref l) => !l.is_empty(),
             _ => false,
@@ -45,18 +113,216 @@ impl Value {
     /// Return the stringified type of the value
     pub fn type_string(&self) -> &'static str {
         match *self {
-            Value::Number(_) => "number",
+            Value::Number(_) | Value::Integer(_) => "number",
+            #[cfg(feature = "bignum")]
+            Value::BigInteger(_) => "number",
             Value::String(_) => "string",
             Value::List(_) => "list",
             Value::Nothing => "nothing",
+            Value::Procedure(_) => "procedure",
+        }
+    }
+
+    /// Return Rurtle source code that evaluates to this value. Procedures
+    /// created by `LAMBDA` lose the variables they captured.
+    pub fn to_source(&self) -> String {
+        match *self {
+            Value::Nothing => "(NOTHING)".to_owned(),
+            Value::Number(x) => number_literal(x),
+            Value::Integer(n) => integer_literal(n),
+            #[cfg(feature = "bignum")]
+            Value::BigInteger(ref n) => format!("(TONUMBER {})", quote(&n.to_string())),
+            Value::String(ref s) => quote(s),
+            Value::List(ref l) => {
+                let elements: Vec<String> = l.iter().map(|v| v.to_source()).collect();
+                format!("[{}]", elements.join(" "))
+            },
+            Value::Procedure(ref p) => match p.function {
+                Function::Defined(Node::LearnStatement(ref name, ref params, ref body))
+                    if name == "LAMBDA" =>
+                {
+                    Node::Lambda(params.clone(), body.clone()).to_source()
+                },
+                _ => format!("(FUNC {})", quote(&p.name)),
+            },
+        }
+    }
+
+    /// Convert the value to JSON. Lists always become arrays and `Nothing`
+    /// becomes `null`. Procedures can't be converted and return `None`.
+    pub fn to_json(&self) -> Option<Json> {
+        match *self {
+            Value::Nothing => Some(Json::Null),
+            Value::Number(n) => Some(Json::F64(n as f64)),
+            Value::Integer(n) => Some(Json::I64(n)),
+            #[cfg(feature = "bignum")]
+            Value::BigInteger(ref n) => Some(Json::F64(bignum::to_float(n) as f64)),
+            Value::String(ref s) => Some(Json::String(s.clone())),
+            Value::List(ref l) => {
+                let items: Option<Vec<Json>> = l.iter().map(|v| v.to_json()).collect();
+                items.map(Json::Array)
+            },
+            Value::Procedure(_) => None,
+        }
+    }
+
+    /// Return the number as float, no matter if it is an integer or a float.
+    /// Returns `None` for all other values.
+    pub fn as_number(&self) -> Option<f32> {
+        match *self {
+            Value::Number(x) => Some(x),
+            Value::Integer(n) => Some(n as f32),
+            #[cfg(feature = "bignum")]
+            Value::BigInteger(ref n) => Some(bignum::to_float(n)),
+            _ => None,
+        }
+    }
+
+    /// Return the integer, `None` for floats, integers that don't fit into an
+    /// `i64` and all other values
+    pub fn as_integer(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Values of different types are ordered by type
+    fn type_rank(&self) -> u8 {
+        match *self {
+            Value::Nothing => 0,
+            Value::Number(_) | Value::Integer(_) => 1,
+            #[cfg(feature = "bignum")]
+            Value::BigInteger(_) => 1,
+            Value::String(_) => 2,
+            Value::List(_) => 3,
+            Value::Procedure(_) => 4,
+        }
+    }
+}
+
+/// Integers and floats are equal if they have the same value
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (&Value::Nothing, &Value::Nothing) => Some(Ordering::Equal),
+            (&Value::Integer(a), &Value::Integer(b)) => Some(a.cmp(&b)),
+            // f64 holds every f32 and (almost) every i64 exactly
+            (&Value::Number(a), &Value::Number(b)) => a.partial_cmp(&b),
+            (&Value::Number(a), &Value::Integer(b)) => (a as f64).partial_cmp(&(b as f64)),
+            (&Value::Integer(a), &Value::Number(b)) => (a as f64).partial_cmp(&(b as f64)),
+            #[cfg(feature = "bignum")]
+            (a, b) if a.type_rank() == 1 && b.type_rank() == 1 => bignum::compare(a, b),
+            (&Value::String(ref a), &Value::String(ref b)) => a.partial_cmp(b),
+            (&Value::List(ref a), &Value::List(ref b)) => a.partial_cmp(b),
+            (&Value::Procedure(ref a), &Value::Procedure(ref b)) => a.partial_cmp(b),
+            (a, b) => a.type_rank().partial_cmp(&b.type_rank()),
+        }
+    }
+}
+
+/// The arithmetic operations that are defined for numbers
+#[derive(Clone, Copy)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Operator {
+    /// Apply the operation to two integers, `None` if the result isn't an
+    /// integer or doesn't fit into an `i64`
+    fn integers(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Operator::Add => a.checked_add(b),
+            Operator::Sub => a.checked_sub(b),
+            Operator::Mul => a.checked_mul(b),
+            Operator::Div => match a.checked_rem(b) {
+                Some(0) => a.checked_div(b),
+                _ => None,
+            },
+        }
+    }
+
+    fn floats(self, a: f32, b: f32) -> f32 {
+        match self {
+            Operator::Add => a + b,
+            Operator::Sub => a - b,
+            Operator::Mul => a * b,
+            Operator::Div => a / b,
+        }
+    }
+
+    /// Apply the operation to two integers of any size, `None` if one of the
+    /// values is no integer or the division has a remainder
+    #[cfg(feature = "bignum")]
+    fn big_integers(self, a: &Value, b: &Value) -> Option<Value> {
+        let (a, b) = match (bignum::to_big(a), bignum::to_big(b)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return None,
+        };
+        let result = match self {
+            Operator::Add => a + b,
+            Operator::Sub => a - b,
+            Operator::Mul => a * b,
+            Operator::Div => {
+                if b.is_zero() || !(&a % &b).is_zero() {
+                    return None;
+                }
+                a / b
+            },
+        };
+        Some(bignum::to_value(result))
+    }
+
+    /// Without the `bignum` feature, overflowing integers become floats
+    #[cfg(not(feature = "bignum"))]
+    fn big_integers(self, _: &Value, _: &Value) -> Option<Value> {
+        None
+    }
+}
+
+/// Apply an arithmetic operation to two numbers. Two integers give an integer
+/// unless the result has a fraction or overflows, everything else gives a
+/// float. Returns `None` if one of the values is no number.
+fn arithmetic(a: &Value, b: &Value, op: Operator) -> Option<Value> {
+    if let (&Value::Integer(x), &Value::Integer(y)) = (a, b) {
+        if let Some(n) = op.integers(x, y) {
+            return Some(Value::Integer(n));
         }
     }
+    if let Some(result) = op.big_integers(a, b) {
+        return Some(result);
+    }
+    match (a.as_number(), b.as_number()) {
+        (Some(a), Some(b)) => Some(Value::Number(op.floats(a, b))),
+        _ => None,
+    }
+}
+
+/// Return how often a string or list is repeated when multiplied by `value`
+fn repetitions(value: &Value) -> Option<i64> {
+    match *value {
+        Value::Number(n) if n.is_finite() => Some(n as i64),
+        Value::Integer(n) => Some(n),
+        _ => None,
+    }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match *self {
             Value::Number(x) => x.fmt(fmt),
+            Value::Integer(n) => n.fmt(fmt),
+            #[cfg(feature = "bignum")]
+            Value::BigInteger(ref n) => n.fmt(fmt),
             Value::String(ref s) => s.fmt(fmt),
             Value::List(ref l) => {
                 try!(fmt.pad("["));
@@ -69,6 +335,7 @@ impl fmt::Display for Value {
                 fmt.pad("]")
             },
             Value::Nothing => fmt.pad("Nothing"),
+            Value::Procedure(ref p) => fmt.pad(&format!("<procedure {}>", p.name)),
         }
     }
 }
@@ -87,17 +354,17 @@ impl<'a> ops::Add for &'a Value {
     /// May return None if the types can not be added.
     fn add(self, other: &Value) -> Option<Value> {
         match *self {
-            Value::Number(a) => {
-                match *other {
-                    Value::Number(b) => Some(Value::Number(a + b)),
-                    _ => None,
-                }
-            },
+            Value::Number(_) | Value::Integer(_) => arithmetic(self, other, Operator::Add),
+            #[cfg(feature = "bignum")]
+            Value::BigInteger(_) => arithmetic(self, other, Operator::Add),
 
             Value::String(ref a) => {
                 match *other {
                     Value::String(ref b) => Some(Value::String(format!("{}{}", a, b))),
                     Value::Number(b) => Some(Value::String(format!("{}{}", a, b))),
+                    Value::Integer(b) => Some(Value::String(format!("{}{}", a, b))),
+                    #[cfg(feature = "bignum")]
+                    Value::BigInteger(ref b) => Some(Value::String(format!("{}{}", a, b))),
                     _ => None,
                 }
             },
@@ -124,16 +391,7 @@ impl<'a> ops::Sub for &'a Value {
     /// meaningful for a `Number` pair. Every other combination will return
     /// `None`
     fn sub(self, other: &Value) -> Option<Value> {
-        match *self {
-            Value::Number(a) => {
-                match *other {
-                    Value::Number(b) => Some(Value::Number(a - b)),
-                    _ => None,
-                }
-            },
-
-            _ => None,
-        }
+        arithmetic(self, other, Operator::Sub)
     }
 }
 
@@ -144,41 +402,34 @@ impl<'a> ops::Mul for &'a Value {
     /// * Number * Number = Number: normal multiplication
     /// * String * Number = String: replicate the String n times
     /// * List * Number = List: replicate the List n times
+    ///
+    /// Replicating by NaN or infinity returns `None`.
     fn mul(self, other: &Value) -> Option<Value> {
         match *self {
-            Value::Number(a) => {
-                match *other {
-                    Value::Number(b) => Some(Value::Number(a * b)),
-                    _ => None,
-                }
-            },
+            Value::Number(_) | Value::Integer(_) => arithmetic(self, other, Operator::Mul),
+            #[cfg(feature = "bignum")]
+            Value::BigInteger(_) => arithmetic(self, other, Operator::Mul),
 
             Value::String(ref a) => {
-                match *other {
-                    Value::Number(b) => Some(Value::String({
-                        let mut temp = String::new();
-                        for _ in 0..b as i32 {
-                            temp.push_str(a);
-                        }
-                        temp
-                    })),
-                    _ => None,
-                }
+                repetitions(other).map(|n| Value::String({
+                    let mut temp = String::new();
+                    for _ in 0..n {
+                        temp.push_str(a);
+                    }
+                    temp
+                }))
             },
 
             Value::List(ref a) => {
-                match *other {
-                    Value::Number(b) => Some(Value::List({
-                        let mut temp = Vec::new();
-                        for _ in 0..b as i32 {
-                            for elem in a.iter() {
-                                temp.push(elem.clone());
-                            }
+                repetitions(other).map(|n| Value::List({
+                    let mut temp = Vec::new();
+                    for _ in 0..n {
+                        for elem in a.iter() {
+                            temp.push(elem.clone());
                         }
-                        temp
-                    })),
-                    _ => None,
-                }
+                    }
+                    temp
+                }))
             },
 
             _ => None,
@@ -188,17 +439,93 @@ impl<'a> ops::Mul for &'a Value {
 
 impl<'a> ops::Div for &'a Value {
     type Output = Option<Value>;
-    /// Divide one value by another value. Only defined for a pair of `Number`s
+    /// Divide one value by another value. Only defined for a pair of `Number`s.
+    /// Dividing two integers gives an integer if there is no remainder.
     fn div(self, other: &Value) -> Option<Value> {
-        match *self {
-            Value::Number(a) => {
-                match *other {
-                    Value::Number(b) => Some(Value::Number(a / b)),
-                    _ => None,
-                }
-            },
+        arithmetic(self, other, Operator::Div)
+    }
+}
 
-            _ => None,
+#[cfg(test)]
+mod tests {
+    use super::{arithmetic, Operator, Value};
+    use super::super::RuntimeError;
+
+    fn integer(value: Option<Value>) -> i64 {
+        match value {
+            Some(Value::Integer(n)) => n,
+            other => panic!("expected an integer, got {:?}", other),
+        }
+    }
+
+    fn float(value: Option<Value>) -> f32 {
+        match value {
+            Some(Value::Number(x)) => x,
+            other => panic!("expected a float, got {:?}", other),
         }
     }
+
+    #[test]
+    fn integers_stay_exact() {
+        assert_eq!(Operator::Add.integers(2, 3), Some(5));
+        assert_eq!(Operator::Div.integers(8, 2), Some(4));
+        assert_eq!(integer(arithmetic(&Value::Integer(6), &Value::Integer(7), Operator::Mul)),
+                   42);
+    }
+
+    #[test]
+    fn division_with_remainder_gives_float() {
+        assert_eq!(Operator::Div.integers(7, 2), None);
+        assert_eq!(float(&Value::Integer(7) / &Value::Integer(2)), 3.5);
+    }
+
+    #[test]
+    fn mixed_operands_give_float() {
+        assert_eq!(float(&Value::Integer(1) + &Value::Number(0.5)), 1.5);
+        assert_eq!(float(&Value::Number(2.) * &Value::Integer(3)), 6.);
+    }
+
+    #[test]
+    fn overflow_is_detected() {
+        assert_eq!(Operator::Add.integers(::std::i64::MAX, 1), None);
+        assert_eq!(Operator::Mul.integers(::std::i64::MIN, -1), None);
+        assert_eq!(Operator::Div.integers(::std::i64::MIN, -1), None);
+        assert_eq!(Operator::Div.integers(1, 0), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "bignum"))]
+    fn overflow_falls_back_to_float() {
+        let max = Value::Integer(::std::i64::MAX);
+        assert_eq!(float(&max + &Value::Integer(1)), ::std::i64::MAX as f32 + 1.);
+        let min = Value::Integer(::std::i64::MIN);
+        assert_eq!(float(&min / &Value::Integer(-1)), -(::std::i64::MIN as f32));
+    }
+
+    #[test]
+    fn division_by_zero_follows_float_rules() {
+        assert_eq!(float(&Value::Integer(1) / &Value::Integer(0)), ::std::f32::INFINITY);
+        assert!(float(&Value::Integer(0) / &Value::Integer(0)).is_nan());
+    }
+
+    fn half(args: &[Value]) -> Result<Value, RuntimeError> {
+        get_args!(args, arg Value::Number(x), => Ok(Value::Number(x / 2.)))
+    }
+
+    fn count(args: &[Value]) -> Result<Value, RuntimeError> {
+        get_args!(args, arg Value::Integer(n), => Ok(Value::Integer(n)))
+    }
+
+    #[test]
+    fn get_args_promotes_integers_to_floats() {
+        assert_eq!(float(half(&[Value::Integer(7)]).ok()), 3.5);
+        assert_eq!(float(half(&[Value::Number(7.)]).ok()), 3.5);
+        assert!(half(&[Value::String("7".to_owned())]).is_err());
+    }
+
+    #[test]
+    fn get_args_keeps_integers_for_integer_parameters() {
+        assert_eq!(integer(count(&[Value::Integer(7)]).ok()), 7);
+        assert!(count(&[Value::Number(7.5)]).is_err());
+    }
 }
@@ -0,0 +1,92 @@
+//! Saving and restoring a Rurtle session.
+//!
+//! A workspace file is a Rurtle program that recreates the state of a session:
+//! the user defined functions, the global variables and constants, the click
+//! handler and the drawing (as replay of the turtle's command log, see the
+//! `replay` module). Loading a workspace resets the turtle and runs the
+//! program.
+//!
+//! Procedures created by `LAMBDA` lose the variables they captured when they
+//! are saved.
+use super::{Environment, Function, PREDEFINED_CONSTANTS};
+use super::super::lex::quote;
+use super::super::parse::ast::Node;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Write};
+
+/// Write the workspace of the given environment to the file at `path`
+pub fn save(env: &Environment, path: &str) -> io::Result<()> {
+    let mut file = io::BufWriter::new(try!(fs::File::create(path)));
+    try!(writeln!(file, "; Rurtle workspace"));
+    let global = &env.stack[0];
+
+    let mut definitions = Vec::new();
+    for scope in &global.functions {
+        for function in scope.values() {
+            match *function {
+                Function::Defined(ref node) => definitions.push(node.clone()),
+                // Macros are stored as LearnStatement too
+                Function::Macro(Node::LearnStatement(ref name, ref params, ref body)) => {
+                    definitions.push(Node::MacroStatement(name.clone(), params.clone(),
+                                                          body.clone()));
+                },
+                _ => (),
+            }
+        }
+    }
+    definitions.sort_by_key(|node| match *node {
+        Node::LearnStatement(ref name, _, _) | Node::MacroStatement(ref name, _, _) => {
+            name.clone()
+        },
+        _ => String::new(),
+    });
+    // The parser needs to know every function before it is called, so we first
+    // write empty definitions. This way, the order of the real definitions
+    // doesn't matter.
+    for node in &definitions {
+        let empty = Box::new(Node::StatementList(Vec::new()));
+        let stub = match *node {
+            Node::LearnStatement(ref name, ref params, _) => {
+                Node::LearnStatement(name.clone(), params.clone(), empty)
+            },
+            Node::MacroStatement(ref name, ref params, _) => {
+                Node::MacroStatement(name.clone(), params.clone(), empty)
+            },
+            _ => continue,
+        };
+        try!(writeln!(file, "{}", stub.to_source()));
+    }
+    for node in &definitions {
+        try!(writeln!(file, "{}", node.to_source()));
+    }
+
+    let mut names: Vec<&String> = global.locals.keys().collect();
+    names.sort();
+    for name in names {
+        if PREDEFINED_CONSTANTS.iter().any(|&(constant, _)| constant == name) {
+            continue;
+        }
+        let function = if env.constants.contains(name) { "CONSTANT" } else { "GLOBAL" };
+        try!(writeln!(file, "{} {} {}", function, quote(name), global.locals[name].to_source()));
+    }
+
+    if let Some(ref handler) = env.click_handler {
+        try!(writeln!(file, "{}", Node::OnClickStatement(Box::new(handler.clone())).to_source()));
+    }
+
+    for command in env.turtle.get_log() {
+        try!(writeln!(file, "{}", command.to_source()));
+    }
+    Ok(())
+}
+
+/// Load the workspace file at `path` into the given environment. The turtle
+/// is reset before.
+pub fn load(env: &mut Environment, path: &str) -> Result<(), Box<Error>> {
+    let mut source = String::new();
+    try!(fs::File::open(path).and_then(|mut f| f.read_to_string(&mut source)));
+    env.turtle.reset();
+    try!(env.eval_source(&source));
+    Ok(())
+}
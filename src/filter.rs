@@ -0,0 +1,128 @@
+//! Filters that post-process the finished drawing pixel by pixel.
+//!
+//! The drawing is rendered into an image, run through the filter and then
+//! replaces the drawing, see `Turtle::apply_filter`. The functions here only
+//! work on images, so they can be used on screenshots as well:
+//!
+//! ```
+//! # extern crate image;
+//! # extern crate rurtle;
+//! # fn main() {
+//! use rurtle::filter::Filter;
+//! let image = image::RgbaImage::from_pixel(4, 4, image::Rgba { data: [255, 0, 0, 255] });
+//! let gray = Filter::Grayscale.apply(&image);
+//! assert_eq!(gray.get_pixel(0, 0).data, [76, 76, 76, 255]);
+//! # }
+//! ```
+use image::{Rgba, RgbaImage};
+
+/// A filter for the rendered drawing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// Replace every color by its brightness
+    Grayscale,
+    /// Replace every color by its complement, white becomes black
+    Invert,
+    /// Brownish tones like an old photograph
+    Sepia,
+    /// Replace every pixel by the average of the pixels around it, up to the
+    /// given distance in pixels (a box blur)
+    Blur(u32),
+}
+
+/// The names that `Filter::from_name` accepts
+pub const FILTER_NAMES: [&'static str; 3] = ["grayscale", "invert", "sepia"];
+
+impl Filter {
+    /// Return the filter with the given name, ignoring case. Blurring needs a
+    /// radius and has no name.
+    pub fn from_name(name: &str) -> Option<Filter> {
+        match &*name.to_lowercase() {
+            "grayscale" => Some(Filter::Grayscale),
+            "invert" => Some(Filter::Invert),
+            "sepia" => Some(Filter::Sepia),
+            _ => None,
+        }
+    }
+
+    /// Return the name of the filter, `None` for `Blur`
+    pub fn name(&self) -> Option<&'static str> {
+        match *self {
+            Filter::Grayscale => Some("grayscale"),
+            Filter::Invert => Some("invert"),
+            Filter::Sepia => Some("sepia"),
+            Filter::Blur(_) => None,
+        }
+    }
+
+    /// Return the filtered image. The transparency of the pixels is kept.
+    pub fn apply(&self, image: &RgbaImage) -> RgbaImage {
+        match *self {
+            Filter::Grayscale => map_colors(image, |r, g, b| {
+                let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+                (luma, luma, luma)
+            }),
+            Filter::Invert => map_colors(image, |r, g, b| (255. - r, 255. - g, 255. - b)),
+            Filter::Sepia => map_colors(image, |r, g, b| {
+                (0.393 * r + 0.769 * g + 0.189 * b,
+                 0.349 * r + 0.686 * g + 0.168 * b,
+                 0.272 * r + 0.534 * g + 0.131 * b)
+            }),
+            Filter::Blur(radius) => blur(image, radius as usize),
+        }
+    }
+}
+
+/// Apply `f` to the red, green and blue values (between 0 and 255) of every
+/// pixel. Results outside of that range are clamped.
+fn map_colors<F>(image: &RgbaImage, f: F) -> RgbaImage
+    where F: Fn(f32, f32, f32) -> (f32, f32, f32)
+{
+    let mut result = image.clone();
+    for pixel in result.pixels_mut() {
+        let (r, g, b) = f(pixel.data[0] as f32, pixel.data[1] as f32, pixel.data[2] as f32);
+        let clamp = |c: f32| c.max(0.).min(255.).round() as u8;
+        pixel.data = [clamp(r), clamp(g), clamp(b), pixel.data[3]];
+    }
+    result
+}
+
+/// Blur the image horizontally, then vertically. Near the border, only the
+/// pixels inside the image are averaged.
+fn blur(image: &RgbaImage, radius: usize) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let mut result = image.clone();
+    for y in 0..height {
+        let row: Vec<Rgba<u8>> = (0..width).map(|x| *result.get_pixel(x, y)).collect();
+        for (x, pixel) in blur_line(&row, radius).into_iter().enumerate() {
+            result.put_pixel(x as u32, y, pixel);
+        }
+    }
+    for x in 0..width {
+        let column: Vec<Rgba<u8>> = (0..height).map(|y| *result.get_pixel(x, y)).collect();
+        for (y, pixel) in blur_line(&column, radius).into_iter().enumerate() {
+            result.put_pixel(x, y as u32, pixel);
+        }
+    }
+    result
+}
+
+/// Blur a single row or column of pixels with running sums, so the radius
+/// doesn't affect the speed
+fn blur_line(line: &[Rgba<u8>], radius: usize) -> Vec<Rgba<u8>> {
+    let mut sums = vec![[0u32; 4]; line.len() + 1];
+    for (i, pixel) in line.iter().enumerate() {
+        for c in 0..4 {
+            sums[i + 1][c] = sums[i][c] + pixel.data[c] as u32;
+        }
+    }
+    (0..line.len()).map(|i| {
+        let (start, end) = (i.saturating_sub(radius), (i + radius + 1).min(line.len()));
+        let count = (end - start) as u32;
+        let mut data = [0; 4];
+        for c in 0..4 {
+            data[c] = ((sums[end][c] - sums[start][c]) / count) as u8;
+        }
+        Rgba { data: data }
+    }).collect()
+}
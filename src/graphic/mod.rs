@@ -0,0 +1,168 @@
+//! This is the backend for turtle graphics. It's a wrapper around glium,
+//! dealing with shaders etc. and providing a few high-level methods upon which
+//! turtles can be built.
+//!
+//! # The Coordinate grid
+//!
+//! Unlike in other graphic libraries, the origin (0, 0) is in the middle of the
+//! whole canvas. Positive x/y coordinates go right/up and negative ones
+//! left/down. The canvas does have as many pixels as the window size, thus it
+//! is possible to increase the section shown by resizing the window. Scrolling
+//! and zooming are currently not supported.
+//!
+//! On high-DPI displays, one unit in turtle coordinates corresponds to one
+//! logical pixel, not one device pixel. The canvas is still rendered at the
+//! native resolution, so a script produces the same picture regardless of the
+//! display's scale factor.
+//!
+//! # Drawing and events
+//!
+//! To stay flexible, `TurtleScreen` has no built-in event loop. To redraw the
+//! screen, use the `draw_and_update`-function. To handle events such as mouse
+//! clicks, use `handle_events`.
+//!
+//! # Headless mode
+//!
+//! If `ScreenOptions::headless` is set, no window is opened and everything is
+//! rendered offscreen. `draw_and_update` and `handle_events` do nothing in
+//! this mode, use `render_image` or `screenshot` to get the result.
+//!
+//! # Example
+//!
+//! ```
+//! # use rurtle::graphic::{TurtleScreen, color};
+//! let mut screen = TurtleScreen::new((640, 480), "Rurtle");
+//! screen.add_line((0.0, 0.0), (50.0, 50.0), color::BLACK);
+//! screen.turtle_position = (50.0, 50.0);
+//! screen.turtle_orientation = 315.0;
+//! screen.draw_and_update();
+//! ```
+//!
+//! # Features
+//!
+//! `TurtleScreen` needs OpenGL and is only available with the `native` feature,
+//! which is on by default. The colors and the embedded images and fonts are
+//! always available, so the other backends can be used without it.
+#[cfg(feature = "native")]
+mod screen;
+#[cfg(feature = "native")]
+pub use self::screen::{ScreenOptions, TurtleScreen};
+
+/// Ferris image bytes
+pub const FERRIS_BYTES: &'static [u8] = include_bytes!("../ferris.png");
+/// The font used to write texts
+pub const FONT_DATA: &'static [u8] = include_bytes!("../dejavusansmono.ttf");
+// FERRIS_WIDTH and FERRIS_HEIGHT specifiy the size in which Ferris should be
+// drawn. The aspect ratio should be kept, the original Ferris image has a ratio
+// of w:h 3:2
+pub const FERRIS_WIDTH: f32 = 36.;
+pub const FERRIS_HEIGHT: f32 = 24.;
+
+/// Module for color aliases
+pub mod color {
+    /// Alias for a 4-f32 tuple, representing the colors as RGB values and the alpha
+    /// channel
+    pub type Color = (f32, f32, f32, f32);
+    pub const BLACK: Color = (0.0, 0.0, 0.0, 1.0);
+    pub const WHITE: Color = (1.0, 1.0, 1.0, 1.0);
+    pub const RED: Color = (1.0, 0.0, 0.0, 1.0);
+    pub const GREEN: Color = (0.0, 1.0, 0.0, 1.0);
+    pub const BLUE: Color = (0.0, 0.0, 1.0, 1.0);
+
+    /// Convert a Color (4-tuple of f32) to a color array ([f32; 4]). Useful for
+    /// sending it to shaders.
+    #[inline]
+    pub fn to_array(color: Color) -> [f32; 4] {
+        [color.0, color.1, color.2, color.3]
+    }
+
+    /// A sequence of colors to pick from by a number between 0 and 1. Colors
+    /// between the given ones are interpolated linearly.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Palette {
+        colors: Vec<Color>,
+    }
+
+    /// Samples of the viridis color map
+    const VIRIDIS: [Color; 9] = [
+        (0.267, 0.005, 0.329, 1.0),
+        (0.283, 0.141, 0.458, 1.0),
+        (0.254, 0.265, 0.530, 1.0),
+        (0.207, 0.372, 0.553, 1.0),
+        (0.164, 0.471, 0.558, 1.0),
+        (0.128, 0.567, 0.551, 1.0),
+        (0.135, 0.659, 0.518, 1.0),
+        (0.478, 0.821, 0.318, 1.0),
+        (0.993, 0.906, 0.144, 1.0),
+    ];
+
+    const RAINBOW: [Color; 7] = [
+        (1.0, 0.0, 0.0, 1.0),
+        (1.0, 0.5, 0.0, 1.0),
+        (1.0, 1.0, 0.0, 1.0),
+        (0.0, 0.8, 0.0, 1.0),
+        (0.0, 0.6, 1.0, 1.0),
+        (0.3, 0.0, 0.8, 1.0),
+        (0.6, 0.0, 0.6, 1.0),
+    ];
+
+    const PASTEL: [Color; 6] = [
+        (0.98, 0.71, 0.68, 1.0),
+        (1.0, 0.85, 0.65, 1.0),
+        (1.0, 0.98, 0.72, 1.0),
+        (0.72, 0.92, 0.74, 1.0),
+        (0.70, 0.84, 0.96, 1.0),
+        (0.85, 0.76, 0.93, 1.0),
+    ];
+
+    const GRAYSCALE: [Color; 2] = [BLACK, WHITE];
+
+    /// Names of the predefined palettes
+    pub const PALETTE_NAMES: [&'static str; 4] = ["viridis", "rainbow", "pastel", "grayscale"];
+
+    impl Palette {
+        /// Create a palette from the given colors. Returns `None` if there
+        /// are no colors.
+        pub fn new(colors: Vec<Color>) -> Option<Palette> {
+            if colors.is_empty() {
+                None
+            } else {
+                Some(Palette { colors: colors })
+            }
+        }
+
+        /// Return the predefined palette with the given name, see
+        /// `PALETTE_NAMES`
+        pub fn named(name: &str) -> Option<Palette> {
+            let colors: &[Color] = match &name.to_lowercase()[..] {
+                "viridis" => &VIRIDIS,
+                "rainbow" => &RAINBOW,
+                "pastel" => &PASTEL,
+                "grayscale" => &GRAYSCALE,
+                _ => return None,
+            };
+            Palette::new(colors.to_vec())
+        }
+
+        /// Return the color at `t`, which is clamped to [0; 1]
+        pub fn color_at(&self, t: f32) -> Color {
+            let t = if t.is_nan() { 0. } else { t.max(0.).min(1.) };
+            let last = self.colors.len() - 1;
+            let position = t * last as f32;
+            let index = (position.floor() as usize).min(last);
+            if index == last {
+                return self.colors[last];
+            }
+            let (a, b) = (self.colors[index], self.colors[index + 1]);
+            let f = position - index as f32;
+            (a.0 + (b.0 - a.0) * f, a.1 + (b.1 - a.1) * f, a.2 + (b.2 - a.2) * f,
+             a.3 + (b.3 - a.3) * f)
+        }
+    }
+
+    impl Default for Palette {
+        fn default() -> Palette {
+            Palette::named("viridis").unwrap()
+        }
+    }
+}
@@ -0,0 +1,1527 @@
+//! The window that turtles draw in, see `TurtleScreen`.
+//!
+//! This module is only available with the `native` feature, which is on by
+//! default.
+use image::{self, GenericImage};
+use glium::{self, Surface};
+use glium_text;
+use na;
+use std::io::{self, Write};
+use std::fs;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use super::super::filter::Filter;
+use super::super::floodfill as ff;
+use super::super::text::{self, FontChain};
+use super::super::backend::{self, Annotation, Bounds, Cursor, Overlay, RenderBackend,
+                            RenderResult, Symmetry, View, ANNOTATION_COLOR, AXES_COLOR,
+                            MINIMAP_FRAME_COLOR};
+use super::{color, FERRIS_BYTES, FERRIS_HEIGHT, FERRIS_WIDTH, FONT_DATA};
+
+/// A Point to pass around to shaders.
+#[derive(Copy, Clone)]
+struct Point {
+    coords: [f32; 2],
+    color: [f32; 4],
+}
+implement_vertex!(Point, coords, color);
+
+#[derive(Copy, Clone)]
+struct FerrisPoint {
+    coords: [f32; 2],
+    tex_coords: [f32; 2],
+}
+implement_vertex!(FerrisPoint, coords, tex_coords);
+
+/// Source for the vertex shader in the OpenGL shader language
+const VERTEX_SHADER: &'static str = include_str!("../shaders/vertex.glsl");
+/// Source for the fragment shader in the OpenGL shader language
+const FRAGMENT_SHADER: &'static str = include_str!("../shaders/fragment.glsl");
+/// Ferris vertex shader source
+const FERRIS_VERTEX: &'static str = include_str!("../shaders/ferris_vertex.glsl");
+/// Ferris fragment shader
+const FERRIS_FRAGMENT: &'static str = include_str!("../shaders/ferris_fragment.glsl");
+const PATCH_VERTEX: &'static str = include_str!("../shaders/patch_vertex.glsl");
+const PATCH_FRAGMENT: &'static str = include_str!("../shaders/patch_fragment.glsl");
+/// Fragment shader for rasterized texts, used with the Ferris vertex shader
+const GLYPH_FRAGMENT: &'static str = include_str!("../shaders/glyph_fragment.glsl");
+/// Size of texts in turtle units
+const FONT_SIZE: f32 = 12.;
+/// Texts that the OpenGL font can't draw are rasterized with this many
+/// pixels per unit, so they stay sharp when zooming in a bit
+const TEXT_RESOLUTION: f32 = 4.;
+
+type ScaleMatrix = [[f32; 4]; 4];
+
+
+/// Options that can be given when creating a `TurtleScreen`
+#[derive(Debug, Clone, Copy)]
+pub struct ScreenOptions {
+    /// Number of samples to use for multisample anti-aliasing. 0 disables
+    /// multisampling.
+    pub multisampling: u16,
+    /// If this is set to true, lines will be smoothed by OpenGL
+    pub smooth_lines: bool,
+    /// If this is set to true, the window won't have a border or title bar
+    pub borderless: bool,
+    /// If this is set to true, no window is opened at all, see the module
+    /// documentation
+    pub headless: bool,
+    /// Position of the window's upper left corner on the screen. If this is
+    /// `None`, the window manager decides.
+    pub position: Option<(i32, i32)>,
+    /// If this is set to true, the window covers the whole primary monitor
+    /// and the given size is ignored
+    pub maximized: bool,
+    /// Size of the canvas in turtle units if it is larger than the window.
+    /// The user can scroll around with the mouse, and screenshots capture
+    /// the whole canvas. If this is `None`, the canvas is the window.
+    pub canvas: Option<(u32, u32)>,
+}
+
+impl Default for ScreenOptions {
+    fn default() -> ScreenOptions {
+        ScreenOptions {
+            multisampling: 0,
+            smooth_lines: false,
+            borderless: false,
+            headless: false,
+            position: None,
+            maximized: false,
+            canvas: None,
+        }
+    }
+}
+
+/// A Line is defined via startpoint, endpoint, a color and its width
+struct Line(f32, f32, f32, f32, color::Color, f32);
+/// A Text is defined via anchor point, angle, color and text. Texts with
+/// non-ASCII characters are rasterized when they are added.
+struct Text(f32, f32, f32, color::Color, String, Option<Glyphs>);
+/// A rasterized text and the distance between the bottom of the texture and
+/// the baseline in pixels, see `TEXT_RESOLUTION`
+struct Glyphs(glium::texture::Texture2d, f32);
+/// A filled area is defined via a starting point, the zoom of the view when
+/// it was filled and a patch texture
+struct Fill(f32, f32, f32, glium::texture::Texture2d);
+/// A stamped image is defined via its center, orientation, scale and texture
+struct Stamp(f32, f32, f32, f32, Rc<glium::texture::Texture2d>);
+/// A batch of points is defined via their positions, color, size and the
+/// vertex buffer that draws all of them at once
+struct Points(Vec<(f32, f32)>, color::Color, f32, glium::VertexBuffer<Point>);
+
+/// Enum for every possible shape object
+// We need this for a Vec<Shape> so that we can store the original order of
+// every drawing. It's still easier to have seperate structs for the relevant
+// data so we can have a function
+//     draw_text(&mut Frame, &Text)
+// instead of
+//     draw_text(&mut Frame, f32, f32, f32, color::Color, &String)
+// or
+//     draw_text(&mut Frame, &Shape), which would require pattern matching twice
+enum Shape {
+    Line(Line),
+    Text(Text),
+    Fill(Fill),
+    Stamp(Stamp),
+    Points(Points),
+}
+
+/// A `TurtleScreen` is a window that houses a turtle. It provides some graphic
+/// methods, but you should use a `Turtle` instead. `TurtleScreen` implements
+/// `RenderBackend` using glium.
+pub struct TurtleScreen {
+    window: glium::backend::glutin_backend::GlutinFacade,
+    program: glium::Program,
+    /// The shapes together with the turtle that drew them
+    shapes: Vec<(usize, Shape)>,
+    /// The turtle that draws the next shapes, see `set_owner`
+    owner: usize,
+    /// The annotations together with their texts
+    annotations: Vec<(Annotation, Text)>,
+    _is_closed: bool,
+    ferris: glium::texture::Texture2d,
+    ferris_program: glium::Program,
+    patch_program: glium::Program,
+    glyph_program: glium::Program,
+    /// Fonts for the characters the OpenGL font lacks
+    fonts: FontChain,
+    text_system: glium_text::TextSystem,
+    font: glium_text::FontTexture,
+    /// The position of the turtle on the canvas
+    pub turtle_position: (f32, f32),
+    /// The color of the turtle
+    pub turtle_color: color::Color,
+    /// The orientation of the turtle in degrees where 0° is north and positive
+    /// degrees count counter-clockwise
+    pub turtle_orientation: f32,
+    /// If this is set to true, the turtle itself won't be drawn
+    pub turtle_hidden: bool,
+    /// Background color of the turtle screen
+    pub background_color: color::Color,
+    /// If this is set to true, lines will be drawn anti-aliased
+    pub smooth_lines: bool,
+    /// Width of the lines that are added from now on
+    pub pen_size: f32,
+    // We need to remember how the window was created so that we can rebuild it
+    // when switching from and to fullscreen mode
+    title: String,
+    size: (u32, u32),
+    options: ScreenOptions,
+    fullscreen: bool,
+    mouse_position: (f32, f32),
+    mouse_down: bool,
+    /// Last cursor position in device pixels, needed to drag the canvas
+    cursor: (i32, i32),
+    /// Set while the right mouse button drags the canvas around
+    panning: bool,
+    clicks: Vec<(f32, f32)>,
+    /// Names of the keys that are currently pressed, see `key_name`
+    pressed_keys: HashSet<String>,
+    overlay: Overlay,
+    view: View,
+    symmetry: Symmetry,
+    trail: Option<usize>,
+    visible_lines: Option<usize>,
+    /// Index of the first shape of the foreground, see `begin_foreground`
+    foreground_start: usize,
+    // Loaded images are cached so that stamping the same sprite over and over
+    // again is cheap
+    image_cache: HashMap<String, Rc<glium::texture::Texture2d>>,
+}
+
+impl TurtleScreen {
+    /// Create a new `TurtleScreen` with the given size and window title.
+    ///
+    /// # Panics
+    ///
+    /// Panics if something in the underlaying glium window creation fails.
+    pub fn new(size: (u32, u32), title: &str) -> TurtleScreen {
+        TurtleScreen::with_options(size, title, ScreenOptions::default())
+    }
+
+    /// Create a new `TurtleScreen` with the given size, window title and
+    /// options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if something in the underlaying glium window creation fails.
+    pub fn with_options(size: (u32, u32), title: &str, options: ScreenOptions) -> TurtleScreen {
+        use glium::DisplayBuild;
+        let builder = if options.headless {
+            glium::glutin::HeadlessRendererBuilder::new(size.0, size.1).build_glium()
+        } else {
+            window_builder(size, title, options, false).build_glium()
+        };
+        let window = match builder {
+            Err(error) => panic!("Window creation failed: {}", error),
+            Ok(win) => win,
+        };
+        place_window(&window, options);
+        let program_builder = glium::Program::from_source(
+            &window, VERTEX_SHADER, FRAGMENT_SHADER, None);
+        let program = match program_builder {
+            Err(error) => panic!("Program creation failed: {}", error),
+            Ok(prg) => prg,
+        };
+        let ferris_image = image::load(io::Cursor::new(FERRIS_BYTES),
+                                       image::ImageFormat::PNG).unwrap();
+        let ferris_texture = image_to_texture(&window, ferris_image).unwrap();
+        let ferris_program = glium::Program::from_source(&window, FERRIS_VERTEX,
+                                                         FERRIS_FRAGMENT, None) .unwrap();
+        let patch_program = glium::Program::from_source(&window, PATCH_VERTEX,
+                                                        PATCH_FRAGMENT, None).unwrap();
+        let glyph_program = glium::Program::from_source(&window, FERRIS_VERTEX,
+                                                        GLYPH_FRAGMENT, None).unwrap();
+        let text_system = glium_text::TextSystem::new(&window);
+        let font = glium_text::FontTexture::new(&window,
+                                                io::Cursor::new(FONT_DATA), 24).unwrap();
+        TurtleScreen {
+            window: window,
+            program: program,
+            shapes: Vec::new(),
+            owner: 0,
+            annotations: Vec::new(),
+            _is_closed: false,
+            ferris: ferris_texture,
+            ferris_program: ferris_program,
+            patch_program: patch_program,
+            glyph_program: glyph_program,
+            fonts: FontChain::new(),
+            text_system: text_system,
+            font: font,
+            turtle_position: (0.0, 0.0),
+            turtle_color: color::BLACK,
+            turtle_orientation: 0.0,
+            turtle_hidden: false,
+            background_color: color::WHITE,
+            smooth_lines: options.smooth_lines,
+            pen_size: 1.0,
+            title: title.to_owned(),
+            size: size,
+            options: options,
+            fullscreen: false,
+            mouse_position: (0.0, 0.0),
+            mouse_down: false,
+            cursor: (0, 0),
+            panning: false,
+            clicks: Vec::new(),
+            pressed_keys: HashSet::new(),
+            overlay: Overlay::default(),
+            view: View::default(),
+            symmetry: Symmetry::default(),
+            trail: None,
+            visible_lines: None,
+            foreground_start: 0,
+            image_cache: HashMap::new(),
+        }
+    }
+
+    /// Show a coordinate grid with the given spacing beneath the drawing. A
+    /// spacing of 0 (or less) hides the grid.
+    pub fn show_grid(&mut self, spacing: f32) {
+        self.overlay.grid = if spacing > 0. { Some(spacing) } else { None };
+    }
+
+    /// Show or hide the x and y axes with labeled ticks beneath the drawing
+    pub fn show_axes(&mut self, show: bool) {
+        self.overlay.axes = show;
+    }
+
+    /// Add a line to the collection, going from point start to point end. In
+    /// symmetry mode, the copies of the line are added too.
+    pub fn add_line(&mut self, start: (f32, f32), end: (f32, f32), color: color::Color) {
+        for (start, end) in self.symmetry.lines(start, end) {
+            self.shapes.push((self.owner, Shape::Line(Line(start.0, start.1, end.0, end.1,
+                                                           color, self.pen_size))));
+        }
+        self.prune_trail();
+    }
+
+    /// Remove the oldest lines that don't fit into the trail anymore
+    fn prune_trail(&mut self) {
+        if let Some(length) = self.trail {
+            let mut surplus = self.line_count().saturating_sub(length);
+            let removed = backend::remove_shapes(&mut self.shapes, self.foreground_start,
+                                                 |&(_, ref shape)| match *shape {
+                Shape::Line(_) if surplus > 0 => {
+                    surplus -= 1;
+                    true
+                },
+                _ => false,
+            });
+            self.foreground_start -= removed;
+        }
+    }
+
+    /// Add points with the current pen size. In symmetry mode, the copies of
+    /// the points are added too.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the vertex buffer can't be created.
+    pub fn plot_points(&mut self, points: &[(f32, f32)], color: color::Color) {
+        let points: Vec<(f32, f32)> = points.iter()
+            .flat_map(|&point| self.symmetry.lines(point, point))
+            .map(|(point, _)| point)
+            .collect();
+        let vertices: Vec<Point> = points.iter()
+            .map(|&(x, y)| Point { coords: [x, y], color: color::to_array(color) })
+            .collect();
+        let buffer = glium::VertexBuffer::new(&self.window, &vertices)
+            .expect("Vertex buffer creation failed");
+        self.shapes.push((self.owner,
+                          Shape::Points(Points(points, color, self.pen_size, buffer))));
+    }
+
+    /// Return the bounding box of everything drawn, `None` if nothing has been
+    /// drawn. The size of texts is estimated.
+    pub fn drawing_bounds(&self) -> Option<Bounds> {
+        let factor = self.hidpi_factor();
+        let mut bounds = None;
+        for &(_, ref shape) in &self.shapes {
+            match *shape {
+                Shape::Line(Line(x1, y1, x2, y2, _, width)) => {
+                    Bounds::extend(&mut bounds, (x1, y1), width);
+                    Bounds::extend(&mut bounds, (x2, y2), width);
+                },
+                Shape::Text(Text(x, y, angle, _, ref text, _)) => {
+                    Bounds::extend_text(&mut bounds, (x, y), angle, text)
+                },
+                Shape::Fill(Fill(x, y, zoom, ref texture)) => {
+                    let (width, height) = (texture.get_width() as f32 / factor / zoom,
+                                           texture.get_height().unwrap() as f32 / factor / zoom);
+                    Bounds::extend(&mut bounds, (x, y), 0.);
+                    Bounds::extend(&mut bounds, (x + width, y - height), 0.);
+                },
+                Shape::Stamp(Stamp(x, y, angle, scale, ref texture)) => {
+                    let size = (texture.get_width() as f32 * scale,
+                                texture.get_height().unwrap() as f32 * scale);
+                    Bounds::extend_rotated(&mut bounds, (x, y), size, angle)
+                },
+                Shape::Points(Points(ref points, _, size, _)) => {
+                    for &point in points {
+                        Bounds::extend(&mut bounds, point, size);
+                    }
+                },
+            }
+        }
+        bounds
+    }
+
+    /// Return the lines in the order they were drawn, points as lines of
+    /// length 0
+    pub fn line_segments(&self) -> Vec<((f32, f32), (f32, f32))> {
+        let mut segments = Vec::new();
+        for &(_, ref shape) in &self.shapes {
+            match *shape {
+                Shape::Line(Line(x1, y1, x2, y2, _, _)) => segments.push(((x1, y1), (x2, y2))),
+                Shape::Points(Points(ref points, _, _, _)) => {
+                    segments.extend(points.iter().map(|&point| (point, point)))
+                },
+                _ => (),
+            }
+        }
+        segments
+    }
+
+    /// Add a new text to the screen. Returns an error if the text has
+    /// characters the OpenGL font lacks and is too long to be rasterized.
+    pub fn add_text(&mut self, anchor: (f32, f32), angle: f32, color: color::Color, text: &str)
+                    -> RenderResult<()>
+    {
+        let text = try!(self.make_text(anchor, angle, color, text));
+        self.shapes.push((self.owner, Shape::Text(text)));
+        Ok(())
+    }
+
+    fn make_text(&self, anchor: (f32, f32), angle: f32, color: color::Color, text: &str)
+                 -> RenderResult<Text>
+    {
+        // The OpenGL font only has the ASCII characters
+        let glyphs = if text.is_ascii() {
+            None
+        } else {
+            Some(try!(self.rasterize_text(text, color)))
+        };
+        Ok(Text(anchor.0, anchor.1, angle, color, text.to_owned(), glyphs))
+    }
+
+    /// Add a speech bubble on top of the drawing, see `add_text` for the
+    /// errors
+    pub fn add_annotation(&mut self, annotation: Annotation) -> RenderResult<()> {
+        let text = try!(self.make_text(annotation.anchor(), 0., color::BLACK, &annotation.text));
+        self.annotations.push((annotation, text));
+        Ok(())
+    }
+
+    /// Remove all speech bubbles
+    pub fn clear_annotations(&mut self) {
+        self.annotations.clear();
+    }
+
+    /// Replace the drawing with an image of it, run through the filter. The
+    /// overlay and the annotations are left out of the image and stay.
+    /// Returns an error and leaves the drawing as it is if the canvas is too
+    /// large for a texture.
+    pub fn apply_filter(&mut self, filter: Filter) -> RenderResult<()> {
+        let overlay = self.overlay;
+        let annotations = ::std::mem::replace(&mut self.annotations, Vec::new());
+        self.overlay = Overlay::default();
+        let rendered = self.render_image(1.0);
+        self.overlay = overlay;
+        self.annotations = annotations;
+        let image = filter.apply(&try!(rendered).to_rgba());
+        let texture = try!(self.texture(image::DynamicImage::ImageRgba8(image)));
+        // The image covers exactly the captured area, see `render_image`
+        let (_, View { center, zoom }) = self.capture_area();
+        self.shapes.clear();
+        self.foreground_start = 0;
+        let stamp = Stamp(center.0, center.1, 0., 1. / zoom, Rc::new(texture));
+        self.shapes.push((self.owner, Shape::Stamp(stamp)));
+        Ok(())
+    }
+
+    /// Upload the image as texture. Returns an error if it's larger than the
+    /// graphics card supports, see `max_image_size`.
+    fn texture(&self, image: image::DynamicImage) -> RenderResult<glium::texture::Texture2d> {
+        let (width, height) = image.dimensions();
+        try!(self.check_image_size(width, height));
+        image_to_texture(&self.window, image).map_err(|e| e.to_string())
+    }
+
+    /// Return an error if an image of the given size is larger than
+    /// `max_image_size`
+    fn check_image_size(&self, width: u32, height: u32) -> RenderResult<()> {
+        let max = self.max_image_size();
+        if width > max || height > max {
+            return Err(format!("an image of {}x{} pixels is too large, the maximum is {}x{}",
+                               width, height, max, max));
+        }
+        Ok(())
+    }
+
+    /// Return the largest width and height of textures, limited by the
+    /// graphics card
+    pub fn max_image_size(&self) -> u32 {
+        use glium::backend::Facade;
+        let max = self.window.get_context().get_capabilities().max_texture_size;
+        (max.max(0) as u32).min(backend::MAX_IMAGE_SIZE)
+    }
+
+    fn rasterize_text(&self, text: &str, color: color::Color) -> RenderResult<Glyphs> {
+        let (r, g, b, a) = color;
+        const MAX: f32 = ::std::u8::MAX as f32;
+        let color = [(MAX * r) as u8, (MAX * g) as u8, (MAX * b) as u8, (MAX * a) as u8];
+        let (image, baseline) = self.fonts.rasterize(&text::visual_order(text),
+                                                     FONT_SIZE * TEXT_RESOLUTION, color);
+        let texture = try!(self.texture(image::DynamicImage::ImageRgba8(image)));
+        Ok(Glyphs(texture, baseline))
+    }
+
+    /// Add the font in the file at `path` to the fonts that are tried for
+    /// characters the bundled font lacks. Only texts added afterwards use it.
+    pub fn add_fallback_font(&mut self, path: &str) -> io::Result<()> {
+        self.fonts.add_fallback(path)
+    }
+
+    /// Floodfill the image at the given point with the given color. Returns
+    /// an error if the screen can't be captured.
+    pub fn floodfill(&mut self, point: (f32, f32), color: color::Color) -> RenderResult<()> {
+        // we floodfill with the turtle, the overlay and the annotations not
+        // shown
+        let original_state = self.turtle_hidden;
+        let overlay = self.overlay;
+        let annotations = ::std::mem::replace(&mut self.annotations, Vec::new());
+        self.turtle_hidden = true;
+        self.overlay = Overlay::default();
+        self.draw_and_update();
+        let image = self.native_screenshot();
+        self.turtle_hidden = original_state;
+        self.overlay = overlay;
+        self.annotations = annotations;
+        self.draw_and_update();
+        let image = try!(image);
+        // point is given in turtle coordinates, we need to translate it to
+        // picture coordinates
+        let (_, view) = self.capture_area();
+        let point = view.apply(point);
+        let factor = self.hidpi_factor();
+        let (width, height) = image.dimensions();
+        let (adj_x, adj_y) = ((width as f32 / 2. + point.0 * factor) as u32,
+                              // minus here because the image coordinates have the
+                              // y-axis downwards while turtle coordinates have the
+                              // y-axis upwars
+                              (height as f32 / 2. - point.1 * factor) as u32);
+        let translated_color = {
+            let (r, g, b, a) = color;
+            const MAX: f32 = ::std::u8::MAX as f32;
+            ((MAX * r) as u8, (MAX * g) as u8, (MAX * b) as u8, (MAX * a) as u8)
+        };
+        let (px, py, patch) = ff::floodfill(&image, (adj_x, adj_y), translated_color);
+        // We need to translate back the start coordinates
+        let (trans_x, trans_y) = view.invert(((px as f32 - width as f32 / 2.) / factor,
+                                              (height as f32 / 2. - py as f32) / factor));
+        let patch = try!(self.texture(patch));
+        self.shapes.push((self.owner, Shape::Fill(Fill(trans_x, trans_y, view.zoom, patch))));
+        Ok(())
+    }
+
+    /// Draw the image found at `path` onto the canvas. The image is centered
+    /// at `position`, rotated by `rotation` degrees (counter-clockwise) and
+    /// scaled by `scale`. Images are cached, so stamping the same file again is
+    /// cheap. Returns an error if the image can't be loaded or is too large
+    /// for a texture.
+    pub fn stamp_image(&mut self, path: &str, position: (f32, f32), rotation: f32, scale: f32)
+                       -> RenderResult<()>
+    {
+        let texture = match self.image_cache.get(path) {
+            Some(texture) => texture.clone(),
+            None => {
+                let image = try!(image::open(path).map_err(|e| e.to_string()));
+                let texture = Rc::new(try!(self.texture(image)));
+                self.image_cache.insert(path.to_owned(), texture.clone());
+                texture
+            },
+        };
+        let stamp = Stamp(position.0, position.1, rotation, scale, texture);
+        self.shapes.push((self.owner, Shape::Stamp(stamp)));
+        Ok(())
+    }
+
+    /// Remove all drawn lines. Note that this does not change the turtle's
+    /// position, color or orientation.
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+        self.foreground_start = 0;
+        self.annotations.clear();
+    }
+
+    /// Draw everything and update the screen
+    pub fn draw_and_update(&self) {
+        if self.options.headless {
+            return;
+        }
+        let mut frame = self.window.draw();
+        self.render(&mut frame, self.hidpi_factor(), !self.turtle_hidden, self.view);
+        if self.overlay.minimap {
+            self.draw_minimap(&mut frame, self.hidpi_factor());
+        }
+        frame.finish().unwrap();
+    }
+
+    /// Draw everything onto the given surface as seen through `view`. `scale`
+    /// gives the number of pixels on the surface per unit in turtle
+    /// coordinates.
+    fn render<S: Surface>(&self, surface: &mut S, scale: f32, with_turtle: bool, view: View) {
+        {
+            let (br, bg, bb, ba) = self.background_color;
+            surface.clear_color(br, bg, bb, ba);
+        }
+        let matrix = projection_matrix(surface.get_dimensions(), scale, view);
+        self.draw_overlay(surface, matrix, scale, view);
+        // Lines and points get wider when zooming in
+        let zoomed = scale * view.zoom;
+        // Lines in a trail fade out with their age. In playback mode,
+        // everything after the last visible line is hidden.
+        let total = self.line_count();
+        let visible = self.visible_lines.unwrap_or(total);
+        let mut age = total;
+        for &(_, ref shape) in &self.shapes {
+            match *shape {
+                Shape::Line(ref l) => {
+                    if total - age >= visible {
+                        break;
+                    }
+                    age -= 1;
+                    match self.trail {
+                        Some(length) => {
+                            let color = backend::fade(l.4, age, length);
+                            let line = Line(l.0, l.1, l.2, l.3, color, l.5);
+                            self.draw_line(surface, &line, matrix, zoomed)
+                        },
+                        None => self.draw_line(surface, l, matrix, zoomed),
+                    }
+                },
+                Shape::Text(ref t) => self.draw_text(surface, t, scale, view),
+                Shape::Fill(ref f) => self.draw_fill(surface, f, matrix),
+                Shape::Stamp(ref s) => self.draw_stamp(surface, s, matrix),
+                Shape::Points(ref p) => self.draw_points(surface, p, matrix, zoomed),
+            }
+        }
+        for &(ref annotation, ref text) in &self.annotations {
+            self.draw_polygon(surface, &annotation.corners(), ANNOTATION_COLOR, matrix);
+            self.draw_polygon(surface, &annotation.pointer(), ANNOTATION_COLOR, matrix);
+            let outline = annotation.outline();
+            for i in 0..outline.len() {
+                let (start, end) = (outline[i], outline[(i + 1) % outline.len()]);
+                let line = Line(start.0, start.1, end.0, end.1, AXES_COLOR, 1.);
+                self.draw_line(surface, &line, matrix, zoomed);
+            }
+            self.draw_text(surface, text, scale, view);
+        }
+        if with_turtle {
+            self.draw_turtle(surface, matrix);
+        }
+    }
+
+    /// Draw the grid and axes
+    fn draw_overlay<S: Surface>(&self, surface: &mut S, matrix: ScaleMatrix, scale: f32,
+                                view: View) {
+        let (width, height) = surface.get_dimensions();
+        let size = view.overlay_size((width as f32 / scale, height as f32 / scale));
+        for (start, end, color) in self.overlay.lines(size) {
+            let line = Line(start.0, start.1, end.0, end.1, color, 1.);
+            self.draw_line(surface, &line, matrix, scale);
+        }
+        for (anchor, label) in self.overlay.labels(size) {
+            let text = Text(anchor.0, anchor.1, 0., AXES_COLOR, label, None);
+            self.draw_text(surface, &text, scale, view);
+        }
+    }
+
+    /// Draw the whole drawing scaled down into the lower right corner of the
+    /// window, with a frame around the part that is visible in the window
+    fn draw_minimap<S: Surface>(&self, surface: &mut S, scale: f32) {
+        const MARGIN: f32 = 8.;
+        let window = self.logical_size();
+        let size = Overlay::minimap_size(window);
+        let view = self.view.minimap(self.drawing_bounds(), window, size);
+        let (width, height) = ((size.0 * scale) as u32, (size.1 * scale) as u32);
+        // Without a texture, the minimap is left out rather than aborting
+        let texture = match glium::texture::Texture2d::empty(&self.window, width, height) {
+            Ok(texture) => texture,
+            Err(_) => return,
+        };
+        {
+            let framebuffer = glium::framebuffer::SimpleFrameBuffer::new(&self.window, &texture);
+            let mut framebuffer = match framebuffer {
+                Ok(framebuffer) => framebuffer,
+                Err(_) => return,
+            };
+            self.render(&mut framebuffer, scale, false, view);
+            let matrix = projection_matrix(framebuffer.get_dimensions(), scale, view);
+            let visible = self.view.visible_bounds(window);
+            self.draw_rectangle(&mut framebuffer, visible, MINIMAP_FRAME_COLOR, matrix, scale);
+        }
+        // The minimap doesn't move with the view
+        let matrix = projection_matrix(surface.get_dimensions(), scale, View::default());
+        let (right, bottom) = (window.0 / 2. - MARGIN, -window.1 / 2. + MARGIN);
+        let border = Bounds { min: (right - size.0, bottom), max: (right, bottom + size.1) };
+        self.draw_fill(surface, &Fill(border.min.0, border.max.1, 1., texture), matrix);
+        self.draw_rectangle(surface, border, AXES_COLOR, matrix, scale);
+    }
+
+    /// Fill the convex polygon with the given corners
+    fn draw_polygon<S: Surface>(&self, surface: &mut S, corners: &[(f32, f32)],
+                                color: color::Color, matrix: ScaleMatrix) {
+        let points: Vec<Point> = corners.iter()
+            .map(|&(x, y)| Point { coords: [x, y], color: color::to_array(color) })
+            .collect();
+        let vertex_buffer = glium::VertexBuffer::new(&self.window, &points);
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleFan);
+        let uniforms = uniform! { matrix: matrix };
+        surface.draw(&vertex_buffer.unwrap(), &indices, &self.program, &uniforms,
+                     &Default::default()).unwrap();
+    }
+
+    /// Draw the outline of the rectangle
+    fn draw_rectangle<S: Surface>(&self, surface: &mut S, rectangle: Bounds, color: color::Color,
+                                  matrix: ScaleMatrix, scale: f32) {
+        let Bounds { min: (x1, y1), max: (x2, y2) } = rectangle;
+        let corners = [(x1, y1), (x2, y1), (x2, y2), (x1, y2)];
+        for i in 0..corners.len() {
+            let (start, end) = (corners[i], corners[(i + 1) % corners.len()]);
+            let line = Line(start.0, start.1, end.0, end.1, color, 1.);
+            self.draw_line(surface, &line, matrix, scale);
+        }
+    }
+
+    /// Render the drawing into an offscreen image that is `scale` times as big
+    /// as the window (or the whole canvas, see `ScreenOptions::canvas`).
+    /// Unlike `screenshot`, this re-renders everything at the higher
+    /// resolution, so lines and texts stay sharp. The turtle is not drawn.
+    /// Returns an error if the image is larger than `max_image_size` or the
+    /// offscreen framebuffer can't be created.
+    pub fn render_image(&self, scale: f32) -> RenderResult<image::DynamicImage> {
+        self.render_offscreen(scale, false)
+    }
+
+    /// Render the drawing into an offscreen image, optionally with the turtle
+    fn render_offscreen(&self, scale: f32, with_turtle: bool)
+                        -> RenderResult<image::DynamicImage>
+    {
+        let ((width, height), view) = self.capture_area();
+        let (width, height) = ((width * scale) as u32, (height * scale) as u32);
+        try!(self.check_image_size(width, height));
+        let texture = try!(glium::texture::Texture2d::empty(&self.window, width, height)
+                           .map_err(|e| e.to_string()));
+        {
+            let framebuffer = glium::framebuffer::SimpleFrameBuffer::new(&self.window, &texture);
+            let mut framebuffer = try!(framebuffer.map_err(|e| e.to_string()));
+            self.render(&mut framebuffer, scale, with_turtle, view);
+        }
+        Ok(raw_image_to_image(texture.read()))
+    }
+
+    /// Return the size of the area that screenshots and exports capture and
+    /// the view to capture it with. A canvas larger than the window is
+    /// captured completely, regardless of where the user scrolled to.
+    fn capture_area(&self) -> ((f32, f32), View) {
+        match self.options.canvas {
+            Some((width, height)) => ((width as f32, height as f32), View::default()),
+            None => (self.logical_size(), self.view),
+        }
+    }
+
+    /// Move the view by the given distance in logical pixels, keeping the
+    /// window inside the canvas. Without a canvas, the view can't be
+    /// scrolled.
+    fn scroll(&mut self, (dx, dy): (f32, f32)) {
+        let (width, height) = match self.options.canvas {
+            Some(canvas) => canvas,
+            None => return,
+        };
+        let View { center: (cx, cy), zoom } = self.view;
+        let (visible_x, visible_y) = self.logical_size();
+        let limit_x = ((width as f32 - visible_x / zoom) / 2.).max(0.);
+        let limit_y = ((height as f32 - visible_y / zoom) / 2.).max(0.);
+        let center = ((cx + dx / zoom).max(-limit_x).min(limit_x),
+                      (cy + dy / zoom).max(-limit_y).min(limit_y));
+        self.view = View { center: center, zoom: zoom };
+        self.draw_and_update();
+    }
+
+    /// Switch the window to fullscreen mode on the primary monitor or back to
+    /// a normal window. The projection is adjusted to the new resolution, so
+    /// the drawing stays centered and keeps its size. Returns an error if the
+    /// window can't be rebuilt.
+    pub fn set_fullscreen(&mut self, fullscreen: bool) -> RenderResult<()> {
+        use glium::DisplayBuild;
+        if fullscreen == self.fullscreen || self.options.headless {
+            return Ok(());
+        }
+        let result = window_builder(self.size, &self.title, self.options, fullscreen)
+            .rebuild_glium(&self.window);
+        if let Err(error) = result {
+            return Err(format!("window rebuilding failed: {}", error));
+        }
+        if !fullscreen {
+            place_window(&self.window, self.options);
+        }
+        self.fullscreen = fullscreen;
+        self.draw_and_update();
+        Ok(())
+    }
+
+    /// Returns true if the screen has no window, see the module documentation
+    pub fn is_headless(&self) -> bool {
+        self.options.headless
+    }
+
+    /// Returns true if the window is currently in fullscreen mode
+    pub fn is_fullscreen(&self) -> bool {
+        self.fullscreen
+    }
+
+    /// Return the scale factor between device pixels and logical pixels. This
+    /// is 1.0 on normal displays and usually 2.0 on "retina" displays.
+    pub fn hidpi_factor(&self) -> f32 {
+        match self.window.get_window() {
+            Some(window) => window.hidpi_factor(),
+            None => 1.0,
+        }
+    }
+
+    /// Return the size of the canvas in logical pixels, that is in turtle
+    /// coordinate units
+    pub fn logical_size(&self) -> (f32, f32) {
+        let (width, height) = self.window.get_framebuffer_dimensions();
+        let factor = self.hidpi_factor();
+        (width as f32 / factor, height as f32 / factor)
+    }
+
+    fn draw_fill<S: Surface>(&self, frame: &mut S, fill: &Fill, matrix: ScaleMatrix) {
+        let Fill(x, y, zoom, ref texture) = *fill;
+        // The patch was cut out of a screenshot at device resolution
+        let factor = self.hidpi_factor() * zoom;
+        let (width, height) = (texture.get_width() as f32 / factor,
+                               texture.get_height().unwrap() as f32 / factor);
+        let vertex_buffer = glium::VertexBuffer::new(
+            &self.window,
+            &vec![
+                // Bottom left corner
+                FerrisPoint { coords: [x, y - height], tex_coords: [0., 0.] },
+                // Bottom right corner
+                FerrisPoint { coords: [x + width, y - height], tex_coords: [1., 0.] },
+                // Top right corner
+                FerrisPoint { coords: [x + width, y], tex_coords: [1., 1.] },
+                // Top left corner
+                FerrisPoint { coords: [x, y], tex_coords: [0., 1.] },
+        ]);
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleFan);
+        let uniforms = uniform! {
+            matrix: matrix,
+            texture_data: texture,
+        };
+        frame.draw(&vertex_buffer.unwrap(), &indices, &self.patch_program, &uniforms,
+                   &Default::default()).unwrap();
+    }
+
+    fn draw_line<S: Surface>(&self, frame: &mut S, line: &Line, matrix: ScaleMatrix, scale: f32) {
+        use std::default::Default;
+        use self::color::to_array;
+        let mut points: Vec<Point> = Vec::new();
+        let Line(x1, y1, x2, y2, color, width) = *line;
+        points.push(Point { coords: [x1, y1], color: to_array(color) });
+        points.push(Point { coords: [x2, y2], color: to_array(color) });
+        let vertex_buffer = glium::VertexBuffer::new(&self.window, &points);
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::LinesList);
+        let uniforms = uniform! { matrix: matrix };
+        let parameters = glium::DrawParameters {
+            smooth: if self.smooth_lines {
+                Some(glium::draw_parameters::Smooth::Nicest)
+            } else {
+                None
+            },
+            blend: glium::Blend::alpha_blending(),
+            // Keep the line width proportional when rendering at a higher scale
+            line_width: Some(scale * width),
+            .. Default::default()
+        };
+        frame.draw(&vertex_buffer.unwrap(), &indices, &self.program, &uniforms, &parameters)
+            .unwrap();
+    }
+
+    fn draw_points<S: Surface>(&self, frame: &mut S, points: &Points, matrix: ScaleMatrix,
+                               scale: f32) {
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::Points);
+        let uniforms = uniform! { matrix: matrix };
+        let parameters = glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            point_size: Some(scale * points.2),
+            .. Default::default()
+        };
+        frame.draw(&points.3, &indices, &self.program, &uniforms, &parameters).unwrap();
+    }
+
+    fn draw_text<S: Surface>(&self, frame: &mut S, text: &Text, scale: f32, view: View) {
+        let Text(pos_x, pos_y, angle_deg, text_color, ref data, ref glyphs) = *text;
+        if let Some(ref glyphs) = *glyphs {
+            return self.draw_glyphs(frame, (pos_x, pos_y), angle_deg, glyphs, scale, view);
+        }
+        let (pos_x, pos_y) = view.apply((pos_x, pos_y));
+        let font_size = FONT_SIZE * view.zoom;
+        // Convert to radians
+        let angle = ::std::f32::consts::PI * angle_deg / 180.;
+        let sin_d = angle.sin();
+        let cos_d = angle.cos();
+        let text_display = glium_text::TextDisplay::new(&self.text_system, &self.font, data);
+        let (width, height) = {
+            let (width, height) = frame.get_dimensions();
+            (width as f32 / scale, height as f32 / scale)
+        };
+        // Note that this is not column-major layout
+        let rotation_matrix = na::Mat4::new(
+            cos_d, -sin_d, 0., 0.,
+            sin_d, cos_d, 0., 0.,
+            0., 0., 1., 0.,
+            0., 0., 0., 1.);
+        let scale_matrix = na::Mat4::new(
+            2. * font_size / width, 0., 0., 0.,
+            0., 2. * font_size / height, 0., 0.,
+            0., 0., 1., 0.,
+            0., 0., 0., 1.);
+        let translate_matrix = na::Mat4::new(
+            1., 0., 0., pos_x * 2. / width,
+            0., 1., 0., pos_y * 2. / height,
+            0., 0., 1., 0.,
+            0., 0., 0., 1.);
+        glium_text::draw(&text_display, &self.text_system, frame,
+                         *(translate_matrix * scale_matrix * rotation_matrix).as_ref(),
+                         text_color);
+    }
+
+    /// Draw a rasterized text whose lower-left corner is at `anchor`
+    fn draw_glyphs<S: Surface>(&self, frame: &mut S, anchor: (f32, f32), angle: f32,
+                               glyphs: &Glyphs, scale: f32, view: View) {
+        let Glyphs(ref texture, baseline) = *glyphs;
+        let size = (texture.get_width() as f32 / TEXT_RESOLUTION,
+                    texture.get_height().unwrap() as f32 / TEXT_RESOLUTION);
+        // The middle of the texture relative to the anchor, before rotating
+        let (dx, dy) = (size.0 / 2., size.1 / 2. - baseline / TEXT_RESOLUTION);
+        let (sin_d, cos_d) = angle.to_radians().sin_cos();
+        let center = (anchor.0 + dx * cos_d - dy * sin_d, anchor.1 + dx * sin_d + dy * cos_d);
+        let matrix = projection_matrix(frame.get_dimensions(), scale, view);
+        let parameters = glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            .. Default::default()
+        };
+        self.draw_textured_quad(frame, texture, center, size, angle, matrix,
+                                &self.glyph_program, &parameters);
+    }
+
+    fn draw_turtle<S: Surface>(&self, frame: &mut S, matrix: ScaleMatrix) {
+        self.draw_textured_quad(frame, &self.ferris, self.turtle_position,
+                                (FERRIS_WIDTH, FERRIS_HEIGHT), self.turtle_orientation, matrix,
+                                &self.ferris_program, &Default::default());
+    }
+
+    fn draw_stamp<S: Surface>(&self, frame: &mut S, stamp: &Stamp, matrix: ScaleMatrix) {
+        let Stamp(x, y, orientation, scale, ref texture) = *stamp;
+        let size = (texture.get_width() as f32 * scale,
+                    texture.get_height().unwrap() as f32 * scale);
+        self.draw_textured_quad(frame, texture, (x, y), size, orientation, matrix,
+                                &self.ferris_program, &Default::default());
+    }
+
+    /// Draw the given texture centered at `center` with the given size and
+    /// rotated by `orientation` degrees (counter-clockwise)
+    fn draw_textured_quad<S: Surface>(&self, frame: &mut S, texture: &glium::texture::Texture2d,
+                          center: (f32, f32), size: (f32, f32), orientation: f32,
+                          matrix: ScaleMatrix, program: &glium::Program,
+                          parameters: &glium::DrawParameters) {
+        let (tx, ty) = center;
+        let (dx, dy) = (size.0 / 2., size.1 / 2.);
+        let orientation_rad = ::std::f32::consts::PI * orientation / 180.0;
+        let sin_d = orientation_rad.sin();
+        let cos_d = orientation_rad.cos();
+
+        let rotation_matrix = [
+            [cos_d, sin_d, 0., 0.],
+            [-sin_d, cos_d, 0., 0.],
+            [0., 0., 1., 0.],
+            [0., 0., 0., 1.],
+        ];
+
+        let vertex_buffer = glium::VertexBuffer::new(
+            &self.window,
+            &vec![
+                // Bottom left corner
+                FerrisPoint { coords: [tx - dx, ty - dy], tex_coords: [0., 0.] },
+                // Bottom right corner
+                FerrisPoint { coords: [tx + dx, ty - dy], tex_coords: [1., 0.] },
+                // Top right corner
+                FerrisPoint { coords: [tx + dx, ty + dy], tex_coords: [1., 1.] },
+                // Top left corner
+                FerrisPoint { coords: [tx - dx, ty + dy], tex_coords: [0., 1.] },
+        ]);
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleFan);
+        let uniforms = uniform! {
+            matrix: matrix,
+            rotation_matrix: rotation_matrix,
+            ferris_tex: texture,
+            tip_x: tx,
+            tip_y: ty,
+        };
+        frame.draw(&vertex_buffer.unwrap(), &indices, program, &uniforms, parameters).unwrap();
+    }
+
+    /// Poll the window's events and handle them. If the canvas is larger than
+    /// the window, dragging with the right mouse button and the mouse wheel
+    /// scroll around.
+    pub fn handle_events(&mut self) {
+        use glium::glutin::{Event, ElementState, MouseButton, MouseScrollDelta};
+        if self.options.headless {
+            return;
+        }
+        let events: Vec<_> = self.window.poll_events().collect();
+        for event in events {
+            match event {
+                Event::Closed => {
+                    self._is_closed = true;
+                    self.window.get_window().unwrap().hide();
+                },
+                Event::MouseMoved((x, y)) => {
+                    if self.panning {
+                        let (last_x, last_y) = self.cursor;
+                        let factor = self.hidpi_factor();
+                        // The canvas follows the cursor
+                        self.scroll(((last_x - x) as f32 / factor, (y - last_y) as f32 / factor));
+                    }
+                    self.cursor = (x, y);
+                    self.mouse_position = self.window_to_turtle_coords((x, y));
+                },
+                Event::MouseInput(state, MouseButton::Right) => {
+                    self.panning = state == ElementState::Pressed;
+                },
+                Event::MouseWheel(delta, ..) => {
+                    const LINE_HEIGHT: f32 = 40.;
+                    let (dx, dy) = match delta {
+                        MouseScrollDelta::LineDelta(x, y) => (x * LINE_HEIGHT, y * LINE_HEIGHT),
+                        MouseScrollDelta::PixelDelta(x, y) => (x, y),
+                    };
+                    self.scroll((-dx, dy));
+                },
+                Event::MouseInput(state, MouseButton::Left) => {
+                    self.mouse_down = state == ElementState::Pressed;
+                    if self.mouse_down {
+                        self.clicks.push(self.mouse_position);
+                    }
+                },
+                Event::KeyboardInput(state, _, Some(key)) => {
+                    let name = key_name(key);
+                    if state == ElementState::Pressed {
+                        self.pressed_keys.insert(name);
+                    } else {
+                        self.pressed_keys.remove(&name);
+                    }
+                },
+                // We won't see the keys being released while the window is in
+                // the background
+                Event::Focused(false) => self.pressed_keys.clear(),
+                _ => (),
+            }
+        }
+    }
+
+    /// Convert a position in window coordinates (device pixels, origin in the
+    /// upper left corner) to turtle coordinates
+    fn window_to_turtle_coords(&self, (x, y): (i32, i32)) -> (f32, f32) {
+        let factor = self.hidpi_factor();
+        let (width, height) = self.logical_size();
+        self.view.invert((x as f32 / factor - width / 2., height / 2. - y as f32 / factor))
+    }
+
+    /// Return the last known position of the mouse cursor in turtle
+    /// coordinates. The position is updated by `handle_events()`.
+    pub fn mouse_position(&self) -> (f32, f32) {
+        self.mouse_position
+    }
+
+    /// Return the positions (in turtle coordinates) of all left clicks that
+    /// happened since the last call of this function.
+    pub fn take_clicks(&mut self) -> Vec<(f32, f32)> {
+        ::std::mem::replace(&mut self.clicks, Vec::new())
+    }
+
+    /// Returns true if the given point (in turtle coordinates) lies on the
+    /// turtle cursor. A hidden turtle can't be hit.
+    pub fn hits_turtle(&self, point: (f32, f32)) -> bool {
+        if self.turtle_hidden {
+            return false;
+        }
+        // Rotate the point back so that we can test against the axis-aligned
+        // cursor rectangle
+        let (tx, ty) = self.turtle_position;
+        let (dx, dy) = (point.0 - tx, point.1 - ty);
+        let orientation_rad = ::std::f32::consts::PI * self.turtle_orientation / 180.0;
+        let sin_d = orientation_rad.sin();
+        let cos_d = orientation_rad.cos();
+        let local_x = cos_d * dx + sin_d * dy;
+        let local_y = -sin_d * dx + cos_d * dy;
+        local_x.abs() <= FERRIS_WIDTH / 2. && local_y.abs() <= FERRIS_HEIGHT / 2.
+    }
+
+    /// Return true if the left mouse button is currently pressed. The state is
+    /// updated by `handle_events()`.
+    pub fn is_mouse_down(&self) -> bool {
+        self.mouse_down
+    }
+
+    /// Returns true if the key with the given name is pressed, see
+    /// `key_name`. The state is updated by `handle_events()`.
+    pub fn is_key_down(&self, key: &str) -> bool {
+        self.pressed_keys.contains(&key.to_lowercase())
+    }
+
+    /// Return if the window has been closed. A closed window can only be
+    /// detected if the window's events have been handled. Thus it is advised to
+    /// use `handle_events()` before checking `is_closed()`.
+    pub fn is_closed(&self) -> bool {
+        self._is_closed
+    }
+
+    /// Return the current screen as an image. The image has the logical size
+    /// of the canvas, so it looks the same on every display. Use
+    /// `native_screenshot` to get the image at device resolution.
+    pub fn screenshot(&self) -> RenderResult<image::DynamicImage> {
+        let image = try!(self.native_screenshot());
+        let factor = self.hidpi_factor();
+        if factor == 1.0 {
+            return Ok(image);
+        }
+        let (width, height) = image.dimensions();
+        Ok(image.resize_exact((width as f32 / factor) as u32, (height as f32 / factor) as u32,
+                              image::FilterType::Lanczos3))
+    }
+
+    /// Return the current screen as an image with the full device resolution.
+    /// If the canvas is larger than the window, the whole canvas is rendered.
+    /// The minimap is never part of the image.
+    pub fn native_screenshot(&self) -> RenderResult<image::DynamicImage> {
+        if self.options.canvas.is_some() || self.overlay.minimap {
+            return self.render_offscreen(self.hidpi_factor(), !self.turtle_hidden);
+        }
+        if self.options.headless {
+            return self.render_offscreen(1.0, !self.turtle_hidden);
+        }
+        Ok(raw_image_to_image(self.window.read_front_buffer()))
+    }
+
+    /// Export the drawing as PostScript file. Lines and texts are written as
+    /// vectors, filled areas and stamped images are embedded as bitmaps. The
+    /// turtle itself is not exported.
+    ///
+    /// Note that PostScript has no transparency, transparent parts of stamped
+    /// images are replaced by the background color.
+    pub fn export_ps(&self, path: &str) -> io::Result<()> {
+        let mut file = io::BufWriter::new(try!(fs::File::create(path)));
+        let ((width, height), view) = self.capture_area();
+        let (width, height) = (width.ceil() as u32, height.ceil() as u32);
+        try!(writeln!(file, "%!PS-Adobe-3.0 EPSF-3.0"));
+        try!(writeln!(file, "%%Creator: Rurtle"));
+        try!(writeln!(file, "%%BoundingBox: 0 0 {} {}", width, height));
+        try!(writeln!(file, "%%EndComments"));
+        let (br, bg, bb, _) = self.background_color;
+        try!(writeln!(file, "{} {} {} setrgbcolor 0 0 {} {} rectfill", br, bg, bb, width, height));
+        // Move the origin to the middle of the page, like the turtle coordinates
+        try!(writeln!(file, "{} {} translate", width as f32 / 2., height as f32 / 2.));
+        let View { center: (cx, cy), zoom } = view;
+        try!(writeln!(file, "{} {} scale {} {} translate", zoom, zoom, -cx, -cy));
+        try!(writeln!(file, "1 setlinewidth 1 setlinecap"));
+        try!(writeln!(file, "/DejaVuSansMono findfont 12 scalefont setfont"));
+        let factor = self.hidpi_factor();
+        for &(_, ref shape) in &self.shapes {
+            match *shape {
+                Shape::Line(Line(x1, y1, x2, y2, (r, g, b, _), width)) => {
+                    try!(writeln!(file, "{} {} {} setrgbcolor {} setlinewidth \
+                                         {} {} moveto {} {} lineto stroke",
+                                  r, g, b, width, x1, y1, x2, y2));
+                },
+                Shape::Text(Text(x, y, angle, (r, g, b, _), ref text, _)) => {
+                    try!(writeln!(file, "gsave {} {} {} setrgbcolor {} {} translate {} rotate \
+                                         0 0 moveto ({}) show grestore",
+                                  r, g, b, x, y, angle, ps_escape(text)));
+                },
+                Shape::Fill(Fill(x, y, zoom, ref texture)) => {
+                    let patch = raw_image_to_image(texture.read());
+                    try!(write_ps_fill(&mut file, x, y, factor * zoom, &patch));
+                },
+                Shape::Stamp(Stamp(x, y, angle, scale, ref texture)) => {
+                    let image = raw_image_to_image(texture.read());
+                    try!(write_ps_stamp(&mut file, (x, y), angle, scale, &image,
+                                        self.background_color));
+                },
+                Shape::Points(Points(ref points, (r, g, b, _), size, _)) => {
+                    try!(writeln!(file, "{} {} {} setrgbcolor", r, g, b));
+                    for &(x, y) in points {
+                        try!(writeln!(file, "{} {} {} {} rectfill",
+                                      x - size / 2., y - size / 2., size, size));
+                    }
+                },
+            }
+        }
+        try!(writeln!(file, "showpage"));
+        try!(writeln!(file, "%%EOF"));
+        Ok(())
+    }
+}
+
+impl RenderBackend for TurtleScreen {
+    fn add_line(&mut self, start: (f32, f32), end: (f32, f32), color: color::Color) {
+        TurtleScreen::add_line(self, start, end, color)
+    }
+
+    fn plot_points(&mut self, points: &[(f32, f32)], color: color::Color) {
+        TurtleScreen::plot_points(self, points, color)
+    }
+
+    fn add_text(&mut self, anchor: (f32, f32), angle: f32, color: color::Color, text: &str)
+                -> RenderResult<()>
+    {
+        TurtleScreen::add_text(self, anchor, angle, color, text)
+    }
+
+    fn add_fallback_font(&mut self, path: &str) -> io::Result<()> {
+        TurtleScreen::add_fallback_font(self, path)
+    }
+
+    fn fill(&mut self, point: (f32, f32), color: color::Color) -> RenderResult<()> {
+        self.floodfill(point, color)
+    }
+
+    fn stamp_image(&mut self, path: &str, position: (f32, f32), rotation: f32, scale: f32)
+                   -> RenderResult<()>
+    {
+        TurtleScreen::stamp_image(self, path, position, rotation, scale)
+    }
+
+    fn clear(&mut self) {
+        TurtleScreen::clear(self)
+    }
+
+    fn add_annotation(&mut self, annotation: Annotation) -> RenderResult<()> {
+        TurtleScreen::add_annotation(self, annotation)
+    }
+
+    fn clear_annotations(&mut self) {
+        TurtleScreen::clear_annotations(self)
+    }
+
+    fn apply_filter(&mut self, filter: Filter) -> RenderResult<()> {
+        TurtleScreen::apply_filter(self, filter)
+    }
+
+    fn set_cursor(&mut self, cursor: Cursor) {
+        self.turtle_position = cursor.position;
+        self.turtle_orientation = cursor.orientation;
+        self.turtle_color = cursor.color;
+        self.turtle_hidden = cursor.hidden;
+    }
+
+    fn set_background_color(&mut self, color: color::Color) {
+        self.background_color = color;
+    }
+
+    fn set_smooth_lines(&mut self, smooth: bool) {
+        self.smooth_lines = smooth;
+    }
+
+    fn smooth_lines(&self) -> bool {
+        self.smooth_lines
+    }
+
+    fn set_pen_size(&mut self, size: f32) {
+        self.pen_size = size;
+    }
+
+    fn set_fullscreen(&mut self, fullscreen: bool) -> RenderResult<()> {
+        TurtleScreen::set_fullscreen(self, fullscreen)
+    }
+
+    fn draw_and_update(&mut self) {
+        TurtleScreen::draw_and_update(self)
+    }
+
+    fn pump_events(&mut self) {
+        self.handle_events()
+    }
+
+    fn is_closed(&self) -> bool {
+        TurtleScreen::is_closed(self)
+    }
+
+    fn canvas_size(&self) -> (f32, f32) {
+        self.capture_area().0
+    }
+
+    fn visible_size(&self) -> (f32, f32) {
+        self.logical_size()
+    }
+
+    fn set_overlay(&mut self, overlay: Overlay) {
+        self.overlay = overlay;
+    }
+
+    fn drawing_bounds(&self) -> Option<Bounds> {
+        TurtleScreen::drawing_bounds(self)
+    }
+
+    fn line_segments(&self) -> Vec<((f32, f32), (f32, f32))> {
+        TurtleScreen::line_segments(self)
+    }
+
+    fn set_view(&mut self, view: View) {
+        self.view = view;
+    }
+
+    fn view(&self) -> View {
+        self.view
+    }
+
+    fn set_symmetry(&mut self, symmetry: Symmetry) {
+        self.symmetry = symmetry;
+    }
+
+    fn set_trail(&mut self, length: Option<usize>) {
+        self.trail = length;
+        self.prune_trail();
+    }
+
+    fn begin_foreground(&mut self) {
+        self.foreground_start = self.shapes.len();
+    }
+
+    fn set_visible_lines(&mut self, count: Option<usize>) {
+        self.visible_lines = count;
+    }
+
+    fn line_count(&self) -> usize {
+        self.shapes.iter()
+            .filter(|&&(_, ref shape)| match *shape { Shape::Line(_) => true, _ => false })
+            .count()
+    }
+
+    fn clear_foreground(&mut self) {
+        self.shapes.truncate(self.foreground_start);
+    }
+
+    fn set_owner(&mut self, owner: usize) {
+        self.owner = owner;
+    }
+
+    fn clear_owner(&mut self, owner: usize) {
+        let removed = backend::remove_shapes(&mut self.shapes, self.foreground_start,
+                                             |&(shape_owner, _)| shape_owner == owner);
+        self.foreground_start -= removed;
+    }
+
+    fn undo_owner(&mut self, owner: usize) -> bool {
+        match self.shapes.iter().rposition(|&(shape_owner, _)| shape_owner == owner) {
+            Some(index) => {
+                self.shapes.remove(index);
+                if index < self.foreground_start {
+                    self.foreground_start -= 1;
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn mouse_position(&self) -> (f32, f32) {
+        TurtleScreen::mouse_position(self)
+    }
+
+    fn is_mouse_down(&self) -> bool {
+        TurtleScreen::is_mouse_down(self)
+    }
+
+    fn is_key_down(&self, key: &str) -> bool {
+        TurtleScreen::is_key_down(self, key)
+    }
+
+    fn take_clicks(&mut self) -> Vec<(f32, f32)> {
+        TurtleScreen::take_clicks(self)
+    }
+
+    fn hits_turtle(&self, point: (f32, f32)) -> bool {
+        TurtleScreen::hits_turtle(self, point)
+    }
+
+    fn max_image_size(&self) -> u32 {
+        TurtleScreen::max_image_size(self)
+    }
+
+    fn screenshot(&self) -> RenderResult<image::DynamicImage> {
+        TurtleScreen::screenshot(self)
+    }
+
+    fn native_screenshot(&self) -> RenderResult<image::DynamicImage> {
+        TurtleScreen::native_screenshot(self)
+    }
+
+    fn render_image(&self, scale: f32) -> RenderResult<image::DynamicImage> {
+        TurtleScreen::render_image(self, scale)
+    }
+
+    fn export_ps(&self, path: &str) -> io::Result<()> {
+        TurtleScreen::export_ps(self, path)
+    }
+}
+
+/// Return a `WindowBuilder` configured with the given parameters
+fn window_builder<'a>(size: (u32, u32), title: &str, options: ScreenOptions, fullscreen: bool)
+                      -> glium::glutin::WindowBuilder<'a>
+{
+    let size = if options.maximized {
+        glium::glutin::get_primary_monitor().get_dimensions()
+    } else {
+        size
+    };
+    let mut builder = glium::glutin::WindowBuilder::new()
+        .with_title(title.to_owned())
+        .with_dimensions(size.0, size.1)
+        .with_decorations(!options.borderless);
+    if options.multisampling > 0 {
+        builder = builder.with_multisampling(options.multisampling);
+    }
+    if fullscreen {
+        builder = builder.with_fullscreen(glium::glutin::get_primary_monitor());
+    }
+    builder
+}
+
+/// Move the window to the position given in the options. The `WindowBuilder`
+/// has no way to set the position, so this has to be done after creation.
+fn place_window(display: &glium::backend::glutin_backend::GlutinFacade, options: ScreenOptions) {
+    let position = if options.maximized { Some((0, 0)) } else { options.position };
+    if let (Some((x, y)), Some(window)) = (position, display.get_window()) {
+        window.set_position(x, y);
+    }
+}
+
+/// Build the matrix that projects turtle coordinates onto a surface of the given
+/// size (in pixels) with `scale` pixels per unit, showing the part of the
+/// drawing given by `view`
+fn projection_matrix((width, height): (u32, u32), scale: f32, view: View) -> ScaleMatrix {
+    let (sx, sy) = (2.0 * scale * view.zoom / width as f32,
+                    2.0 * scale * view.zoom / height as f32);
+    [
+        [sx, 0.0, 0.0, 0.0],
+        [0.0, sy, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [-view.center.0 * sx, -view.center.1 * sy, 0.0, 1.0],
+    ]
+}
+
+/// Return the name of the key as used by `KEYDOWN?`: the lowercase name of
+/// the key code, e.g. "left", "space" or "a", and the digits "0" to "9"
+fn key_name(key: glium::glutin::VirtualKeyCode) -> String {
+    let name = format!("{:?}", key).to_lowercase();
+    // The digit keys are called Key0 to Key9
+    if name.len() == 4 && name.starts_with("key") {
+        name[3..].to_owned()
+    } else {
+        name
+    }
+}
+
+/// Escape the special characters of a PostScript string literal
+fn ps_escape(text: &str) -> String {
+    text.replace("\\", "\\\\")
+        .replace("(", "\\(")
+        .replace(")", "\\)")
+        .replace("\n", "\\n")
+        .replace("\r", "\\r")
+}
+
+/// Write a filled area as PostScript `imagemask`. Fill patches only have a
+/// single color, every non-transparent pixel is painted with it.
+fn write_ps_fill<W: Write>(out: &mut W, x: f32, y: f32, factor: f32,
+                           patch: &image::DynamicImage) -> io::Result<()> {
+    let (pw, ph) = patch.dimensions();
+    let color = patch.pixels()
+        .map(|(_, _, pixel)| pixel.data)
+        .find(|data| data[3] > 0)
+        .unwrap_or([0, 0, 0, 0]);
+    try!(writeln!(out, "gsave {} {} {} setrgbcolor", color[0] as f32 / 255.,
+                  color[1] as f32 / 255., color[2] as f32 / 255.));
+    try!(writeln!(out, "{} {} translate {} {} scale", x, y - ph as f32 / factor,
+                  pw as f32 / factor, ph as f32 / factor));
+    try!(writeln!(out, "{} {} true [{} 0 0 -{} 0 {}] <", pw, ph, pw, ph, ph));
+    // Each row is padded to a full byte
+    for py in 0..ph {
+        let mut byte = 0u8;
+        for px in 0..pw {
+            if patch.get_pixel(px, py).data[3] > 0 {
+                byte |= 0x80 >> (px % 8);
+            }
+            if px % 8 == 7 || px == pw - 1 {
+                try!(write!(out, "{:02x}", byte));
+                byte = 0;
+            }
+        }
+        try!(writeln!(out, ""));
+    }
+    writeln!(out, "> imagemask grestore")
+}
+
+/// Write a stamped image as PostScript `colorimage`
+fn write_ps_stamp<W: Write>(out: &mut W, center: (f32, f32), angle: f32, scale: f32,
+                            image: &image::DynamicImage, background: color::Color)
+                            -> io::Result<()> {
+    let (pw, ph) = image.dimensions();
+    let (w, h) = (pw as f32 * scale, ph as f32 * scale);
+    try!(writeln!(out, "gsave {} {} translate {} rotate {} {} translate {} {} scale",
+                  center.0, center.1, angle, -w / 2., -h / 2., w, h));
+    try!(writeln!(out, "{} {} 8 [{} 0 0 -{} 0 {}] <", pw, ph, pw, ph, ph));
+    let (br, bg, bb, _) = background;
+    let background = [(br * 255.) as u8, (bg * 255.) as u8, (bb * 255.) as u8];
+    for py in 0..ph {
+        for px in 0..pw {
+            let data = image.get_pixel(px, py).data;
+            // Same threshold as in the ferris fragment shader
+            let rgb = if data[3] < 128 { background } else { [data[0], data[1], data[2]] };
+            try!(write!(out, "{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2]));
+        }
+        try!(writeln!(out, ""));
+    }
+    writeln!(out, "> false 3 colorimage grestore")
+}
+
+/// Convert an image::DynamicImage to a glium::texture::Texture2d
+fn image_to_texture<F: glium::backend::Facade>(display: &F, im: image::DynamicImage)
+    -> Result<glium::texture::Texture2d, glium::texture::TextureCreationError>
+{
+    let glium_image = glium::texture::RawImage2d::from_raw_rgba_reversed(
+        im.to_rgba().into_raw(), im.dimensions()
+    );
+    glium::texture::Texture2d::new(display, glium_image)
+}
+
+/// Convert a glium::texture::RawImage2d to an image::DynamicImage
+fn raw_image_to_image(tex: glium::texture::RawImage2d<u8>) -> image::DynamicImage {
+    assert_eq!(tex.format, glium::texture::ClientFormat::U8U8U8U8);
+    let channels = tex.format.get_num_components() as usize;
+    // opengl gives us rows from bottom to top, so we need to flip them
+    let data: Vec<u8> = tex.data
+        .chunks(tex.width as usize * channels)
+        .rev()
+        .flat_map(|row| row.iter())
+        .cloned()
+        .collect();
+    let buffer = image::ImageBuffer::from_vec(tex.width, tex.height, data);
+    image::DynamicImage::ImageRgba8(buffer.expect("Conversion to DynamicImage failed"))
+}
@@ -0,0 +1,81 @@
+//! Iterated function systems (IFS) like the Barnsley fern.
+//!
+//! An IFS is a set of affine maps, each of which is chosen with a given
+//! probability. The "chaos game" starts at the origin and applies a randomly
+//! chosen map over and over again, the visited points approximate the
+//! attractor of the system. An attractor needs many thousand points, far too
+//! many to compute them in Rurtle itself, so `draw` computes them natively and
+//! plots them all at once.
+use super::turtle::{Turtle, TurtleResult};
+
+/// The first points are skipped, they haven't reached the attractor yet
+const SKIP: usize = 20;
+/// The most points `attractor` computes at once, more wouldn't fit in memory
+pub const MAX_POINTS: usize = 10_000_000;
+/// Seed of the random number generator. A fixed seed makes drawings
+/// reproducible.
+const SEED: u32 = 2463534242;
+
+/// The affine map `(x, y) -> (a*x + b*y + e, c*x + d*y + f)`, chosen with a
+/// probability proportional to `weight`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AffineMap {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+    pub weight: f32,
+}
+
+impl AffineMap {
+    pub fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (self.a * x + self.b * y + self.e, self.c * x + self.d * y + self.f)
+    }
+}
+
+/// A xorshift pseudo random number generator. The chaos game doesn't need
+/// good random numbers, but it needs many of them.
+struct Random(u32);
+
+impl Random {
+    /// Return a number between 0 and 1
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / ::std::u32::MAX as f32
+    }
+}
+
+/// Return `count` points of the attractor of the given maps. The weights
+/// don't need to add up to 1, but they must not be negative and at least one
+/// of them has to be positive. `count` must not be greater than `MAX_POINTS`.
+pub fn attractor(maps: &[AffineMap], count: usize) -> Vec<(f32, f32)> {
+    assert!(count <= MAX_POINTS, "too many points: {}", count);
+    let total: f32 = maps.iter().map(|map| map.weight).sum();
+    let mut random = Random(SEED);
+    let mut point = (0., 0.);
+    let mut points = Vec::with_capacity(count);
+    for i in 0..count + SKIP {
+        let mut choice = random.next() * total;
+        // Rounding may leave a tiny rest, the last map takes it
+        let map = maps.iter()
+                      .find(|map| { choice -= map.weight; choice < 0. })
+                      .unwrap_or(&maps[maps.len() - 1]);
+        point = map.apply(point);
+        if i >= SKIP {
+            points.push(point);
+        }
+    }
+    points
+}
+
+/// Plot `count` points of the attractor with the turtle's color and pen size,
+/// see `attractor`. Returns an error if the points aren't finite, e.g.
+/// because the maps don't shrink the plane and the points escape to
+/// infinity.
+pub fn draw(turtle: &mut Turtle, maps: &[AffineMap], count: usize) -> TurtleResult {
+    turtle.plot_points(&attractor(maps, count))
+}
@@ -3,7 +3,8 @@
 //! the interpreter.
 //!
 //! Valid identifiers start with any (unicode) alphabetic character and may
-//! consist of any alpha-numeric character thereafter.
+//! consist of any alpha-numeric character thereafter. Identifiers may also
+//! contain question marks, which is used for predicates such as `MOUSEDOWN?`.
 //!
 //! Strings have to be enclosed in double quotes ("), there are no strings in
 //! enclosed in lists. For example, this is valid: "Hello", this is not: [Hello]
@@ -20,6 +21,8 @@ pub enum Token {
     /// An identifier, also called Word
     Word(String),
     Number(f32),
+    /// A number literal without decimal point
+    Integer(i64),
     /// The left bracket [
     LBracket,
     /// The right bracket ]
@@ -74,6 +77,20 @@ pub enum Token {
     KeyReturn,
     /// Keyword "TRY"
     KeyTry,
+    /// Keyword "ONTURTLECLICK"
+    KeyOnTurtleClick,
+    /// Keyword "ELIF"
+    KeyElif,
+    /// Keyword "IFELSE"
+    KeyIfElse,
+    /// Keyword "LAMBDA"
+    KeyLambda,
+    /// Keyword "BREAK"
+    KeyBreak,
+    /// Keyword "CONTINUE"
+    KeyContinue,
+    /// Keyword ".MACRO". With the dot, plain `MACRO` stays free as a name.
+    KeyMacro,
 }
 
 impl ::std::fmt::Display for Token {
@@ -82,7 +99,7 @@ impl ::std::fmt::Display for Token {
         let debug = format!("{:?}", self);
         fmt.pad(match *self {
             Word(_) => "word",
-            Number(_) => "number",
+            Number(_) | Integer(_) => "number",
             LBracket => "left bracket",
             RBracket => "right bracket",
             LParens => "left parenthesis",
@@ -142,12 +159,51 @@ impl ::std::error::Error for LexError {
     }
 }
 
+/// Quote the given string so that the lexer reads it back unchanged
+pub fn quote(string: &str) -> String {
+    let escaped = string.replace("\\", "\\\\")
+        .replace("\"", "\\\"")
+        .replace("\n", "\\n")
+        .replace("\r", "\\r");
+    format!("\"{}\"", escaped)
+}
+
+/// Format the number as literal. Negative numbers are enclosed in parenthesis
+/// so that they can't be mistaken for a subtraction.
+pub fn number_literal(x: f32) -> String {
+    // There are no literals for NaN and infinity, but divisions give them
+    if x.is_nan() {
+        "(0 / 0)".to_owned()
+    } else if x.is_infinite() {
+        if x > 0. { "(1 / 0)".to_owned() } else { "(-1 / 0)".to_owned() }
+    } else if x.fract() == 0. {
+        // Keep the decimal point, otherwise the literal becomes an integer
+        float_literal(format!("{:.1}", x), x)
+    } else {
+        float_literal(format!("{}", x), x)
+    }
+}
+
+/// Enclose the literal in parenthesis if the number is negative
+fn float_literal(literal: String, x: f32) -> String {
+    if x < 0. { format!("({})", literal) } else { literal }
+}
+
+/// Format the integer as literal, see `number_literal`
+pub fn integer_literal(x: i64) -> String {
+    if x < 0 {
+        format!("({})", x)
+    } else {
+        format!("{}", x)
+    }
+}
+
 fn is_identifier_start(c: char) -> bool {
     c.is_alphabetic() || c == '_'
 }
 
 fn is_identifier_cont(c: char) -> bool {
-    is_identifier_start(c) || c.is_alphanumeric()
+    is_identifier_start(c) || c.is_alphanumeric() || c == '?'
 }
 
 struct Tokenizer {
@@ -244,7 +300,13 @@ impl Tokenizer {
                         "WHILE" => Token::KeyWhile,
                         "RETURN" => Token::KeyReturn,
                         "ELSE" => Token::KeyElse,
+                        "ELIF" => Token::KeyElif,
+                        "IFELSE" => Token::KeyIfElse,
                         "TRY" => Token::KeyTry,
+                        "ONTURTLECLICK" => Token::KeyOnTurtleClick,
+                        "LAMBDA" => Token::KeyLambda,
+                        "BREAK" => Token::KeyBreak,
+                        "CONTINUE" => Token::KeyContinue,
                         _ => Token::Word(word),
                     });
                 },
@@ -258,9 +320,11 @@ impl Tokenizer {
                             break
                         }
                     }
-                    match number.parse() {
-                        Ok(f) => self.push(Token::Number(f)),
-                        Err(_) => return Err(LexError::InvalidNumber(self.line_number, number)),
+                    // Integers that don't fit into an i64 become floats
+                    match (number.parse(), number.parse()) {
+                        (Ok(n), _) => self.push(Token::Integer(n)),
+                        (_, Ok(f)) => self.push(Token::Number(f)),
+                        _ => return Err(LexError::InvalidNumber(self.line_number, number)),
                     }
                 },
                 // Parse a String literal
@@ -304,6 +368,21 @@ impl Tokenizer {
                         return Err(LexError::UnterminatedString(self.line_number));
                     }
                 },
+                // The only word with a leading dot is the keyword .MACRO
+                '.' => {
+                    let mut word = String::new();
+                    while let Some(c) = chars.peek().cloned() {
+                        if is_identifier_cont(c) {
+                            word.push(chars.next().unwrap());
+                        } else {
+                            break
+                        }
+                    }
+                    if word.to_uppercase() != "MACRO" {
+                        return Err(LexError::UnexpectedCharacter(self.line_number, c));
+                    }
+                    self.push(Token::KeyMacro);
+                },
                 '\n' => self.line_number += 1,
                 _ if c.is_whitespace() => {},
                 _ => return Err(LexError::UnexpectedCharacter(self.line_number, c)),
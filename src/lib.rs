@@ -1,5 +1,10 @@
 //! rurtle provides turtle-graphics in Rust
 //!
+//! To run Rurtle code from your own program, see the `embed` module and
+//! `Rurtle`. Additional functions can be bundled as plugins, see the `plugin`
+//! module. The `testing` module helps to test the drawn output against stored
+//! images.
+//!
 //! # Windows support
 //!
 //! rurtle should run fine under Windows, it uses no Linux-specific functions.
@@ -10,14 +15,45 @@
 #![cfg_attr(feature = "linted", plugin(clippy))]
 
 extern crate bit_vec;
+extern crate fontdue;
+#[cfg(feature = "native")]
 #[macro_use]
 extern crate glium;
+#[cfg(feature = "native")]
 extern crate glium_text;
 extern crate image;
+#[cfg(feature = "native")]
 extern crate nalgebra as na;
+#[cfg(feature = "native")]
+extern crate rodio;
+extern crate rustc_serialize;
+#[cfg(feature = "fetch")]
+extern crate hyper;
+#[cfg(feature = "web")]
+extern crate wasm_bindgen;
+#[cfg(feature = "web")]
+extern crate web_sys;
+#[cfg(feature = "web")]
+extern crate instant;
+#[cfg(feature = "bignum")]
+extern crate num;
+#[cfg(feature = "midi")]
+extern crate midir;
+
+pub mod backend;
+pub use backend::RenderBackend;
+
+pub mod software;
+pub use software::SoftwareBackend;
+
+pub mod terminal;
+pub use terminal::TerminalBackend;
 
 pub mod graphic;
+#[cfg(feature = "native")]
 pub use graphic::TurtleScreen;
+#[cfg(feature = "native")]
+pub use graphic::ScreenOptions;
 pub use graphic::color;
 
 pub mod turtle;
@@ -31,7 +67,56 @@ pub use parse::Parser;
 
 pub mod environ;
 pub use environ::Environment;
+pub use environ::RuntimeError;
+pub use environ::value::Value;
+
+pub mod embed;
+pub use embed::Rurtle;
+
+pub mod plugin;
+pub use plugin::Plugin;
 
 pub mod readline;
 
 pub mod floodfill;
+
+pub mod clipboard;
+
+pub mod sound;
+
+pub mod space;
+
+pub mod physics;
+
+pub mod transform;
+
+pub mod plot;
+
+pub mod ifs;
+
+pub mod replay;
+
+pub mod video;
+
+pub mod text;
+
+pub mod server;
+
+pub mod stats;
+
+pub mod testing;
+
+pub mod diff;
+
+pub mod filter;
+
+pub mod path;
+
+pub mod plotter;
+
+pub mod robot;
+
+pub mod midi;
+
+#[cfg(feature = "web")]
+pub mod web;
@@ -2,12 +2,26 @@
 #![cfg_attr(feature = "linted", plugin(clippy))]
 
 extern crate bit_vec;
+extern crate fontdue;
 #[macro_use]
 extern crate glium;
 extern crate glium_text;
 extern crate image;
 extern crate nalgebra as na;
+extern crate rodio;
+extern crate rustc_serialize;
+#[cfg(feature = "fetch")]
+extern crate hyper;
+#[cfg(feature = "bignum")]
+extern crate num;
+#[cfg(feature = "midi")]
+extern crate midir;
+#[cfg(feature = "web")]
+extern crate instant;
 
+pub mod backend;
+pub mod software;
+pub mod terminal;
 pub mod graphic;
 pub mod turtle;
 pub mod lex;
@@ -15,30 +29,419 @@ pub mod parse;
 pub mod environ;
 pub mod readline;
 pub mod floodfill;
+pub mod clipboard;
+pub mod sound;
+pub mod space;
+pub mod physics;
+pub mod transform;
+pub mod plot;
+pub mod ifs;
+pub mod replay;
+pub mod video;
+pub mod text;
+pub mod server;
+pub mod stats;
+pub mod diff;
+pub mod filter;
+pub mod path;
+pub mod plotter;
+pub mod robot;
+pub mod midi;
 
-use std::{env, fs, thread, time};
+use std::{env, fs, process, thread, time};
 use std::error::Error;
 use std::io::Read;
 use std::sync::mpsc;
 
 const PROMPT: &'static str = "Rurtle> ";
 
+const USAGE: &'static str =
+    "Usage: rurtle [--size WxH] [--canvas WxH] [--position X,Y] [--maximized] \
+     [--headless [--software] --output FILE | --watch] [--terminal] [--serve ADDRESS \
+     [--serve-unsandboxed]] [--max-statements N] [--max-depth N] [--max-segments N] \
+     [--max-time SECONDS] [--sandbox] [--robot PORT [--robot-protocol FILE]] \
+     [script [args...]]\n       \
+     rurtle [--size WxH] test DIRECTORY\n       \
+     rurtle [--size WxH] [--output FILE] diff REFERENCE DRAWING";
+
+/// Window size if none is given on the command line
+const DEFAULT_SIZE: (u32, u32) = (640, 640);
+
+/// Options given on the command line
+#[derive(Debug)]
+struct Options {
+    size: (u32, u32),
+    /// Size of a scrollable canvas that is larger than the window
+    canvas: Option<(u32, u32)>,
+    position: Option<(i32, i32)>,
+    maximized: bool,
+    /// Run the script without a window and save the result to `output`
+    headless: bool,
+    /// Render on the CPU instead of using OpenGL (headless mode only)
+    software: bool,
+    output: Option<String>,
+    /// Re-run the script whenever it changes
+    watch: bool,
+    /// Draw into the terminal instead of a window
+    terminal: bool,
+    /// Accept commands over TCP on this address
+    serve: Option<String>,
+    /// Don't run the commands received over TCP in sandbox mode
+    serve_unsandboxed: bool,
+    script: Option<String>,
+    /// Arguments after the script's filename, passed to the script
+    script_args: Vec<String>,
+    /// Run the test files in this directory
+    test_directory: Option<String>,
+    /// Compare the two drawings, see the `diff` module
+    diff: Option<(String, String)>,
+    /// Resource limits for the script and each command
+    limits: environ::Limits,
+    /// Disable the built-in functions that access files and the network
+    sandbox: bool,
+    /// Mirror the drawing to a robot connected to this serial port
+    robot: Option<String>,
+    /// File with the commands the robot understands, see `RobotProtocol`
+    robot_protocol: Option<String>,
+}
+
+/// Parse the command line arguments (without the program name). Options have
+/// to come before the script, everything after the script belongs to it.
+fn parse_args<I: Iterator<Item=String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        size: DEFAULT_SIZE,
+        canvas: None,
+        position: None,
+        maximized: false,
+        headless: false,
+        software: false,
+        output: None,
+        watch: false,
+        terminal: false,
+        serve: None,
+        serve_unsandboxed: false,
+        script: None,
+        script_args: Vec::new(),
+        test_directory: None,
+        diff: None,
+        limits: environ::Limits::default(),
+        sandbox: false,
+        robot: None,
+        robot_protocol: None,
+    };
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--size" => match args.next().as_ref().and_then(|s| parse_pair(s, 'x')) {
+                Some(size) => options.size = size,
+                None => return Err("--size requires a size like 1280x720".to_owned()),
+            },
+            "--canvas" => match args.next().as_ref().and_then(|s| parse_pair(s, 'x')) {
+                Some(canvas) => options.canvas = Some(canvas),
+                None => return Err("--canvas requires a size like 8000x8000".to_owned()),
+            },
+            "--position" => match args.next().as_ref().and_then(|s| parse_pair(s, ',')) {
+                Some(position) => options.position = Some(position),
+                None => return Err("--position requires a position like 100,100".to_owned()),
+            },
+            "--maximized" => options.maximized = true,
+            "--headless" => options.headless = true,
+            "--watch" => options.watch = true,
+            "--software" => options.software = true,
+            "--terminal" => options.terminal = true,
+            "--sandbox" => options.sandbox = true,
+            "--serve-unsandboxed" => options.serve_unsandboxed = true,
+            "--serve" => match args.next() {
+                Some(address) => options.serve = Some(address),
+                None => return Err("--serve requires an address like 127.0.0.1:7777".to_owned()),
+            },
+            "--robot" => match args.next() {
+                Some(port) => options.robot = Some(port),
+                None => return Err("--robot requires a serial port like /dev/ttyUSB0".to_owned()),
+            },
+            "--robot-protocol" => match args.next() {
+                Some(file) => options.robot_protocol = Some(file),
+                None => return Err("--robot-protocol requires a filename".to_owned()),
+            },
+            "--output" => match args.next() {
+                Some(output) => options.output = Some(output),
+                None => return Err("--output requires a filename".to_owned()),
+            },
+            "--max-statements" => {
+                options.limits.statements = Some(try!(parse_limit(args.next(), &arg)));
+            },
+            "--max-depth" => {
+                options.limits.call_depth = Some(try!(parse_limit(args.next(), &arg)));
+            },
+            "--max-segments" => {
+                options.limits.segments = Some(try!(parse_limit(args.next(), &arg)));
+            },
+            "--max-time" => {
+                let seconds: f32 = try!(parse_limit(args.next(), &arg));
+                options.limits.time = Some(time::Duration::from_millis((seconds * 1000.) as u64));
+            },
+            option if option.starts_with("--") => {
+                return Err(format!("unknown option: {}", option));
+            },
+            "test" => match args.next() {
+                Some(directory) => {
+                    options.test_directory = Some(directory);
+                    break;
+                },
+                None => return Err("test requires a directory".to_owned()),
+            },
+            "diff" => match (args.next(), args.next()) {
+                (Some(reference), Some(drawing)) => {
+                    options.diff = Some((reference, drawing));
+                    break;
+                },
+                _ => return Err("diff requires a reference and a drawing".to_owned()),
+            },
+            _ => {
+                options.script = Some(arg);
+                options.script_args = args.collect();
+                break;
+            },
+        }
+    }
+    if options.test_directory.is_some() && (options.headless || options.watch ||
+                                            options.terminal) {
+        return Err("test can't be used with --headless, --watch or --terminal".to_owned());
+    }
+    if options.diff.is_some() && (options.headless || options.watch || options.terminal) {
+        return Err("diff can't be used with --headless, --watch or --terminal".to_owned());
+    }
+    if options.headless && (options.output.is_none() || options.script.is_none()) {
+        return Err("--headless requires --output and a script".to_owned());
+    }
+    if options.robot_protocol.is_some() && options.robot.is_none() {
+        return Err("--robot-protocol can only be used with --robot".to_owned());
+    }
+    if options.software && !options.headless {
+        return Err("--software can only be used with --headless".to_owned());
+    }
+    if options.serve_unsandboxed && (options.serve.is_none() || options.sandbox) {
+        return Err("--serve-unsandboxed requires --serve and can't be used with --sandbox"
+                   .to_owned());
+    }
+    if options.serve.is_some() && options.headless {
+        return Err("--serve can't be used with --headless".to_owned());
+    }
+    if options.terminal && options.headless {
+        return Err("--terminal can't be used with --headless".to_owned());
+    }
+    if options.watch && (options.headless || options.script.is_none()) {
+        return Err("--watch requires a script and can't be used with --headless".to_owned());
+    }
+    Ok(options)
+}
+
+/// Parse the number given to a limit option like `--max-statements`
+fn parse_limit<T: std::str::FromStr>(value: Option<String>, option: &str) -> Result<T, String> {
+    value.as_ref().and_then(|v| v.parse().ok()).ok_or(format!("{} requires a number", option))
+}
+
+/// Return the size of the terminal in characters from the COLUMNS and LINES
+/// environment variables. Two lines are left for the prompt.
+fn terminal_size() -> (u32, u32) {
+    let get = |name, default| env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+    let (columns, lines): (u32, u32) = (get("COLUMNS", 80), get("LINES", 24));
+    (columns, lines.saturating_sub(2).max(1))
+}
+
+/// Parse two numbers separated by `separator`, e.g. "1280x720"
+fn parse_pair<T: std::str::FromStr>(string: &str, separator: char) -> Option<(T, T)> {
+    let mut parts = string.splitn(2, separator);
+    match (parts.next().map(str::parse), parts.next().map(str::parse)) {
+        (Some(Ok(a)), Some(Ok(b))) => Some((a, b)),
+        _ => None,
+    }
+}
+
+/// Return the time the file was last modified, if it can be determined
+fn modification_time(filename: &str) -> Option<time::SystemTime> {
+    fs::metadata(filename).and_then(|m| m.modified()).ok()
+}
+
+/// Read the file and evaluate it. Errors are printed.
+fn run_file(environ: &mut environ::Environment, filename: &str) -> bool {
+    let mut source = String::new();
+    if let Err(e) = fs::File::open(filename).and_then(|mut f| f.read_to_string(&mut source)) {
+        println!("[error] {}: {}", filename, e);
+        return false;
+    }
+    if let Err(e) = environ.eval_source(&source) {
+        println!("[error] {}:", filename);
+        println!("{}: {}", e.description(), e);
+        return false;
+    }
+    true
+}
+
+/// Run every .rtl file in the directory with a fresh environment and print the
+/// results. Errors outside of test cases count as a failure. Returns true if
+/// all tests passed.
+fn run_tests(directory: &str, size: (u32, u32)) -> bool {
+    let entries = match fs::read_dir(directory) {
+        Ok(e) => e,
+        Err(e) => {
+            println!("[error] {}: {}", directory, e);
+            return false;
+        },
+    };
+    let mut files: Vec<_> = entries.filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().map_or(false, |e| e == "rtl"))
+        .collect();
+    files.sort();
+    let (mut passed, mut failed) = (0, 0);
+    for file in files {
+        let filename = file.to_string_lossy().into_owned();
+        println!("[running] {}", filename);
+        let turtle = turtle::Turtle::new(software::SoftwareBackend::new(size));
+        let mut environ = environ::Environment::new(turtle);
+        if !run_file(&mut environ, &filename) {
+            failed += 1;
+        }
+        let results = environ.test_results();
+        passed += results.passed;
+        failed += results.failed.len();
+    }
+    println!("\n{} passed, {} failed", passed, failed);
+    failed == 0
+}
+
+/// Compare the drawing with the reference and print how much they differ. If
+/// `output` is given, the differences are saved there as image. Returns
+/// `None` on errors, otherwise whether the drawings are the same.
+fn run_diff(reference: &str, drawing: &str, size: (u32, u32), output: Option<&str>)
+            -> Option<bool> {
+    let load = |path: &str| {
+        diff::load_drawing(path, size).map_err(|e| println!("[error] {}: {}", path, e)).ok()
+    };
+    let (reference, drawing) = match (load(reference), load(drawing)) {
+        (Some(reference), Some(drawing)) => (reference, drawing),
+        _ => return None,
+    };
+    let comparison = match diff::compare(&reference, &drawing) {
+        Ok(comparison) => comparison,
+        Err(e) => {
+            println!("[error] {}", e);
+            return None;
+        },
+    };
+    println!("{} of {} drawn pixels differ, {:.1}% similar", comparison.differing,
+             comparison.drawn, comparison.similarity() * 100.);
+    if let Some(output) = output {
+        if let Err(e) = comparison.image.save(output) {
+            println!("[error] {}: {}", output, e);
+            return None;
+        }
+    }
+    Some(comparison.differing == 0)
+}
+
+/// Connect to the robot at `port` with the protocol given by
+/// `--robot-protocol`
+fn open_robot(port: &str, options: &Options) -> Result<robot::Robot, String> {
+    let protocol = match options.robot_protocol {
+        Some(ref file) => try!(robot::RobotProtocol::from_file(file)
+                                   .map_err(|e| format!("{}: {}", file, e))),
+        None => robot::RobotProtocol::default(),
+    };
+    robot::Robot::open(port, protocol).map_err(|e| format!("{}: {}", port, e))
+}
+
 fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(o) => o,
+        Err(e) => {
+            println!("{}\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+    if let Some(ref directory) = options.test_directory {
+        process::exit(if run_tests(directory, options.size) { 0 } else { 1 });
+    }
+    if let Some((ref reference, ref drawing)) = options.diff {
+        // Like diff(1): 0 if the drawings are the same, 1 if not, 2 on errors
+        let output = options.output.as_ref().map(|output| output.as_str());
+        process::exit(match run_diff(reference, drawing, options.size, output) {
+            Some(true) => 0,
+            Some(false) => 1,
+            None => 2,
+        });
+    }
+    let robot = options.robot.as_ref().map(|port| match open_robot(port, &options) {
+        Ok(robot) => robot,
+        Err(e) => {
+            println!("[error] {}", e);
+            process::exit(1);
+        },
+    });
     let mut environ = {
-        let screen = graphic::TurtleScreen::new((640, 640), "Rurtle");
-        let turtle = turtle::Turtle::new(screen);
+        let screen_options = graphic::ScreenOptions {
+            headless: options.headless,
+            position: options.position,
+            maximized: options.maximized,
+            canvas: options.canvas,
+            .. Default::default()
+        };
+        let mut turtle = if options.software {
+            // Without a window, there is nothing to scroll
+            let size = options.canvas.unwrap_or(options.size);
+            turtle::Turtle::new(software::SoftwareBackend::new(size))
+        } else if options.terminal {
+            let (columns, rows) = terminal_size();
+            turtle::Turtle::new(terminal::TerminalBackend::new(options.size, columns, rows))
+        } else {
+            let screen = graphic::TurtleScreen::with_options(options.size, "Rurtle",
+                                                             screen_options);
+            turtle::Turtle::new(screen)
+        };
+        turtle.set_robot(robot);
         environ::Environment::new(turtle)
     };
-    for filename in env::args().skip(1) {
-        let mut file = fs::File::open(&filename).unwrap();
-        let mut source = String::new();
-        file.read_to_string(&mut source).unwrap();
-        if let Err(e) = environ.eval_source(&source) {
-            println!("[error] {}:", filename);
-            println!("{}: {}", e.description(), e);
+    // NEWSCREEN opens windows of the same kind, the terminal only has room
+    // for one
+    if !options.terminal {
+        let (software, headless) = (options.software, options.headless);
+        environ.set_screen_factory(move |size, name| if software {
+            turtle::Turtle::new(software::SoftwareBackend::new(size))
+        } else {
+            let screen_options = graphic::ScreenOptions {
+                headless: headless,
+                .. Default::default()
+            };
+            turtle::Turtle::new(graphic::TurtleScreen::with_options(size, name, screen_options))
+        });
+    }
+    environ.set_args(options.script_args.clone());
+    environ.set_limits(options.limits);
+    environ.set_sandbox(options.sandbox);
+    if let Some(ref filename) = options.script {
+        let success = run_file(&mut environ, filename);
+        if options.headless {
+            process::exit(if success && save_output(&mut environ, &options) { 0 } else { 1 });
+        }
+        // In watch mode, the user can fix the error and save again
+        if !success && !options.watch {
             return
         }
     };
+    let mut session = match options.serve {
+        Some(ref address) => match server::Session::listen(address) {
+            Ok(mut session) => {
+                session.set_sandbox(!options.serve_unsandboxed);
+                println!("[serving] {}", address);
+                Some(session)
+            },
+            Err(e) => {
+                println!("[error] {}: {}", address, e);
+                return
+            },
+        },
+        None => None,
+    };
+    let mut last_modified = options.script.as_ref().and_then(|f| modification_time(f));
     let (tx, rx) = mpsc::channel();
     // We use the hermes channel to make the "read thread" wait before printing
     // the next prompt and to signal it when the window closed.
@@ -79,10 +482,24 @@ fn main() {
         if let Err(e) = environ.eval_source(&source) {
             println!("{}: {}", e.description(), e);
         }
-        let screen = environ.get_turtle().get_screen();
-        screen.draw_and_update();
-        screen.handle_events();
-        if screen.is_closed() {
+        if let Some(ref mut session) = session {
+            session.handle(&mut environ);
+        }
+        if options.watch {
+            let filename = options.script.as_ref().unwrap();
+            let modified = modification_time(filename);
+            if modified != last_modified {
+                last_modified = modified;
+                println!("\n[reloading] {}", filename);
+                environ.get_turtle().reset();
+                run_file(&mut environ, filename);
+            }
+        }
+        if let Err(e) = environ.handle_events() {
+            println!("{}: {}", e.description(), e);
+        }
+        environ.update_screens();
+        if environ.is_main_screen_closed() {
             println!("\n\nWindow closed, press enter to exit...");
             break;
         }
@@ -98,3 +515,39 @@ fn main() {
     hermes_out.send(true).unwrap_or(());
     guard.join().unwrap();
 }
+
+/// Save the canvas to the file given by `--output`. Errors are printed.
+fn save_output(environ: &mut environ::Environment, options: &Options) -> bool {
+    let output = options.output.as_ref().unwrap();
+    let format = if output.ends_with(".gcode") {
+        Some(plotter::Format::Gcode)
+    } else if output.ends_with(".hpgl") {
+        Some(plotter::Format::Hpgl)
+    } else {
+        None
+    };
+    if let Some(format) = format {
+        // Use the settings the script made with SETPLOTTER
+        let plotter_options = plotter::PlotterOptions {
+            format: format,
+            .. environ.plotter_options().clone()
+        };
+        if let Err(e) = environ.get_turtle().export_plotter(output, &plotter_options) {
+            println!("[error] {}: {}", output, e);
+            return false;
+        }
+        return true;
+    }
+    let image = environ.get_turtle().render_image(1.0).map_err(|e| e.to_string());
+    let result = image.and_then(|image| {
+        let mut file = try!(fs::File::create(output).map_err(|e| e.to_string()));
+        image.save(&mut file, image::ImageFormat::PNG).map_err(|e| e.to_string())
+    });
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            println!("[error] {}: {}", output, e);
+            false
+        },
+    }
+}
@@ -0,0 +1,77 @@
+//! MIDI output for Rurtle.
+//!
+//! Besides the built-in synthesizer of the `sound` module, scripts can play
+//! notes on a synthesizer, a keyboard or any other program connected to a MIDI
+//! output port. Messages are sent to the first port right away, the timing is
+//! up to the script. MIDI is only available if rurtle was built with
+//! `cargo build --features midi`.
+#[cfg(feature = "midi")]
+use midir::{MidiOutput, MidiOutputConnection};
+
+/// Status bytes of the channel messages, the channel is added to them
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xB0;
+const PROGRAM_CHANGE: u8 = 0xC0;
+
+/// The controller that silences all notes of a channel
+const ALL_NOTES_OFF: u8 = 123;
+
+/// A connection to a MIDI output port
+pub struct MidiOut {
+    #[cfg(feature = "midi")]
+    connection: MidiOutputConnection,
+}
+
+impl MidiOut {
+    /// Connect to the first MIDI output port
+    #[cfg(feature = "midi")]
+    pub fn new() -> Result<MidiOut, String> {
+        let output = try!(MidiOutput::new("Rurtle").map_err(|e| e.to_string()));
+        if output.port_count() == 0 {
+            return Err("No MIDI output port available".to_owned());
+        }
+        let connection = try!(output.connect(0, "Rurtle").map_err(|e| e.to_string()));
+        Ok(MidiOut { connection: connection })
+    }
+
+    #[cfg(not(feature = "midi"))]
+    pub fn new() -> Result<MidiOut, String> {
+        Err("MIDI is not available, rebuild rurtle with --features midi".to_owned())
+    }
+
+    #[cfg(feature = "midi")]
+    fn send(&mut self, message: &[u8]) -> Result<(), String> {
+        self.connection.send(message).map_err(|e| e.to_string())
+    }
+
+    #[cfg(not(feature = "midi"))]
+    fn send(&mut self, _: &[u8]) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Start playing `note` (0 to 127, 60 is the middle C) on `channel` (0
+    /// to 15) with the given `velocity` (0 to 127)
+    pub fn note_on(&mut self, channel: u8, note: u8, velocity: u8) -> Result<(), String> {
+        self.send(&[NOTE_ON | channel, note, velocity])
+    }
+
+    /// Stop playing `note` on `channel`
+    pub fn note_off(&mut self, channel: u8, note: u8) -> Result<(), String> {
+        self.send(&[NOTE_OFF | channel, note, 0])
+    }
+
+    /// Select the instrument `program` (0 to 127) for `channel`
+    pub fn program_change(&mut self, channel: u8, program: u8) -> Result<(), String> {
+        self.send(&[PROGRAM_CHANGE | channel, program])
+    }
+}
+
+impl Drop for MidiOut {
+    /// Notes that are still playing would go on after Rurtle exited
+    fn drop(&mut self) {
+        for channel in 0..16 {
+            let _ = self.send(&[CONTROL_CHANGE | channel, ALL_NOTES_OFF, 0]);
+        }
+    }
+}
@@ -7,6 +7,9 @@
 //! The ast itself does not provide any methods to run or evaluate the program,
 //! it is merely a method to represent the program in memory. See the
 //! `environ::Environment::eval` method to find a way to execute the program.
+//!
+//! A node can be turned back into source code using `Node::to_source`.
+use super::super::lex::{quote, number_literal, integer_literal};
 #[derive(Debug, Clone)]
 pub enum Node {
     /// A list of statements as found inside a loop body
@@ -17,12 +20,20 @@ pub enum Node {
     RepeatStatement(Box<Node>, Box<Node>),
     /// The while statement (condition, loop body)
     WhileStatement(Box<Node>, Box<Node>),
-    /// The function definition statement (func name, func arg names, func body)
-    LearnStatement(String, Vec<String>, Box<Node>),
+    /// The for statement (variable name, start, end, maybe step, loop body)
+    ForStatement(String, Box<Node>, Box<Node>, Option<Box<Node>>, Box<Node>),
+    /// The function definition statement (func name, func parameters, func body)
+    LearnStatement(String, Vec<Parameter>, Box<Node>),
+    /// The macro definition statement (macro name, parameters, body)
+    MacroStatement(String, Vec<Parameter>, Box<Node>),
     /// A block that ignores errors, the first element is the "ordinary" block,
     /// the second element is the block that will be called when an exception
     /// occurs
     TryStatement(Box<Node>, Box<Node>),
+    /// An anonymous function (parameters, body)
+    Lambda(Vec<Parameter>, Box<Node>),
+    /// A block that is executed each time the turtle is clicked
+    OnClickStatement(Box<Node>),
     Comparison(Box<Node>, CompOp, Box<Node>),
     /// Addition or subtraction. One addition may hold more than one operation.
     Addition(Box<Node>, Vec<(AddOp, Node)>),
@@ -32,13 +43,59 @@ pub enum Node {
     /// A function call (function, arguments)
     FuncCall(String, Vec<Node>),
     ReturnStatement(Box<Node>),
+    /// Leave the innermost loop
+    BreakStatement,
+    /// Continue with the next iteration of the innermost loop
+    ContinueStatement,
     Assignment(String, Box<Node>),
     List(Vec<Node>),
     StringLiteral(String),
     Number(f32),
+    Integer(i64),
     Variable(String),
 }
 
+/// A parameter in a function definition
+#[derive(Debug, Clone)]
+pub enum Parameter {
+    /// A parameter that has to be given by the caller
+    Required(String),
+    /// A parameter that may be omitted, the node is evaluated to get the
+    /// default value
+    Optional(String, Node),
+    /// A parameter that collects all remaining arguments into a list
+    Rest(String),
+}
+
+impl Parameter {
+    /// Return the name of the parameter
+    pub fn name(&self) -> &str {
+        match *self {
+            Parameter::Required(ref name) |
+            Parameter::Optional(ref name, _) |
+            Parameter::Rest(ref name) => name,
+        }
+    }
+
+    /// Return the parameter as it is written in a function definition
+    pub fn to_source(&self) -> String {
+        match *self {
+            Parameter::Required(ref name) => format!(":{}", name),
+            Parameter::Optional(ref name, ref default) => {
+                format!(":{} {}", name, default.source(0))
+            },
+            Parameter::Rest(ref name) => format!("[:{}]", name),
+        }
+    }
+
+    fn flatten(self) -> Parameter {
+        match self {
+            Parameter::Optional(name, default) => Parameter::Optional(name, default.flatten()),
+            parameter => parameter,
+        }
+    }
+}
+
 /// Helper function to flatten a vector of boxes to nodes
 fn flatten(input: Vec<Node>) -> Vec<Node> {
     input.into_iter().map(|n| n.flatten()).collect()
@@ -48,7 +105,135 @@ fn flatten_tuple<T>(input: Vec<(T, Node)>) -> Vec<(T, Node)> {
     input.into_iter().map(|(o, n)| (o, n.flatten())).collect()
 }
 
+/// Return the source of the given parameters, each followed by a space
+fn parameters_source(params: &[Parameter]) -> String {
+    params.iter().map(|p| format!("{} ", p.to_source())).collect()
+}
+
+/// Return the indentation for the given depth
+fn indentation(depth: usize) -> String {
+    ::std::iter::repeat("    ").take(depth).collect()
+}
+
 impl Node {
+    /// Return Rurtle source code that is parsed to this node again. Function
+    /// calls and operations are enclosed in parenthesis, so the result doesn't
+    /// depend on operator precedence or the number of arguments a function
+    /// takes.
+    pub fn to_source(&self) -> String {
+        self.source(0)
+    }
+
+    /// Return the source of the node, `depth` gives the indentation level of
+    /// nested blocks
+    fn source(&self, depth: usize) -> String {
+        use self::Node::*;
+        match *self {
+            StatementList(ref stmts) => {
+                let lines: Vec<String> = stmts.iter().map(|s| s.source(depth)).collect();
+                lines.join(&format!("\n{}", indentation(depth)))
+            },
+            IfStatement(ref cond, ref true_body, ref false_body) => {
+                let mut result = format!("IF {} DO{}", cond.source(depth),
+                                         Node::block_source(true_body, depth));
+                if let Some(ref false_body) = *false_body {
+                    result.push_str(&format!("ELSE{}", Node::block_source(false_body, depth)));
+                }
+                result.push_str("END");
+                result
+            },
+            RepeatStatement(ref count, ref body) => {
+                format!("REPEAT {} DO{}END", count.source(depth), Node::block_source(body, depth))
+            },
+            WhileStatement(ref cond, ref body) => {
+                format!("WHILE {} DO{}END", cond.source(depth), Node::block_source(body, depth))
+            },
+            ForStatement(ref name, ref start, ref end, ref step, ref body) => {
+                let step = match *step {
+                    Some(ref s) => format!(" {}", s.source(depth)),
+                    None => String::new(),
+                };
+                format!("FOR [{} {} {}{}] DO{}END", name, start.source(depth), end.source(depth),
+                        step, Node::block_source(body, depth))
+            },
+            LearnStatement(ref name, ref params, ref body) => {
+                format!("LEARN {} {}DO{}END", name, parameters_source(params),
+                        Node::block_source(body, depth))
+            },
+            MacroStatement(ref name, ref params, ref body) => {
+                format!(".MACRO {} {}DO{}END", name, parameters_source(params),
+                        Node::block_source(body, depth))
+            },
+            TryStatement(ref normal, ref exception) => {
+                format!("TRY{}ELSE{}END", Node::block_source(normal, depth),
+                        Node::block_source(exception, depth))
+            },
+            Lambda(ref params, ref body) => {
+                format!("LAMBDA {}DO{}END", parameters_source(params),
+                        Node::block_source(body, depth))
+            },
+            OnClickStatement(ref body) => {
+                format!("ONTURTLECLICK DO{}END", Node::block_source(body, depth))
+            },
+            Comparison(ref a, op, ref b) => {
+                let op = match op {
+                    CompOp::Equal => "=",
+                    CompOp::Less => "<",
+                    CompOp::Greater => ">",
+                    CompOp::LessEqual => "<=",
+                    CompOp::GreaterEqual => ">=",
+                    CompOp::NotEqual => "<>",
+                };
+                format!("({} {} {})", a.source(depth), op, b.source(depth))
+            },
+            Addition(ref first, ref rest) => {
+                let mut result = format!("({}", first.source(depth));
+                for &(op, ref node) in rest {
+                    let op = match op { AddOp::Add => "+", AddOp::Sub => "-" };
+                    result.push_str(&format!(" {} {}", op, node.source(depth)));
+                }
+                result.push(')');
+                result
+            },
+            Multiplication(ref first, ref rest) => {
+                let mut result = format!("({}", first.source(depth));
+                for &(op, ref node) in rest {
+                    let op = match op { MulOp::Mul => "*", MulOp::Div => "/" };
+                    result.push_str(&format!(" {} {}", op, node.source(depth)));
+                }
+                result.push(')');
+                result
+            },
+            FuncCall(ref name, ref args) => {
+                let mut result = format!("({}", name);
+                for arg in args {
+                    result.push_str(&format!(" {}", arg.source(depth)));
+                }
+                result.push(')');
+                result
+            },
+            ReturnStatement(ref value) => format!("RETURN {}", value.source(depth)),
+            BreakStatement => "BREAK".to_owned(),
+            ContinueStatement => "CONTINUE".to_owned(),
+            Assignment(ref name, ref value) => format!("(:{} := {})", name, value.source(depth)),
+            List(ref elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.source(depth)).collect();
+                format!("[{}]", elements.join(" "))
+            },
+            StringLiteral(ref string) => quote(string),
+            Number(x) => number_literal(x),
+            Integer(x) => integer_literal(x),
+            Variable(ref name) => format!(":{}", name),
+        }
+    }
+
+    /// Return the source of a block body, indented by one level and
+    /// surrounded by line breaks
+    fn block_source(body: &Node, depth: usize) -> String {
+        format!("\n{}{}\n{}", indentation(depth + 1), body.source(depth + 1),
+                indentation(depth))
+    }
+
     /// Consume the node and produce a flat version
     pub fn flatten(self) -> Node {
         use self::Node::*;
@@ -87,10 +272,24 @@ impl Node {
                                                             Box::new(body.flatten())),
             WhileStatement(cond, body) => WhileStatement(Box::new(cond.flatten()),
                                                          Box::new(body.flatten())),
-            LearnStatement(name, args, body) => LearnStatement(name, args,
-                                                               Box::new(body.flatten())),
+            ForStatement(name, start, end, step, body) => {
+                ForStatement(name, Box::new(start.flatten()), Box::new(end.flatten()),
+                             step.map(|s| Box::new(s.flatten())), Box::new(body.flatten()))
+            },
+            LearnStatement(name, args, body) => {
+                LearnStatement(name, args.into_iter().map(|a| a.flatten()).collect(),
+                               Box::new(body.flatten()))
+            },
+            MacroStatement(name, args, body) => {
+                MacroStatement(name, args.into_iter().map(|a| a.flatten()).collect(),
+                               Box::new(body.flatten()))
+            },
             TryStatement(normal, exception) => TryStatement(Box::new(normal.flatten()),
                                                             Box::new(exception.flatten())),
+            Lambda(args, body) => {
+                Lambda(args.into_iter().map(|a| a.flatten()).collect(), Box::new(body.flatten()))
+            },
+            OnClickStatement(body) => OnClickStatement(Box::new(body.flatten())),
             Comparison(operand1, op, operand2) => Comparison(Box::new(operand1.flatten()),
                                                              op,
                                                              Box::new(operand2.flatten())),
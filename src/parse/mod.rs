@@ -11,30 +11,42 @@
 //! Depending on how many arguments each function takes, this may be parsed as
 //! either `funca(funcb(10))` or `funca(funcb(), 10)`.
 //!
+//! Functions with optional parameters take only their required arguments by
+//! default. To pass more arguments, the call has to be enclosed in parenthesis:
+//! `(funca 10 20 30)`.
+//!
 //! # Grammar
 //!
 //! A EBNF-like (incomplete) grammar may look like
 //!
 //! ```text
 //! root := {statement} ;
-//! statement := learn-def | if-stmt | repeat-stmt | while-stmt | return-stmt |
-//!              try-stmt | expression ;
-//! learn-def := 'LEARN' identifier {variable} 'DO' {statement} 'END' ;
-//! if-stmt := 'IF' expression 'DO' {statement} ['ELSE' {statement}]'END' ;
+//! statement := learn-def | if-stmt | repeat-stmt | while-stmt | for-stmt | return-stmt |
+//!              try-stmt | onclick-stmt | 'BREAK' | 'CONTINUE' | expression ;
+//! learn-def := 'LEARN' identifier {parameter} 'DO' {statement} 'END' ;
+//! parameter := variable [factor] | '[' variable ']' ;
+//! if-stmt := 'IF' if-rest 'END' | 'IFELSE' expression 'DO' {statement}
+//!            'ELSE' {statement} 'END' ;
+//! if-rest := expression 'DO' {statement} ['ELIF' if-rest | 'ELSE' {statement}] ;
 //! repeat-stmt := 'REPEAT' expression 'DO' {statement} 'END' ;
 //! while-stmt := 'WHILE' expression 'DO' {statement} 'END' ;
+//! for-stmt := 'FOR' '[' identifier expression expression [expression] ']' 'DO' {statement}
+//!             'END' ;
 //! return-stmt := 'RETURN' expression ;
 //! try-stmt := 'TRY' {statement} 'ELSE' {statement} 'END' ;
+//! onclick-stmt := 'ONTURTLECLICK' 'DO' {statement} 'END' ;
 //! variable := ':' identifier ;
 //! identifier := idenfitier-start {identifier-cont} ;
 //! idenfitier-start := <any alphabetic character> ;
-//! idenfitier-cont := <any alpabetic or numeric character> ;
+//! idenfitier-cont := <any alpabetic or numeric character> | '?' ;
 //! expression := comparison ;
 //! comparison := expr [comp_op expr] ;
 //! comp_op := '=' | '<' | '>' | ''<=' | '>=' | '<>' ;
 //! expr := product {('+' | '-') product} ;
 //! product := factor {('*' | '/') factor} ;
-//! factor := '(' expression ')' | list | variable | string | number | (identifier {expression}) ;
+//! lambda := 'LAMBDA' {parameter} 'DO' {statement} 'END' ;
+//! factor := if-stmt | lambda | '(' expression ')' | '(' identifier {expression} ')' | list | variable |
+//!           string | number | (identifier {expression}) ;
 //! list := '[' {expression} ']' ;
 //! string := '"' {<any character>} '"' ;
 //! number := ['+' | '-'] <any valid floating point number literal> ;
@@ -42,13 +54,54 @@
 pub mod ast;
 
 use super::lex::{Token, MetaToken};
-use self::ast::{Node, AddOp, MulOp, CompOp};
+use self::ast::{Node, AddOp, MulOp, CompOp, Parameter};
 use self::ast::Node::*;
 use std::collections::{HashMap, VecDeque};
 use std::{error, fmt};
 
+/// The number of arguments that a function takes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arity {
+    /// Number of arguments that a call without parenthesis takes
+    pub default: i32,
+    /// Maximum number of arguments or `None` if the function takes arbitrary
+    /// many arguments
+    pub maximum: Option<i32>,
+}
+
+impl Arity {
+    /// Return the `Arity` of a function that takes exactly `count` arguments
+    pub fn fixed(count: i32) -> Arity {
+        Arity {
+            default: count,
+            maximum: Some(count),
+        }
+    }
+
+    /// Return the `Arity` of a function with the given parameters
+    pub fn of_parameters(parameters: &[Parameter]) -> Arity {
+        let mut arity = Arity::fixed(0);
+        for parameter in parameters {
+            match *parameter {
+                Parameter::Required(_) => {
+                    arity.default += 1;
+                    arity.maximum = arity.maximum.map(|m| m + 1);
+                },
+                Parameter::Optional(..) => arity.maximum = arity.maximum.map(|m| m + 1),
+                Parameter::Rest(_) => arity.maximum = None,
+            }
+        }
+        arity
+    }
+
+    /// Returns true if a call with `count` arguments is valid
+    pub fn accepts(&self, count: i32) -> bool {
+        count >= self.default && self.maximum.map_or(true, |m| count <= m)
+    }
+}
+
 /// A `FuncMap` maps the name of a function to the number of arguments it takes
-pub type FuncMap = HashMap<String, i32>;
+pub type FuncMap = HashMap<String, Arity>;
 
 /// A `Parser` builds an AST from the given input token stream.
 pub struct Parser {
@@ -62,6 +115,7 @@ pub enum ParseErrorKind {
     UnexpectedToken(&'static str, Token),
     UnexpectedEnd,
     UnknownFunction(String),
+    InvalidParameter(String),
 }
 
 impl fmt::Display for ParseErrorKind {
@@ -79,7 +133,11 @@ impl fmt::Display for ParseErrorKind {
             UnknownFunction(ref name) => {
                 try!(fmt.pad("unknown function: "));
                 name.fmt(fmt)
-            }
+            },
+            InvalidParameter(ref name) => {
+                try!(fmt.pad("invalid parameter: "));
+                name.fmt(fmt)
+            },
         }
     }
 }
@@ -104,6 +162,7 @@ impl error::Error for ParseError {
             UnexpectedToken(..) => "unexpected token",
             UnexpectedEnd => "unexpected end",
             UnknownFunction(..) => "unknown function",
+            InvalidParameter(..) => "invalid parameter",
         }
     }
 }
@@ -187,7 +246,7 @@ impl Parser {
         self.scope_stack.pop().expect("scope_stack is empty, should have global scope");
     }
 
-    fn find_function_arg_count(&self, name: &str) -> Option<i32> {
+    fn find_function_arity(&self, name: &str) -> Option<Arity> {
         for scope in self.scope_stack.iter().rev() {
             let function_map = &scope.functions;
             match function_map.get(name) {
@@ -226,7 +285,7 @@ impl Parser {
         let mut statements = Vec::new();
         while !self.tokens.is_empty() {
             match self.peek() {
-                Token::KeyElse | Token::KeyEnd => break,
+                Token::KeyElse | Token::KeyElif | Token::KeyEnd => break,
                 _ => {
                     statements.push(try!(self.parse_statement()));
                 },
@@ -239,53 +298,170 @@ impl Parser {
     fn parse_statement(&mut self) -> ParseResult {
         let token = self.peek();
         match token {
-            Token::KeyLearn => self.parse_learn_stmt(),
-            Token::KeyIf => self.parse_if_stmt(),
+            Token::KeyLearn | Token::KeyMacro => self.parse_learn_stmt(),
+            Token::KeyIf | Token::KeyIfElse => self.parse_if_stmt(),
             Token::KeyRepeat => self.parse_repeat_stmt(),
             Token::KeyWhile => self.parse_while_stmt(),
+            Token::KeyFor => self.parse_for_stmt(),
             Token::KeyReturn => self.parse_return_stmt(),
             Token::KeyTry => self.parse_try_stmt(),
+            Token::KeyOnTurtleClick => self.parse_onclick_stmt(),
+            Token::KeyBreak => {
+                try!(self.pop_left());
+                Ok(BreakStatement)
+            },
+            Token::KeyContinue => {
+                try!(self.pop_left());
+                Ok(ContinueStatement)
+            },
             _ => self.parse_expression(),
         }
     }
 
+    /// Parse a function or macro definition, they only differ in the keyword
     fn parse_learn_stmt(&mut self) -> ParseResult {
-        expect!(self, Token::KeyLearn);
+        let is_macro = try!(self.pop_left()) == Token::KeyMacro;
         let name = match try!(self.pop_left()) {
             Token::Word(string) => string.to_uppercase(),
             token => parse_error!(self, UnexpectedToken("Token::Word", token)),
         };
-        let mut variables = Vec::new();
+        let parameters = try!(self.parse_parameters());
+        // We need the argument count for this function if it appears later
+        // during the parsing stage (e.g. in a recursive call)
+        let arity = Arity::of_parameters(&parameters);
+        self.current_scope_mut().functions.insert(name.clone(), arity);
+        let statements = try!(self.parse_loop_body());
+        expect!(self, Token::KeyEnd);
+        if is_macro {
+            Ok(MacroStatement(name, parameters, Box::new(statements)))
+        } else {
+            Ok(LearnStatement(name, parameters, Box::new(statements)))
+        }
+    }
+
+    fn parse_lambda(&mut self) -> ParseResult {
+        expect!(self, Token::KeyLambda);
+        let parameters = try!(self.parse_parameters());
+        let statements = try!(self.parse_loop_body());
+        expect!(self, Token::KeyEnd);
+        Ok(Lambda(parameters, Box::new(statements)))
+    }
+
+    /// Parse the parameter list of a function definition, including the
+    /// final DO
+    fn parse_parameters(&mut self) -> Result<Vec<Parameter>, ParseError> {
+        let mut parameters = Vec::new();
         while !self.tokens.is_empty() {
-            match try!(self.pop_left()) {
+            let parameter = match try!(self.pop_left()) {
                 Token::Colon => {
-                    match try!(self.pop_left()) {
-                        Token::Word(s) => variables.push(s),
-                        token => parse_error!(self, UnexpectedToken("Token::Word", token)),
+                    let variable = try!(self.parse_parameter_name());
+                    if self.tokens.is_empty() {
+                        Parameter::Required(variable)
+                    } else {
+                        match self.peek() {
+                            Token::Colon | Token::KeyDo => Parameter::Required(variable),
+                            Token::LBracket if self.is_rest_parameter() => {
+                                Parameter::Required(variable)
+                            },
+                            _ => Parameter::Optional(variable, try!(self.parse_factor())),
+                        }
                     }
                 },
+                // A rest parameter [:name]
+                Token::LBracket => {
+                    expect!(self, Token::Colon);
+                    let variable = try!(self.parse_parameter_name());
+                    expect!(self, Token::RBracket);
+                    Parameter::Rest(variable)
+                },
                 Token::KeyDo => break,
                 token => parse_error!(self, UnexpectedToken("Token::KeyDo", token)),
+            };
+            // Required parameters have to come first, followed by optional
+            // parameters and at most one rest parameter at the end
+            let valid = match (parameters.last(), &parameter) {
+                (Some(&Parameter::Rest(_)), _) => false,
+                (Some(&Parameter::Optional(..)), &Parameter::Required(_)) => false,
+                _ => true,
+            };
+            if !valid {
+                parse_error!(self, InvalidParameter(parameter.name().to_owned()));
             }
+            parameters.push(parameter);
         }
-        // We need the argument count for this function if it appears later
-        // during the parsing stage (e.g. in a recursive call)
-        self.current_scope_mut().functions.insert(name.clone(), variables.len() as i32);
-        let statements = try!(self.parse_loop_body());
-        expect!(self, Token::KeyEnd);
-        Ok(LearnStatement(name, variables, Box::new(statements)))
+        Ok(parameters)
+    }
+
+    fn parse_parameter_name(&mut self) -> Result<String, ParseError> {
+        match try!(self.pop_left()) {
+            Token::Word(s) => Ok(s),
+            token => parse_error!(self, UnexpectedToken("Token::Word", token)),
+        }
+    }
+
+    /// Returns true if the next tokens are the start of a rest parameter
+    /// ("[:"), otherwise they are a list literal
+    fn is_rest_parameter(&self) -> bool {
+        match (self.tokens.get(0), self.tokens.get(1)) {
+            (Some(first), Some(second)) => {
+                first.token == Token::LBracket && second.token == Token::Colon
+            },
+            _ => false,
+        }
+    }
+
+    /// If the next token is the name of a function that takes a variable
+    /// number of arguments, return the function's name
+    fn peek_variadic_function(&self) -> Option<String> {
+        if let Some(&Token::Word(ref name)) = self.tokens.front().map(|m| &m.token) {
+            if let Some(arity) = self.find_function_arity(&name.to_uppercase()) {
+                if arity.maximum != Some(arity.default) {
+                    return Some(name.clone());
+                }
+            }
+        }
+        None
     }
 
     fn parse_if_stmt(&mut self) -> ParseResult {
-        expect!(self, Token::KeyIf);
+        let statement = match try!(self.pop_left()) {
+            Token::KeyIf => try!(self.parse_if_rest()),
+            // IFELSE is an IF that requires the ELSE branch
+            Token::KeyIfElse => {
+                let condition = Box::new(try!(self.parse_expression()));
+                expect!(self, Token::KeyDo);
+                let true_body = Box::new(try!(self.parse_loop_body()));
+                expect!(self, Token::KeyElse);
+                let false_body = Box::new(try!(self.parse_loop_body()));
+                IfStatement(condition, true_body, Some(false_body))
+            },
+            token => parse_error!(self, UnexpectedToken("Token::KeyIf", token)),
+        };
+        expect!(self, Token::KeyEnd);
+        Ok(statement)
+    }
+
+    /// Parse the condition and branches of an IF statement. ELIF branches are
+    /// turned into nested IF statements in the false branch. The final END is
+    /// not consumed.
+    fn parse_if_rest(&mut self) -> ParseResult {
         let condition = Box::new(try!(self.parse_expression()));
         expect!(self, Token::KeyDo);
         let true_body = Box::new(try!(self.parse_loop_body()));
-        let false_body = if let Token::KeyElse = self.peek() {
-            try!(self.pop_left());
-            Some(Box::new(try!(self.parse_loop_body())))
-        } else { None };
-        expect!(self, Token::KeyEnd);
+        if self.tokens.is_empty() {
+            parse_error!(self, UnexpectedEnd);
+        }
+        let false_body = match self.peek() {
+            Token::KeyElse => {
+                try!(self.pop_left());
+                Some(Box::new(try!(self.parse_loop_body())))
+            },
+            Token::KeyElif => {
+                try!(self.pop_left());
+                Some(Box::new(try!(self.parse_if_rest())))
+            },
+            _ => None,
+        };
         Ok(IfStatement(condition, true_body, false_body))
     }
 
@@ -307,6 +483,27 @@ impl Parser {
         Ok(WhileStatement(condition, Box::new(body)))
     }
 
+    fn parse_for_stmt(&mut self) -> ParseResult {
+        expect!(self, Token::KeyFor);
+        expect!(self, Token::LBracket);
+        let name = match try!(self.pop_left()) {
+            Token::Word(string) => string,
+            token => parse_error!(self, UnexpectedToken("Token::Word", token)),
+        };
+        let start = Box::new(try!(self.parse_expression()));
+        let end = Box::new(try!(self.parse_expression()));
+        let step = if let Token::RBracket = self.peek() {
+            None
+        } else {
+            Some(Box::new(try!(self.parse_expression())))
+        };
+        expect!(self, Token::RBracket);
+        expect!(self, Token::KeyDo);
+        let body = try!(self.parse_loop_body());
+        expect!(self, Token::KeyEnd);
+        Ok(ForStatement(name, start, end, step, Box::new(body)))
+    }
+
     fn parse_return_stmt(&mut self) -> ParseResult {
         expect!(self, Token::KeyReturn);
         let result = Box::new(try!(self.parse_expression()));
@@ -322,6 +519,14 @@ impl Parser {
         Ok(TryStatement(normal, exception))
     }
 
+    fn parse_onclick_stmt(&mut self) -> ParseResult {
+        expect!(self, Token::KeyOnTurtleClick);
+        expect!(self, Token::KeyDo);
+        let body = try!(self.parse_loop_body());
+        expect!(self, Token::KeyEnd);
+        Ok(OnClickStatement(Box::new(body)))
+    }
+
     fn parse_expression(&mut self) -> ParseResult {
         self.parse_comparison()
     }
@@ -392,8 +597,28 @@ impl Parser {
         if self.tokens.is_empty() {
             parse_error!(self, UnexpectedEnd);
         };
+        // IF statements may be used as expressions, their value is the value of
+        // the taken branch
+        match self.peek() {
+            Token::KeyIf | Token::KeyIfElse => return self.parse_if_stmt(),
+            Token::KeyLambda => return self.parse_lambda(),
+            _ => (),
+        }
         match try!(self.pop_left()) {
             Token::LParens => {
+                // A call with a variable number of arguments: (func arg1 arg2 ...)
+                if let Some(name) = self.peek_variadic_function() {
+                    try!(self.pop_left());
+                    let mut arguments = Vec::new();
+                    while !self.tokens.is_empty() {
+                        if let Token::RParens = self.peek() {
+                            break
+                        }
+                        arguments.push(try!(self.parse_expression()));
+                    }
+                    expect!(self, Token::RParens);
+                    return Ok(FuncCall(name, arguments));
+                }
                 let factor = try!(self.parse_expression());
                 expect!(self, Token::RParens);
                 Ok(factor)
@@ -428,8 +653,8 @@ impl Parser {
             },
             // A function call
             Token::Word(name) => {
-                let argument_count = match self.find_function_arg_count(&name.to_uppercase()) {
-                    Some(i) => i,
+                let argument_count = match self.find_function_arity(&name.to_uppercase()) {
+                    Some(arity) => arity.default,
                     None => parse_error!(self, UnknownFunction(name)),
                 };
                 let mut arguments = Vec::new();
@@ -440,16 +665,19 @@ impl Parser {
             },
             Token::String(string) => Ok(StringLiteral(string)),
             Token::Number(num) => Ok(Number(num)),
+            Token::Integer(num) => Ok(Integer(num)),
             // Unary prefixes for numbers
             Token::OpMinus => {
                 match try!(self.pop_left()) {
                     Token::Number(num) => Ok(Number(-num)),
+                    Token::Integer(num) => Ok(Integer(-num)),
                     token => parse_error!(self, UnexpectedToken("Token::Number", token)),
                 }
             },
             Token::OpPlus => {
                 match try!(self.pop_left()) {
                     Token::Number(num) => Ok(Number(num)),
+                    Token::Integer(num) => Ok(Integer(num)),
                     token => parse_error!(self, UnexpectedToken("Token::Number", token)),
                 }
             },
@@ -0,0 +1,54 @@
+//! Post-processing of the paths the turtle walked, see `Turtle::begin_path`.
+//!
+//! `simplify` removes the vertices that hardly change the shape of a path
+//! with the Douglas–Peucker algorithm. This makes generated paths smaller for
+//! exporting or redrawing:
+//!
+//! ```
+//! use rurtle::path::simplify;
+//! let path = [(0., 0.), (50., 0.5), (100., 0.), (100., 100.)];
+//! assert_eq!(simplify(&path, 1.), vec![(0., 0.), (100., 0.), (100., 100.)]);
+//! ```
+
+/// Return the distance of `point` to the line segment from `start` to `end`
+fn segment_distance(point: (f32, f32), start: (f32, f32), end: (f32, f32)) -> f32 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared == 0. {
+        0.
+    } else {
+        (((point.0 - start.0) * dx + (point.1 - start.1) * dy) / length_squared).max(0.).min(1.)
+    };
+    let (x, y) = (start.0 + t * dx, start.1 + t * dy);
+    ((point.0 - x).powi(2) + (point.1 - y).powi(2)).sqrt()
+}
+
+/// Return the path without the vertices that are closer than `tolerance` to
+/// the simplified path. The first and the last vertex are always kept.
+pub fn simplify(points: &[(f32, f32)], tolerance: f32) -> Vec<(f32, f32)> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    // The ranges that still have to be checked, a stack instead of recursion
+    // so that long paths can't overflow the call stack
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = ranges.pop() {
+        let farthest = (first + 1..last)
+            .map(|i| (i, segment_distance(points[i], points[first], points[last])))
+            .fold(None, |farthest: Option<(usize, f32)>, (i, distance)| match farthest {
+                Some((_, max)) if max >= distance => farthest,
+                _ => Some((i, distance)),
+            });
+        if let Some((index, distance)) = farthest {
+            if distance > tolerance {
+                keep[index] = true;
+                ranges.push((first, index));
+                ranges.push((index, last));
+            }
+        }
+    }
+    points.iter().zip(keep).filter(|&(_, keep)| keep).map(|(&point, _)| point).collect()
+}
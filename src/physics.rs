@@ -0,0 +1,63 @@
+//! A simple physics simulation for the turtle.
+//!
+//! The turtle can be given a velocity and be pulled down by gravity. Each call
+//! to `Physics::step` advances the simulation by the given time, optionally
+//! bouncing off the edges of the canvas. This is enough for projectiles and
+//! bouncing balls without having to write the integration by hand.
+
+/// The simulation state of a turtle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Physics {
+    /// Velocity in units per second
+    pub velocity: (f32, f32),
+    /// Downward acceleration in units per second²
+    pub gravity: f32,
+    /// Bounce off the edges of the canvas
+    pub bounce: bool,
+}
+
+impl Default for Physics {
+    fn default() -> Physics {
+        Physics {
+            velocity: (0., 0.),
+            gravity: 0.,
+            bounce: false,
+        }
+    }
+}
+
+/// Reflect the coordinate at the edges `-limit` and `limit`. Returns the new
+/// coordinate and whether it was reflected.
+fn reflect(x: f32, limit: f32) -> (f32, bool) {
+    if x > limit {
+        (2. * limit - x, true)
+    } else if x < -limit {
+        (-2. * limit - x, true)
+    } else {
+        (x, false)
+    }
+}
+
+impl Physics {
+    /// Advance the simulation by `time` seconds starting at `position` and
+    /// return the new position. `size` is the size of the canvas, which is
+    /// used for bouncing.
+    pub fn step(&mut self, position: (f32, f32), time: f32, size: (f32, f32)) -> (f32, f32) {
+        // Semi-implicit Euler integration, which is stable for bouncing
+        self.velocity.1 -= self.gravity * time;
+        let x = position.0 + self.velocity.0 * time;
+        let y = position.1 + self.velocity.1 * time;
+        if !self.bounce {
+            return (x, y);
+        }
+        let (x, bounced_x) = reflect(x, size.0 / 2.);
+        let (y, bounced_y) = reflect(y, size.1 / 2.);
+        if bounced_x {
+            self.velocity.0 = -self.velocity.0;
+        }
+        if bounced_y {
+            self.velocity.1 = -self.velocity.1;
+        }
+        (x, y)
+    }
+}
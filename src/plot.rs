@@ -0,0 +1,139 @@
+//! Simple charts drawn by the turtle.
+//!
+//! `plot` scales the given data to fit the canvas, draws axes with labeled
+//! ticks and connects the points with lines in the turtle's color. The chart
+//! is drawn with ordinary turtle commands, so it is part of the drawing and of
+//! the command log. The turtle's state is restored afterwards.
+use super::turtle::{Turtle, TurtleResult};
+
+/// Part of the canvas (in each direction) that is used for the chart
+const AREA: f32 = 0.8;
+/// Number of ticks on an axis that `nice_step` aims for
+const TICKS: f32 = 5.;
+/// Color of the axes and labels
+const AXES_COLOR: (f32, f32, f32) = (0.3, 0.3, 0.3);
+
+/// Return a "nice" step (1, 2 or 5 times a power of ten) to divide `range`
+/// into about `TICKS` parts
+fn nice_step(range: f32) -> f32 {
+    let rough = range / TICKS;
+    let magnitude = 10f32.powf(rough.log10().floor());
+    let fraction = rough / magnitude;
+    let nice = if fraction < 1.5 {
+        1.
+    } else if fraction < 3.5 {
+        2.
+    } else if fraction < 7.5 {
+        5.
+    } else {
+        10.
+    };
+    nice * magnitude
+}
+
+/// Return the smallest and the largest value, widened if they are equal
+fn bounds<I: Iterator<Item=f32>>(values: I) -> (f32, f32) {
+    let (min, max) = values.fold((::std::f32::INFINITY, ::std::f32::NEG_INFINITY),
+                                 |(min, max), v| (min.min(v), max.max(v)));
+    if min == max {
+        (min - 1., max + 1.)
+    } else {
+        (min, max)
+    }
+}
+
+/// Maps data coordinates to turtle coordinates
+struct Scale {
+    x: (f32, f32),
+    y: (f32, f32),
+    size: (f32, f32),
+}
+
+impl Scale {
+    fn to_screen(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let (w, h) = (self.size.0 * AREA, self.size.1 * AREA);
+        ((x - self.x.0) / (self.x.1 - self.x.0) * w - w / 2.,
+         (y - self.y.0) / (self.y.1 - self.y.0) * h - h / 2.)
+    }
+}
+
+/// Draw a chart of the given lines, each line being a list of points in data
+/// coordinates. Points with a non-finite coordinate are skipped. Returns an
+/// error if the values are too large to be scaled to the canvas.
+pub fn plot(turtle: &mut Turtle, lines: &[Vec<(f32, f32)>]) -> TurtleResult {
+    let points = || {
+        lines.iter().flat_map(|l| l.iter()).filter(|p| p.0.is_finite() && p.1.is_finite())
+    };
+    if points().next().is_none() {
+        return Ok(());
+    }
+    let scale = Scale {
+        x: bounds(points().map(|p| p.0)),
+        y: bounds(points().map(|p| p.1)),
+        size: turtle.get_screen().visible_size(),
+    };
+    let state = turtle.state();
+    turtle.pen_up();
+    try!(draw_axes(turtle, &scale));
+    let (r, g, b, _) = state.color;
+    try!(turtle.set_color(r, g, b));
+    for line in lines {
+        turtle.pen_up();
+        for &point in line {
+            if !(point.0.is_finite() && point.1.is_finite()) {
+                turtle.pen_up();
+                continue;
+            }
+            let (x, y) = scale.to_screen(point);
+            try!(turtle.teleport(x, y));
+            turtle.pen_down();
+        }
+    }
+    turtle.pen_up();
+    turtle.set_state(&state)
+}
+
+/// Draw the axes at 0 (or at the edge of the chart if 0 is not visible) with
+/// labeled ticks
+fn draw_axes(turtle: &mut Turtle, scale: &Scale) -> TurtleResult {
+    let (r, g, b) = AXES_COLOR;
+    try!(turtle.set_color(r, g, b));
+    try!(turtle.set_orientation(0.));
+    let clamp = |v: f32, (min, max): (f32, f32)| v.max(min).min(max);
+    let x_axis = clamp(0., scale.y);
+    let y_axis = clamp(0., scale.x);
+    try!(line(turtle, scale.to_screen((scale.x.0, x_axis)), scale.to_screen((scale.x.1, x_axis))));
+    try!(line(turtle, scale.to_screen((y_axis, scale.y.0)), scale.to_screen((y_axis, scale.y.1))));
+    let step = nice_step(scale.x.1 - scale.x.0);
+    let mut x = (scale.x.0 / step).ceil() * step;
+    while x <= scale.x.1 {
+        let (sx, sy) = scale.to_screen((x, x_axis));
+        try!(line(turtle, (sx, sy - 3.), (sx, sy + 3.)));
+        try!(label(turtle, (sx + 2., sy - 14.), x));
+        x += step;
+    }
+    let step = nice_step(scale.y.1 - scale.y.0);
+    let mut y = (scale.y.0 / step).ceil() * step;
+    while y <= scale.y.1 {
+        let (sx, sy) = scale.to_screen((y_axis, y));
+        try!(line(turtle, (sx - 3., sy), (sx + 3., sy)));
+        try!(label(turtle, (sx + 5., sy + 2.), y));
+        y += step;
+    }
+    Ok(())
+}
+
+fn line(turtle: &mut Turtle, start: (f32, f32), end: (f32, f32)) -> TurtleResult {
+    try!(turtle.teleport(start.0, start.1));
+    turtle.pen_down();
+    try!(turtle.teleport(end.0, end.1));
+    turtle.pen_up();
+    Ok(())
+}
+
+fn label(turtle: &mut Turtle, position: (f32, f32), value: f32) -> TurtleResult {
+    // Avoid labels like 0.30000001 and -0
+    let value = (value * 1e4).round() / 1e4 + 0.;
+    try!(turtle.teleport(position.0, position.1));
+    turtle.write(&value.to_string())
+}
@@ -0,0 +1,134 @@
+//! Export of the drawn lines for pen plotters.
+//!
+//! Plotters only draw lines, so texts, filled areas and images are left out.
+//! Lines that continue where the last one ended are drawn without lifting
+//! the pen. The drawing is moved so that its lower left corner is at the
+//! origin of the plotter and scaled from turtle units to millimeters:
+//!
+//! ```
+//! use rurtle::plotter::{self, PlotterOptions};
+//! let lines = [((0., 0.), (100., 0.)), ((100., 0.), (100., 100.))];
+//! let mut gcode = Vec::new();
+//! plotter::write(&mut gcode, &lines, &PlotterOptions::default()).unwrap();
+//! assert!(String::from_utf8(gcode).unwrap().contains("G1 X25 Y25"));
+//! ```
+use std::fs;
+use std::io::{self, Write};
+
+/// The languages that plotters understand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// G-code as used by CNC machines and most hobby plotters, e.g. the ones
+    /// running GRBL
+    Gcode,
+    /// The Hewlett-Packard Graphics Language of classic pen plotters
+    Hpgl,
+}
+
+/// HPGL coordinates are given in plotter units of 0.025 mm
+const HPGL_UNITS_PER_MM: f32 = 40.;
+
+/// How the drawing is converted for the plotter
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlotterOptions {
+    pub format: Format,
+    /// Millimeters per turtle unit
+    pub scale: f32,
+    /// G-code that lowers the pen onto the paper
+    pub pen_down: String,
+    /// G-code that lifts the pen
+    pub pen_up: String,
+    /// Speed while drawing in millimeters per minute (G-code only)
+    pub feed_rate: f32,
+}
+
+impl Default for PlotterOptions {
+    fn default() -> PlotterOptions {
+        PlotterOptions {
+            format: Format::Gcode,
+            scale: 0.25,
+            pen_down: "G0 Z0".to_owned(),
+            pen_up: "G0 Z5".to_owned(),
+            feed_rate: 1500.,
+        }
+    }
+}
+
+/// Format a coordinate in millimeters with at most three decimals
+fn millimeters(x: f32) -> String {
+    let rounded = format!("{:.3}", x);
+    let trimmed = rounded.trim_right_matches('0').trim_right_matches('.');
+    if trimmed == "-0" { "0".to_owned() } else { trimmed.to_owned() }
+}
+
+/// Split the lines into strokes, lists of points that are drawn without
+/// lifting the pen
+fn strokes(lines: &[((f32, f32), (f32, f32))]) -> Vec<Vec<(f32, f32)>> {
+    let mut strokes: Vec<Vec<(f32, f32)>> = Vec::new();
+    for &(start, end) in lines {
+        let continues = strokes.last().and_then(|stroke| stroke.last()) == Some(&start);
+        if continues {
+            strokes.last_mut().unwrap().push(end);
+        } else {
+            strokes.push(vec![start, end]);
+        }
+    }
+    strokes
+}
+
+/// Write the lines (given in turtle units) for the plotter
+pub fn write<W: Write>(out: &mut W, lines: &[((f32, f32), (f32, f32))],
+                       options: &PlotterOptions) -> io::Result<()> {
+    // The plotter's origin is in a corner, not in the middle
+    let min_x = lines.iter().map(|&(a, b)| a.0.min(b.0)).fold(::std::f32::INFINITY, f32::min);
+    let min_y = lines.iter().map(|&(a, b)| a.1.min(b.1)).fold(::std::f32::INFINITY, f32::min);
+    let to_paper = |(x, y): (f32, f32)| ((x - min_x) * options.scale, (y - min_y) * options.scale);
+    let strokes = strokes(lines);
+    match options.format {
+        Format::Gcode => {
+            try!(writeln!(out, "; Exported from Rurtle"));
+            // Millimeters, absolute coordinates
+            try!(writeln!(out, "G21\nG90"));
+            try!(writeln!(out, "{}", options.pen_up));
+            for stroke in &strokes {
+                let (x, y) = to_paper(stroke[0]);
+                try!(writeln!(out, "G0 X{} Y{}", millimeters(x), millimeters(y)));
+                try!(writeln!(out, "{}", options.pen_down));
+                for &point in &stroke[1..] {
+                    let (x, y) = to_paper(point);
+                    try!(writeln!(out, "G1 X{} Y{} F{}", millimeters(x), millimeters(y),
+                                  millimeters(options.feed_rate)));
+                }
+                try!(writeln!(out, "{}", options.pen_up));
+            }
+            try!(writeln!(out, "G0 X0 Y0"));
+        },
+        Format::Hpgl => {
+            let units = |point| {
+                let (x, y) = to_paper(point);
+                ((x * HPGL_UNITS_PER_MM).round() as i64, (y * HPGL_UNITS_PER_MM).round() as i64)
+            };
+            try!(writeln!(out, "IN;SP1;"));
+            for stroke in &strokes {
+                let (x, y) = units(stroke[0]);
+                try!(write!(out, "PU{},{};PD", x, y));
+                let points: Vec<String> = stroke[1..].iter()
+                    .map(|&point| {
+                        let (x, y) = units(point);
+                        format!("{},{}", x, y)
+                    })
+                    .collect();
+                try!(writeln!(out, "{};", points.join(",")));
+            }
+            try!(writeln!(out, "PU0,0;SP0;"));
+        },
+    }
+    Ok(())
+}
+
+/// Write the lines to the file at `path`, see `write`
+pub fn export(path: &str, lines: &[((f32, f32), (f32, f32))], options: &PlotterOptions)
+              -> io::Result<()> {
+    let mut file = io::BufWriter::new(try!(fs::File::create(path)));
+    write(&mut file, lines, options)
+}
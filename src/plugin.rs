@@ -0,0 +1,212 @@
+//! Extending Rurtle with plugins.
+//!
+//! A plugin is a bundle of native functions (and constants) that is registered
+//! at once, so domain specific extensions can live in their own crates. To
+//! pass their own data types to and from Rurtle code, plugins can implement
+//! `ToValue` and `FromValue` for them.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rurtle::{Rurtle, ScreenOptions, Value};
+//! use rurtle::environ::{Environment, ResultType};
+//! use rurtle::plugin::{FromValue, Plugin, Registry, ToValue};
+//!
+//! struct Distance;
+//!
+//! fn distance(_: &mut Environment, args: &[Value]) -> ResultType {
+//!     let a = try!(Vec::<f32>::from_value(&args[0]));
+//!     let b = try!(Vec::<f32>::from_value(&args[1]));
+//!     Ok(((a[0] - b[0]).hypot(a[1] - b[1])).to_value())
+//! }
+//!
+//! impl Plugin for Distance {
+//!     fn name(&self) -> &str { "distance" }
+//!     fn register(&self, registry: &mut Registry) {
+//!         registry.native("DISTANCE", 2, distance);
+//!     }
+//! }
+//!
+//! let mut rurtle = Rurtle::new(ScreenOptions::default());
+//! rurtle.load_plugin(&Distance).unwrap();
+//! rurtle.eval("print distance [0 0] [3 4]").unwrap();
+//! ```
+use super::environ::{Environment, FuncType, Function, RuntimeError, SpecialFuncType};
+use super::environ::value::Value;
+use super::parse::Arity;
+
+/// A bundle of functions that can be loaded into an `Environment`
+pub trait Plugin {
+    /// Name of the plugin, used in error messages
+    fn name(&self) -> &str;
+    /// Register the plugin's functions and constants
+    fn register(&self, registry: &mut Registry);
+}
+
+/// Interface through which plugins register their functions
+pub struct Registry<'a> {
+    environment: &'a mut Environment,
+    errors: Vec<RuntimeError>,
+}
+
+impl<'a> Registry<'a> {
+    /// Register a native function taking `arity` arguments
+    pub fn native(&mut self, name: &str, arity: i32, function: FuncType) {
+        self.environment.define_function(name, Function::Native(arity, function));
+    }
+
+    /// Register a special form, see `environ::SpecialFuncType`
+    pub fn special(&mut self, name: &str, arity: Arity, function: SpecialFuncType) {
+        self.environment.define_function(name, Function::Special(arity, function));
+    }
+
+    /// Register a global constant. Defining an existing constant is an error
+    /// which is reported by `load`.
+    pub fn constant<T: ToValue>(&mut self, name: &str, value: T) {
+        if let Err(e) = self.environment.define_constant(name, value.to_value()) {
+            self.errors.push(e);
+        }
+    }
+}
+
+/// Register the plugin's functions in the given environment
+pub fn load(environment: &mut Environment, plugin: &Plugin) -> Result<(), RuntimeError> {
+    let errors = {
+        let mut registry = Registry {
+            environment: environment,
+            errors: Vec::new(),
+        };
+        plugin.register(&mut registry);
+        registry.errors
+    };
+    match errors.into_iter().next() {
+        Some(e) => Err(RuntimeError::new(format!("plugin {}: {}", plugin.name(), e))),
+        None => Ok(()),
+    }
+}
+
+/// Types that can be converted to a Rurtle `Value`
+pub trait ToValue {
+    fn to_value(self) -> Value;
+}
+
+/// Types that can be extracted from a Rurtle `Value`
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError>;
+}
+
+/// Return the error for a value that has the wrong type
+fn type_error(expected: &str, value: &Value) -> RuntimeError {
+    RuntimeError::new(format!("expected {}, got {}", expected, value.type_string()))
+}
+
+impl ToValue for Value {
+    fn to_value(self) -> Value { self }
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Value, RuntimeError> {
+        Ok(value.clone())
+    }
+}
+
+impl ToValue for () {
+    fn to_value(self) -> Value { Value::Nothing }
+}
+
+impl ToValue for f32 {
+    fn to_value(self) -> Value { Value::Number(self) }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: &Value) -> Result<f32, RuntimeError> {
+        value.as_number().ok_or_else(|| type_error("number", value))
+    }
+}
+
+impl ToValue for i32 {
+    fn to_value(self) -> Value { Value::Integer(self as i64) }
+}
+
+/// Floats are truncated
+impl FromValue for i32 {
+    fn from_value(value: &Value) -> Result<i32, RuntimeError> {
+        match *value {
+            Value::Integer(n) if n as i32 as i64 == n => Ok(n as i32),
+            ref val => f32::from_value(val).map(|x| x as i32),
+        }
+    }
+}
+
+impl ToValue for i64 {
+    fn to_value(self) -> Value { Value::Integer(self) }
+}
+
+/// Only integers are accepted
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<i64, RuntimeError> {
+        value.as_integer().ok_or_else(|| type_error("integer", value))
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(self) -> Value { Value::Number(if self { 1. } else { 0. }) }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<bool, RuntimeError> {
+        Ok(value.boolean())
+    }
+}
+
+impl ToValue for String {
+    fn to_value(self) -> Value { Value::String(self) }
+}
+
+impl<'a> ToValue for &'a str {
+    fn to_value(self) -> Value { Value::String(self.to_owned()) }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<String, RuntimeError> {
+        match *value {
+            Value::String(ref s) => Ok(s.clone()),
+            ref val => Err(type_error("string", val)),
+        }
+    }
+}
+
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(self) -> Value {
+        Value::List(self.into_iter().map(ToValue::to_value).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Vec<T>, RuntimeError> {
+        match *value {
+            Value::List(ref l) => l.iter().map(T::from_value).collect(),
+            ref val => Err(type_error("list", val)),
+        }
+    }
+}
+
+/// `None` is converted to `Nothing`
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(self) -> Value {
+        match self {
+            Some(v) => v.to_value(),
+            None => Value::Nothing,
+        }
+    }
+}
+
+/// `Nothing` is converted to `None`
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Option<T>, RuntimeError> {
+        match *value {
+            Value::Nothing => Ok(None),
+            ref val => T::from_value(val).map(Some),
+        }
+    }
+}
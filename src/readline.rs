@@ -9,11 +9,12 @@
 //! # Windows support
 //!
 //! Note that readline is not available on Windows. `readline` will still work
-//! with less awesomeness though, and `add_history` is just a stub.
+//! with less awesomeness though, and `add_history` is just a stub. The same
+//! goes for builds without the `native` feature, e.g. for the browser.
 //!
 //! This module will automatically select the right readline version for the
 //! target os.
-#[cfg(not(windows))]
+#[cfg(all(not(windows), feature = "native"))]
 mod module {
     extern crate libc;
     use self::libc::{c_void, free};
@@ -72,7 +73,7 @@ mod module {
     }
 }
 
-#[cfg(windows)]
+#[cfg(any(windows, not(feature = "native")))]
 mod module {
     use std::io::{self, Write};
 
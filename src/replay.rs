@@ -0,0 +1,267 @@
+//! Recording and replaying of turtle commands.
+//!
+//! Every drawing command that is given to a `Turtle` is recorded in its
+//! command log. The log can be saved to a file and replayed later, for example
+//! to regenerate a drawing from an interactive session.
+//!
+//! # File format
+//!
+//! A log file contains one command per line, written as a Rurtle function call
+//! (`FORWARD 100`, `COLOR 1 0 0`, `WRITE "Hello"`). Thus a saved log is also a
+//! valid Rurtle program.
+use super::filter::Filter;
+use super::lex::{self, quote, Token};
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+/// Format the number as literal. Whole numbers are written without decimal
+/// point, the commands accept them as well.
+fn number(x: f32) -> String {
+    if x.fract() == 0. && x.abs() < 1e15 {
+        lex::integer_literal(x as i64)
+    } else {
+        lex::number_literal(x)
+    }
+}
+
+/// Format the number as list element. Negative numbers are put in
+/// parenthesis, otherwise `[1 -2]` would be read as `[(1 - 2)]`.
+fn element(x: f32) -> String {
+    if x < 0. { format!("({})", number(x)) } else { number(x) }
+}
+
+/// A single recorded turtle command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Forward(f32),
+    Backward(f32),
+    Left(f32),
+    Right(f32),
+    PenUp,
+    PenDown,
+    Color(f32, f32, f32),
+    BackgroundColor(f32, f32, f32),
+    SmoothLines(bool),
+    PenSize(f32),
+    Teleport(f32, f32),
+    Realign(f32),
+    Home,
+    SetHome(f32, f32, f32),
+    Hide,
+    Show,
+    Write(String),
+    WriteRotated(String, f32),
+    HorizontalText(bool),
+    WriteAlong(String),
+    WriteArc(String, f32),
+    StampImage(String, f32),
+    Flood,
+    Clear,
+    Mode3D(bool),
+    PitchUp(f32),
+    PitchDown(f32),
+    RollLeft(f32),
+    RollRight(f32),
+    PushTransform,
+    PopTransform,
+    Scale(f32, f32),
+    Rotate(f32),
+    Translate(f32, f32),
+    Mirror(u32, bool),
+    Trail(u32),
+    Snap(f32),
+    SnapGrid(f32),
+    Isometric(bool),
+    BeginForeground,
+    ClearForeground,
+    Points(Vec<(f32, f32)>),
+    ApplyFilter(Filter),
+}
+
+impl Command {
+    /// Return the command as a line of Rurtle source
+    pub fn to_source(&self) -> String {
+        use self::Command::*;
+        match *self {
+            Forward(x) => format!("FORWARD {}", number(x)),
+            Backward(x) => format!("BACKWARD {}", number(x)),
+            Left(x) => format!("LEFT {}", number(x)),
+            Right(x) => format!("RIGHT {}", number(x)),
+            PenUp => "PENUP".to_owned(),
+            PenDown => "PENDOWN".to_owned(),
+            Color(r, g, b) => format!("COLOR {} {} {}", number(r), number(g), number(b)),
+            BackgroundColor(r, g, b) => {
+                format!("BGCOLOR {} {} {}", number(r), number(g), number(b))
+            },
+            SmoothLines(flag) => format!("SMOOTHLINES {}", if flag { 1 } else { 0 }),
+            PenSize(x) => format!("SETPENSIZE {}", number(x)),
+            Teleport(x, y) => format!("TELEPORT {} {}", number(x), number(y)),
+            Realign(x) => format!("REALIGN {}", number(x)),
+            Home => "HOME".to_owned(),
+            SetHome(x, y, orientation) => {
+                format!("SETHOME {} {} {}", number(x), number(y), number(orientation))
+            },
+            Hide => "HIDE".to_owned(),
+            Show => "SHOW".to_owned(),
+            Write(ref text) => format!("WRITE {}", quote(text)),
+            WriteRotated(ref text, heading) => {
+                format!("(WRITE {} {})", quote(text), element(heading))
+            },
+            HorizontalText(flag) => format!("HORIZONTALTEXT {}", if flag { 1 } else { 0 }),
+            WriteAlong(ref text) => format!("WRITEALONG {}", quote(text)),
+            WriteArc(ref text, radius) => format!("WRITEARC {} {}", quote(text), number(radius)),
+            StampImage(ref path, scale) => {
+                format!("STAMPIMAGE {} {}", quote(path), number(scale))
+            },
+            Flood => "FLOOD".to_owned(),
+            Clear => "CLEAN".to_owned(),
+            Mode3D(flag) => format!("MODE3D {}", if flag { 1 } else { 0 }),
+            PitchUp(x) => format!("PITCHUP {}", number(x)),
+            PitchDown(x) => format!("PITCHDOWN {}", number(x)),
+            RollLeft(x) => format!("ROLLLEFT {}", number(x)),
+            RollRight(x) => format!("ROLLRIGHT {}", number(x)),
+            PushTransform => "PUSHTRANSFORM".to_owned(),
+            PopTransform => "POPTRANSFORM".to_owned(),
+            Scale(x, y) => format!("SCALE {} {}", number(x), number(y)),
+            Rotate(x) => format!("ROTATE {}", number(x)),
+            Translate(x, y) => format!("TRANSLATE {} {}", number(x), number(y)),
+            Mirror(count, false) => format!("MIRROR {}", count),
+            Mirror(count, true) => format!("KALEIDOSCOPE {}", count),
+            Trail(length) => format!("SETTRAIL {}", length),
+            Snap(angle) => format!("SNAP {}", number(angle)),
+            SnapGrid(size) => format!("SNAPGRID {}", number(size)),
+            Isometric(flag) => format!("ISOMETRIC {}", if flag { 1 } else { 0 }),
+            BeginForeground => "BEGINFOREGROUND".to_owned(),
+            ClearForeground => "CLEARFOREGROUND".to_owned(),
+            Points(ref points) => {
+                let points: Vec<String> = points.iter()
+                    .map(|&(x, y)| format!("[{} {}]", element(x), element(y)))
+                    .collect();
+                format!("PLOTPOINTS [{}]", points.join(" "))
+            },
+            ApplyFilter(Filter::Blur(radius)) => format!("BLUR {}", radius),
+            ApplyFilter(filter) => {
+                let name = filter.name().expect("every other filter has a name");
+                format!("IMAGEFILTER {}", quote(name))
+            },
+        }
+    }
+
+    /// Parse a line as written by `to_source`. Returns `None` if the line is
+    /// not a valid command.
+    pub fn from_source(line: &str) -> Option<Command> {
+        use self::Command::*;
+        let tokens = match lex::tokenize(line) {
+            Ok(tokens) => tokens,
+            Err(_) => return None,
+        };
+        let mut tokens = tokens.into_iter().map(|meta| meta.token);
+        let name = match tokens.next() {
+            Some(Token::Word(name)) => name.to_uppercase(),
+            _ => return None,
+        };
+        // Collect the arguments, folding unary minus into the numbers
+        let mut numbers = Vec::new();
+        let mut strings = Vec::new();
+        let mut negate = false;
+        for token in tokens {
+            match token {
+                Token::OpMinus => negate = true,
+                Token::Number(x) => {
+                    numbers.push(if negate { -x } else { x });
+                    negate = false;
+                },
+                Token::Integer(x) => {
+                    numbers.push(if negate { -x as f32 } else { x as f32 });
+                    negate = false;
+                },
+                Token::String(s) => strings.push(s),
+                Token::LParens | Token::RParens | Token::LBracket | Token::RBracket => (),
+                _ => return None,
+            }
+        }
+        let command = match (name.as_ref(), &numbers[..], &strings[..]) {
+            ("FORWARD", &[x], &[]) => Forward(x),
+            ("BACKWARD", &[x], &[]) => Backward(x),
+            ("LEFT", &[x], &[]) => Left(x),
+            ("RIGHT", &[x], &[]) => Right(x),
+            ("PENUP", &[], &[]) => PenUp,
+            ("PENDOWN", &[], &[]) => PenDown,
+            ("COLOR", &[r, g, b], &[]) => Color(r, g, b),
+            ("BGCOLOR", &[r, g, b], &[]) => BackgroundColor(r, g, b),
+            ("SMOOTHLINES", &[x], &[]) => SmoothLines(x != 0.),
+            ("SETPENSIZE", &[x], &[]) => PenSize(x),
+            ("TELEPORT", &[x, y], &[]) => Teleport(x, y),
+            ("REALIGN", &[x], &[]) => Realign(x),
+            ("HOME", &[], &[]) => Home,
+            ("SETHOME", &[x, y, orientation], &[]) => SetHome(x, y, orientation),
+            ("HIDE", &[], &[]) => Hide,
+            ("SHOW", &[], &[]) => Show,
+            ("WRITE", &[], &[ref text]) => Write(text.clone()),
+            ("WRITE", &[heading], &[ref text]) => WriteRotated(text.clone(), heading),
+            ("HORIZONTALTEXT", &[x], &[]) => HorizontalText(x != 0.),
+            ("WRITEALONG", &[], &[ref text]) => WriteAlong(text.clone()),
+            ("WRITEARC", &[radius], &[ref text]) => WriteArc(text.clone(), radius),
+            ("STAMPIMAGE", &[scale], &[ref path]) => StampImage(path.clone(), scale),
+            ("FLOOD", &[], &[]) => Flood,
+            // Older logs use CLEAR, which is the same
+            ("CLEAN", &[], &[]) | ("CLEAR", &[], &[]) => Clear,
+            ("MODE3D", &[x], &[]) => Mode3D(x != 0.),
+            ("PITCHUP", &[x], &[]) => PitchUp(x),
+            ("PITCHDOWN", &[x], &[]) => PitchDown(x),
+            ("ROLLLEFT", &[x], &[]) => RollLeft(x),
+            ("ROLLRIGHT", &[x], &[]) => RollRight(x),
+            ("PUSHTRANSFORM", &[], &[]) => PushTransform,
+            ("POPTRANSFORM", &[], &[]) => PopTransform,
+            ("SCALE", &[x, y], &[]) => Scale(x, y),
+            ("ROTATE", &[x], &[]) => Rotate(x),
+            ("TRANSLATE", &[x, y], &[]) => Translate(x, y),
+            ("MIRROR", &[x], &[]) => Mirror(x as u32, false),
+            ("KALEIDOSCOPE", &[x], &[]) => Mirror(x as u32, true),
+            ("SETTRAIL", &[x], &[]) => Trail(x as u32),
+            ("SNAP", &[x], &[]) => Snap(x),
+            ("SNAPGRID", &[x], &[]) => SnapGrid(x),
+            ("ISOMETRIC", &[x], &[]) => Isometric(x != 0.),
+            ("BEGINFOREGROUND", &[], &[]) => BeginForeground,
+            ("CLEARFOREGROUND", &[], &[]) => ClearForeground,
+            ("PLOTPOINTS", coordinates, &[]) if coordinates.len() % 2 == 0 => {
+                Points(coordinates.chunks(2).map(|c| (c[0], c[1])).collect())
+            },
+            ("IMAGEFILTER", &[], &[ref name]) => match Filter::from_name(name) {
+                Some(filter) => ApplyFilter(filter),
+                None => return None,
+            },
+            ("BLUR", &[radius], &[]) => ApplyFilter(Filter::Blur(radius as u32)),
+            _ => return None,
+        };
+        Some(command)
+    }
+}
+
+/// Write the given commands to the file at `path`, one command per line
+pub fn save_log(path: &str, commands: &[Command]) -> io::Result<()> {
+    let mut file = try!(fs::File::create(path));
+    for command in commands {
+        try!(writeln!(file, "{}", command.to_source()));
+    }
+    Ok(())
+}
+
+/// Read a command log from the file at `path`. Empty lines are skipped.
+pub fn load_log(path: &str) -> io::Result<Vec<Command>> {
+    let file = try!(fs::File::open(path));
+    let mut commands = Vec::new();
+    for (number, line) in io::BufReader::new(file).lines().enumerate() {
+        let line = try!(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        match Command::from_source(&line) {
+            Some(command) => commands.push(command),
+            None => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid command in line {}: {}", number + 1, line))),
+        }
+    }
+    Ok(commands)
+}
@@ -0,0 +1,187 @@
+//! Mirroring of the turtle to a physical robot.
+//!
+//! A `Robot` is a floor robot with a pen that follows the turtle, see
+//! `Turtle::set_robot`. It is controlled with simple text commands, one per
+//! line, that are written to a serial port (or any other `Write`). The
+//! commands are configured by a `RobotProtocol`, the default one sends
+//! `FD 100`, `LT 90`, `RT 90`, `PU` and `PD`.
+//!
+//! The robot starts at the origin facing north with the pen up. For each move
+//! of the turtle, it turns towards the start of the move and drives there with
+//! the pen up if it isn't there already, then it drives along the move with
+//! the pen up or down like the turtle's. Only new moves are sent, redrawing or
+//! replaying the drawing doesn't move the robot. Texts, fills and images can't
+//! be drawn by the robot.
+//!
+//! On Linux, the serial port has to be configured before, e.g. with
+//! `stty -F /dev/ttyUSB0 9600 raw`.
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+/// Distances and angles below this are not sent to the robot
+const EPSILON: f32 = 1e-3;
+
+/// The commands understood by the robot. In the templates, `{}` is replaced
+/// by the distance in millimeters or the angle in degrees.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RobotProtocol {
+    pub forward: String,
+    pub left: String,
+    pub right: String,
+    pub pen_up: String,
+    pub pen_down: String,
+    /// Millimeters per turtle unit
+    pub scale: f32,
+}
+
+impl Default for RobotProtocol {
+    fn default() -> RobotProtocol {
+        RobotProtocol {
+            forward: "FD {}".to_owned(),
+            left: "LT {}".to_owned(),
+            right: "RT {}".to_owned(),
+            pen_up: "PU".to_owned(),
+            pen_down: "PD".to_owned(),
+            scale: 1.,
+        }
+    }
+}
+
+impl RobotProtocol {
+    /// Read a protocol from a file with lines like `forward = F{}` for the
+    /// keys `forward`, `left`, `right`, `penup`, `pendown` and `scale`. Keys
+    /// that are missing keep their default, empty lines and lines starting
+    /// with `#` are skipped.
+    pub fn from_file(path: &str) -> io::Result<RobotProtocol> {
+        let file = try!(fs::File::open(path));
+        let mut protocol = RobotProtocol::default();
+        for (number, line) in io::BufReader::new(file).lines().enumerate() {
+            let line = try!(line);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData,
+                                            format!("invalid line {}: {}", number + 1, line));
+            let mut parts = line.splitn(2, '=');
+            let (key, value) = match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => (key.trim().to_lowercase(), value.trim().to_owned()),
+                _ => return Err(invalid()),
+            };
+            match &key[..] {
+                "forward" => protocol.forward = value,
+                "left" => protocol.left = value,
+                "right" => protocol.right = value,
+                "penup" => protocol.pen_up = value,
+                "pendown" => protocol.pen_down = value,
+                "scale" => match value.parse() {
+                    Ok(scale) if scale > 0. => protocol.scale = scale,
+                    _ => return Err(invalid()),
+                },
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(protocol)
+    }
+}
+
+/// Format the number with at most two decimals
+fn number(x: f32) -> String {
+    let rounded = format!("{:.2}", x);
+    rounded.trim_right_matches('0').trim_right_matches('.').to_owned()
+}
+
+/// The connection to the robot and where the robot is
+pub struct Robot {
+    output: Box<Write>,
+    protocol: RobotProtocol,
+    position: (f32, f32),
+    /// In degrees, like the turtle's orientation
+    heading: f32,
+    pen_down: bool,
+    /// Set after the first error, nothing is sent anymore afterwards
+    failed: bool,
+}
+
+impl Robot {
+    /// Control the robot by writing the commands to `output`
+    pub fn new(output: Box<Write>, protocol: RobotProtocol) -> Robot {
+        Robot {
+            output: output,
+            protocol: protocol,
+            position: (0., 0.),
+            heading: 0.,
+            pen_down: false,
+            failed: false,
+        }
+    }
+
+    /// Control the robot connected to the serial port at `port`, e.g.
+    /// `/dev/ttyUSB0` or `COM3`
+    pub fn open(port: &str, protocol: RobotProtocol) -> io::Result<Robot> {
+        let output = try!(fs::OpenOptions::new().write(true).open(port));
+        Ok(Robot::new(Box::new(output), protocol))
+    }
+
+    /// Send a line to the robot. After the first error, the robot is not
+    /// controlled anymore and nothing is sent.
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        if self.failed {
+            return Ok(());
+        }
+        let result = writeln!(self.output, "{}", command).and_then(|_| self.output.flush());
+        if result.is_err() {
+            self.failed = true;
+        }
+        result
+    }
+
+    fn set_pen(&mut self, down: bool) -> io::Result<()> {
+        if self.pen_down != down {
+            let command = if down {
+                self.protocol.pen_down.clone()
+            } else {
+                self.protocol.pen_up.clone()
+            };
+            try!(self.send(&command));
+            self.pen_down = down;
+        }
+        Ok(())
+    }
+
+    /// Turn the robot the shorter way until it faces `heading`
+    fn turn_to(&mut self, heading: f32) -> io::Result<()> {
+        let mut delta = (heading - self.heading) % 360.;
+        if delta > 180. {
+            delta -= 360.;
+        } else if delta <= -180. {
+            delta += 360.;
+        }
+        if delta.abs() < EPSILON {
+            return Ok(());
+        }
+        let command = {
+            let template = if delta > 0. { &self.protocol.left } else { &self.protocol.right };
+            template.replace("{}", &number(delta.abs()))
+        };
+        try!(self.send(&command));
+        self.heading = heading;
+        Ok(())
+    }
+
+    /// Drive in a straight line to `target`, with the pen up or down.
+    /// Returns the error if sending fails, later calls do nothing then.
+    pub fn move_to(&mut self, target: (f32, f32), pen_down: bool) -> io::Result<()> {
+        let (dx, dy) = (target.0 - self.position.0, target.1 - self.position.1);
+        let distance = (dx * dx + dy * dy).sqrt();
+        if distance < EPSILON {
+            return Ok(());
+        }
+        try!(self.set_pen(pen_down));
+        try!(self.turn_to((-dx).atan2(dy).to_degrees()));
+        let command = self.protocol.forward.replace("{}", &number(distance * self.protocol.scale));
+        try!(self.send(&command));
+        self.position = target;
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+//! Remote control of Rurtle over TCP.
+//!
+//! Clients send Rurtle source code, one command per line. Each line is
+//! evaluated against the running environment and answered with a single line
+//! of JSON, either `{"ok":true,"value":...}` or `{"ok":false,"error":"..."}`.
+//!
+//! Several clients can be connected at the same time. They draw on the same
+//! screen, but each client has its own turtle (position, orientation, color
+//! and pen) and starts with a different color. The functions and variables
+//! are shared. The shapes remember which client drew them, so `CLEARMINE` and
+//! `UNDO` only remove the client's own shapes. Right after connecting, a
+//! client receives a greeting line with its number, its color and the
+//! drawing so far as list of commands:
+//! `{"client":1,"color":[r,g,b],"drawing":["FORWARD 100",...]}`.
+//!
+//! Anyone who can connect can run code, so the clients' commands are
+//! evaluated in sandbox mode (see `Environment::set_sandbox`) unless
+//! `Session::set_sandbox(false)` is called. The local commands are not
+//! affected. Without the sandbox, only listen on a loopback address like
+//! `127.0.0.1` or in a network you trust. A command also can't run longer
+//! than the limits given with `Session::set_limits`, by default 5 seconds and
+//! a million statements, so a client can't block the others with an endless
+//! loop. Lines longer than 64 KiB close the connection.
+//!
+//! The network is handled in background threads, but the commands have to be
+//! evaluated by the owner of the `Environment` since it can't be shared
+//! between threads:
+//!
+//! ```no_run
+//! # fn example(environ: &mut rurtle::Environment) {
+//! let mut session = rurtle::server::Session::listen("127.0.0.1:7777").unwrap();
+//! loop {
+//!     session.handle(environ);
+//! #   break;
+//! }
+//! # }
+//! ```
+use super::environ::{Environment, Limits};
+use super::environ::value::Value;
+use super::graphic::color::Color;
+use super::turtle::TurtleState;
+use rustc_serialize::json::{self, Json};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// Lines longer than this many bytes (including the line break) close the
+/// connection, so a client can't make the server buffer an endless line
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// Colors given to the clients in the order they connect
+const CLIENT_COLORS: [Color; 6] = [
+    (0.8, 0.1, 0.1, 1.0),
+    (0.1, 0.5, 0.1, 1.0),
+    (0.1, 0.2, 0.8, 1.0),
+    (0.9, 0.5, 0.0, 1.0),
+    (0.6, 0.1, 0.7, 1.0),
+    (0.0, 0.6, 0.6, 1.0),
+];
+
+/// Something that happened on a connection
+pub enum Event {
+    /// A new client connected, the first message sent to `reply` is the
+    /// greeting
+    Connected(usize, Sender<String>),
+    /// A client sent a line of source code
+    Command(Request),
+    /// The client closed the connection
+    Disconnected(usize),
+}
+
+/// A line of source code sent by a client
+pub struct Request {
+    /// Number of the client that sent the request
+    pub client: usize,
+    /// The source code to evaluate
+    pub source: String,
+    reply: Sender<String>,
+}
+
+impl Request {
+    /// Send the result of the evaluation back to the client
+    pub fn answer(self, result: Result<Value, Box<Error>>) {
+        let mut object = BTreeMap::new();
+        match result {
+            Ok(value) => {
+                object.insert("ok".to_owned(), Json::Boolean(true));
+                // Procedures are sent as source code
+                let value = value.to_json().unwrap_or_else(|| Json::String(value.to_source()));
+                object.insert("value".to_owned(), value);
+            },
+            Err(e) => {
+                object.insert("ok".to_owned(), Json::Boolean(false));
+                object.insert("error".to_owned(),
+                              Json::String(format!("{}: {}", e.description(), e)));
+            },
+        }
+        // If the client is gone, nobody is interested in the answer
+        self.reply.send(json::encode(&Json::Object(object)).unwrap()).unwrap_or(());
+    }
+}
+
+/// Listen on the given address (e.g. "127.0.0.1:7777") in a background thread.
+/// The events of all clients are delivered through the returned channel.
+pub fn listen(address: &str) -> io::Result<Receiver<Event>> {
+    let listener = try!(TcpListener::bind(address));
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for (id, stream) in listener.incoming().enumerate() {
+            if let Ok(stream) = stream {
+                let tx = tx.clone();
+                thread::spawn(move || serve_client(id + 1, stream, tx));
+            }
+        }
+    });
+    Ok(rx)
+}
+
+/// Forward the client's lines and write back the answers, one per line
+fn serve_client(id: usize, stream: TcpStream, events: Sender<Event>) {
+    let mut writer = match stream.try_clone() {
+        Ok(w) => w,
+        Err(_) => return,
+    };
+    let (reply_tx, reply_rx) = mpsc::channel();
+    // Errors mean that the interpreter has stopped
+    if events.send(Event::Connected(id, reply_tx.clone())).is_err() {
+        return;
+    }
+    let mut reader = BufReader::new(stream);
+    // The first answer is the greeting
+    while let Ok(answer) = reply_rx.recv() {
+        if writeln!(writer, "{}", answer).is_err() {
+            break;
+        }
+        let line = match read_command(&mut reader) {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                writeln!(writer, "{}", error_line(&e.to_string())).unwrap_or(());
+                break;
+            },
+        };
+        let request = Request {
+            client: id,
+            source: line,
+            reply: reply_tx.clone(),
+        };
+        if events.send(Event::Command(request)).is_err() {
+            return;
+        }
+    }
+    events.send(Event::Disconnected(id)).unwrap_or(());
+}
+
+/// Read the next line that isn't empty, without the line break. Returns
+/// `None` at the end of the stream and an error if the line is longer than
+/// `MAX_LINE_LENGTH`.
+fn read_command<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    loop {
+        let mut line = Vec::new();
+        try!(reader.by_ref().take(MAX_LINE_LENGTH as u64 + 1).read_until(b'\n', &mut line));
+        if line.is_empty() {
+            return Ok(None);
+        }
+        if line.len() > MAX_LINE_LENGTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("line longer than {} bytes", MAX_LINE_LENGTH)));
+        }
+        let line = try!(String::from_utf8(line)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)));
+        let line = line.trim_right_matches(|c| c == '\n' || c == '\r');
+        if !line.trim().is_empty() {
+            return Ok(Some(line.to_owned()));
+        }
+    }
+}
+
+/// Return the answer for an error that isn't caused by a command
+fn error_line(message: &str) -> String {
+    let mut object = BTreeMap::new();
+    object.insert("ok".to_owned(), Json::Boolean(false));
+    object.insert("error".to_owned(), Json::String(message.to_owned()));
+    json::encode(&Json::Object(object)).unwrap()
+}
+
+/// Return the limits that apply to the clients' commands unless
+/// `Session::set_limits` is called
+pub fn default_limits() -> Limits {
+    Limits {
+        statements: Some(1_000_000),
+        call_depth: Some(200),
+        segments: None,
+        time: Some(Duration::from_secs(5)),
+    }
+}
+
+/// Return the stricter one of two limits, `None` meaning unlimited
+fn stricter<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(::std::cmp::min(a, b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// A server that lets every client control its own turtle on the
+/// environment's screen
+pub struct Session {
+    events: Receiver<Event>,
+    turtles: HashMap<usize, TurtleState>,
+    sandbox: bool,
+    limits: Limits,
+}
+
+impl Session {
+    /// Start listening on the given address, see `listen`
+    pub fn listen(address: &str) -> io::Result<Session> {
+        Ok(Session {
+            events: try!(listen(address)),
+            turtles: HashMap::new(),
+            sandbox: true,
+            limits: default_limits(),
+        })
+    }
+
+    /// Evaluate the clients' commands in sandbox mode or not. The sandbox is
+    /// enabled by default.
+    pub fn set_sandbox(&mut self, sandbox: bool) {
+        self.sandbox = sandbox;
+    }
+
+    /// Set the limits for each of the clients' commands, see
+    /// `Environment::set_limits`. The environment's own limits apply as
+    /// well, the stricter one wins. The default is `default_limits()`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Handle all pending events. The commands are evaluated with the turtle
+    /// of the client that sent them, the local turtle is restored afterwards.
+    pub fn handle(&mut self, environ: &mut Environment) {
+        for event in self.events.try_iter() {
+            match event {
+                Event::Connected(id, reply) => {
+                    let color = CLIENT_COLORS[(id - 1) % CLIENT_COLORS.len()];
+                    let mut state = environ.get_turtle().state();
+                    state.position = (0., 0.);
+                    state.orientation = 0.;
+                    state.color = color;
+                    state.pen_down = true;
+                    state.hidden = false;
+                    self.turtles.insert(id, state);
+                    reply.send(greeting(environ, id, color)).unwrap_or(());
+                },
+                Event::Command(request) => {
+                    let local = environ.get_turtle().state();
+                    // The states come from `Turtle::state` and are always valid
+                    environ.get_turtle().set_state(&self.turtles[&request.client]).unwrap_or(());
+                    environ.get_turtle().set_owner(request.client);
+                    let (sandbox, limits) = (environ.is_sandboxed(), environ.limits());
+                    environ.set_sandbox(sandbox || self.sandbox);
+                    environ.set_limits(Limits {
+                        statements: stricter(limits.statements, self.limits.statements),
+                        call_depth: stricter(limits.call_depth, self.limits.call_depth),
+                        segments: stricter(limits.segments, self.limits.segments),
+                        time: stricter(limits.time, self.limits.time),
+                    });
+                    let result = environ.eval_source(&request.source);
+                    environ.set_sandbox(sandbox);
+                    environ.set_limits(limits);
+                    self.turtles.insert(request.client, environ.get_turtle().state());
+                    environ.get_turtle().set_owner(0);
+                    environ.get_turtle().set_state(&local).unwrap_or(());
+                    request.answer(result);
+                },
+                Event::Disconnected(id) => {
+                    self.turtles.remove(&id);
+                },
+            }
+        }
+    }
+}
+
+/// Return the greeting for a new client: its number, color and the drawing
+/// so far
+fn greeting(environ: &mut Environment, id: usize, color: Color) -> String {
+    let (r, g, b, _) = color;
+    let drawing = environ.get_turtle().get_log().iter()
+        .map(|c| Json::String(c.to_source()))
+        .collect();
+    let mut object = BTreeMap::new();
+    object.insert("client".to_owned(), Json::U64(id as u64));
+    object.insert("color".to_owned(),
+                  Json::Array(vec![Json::F64(r as f64), Json::F64(g as f64), Json::F64(b as f64)]));
+    object.insert("drawing".to_owned(), Json::Array(drawing));
+    json::encode(&Json::Object(object)).unwrap()
+}
@@ -0,0 +1,9 @@
+#version 120
+varying vec2 v_tex_coords;
+
+uniform sampler2D ferris_tex;
+
+void main(void) {
+    // Unlike ferris and stamps, the edges of glyphs are blended smoothly
+    gl_FragColor = texture2D(ferris_tex, v_tex_coords);
+}
\ No newline at end of file
@@ -0,0 +1,599 @@
+//! A `RenderBackend` that rasterizes on the CPU.
+//!
+//! `SoftwareBackend` doesn't need OpenGL at all, it renders into an
+//! `image::RgbaImage`. There is no window, so it is meant for batch rendering
+//! and for tests that compare pixels. Like `TurtleScreen`, it keeps a list of
+//! the drawn shapes and rasterizes them when an image is requested, so the
+//! background color can change at any time and images can be rendered at any
+//! scale.
+//!
+//! Lines are as wide as the pen size and texts use the same font and size as the
+//! `TurtleScreen`. The output is close to, but not pixel-identical with the
+//! OpenGL rendering.
+use super::backend::{self, Annotation, Bounds, Cursor, Overlay, RenderBackend, RenderResult,
+                     Symmetry, View, ANNOTATION_COLOR, AXES_COLOR};
+use super::filter::Filter;
+use super::floodfill as ff;
+use super::graphic::{color, FERRIS_BYTES, FERRIS_HEIGHT, FERRIS_WIDTH};
+use super::graphic::color::Color;
+use super::text::{self, FontChain};
+use image::{self, DynamicImage, GenericImage, RgbaImage};
+use std::collections::HashMap;
+use std::io;
+use std::rc::Rc;
+
+/// Font size of texts in turtle units, same as in `TurtleScreen`
+const FONT_SIZE: f32 = 12.;
+
+enum Shape {
+    Line((f32, f32), (f32, f32), Color, f32),
+    Text((f32, f32), f32, Color, String),
+    Fill((f32, f32), Color),
+    Stamp((f32, f32), f32, f32, Rc<RgbaImage>),
+    Points(Vec<(f32, f32)>, Color, f32),
+}
+
+/// A backend that renders into an image without using the GPU
+pub struct SoftwareBackend {
+    size: (u32, u32),
+    /// The shapes together with the turtle that drew them
+    shapes: Vec<(usize, Shape)>,
+    /// The turtle that draws the next shapes, see `set_owner`
+    owner: usize,
+    cursor: Cursor,
+    background_color: Color,
+    smooth_lines: bool,
+    pen_size: f32,
+    symmetry: Symmetry,
+    trail: Option<usize>,
+    visible_lines: Option<usize>,
+    /// Index of the first shape of the foreground, see `begin_foreground`
+    foreground_start: usize,
+    overlay: Overlay,
+    annotations: Vec<Annotation>,
+    view: View,
+    fonts: FontChain,
+    ferris: RgbaImage,
+    image_cache: HashMap<String, Rc<RgbaImage>>,
+}
+
+impl SoftwareBackend {
+    /// Create a new backend whose canvas has the given size
+    pub fn new(size: (u32, u32)) -> SoftwareBackend {
+        let ferris = image::load(io::Cursor::new(FERRIS_BYTES), image::ImageFormat::PNG)
+            .unwrap().to_rgba();
+        SoftwareBackend {
+            size: size,
+            shapes: Vec::new(),
+            owner: 0,
+            cursor: Cursor {
+                position: (0., 0.),
+                orientation: 0.,
+                color: color::BLACK,
+                hidden: false,
+            },
+            background_color: color::WHITE,
+            smooth_lines: false,
+            pen_size: 1.,
+            symmetry: Symmetry::default(),
+            trail: None,
+            visible_lines: None,
+            foreground_start: 0,
+            overlay: Overlay::default(),
+            annotations: Vec::new(),
+            view: View::default(),
+            fonts: FontChain::new(),
+            ferris: ferris,
+            image_cache: HashMap::new(),
+        }
+    }
+
+    /// Rasterize everything with `scale` pixels per unit
+    fn render(&self, scale: f32, with_cursor: bool) -> RgbaImage {
+        let (width, height) = ((self.size.0 as f32 * scale) as u32,
+                               (self.size.1 as f32 * scale) as u32);
+        let mut canvas = Canvas {
+            image: RgbaImage::from_pixel(width, height, to_rgba(self.background_color)),
+            scale: scale * self.view.zoom,
+            center: self.view.center,
+        };
+        let total = self.line_count();
+        let visible = self.visible_lines.unwrap_or(total);
+        let mut age = total;
+        for &(_, ref shape) in &self.shapes {
+            match *shape {
+                Shape::Line(start, end, color, width) => {
+                    if total - age >= visible {
+                        break;
+                    }
+                    age -= 1;
+                    let color = match self.trail {
+                        Some(length) => backend::fade(color, age, length),
+                        None => color,
+                    };
+                    canvas.line(start, end, color, width, self.smooth_lines)
+                },
+                Shape::Text(anchor, angle, color, ref text) => {
+                    canvas.text(&self.fonts, anchor, angle, color, text)
+                },
+                Shape::Fill(point, color) => canvas.fill(point, color),
+                Shape::Stamp(center, angle, factor, ref image) => {
+                    let size = (image.width() as f32 * factor, image.height() as f32 * factor);
+                    canvas.image(image, center, size, angle)
+                },
+                Shape::Points(ref points, color, size) => canvas.points(points, color, size),
+            }
+        }
+        if self.overlay != Overlay::default() {
+            self.put_overlay_beneath(&mut canvas);
+        }
+        for annotation in &self.annotations {
+            canvas.polygon(&annotation.corners(), ANNOTATION_COLOR);
+            canvas.polygon(&annotation.pointer(), ANNOTATION_COLOR);
+            let outline = annotation.outline();
+            for i in 0..outline.len() {
+                let end = outline[(i + 1) % outline.len()];
+                canvas.line(outline[i], end, AXES_COLOR, 1., true);
+            }
+            canvas.text(&self.fonts, annotation.anchor(), 0., color::BLACK, &annotation.text);
+        }
+        if with_cursor && !self.cursor.hidden {
+            canvas.image(&self.ferris, self.cursor.position, (FERRIS_WIDTH, FERRIS_HEIGHT),
+                         self.cursor.orientation);
+        }
+        canvas.image
+    }
+}
+
+impl SoftwareBackend {
+    /// Remove the oldest lines that don't fit into the trail anymore
+    fn prune_trail(&mut self) {
+        if let Some(length) = self.trail {
+            let mut surplus = self.line_count().saturating_sub(length);
+            let removed = backend::remove_shapes(&mut self.shapes, self.foreground_start,
+                                                 |&(_, ref shape)| match *shape {
+                Shape::Line(..) if surplus > 0 => {
+                    surplus -= 1;
+                    true
+                },
+                _ => false,
+            });
+            self.foreground_start -= removed;
+        }
+    }
+
+    /// Draw the grid and axes into every pixel of the canvas that still has
+    /// the background color. This way the overlay is beneath the drawing and
+    /// doesn't stop floodfills.
+    fn put_overlay_beneath(&self, canvas: &mut Canvas) {
+        let background = to_rgba(self.background_color);
+        let mut layer = Canvas {
+            image: RgbaImage::from_pixel(canvas.image.width(), canvas.image.height(), background),
+            scale: canvas.scale,
+            center: canvas.center,
+        };
+        let size = self.view.overlay_size((self.size.0 as f32, self.size.1 as f32));
+        for (start, end, color) in self.overlay.lines(size) {
+            layer.line(start, end, color, 1., false);
+        }
+        for (anchor, label) in self.overlay.labels(size) {
+            layer.text(&self.fonts, anchor, 0., AXES_COLOR, &label);
+        }
+        for (drawn, beneath) in canvas.image.pixels_mut().zip(layer.image.pixels()) {
+            if *drawn == background {
+                *drawn = *beneath;
+            }
+        }
+    }
+}
+
+/// Convert a color to an 8 bit pixel
+fn to_rgba(color: Color) -> image::Rgba<u8> {
+    let (r, g, b, a) = color;
+    image::Rgba { data: [(r * 255.) as u8, (g * 255.) as u8, (b * 255.) as u8, (a * 255.) as u8] }
+}
+
+/// An image together with the transformation from turtle coordinates
+struct Canvas {
+    image: RgbaImage,
+    /// Pixels per unit, including the zoom of the view
+    scale: f32,
+    /// The point shown in the middle of the image
+    center: (f32, f32),
+}
+
+impl Canvas {
+    /// Convert turtle coordinates to pixel coordinates
+    fn to_pixel(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (self.image.width() as f32 / 2. + (x - self.center.0) * self.scale,
+         self.image.height() as f32 / 2. - (y - self.center.1) * self.scale)
+    }
+
+    /// Blend the color onto the pixel, `coverage` is the fraction of the
+    /// pixel that is covered
+    fn blend(&mut self, x: i64, y: i64, color: [u8; 4], coverage: f32) {
+        if x < 0 || y < 0 || x >= self.image.width() as i64 || y >= self.image.height() as i64 {
+            return;
+        }
+        let alpha = color[3] as f32 / 255. * coverage.min(1.);
+        let pixel = self.image.get_pixel_mut(x as u32, y as u32);
+        for i in 0..3 {
+            pixel.data[i] = (color[i] as f32 * alpha + pixel.data[i] as f32 * (1. - alpha)) as u8;
+        }
+        pixel.data[3] = 255;
+    }
+
+    fn line(&mut self, start: (f32, f32), end: (f32, f32), color: Color, width: f32,
+            smooth: bool) {
+        let (x1, y1) = self.to_pixel(start);
+        let (x2, y2) = self.to_pixel(end);
+        let radius = (self.scale * width / 2.).max(0.5);
+        let (dx, dy) = (x2 - x1, y2 - y1);
+        let length_sq = dx * dx + dy * dy;
+        // Collect the coverage of each pixel first, otherwise pixels would be
+        // blended multiple times
+        let mut coverage: HashMap<(i64, i64), f32> = HashMap::new();
+        let steps = length_sq.sqrt().ceil() as i64 + 1;
+        let reach = radius.ceil() as i64 + 1;
+        for step in 0..steps + 1 {
+            let t = step as f32 / steps as f32;
+            let (cx, cy) = ((x1 + t * dx) as i64, (y1 + t * dy) as i64);
+            for px in cx - reach..cx + reach + 1 {
+                for py in cy - reach..cy + reach + 1 {
+                    // Distance from the pixel center to the segment
+                    let (mx, my) = (px as f32 + 0.5, py as f32 + 0.5);
+                    let u = if length_sq == 0. {
+                        0.
+                    } else {
+                        (((mx - x1) * dx + (my - y1) * dy) / length_sq).max(0.).min(1.)
+                    };
+                    let (nx, ny) = (x1 + u * dx - mx, y1 + u * dy - my);
+                    let distance = (nx * nx + ny * ny).sqrt();
+                    let value = if smooth {
+                        (radius + 0.5 - distance).max(0.).min(1.)
+                    } else if distance <= radius {
+                        1.
+                    } else {
+                        0.
+                    };
+                    if value > 0. {
+                        let entry = coverage.entry((px, py)).or_insert(0.);
+                        *entry = entry.max(value);
+                    }
+                }
+            }
+        }
+        let color = to_rgba(color).data;
+        for ((x, y), value) in coverage {
+            self.blend(x, y, color, value);
+        }
+    }
+
+    /// Draw a square with sides of `size` units (at least one pixel) centered
+    /// at each point
+    fn points(&mut self, points: &[(f32, f32)], color: Color, size: f32) {
+        let color = to_rgba(color).data;
+        let side = (self.scale * size).max(1.);
+        let pixels = side.round() as i64;
+        for &point in points {
+            let (x, y) = self.to_pixel(point);
+            let (left, top) = ((x - side / 2.).round() as i64, (y - side / 2.).round() as i64);
+            for px in left..left + pixels {
+                for py in top..top + pixels {
+                    self.blend(px, py, color, 1.);
+                }
+            }
+        }
+    }
+
+    fn text(&mut self, fonts: &FontChain, anchor: (f32, f32), angle: f32, color: Color,
+            text: &str) {
+        let (ax, ay) = self.to_pixel(anchor);
+        let angle = angle.to_radians();
+        let (sin_d, cos_d) = (angle.sin(), angle.cos());
+        let color = to_rgba(color).data;
+        let mut pen = 0.;
+        for c in text::visual_order(text).chars() {
+            let (metrics, bitmap) = fonts.font_for(c).rasterize(c, FONT_SIZE * self.scale);
+            for row in 0..metrics.height {
+                for column in 0..metrics.width {
+                    let value = bitmap[row * metrics.width + column];
+                    if value == 0 {
+                        continue;
+                    }
+                    // Glyph coordinates relative to the anchor, y pointing up
+                    let lx = pen + (metrics.xmin + column as i32) as f32;
+                    let ly = (metrics.ymin + (metrics.height - 1 - row) as i32) as f32;
+                    let rx = lx * cos_d - ly * sin_d;
+                    let ry = lx * sin_d + ly * cos_d;
+                    self.blend((ax + rx) as i64, (ay - ry) as i64, color, value as f32 / 255.);
+                }
+            }
+            pen += metrics.advance_width;
+        }
+    }
+
+    /// Fill the convex polygon with the given corners
+    fn polygon(&mut self, corners: &[(f32, f32)], color: Color) {
+        let corners: Vec<(f32, f32)> = corners.iter().map(|&c| self.to_pixel(c)).collect();
+        let color = to_rgba(color).data;
+        let left = corners.iter().map(|c| c.0).fold(::std::f32::INFINITY, f32::min);
+        let right = corners.iter().map(|c| c.0).fold(::std::f32::NEG_INFINITY, f32::max);
+        let top = corners.iter().map(|c| c.1).fold(::std::f32::INFINITY, f32::min);
+        let bottom = corners.iter().map(|c| c.1).fold(::std::f32::NEG_INFINITY, f32::max);
+        for px in left.floor() as i64..right.ceil() as i64 {
+            for py in top.floor() as i64..bottom.ceil() as i64 {
+                // A pixel is inside if its center is on the same side of all
+                // edges
+                let (mx, my) = (px as f32 + 0.5, py as f32 + 0.5);
+                let sides: Vec<f32> = (0..corners.len()).map(|i| {
+                    let (a, b) = (corners[i], corners[(i + 1) % corners.len()]);
+                    (b.0 - a.0) * (my - a.1) - (b.1 - a.1) * (mx - a.0)
+                }).collect();
+                if sides.iter().all(|&s| s >= 0.) || sides.iter().all(|&s| s <= 0.) {
+                    self.blend(px, py, color, 1.);
+                }
+            }
+        }
+    }
+
+    fn fill(&mut self, point: (f32, f32), color: Color) {
+        let (x, y) = self.to_pixel(point);
+        if x < 0. || y < 0. || x >= self.image.width() as f32 || y >= self.image.height() as f32 {
+            return;
+        }
+        let current = DynamicImage::ImageRgba8(self.image.clone());
+        let rgba = to_rgba(color).data;
+        let (px, py, patch) = ff::floodfill(&current, (x as u32, y as u32),
+                                            (rgba[0], rgba[1], rgba[2], rgba[3]));
+        for (x, y, pixel) in patch.pixels() {
+            if pixel.data[3] > 0 {
+                self.blend((px + x) as i64, (py + y) as i64, pixel.data, 1.);
+            }
+        }
+    }
+
+    /// Draw the image centered at `center` with the given size (in turtle
+    /// units), rotated by `angle` degrees counter-clockwise
+    fn image(&mut self, image: &RgbaImage, center: (f32, f32), size: (f32, f32), angle: f32) {
+        let (cx, cy) = self.to_pixel(center);
+        let angle = angle.to_radians();
+        let (sin_d, cos_d) = (angle.sin(), angle.cos());
+        let (width, height) = size;
+        let reach = ((width * width + height * height).sqrt() / 2. * self.scale).ceil() as i64;
+        let (iw, ih) = (image.width() as f32, image.height() as f32);
+        for px in cx as i64 - reach..cx as i64 + reach + 1 {
+            for py in cy as i64 - reach..cy as i64 + reach + 1 {
+                // Rotate the pixel center back into the image's frame
+                let dx = (px as f32 + 0.5 - cx) / self.scale;
+                let dy = (cy - py as f32 - 0.5) / self.scale;
+                let lx = cos_d * dx + sin_d * dy;
+                let ly = -sin_d * dx + cos_d * dy;
+                let u = (lx / width + 0.5) * iw;
+                let v = (0.5 - ly / height) * ih;
+                if u < 0. || v < 0. || u >= iw || v >= ih {
+                    continue;
+                }
+                let pixel = image.get_pixel(u as u32, v as u32).data;
+                self.blend(px, py, pixel, 1.);
+            }
+        }
+    }
+}
+
+impl RenderBackend for SoftwareBackend {
+    fn add_line(&mut self, start: (f32, f32), end: (f32, f32), color: Color) {
+        for (start, end) in self.symmetry.lines(start, end) {
+            self.shapes.push((self.owner, Shape::Line(start, end, color, self.pen_size)));
+        }
+        self.prune_trail();
+    }
+
+    fn plot_points(&mut self, points: &[(f32, f32)], color: Color) {
+        let points = points.iter()
+            .flat_map(|&point| self.symmetry.lines(point, point))
+            .map(|(point, _)| point)
+            .collect();
+        self.shapes.push((self.owner, Shape::Points(points, color, self.pen_size)));
+    }
+
+    fn add_text(&mut self, anchor: (f32, f32), angle: f32, color: Color, text: &str)
+                -> RenderResult<()>
+    {
+        self.shapes.push((self.owner, Shape::Text(anchor, angle, color, text.to_owned())));
+        Ok(())
+    }
+
+    fn add_fallback_font(&mut self, path: &str) -> io::Result<()> {
+        self.fonts.add_fallback(path)
+    }
+
+    fn fill(&mut self, point: (f32, f32), color: Color) -> RenderResult<()> {
+        self.shapes.push((self.owner, Shape::Fill(point, color)));
+        Ok(())
+    }
+
+    fn stamp_image(&mut self, path: &str, position: (f32, f32), rotation: f32, scale: f32)
+                   -> RenderResult<()>
+    {
+        let image = match self.image_cache.get(path) {
+            Some(image) => image.clone(),
+            None => Rc::new(try!(image::open(path).map_err(|e| e.to_string())).to_rgba()),
+        };
+        self.image_cache.insert(path.to_owned(), image.clone());
+        self.shapes.push((self.owner, Shape::Stamp(position, rotation, scale, image)));
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.shapes.clear();
+        self.foreground_start = 0;
+        self.annotations.clear();
+    }
+
+    fn begin_foreground(&mut self) {
+        self.foreground_start = self.shapes.len();
+    }
+
+    fn set_visible_lines(&mut self, count: Option<usize>) {
+        self.visible_lines = count;
+    }
+
+    fn line_count(&self) -> usize {
+        self.shapes.iter()
+            .filter(|&&(_, ref shape)| match *shape { Shape::Line(..) => true, _ => false })
+            .count()
+    }
+
+    fn clear_foreground(&mut self) {
+        self.shapes.truncate(self.foreground_start);
+    }
+
+    fn set_owner(&mut self, owner: usize) {
+        self.owner = owner;
+    }
+
+    fn clear_owner(&mut self, owner: usize) {
+        let removed = backend::remove_shapes(&mut self.shapes, self.foreground_start,
+                                             |&(shape_owner, _)| shape_owner == owner);
+        self.foreground_start -= removed;
+    }
+
+    fn undo_owner(&mut self, owner: usize) -> bool {
+        match self.shapes.iter().rposition(|&(shape_owner, _)| shape_owner == owner) {
+            Some(index) => {
+                self.shapes.remove(index);
+                if index < self.foreground_start {
+                    self.foreground_start -= 1;
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn add_annotation(&mut self, annotation: Annotation) -> RenderResult<()> {
+        self.annotations.push(annotation);
+        Ok(())
+    }
+
+    fn clear_annotations(&mut self) {
+        self.annotations.clear();
+    }
+
+    fn apply_filter(&mut self, filter: Filter) -> RenderResult<()> {
+        let overlay = self.overlay;
+        let annotations = ::std::mem::replace(&mut self.annotations, Vec::new());
+        self.overlay = Overlay::default();
+        let image = filter.apply(&self.render(1., false));
+        self.overlay = overlay;
+        self.annotations = annotations;
+        // The image covers exactly the visible part of the canvas
+        let View { center, zoom } = self.view;
+        self.shapes.clear();
+        self.foreground_start = 0;
+        self.shapes.push((self.owner, Shape::Stamp(center, 0., 1. / zoom, Rc::new(image))));
+        Ok(())
+    }
+
+    fn line_segments(&self) -> Vec<((f32, f32), (f32, f32))> {
+        let mut segments = Vec::new();
+        for &(_, ref shape) in &self.shapes {
+            match *shape {
+                Shape::Line(start, end, _, _) => segments.push((start, end)),
+                Shape::Points(ref points, _, _) => {
+                    segments.extend(points.iter().map(|&point| (point, point)))
+                },
+                _ => (),
+            }
+        }
+        segments
+    }
+
+    fn drawing_bounds(&self) -> Option<Bounds> {
+        let mut bounds = None;
+        for &(_, ref shape) in &self.shapes {
+            match *shape {
+                Shape::Line(start, end, _, width) => {
+                    Bounds::extend(&mut bounds, start, width);
+                    Bounds::extend(&mut bounds, end, width);
+                },
+                Shape::Text(anchor, angle, _, ref text) => {
+                    Bounds::extend_text(&mut bounds, anchor, angle, text)
+                },
+                Shape::Fill(point, _) => Bounds::extend(&mut bounds, point, 0.),
+                Shape::Stamp(center, angle, factor, ref image) => {
+                    let size = (image.width() as f32 * factor, image.height() as f32 * factor);
+                    Bounds::extend_rotated(&mut bounds, center, size, angle)
+                },
+                Shape::Points(ref points, _, size) => {
+                    for &point in points {
+                        Bounds::extend(&mut bounds, point, size);
+                    }
+                },
+            }
+        }
+        bounds
+    }
+
+    fn set_view(&mut self, view: View) {
+        self.view = view;
+    }
+
+    fn view(&self) -> View {
+        self.view
+    }
+
+    fn set_cursor(&mut self, cursor: Cursor) {
+        self.cursor = cursor;
+    }
+
+    fn set_background_color(&mut self, color: Color) {
+        self.background_color = color;
+    }
+
+    fn set_smooth_lines(&mut self, smooth: bool) {
+        self.smooth_lines = smooth;
+    }
+
+    fn smooth_lines(&self) -> bool {
+        self.smooth_lines
+    }
+
+    fn set_pen_size(&mut self, size: f32) {
+        self.pen_size = size;
+    }
+
+    fn draw_and_update(&mut self) {}
+
+    fn pump_events(&mut self) {}
+
+    fn is_closed(&self) -> bool {
+        false
+    }
+
+    fn canvas_size(&self) -> (f32, f32) {
+        (self.size.0 as f32, self.size.1 as f32)
+    }
+
+    fn set_overlay(&mut self, overlay: Overlay) {
+        self.overlay = overlay;
+    }
+
+    fn set_symmetry(&mut self, symmetry: Symmetry) {
+        self.symmetry = symmetry;
+    }
+
+    fn set_trail(&mut self, length: Option<usize>) {
+        self.trail = length;
+        self.prune_trail();
+    }
+
+    fn screenshot(&self) -> RenderResult<DynamicImage> {
+        Ok(DynamicImage::ImageRgba8(self.render(1., true)))
+    }
+
+    fn render_image(&self, scale: f32) -> RenderResult<DynamicImage> {
+        Ok(DynamicImage::ImageRgba8(self.render(scale, false)))
+    }
+}
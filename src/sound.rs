@@ -0,0 +1,58 @@
+//! Simple sound output for Rurtle.
+//!
+//! This module provides a small sine-wave synthesizer that can play single
+//! notes, similar to the `TOOT` command of classic Logo implementations.
+//! Without the `native` feature, there is never an audio device.
+#[cfg(feature = "native")]
+use rodio::{self, Source};
+use std::{thread, time};
+
+/// A `Speaker` plays notes on the default audio device.
+pub struct Speaker {
+    #[cfg(feature = "native")]
+    endpoint: rodio::Endpoint,
+}
+
+impl Speaker {
+    /// Create a new `Speaker` for the default audio device. Returns `None` if
+    /// there is no audio device available.
+    #[cfg(feature = "native")]
+    pub fn new() -> Option<Speaker> {
+        rodio::get_default_endpoint().map(|endpoint| Speaker { endpoint: endpoint })
+    }
+
+    #[cfg(not(feature = "native"))]
+    pub fn new() -> Option<Speaker> {
+        None
+    }
+
+    /// Play a sine wave with the given frequency (in Hz) for the given
+    /// duration (in milliseconds). This function blocks until the note has
+    /// been played. A frequency of 0 plays nothing and just waits.
+    pub fn toot(&self, frequency: u32, duration: u64) {
+        let duration = time::Duration::from_millis(duration);
+        if frequency == 0 {
+            thread::sleep(duration);
+            return;
+        }
+        self.play_sine(frequency, duration);
+    }
+
+    #[cfg(feature = "native")]
+    fn play_sine(&self, frequency: u32, duration: time::Duration) {
+        let sink = rodio::Sink::new(&self.endpoint);
+        sink.append(rodio::source::SineWave::new(frequency).take_duration(duration));
+        sink.sleep_until_end();
+    }
+
+    #[cfg(not(feature = "native"))]
+    fn play_sine(&self, _: u32, _: time::Duration) {}
+
+    /// Play the given notes one after another. Each note is a pair of
+    /// frequency and duration, see `toot`.
+    pub fn play_notes(&self, notes: &[(u32, u64)]) {
+        for &(frequency, duration) in notes {
+            self.toot(frequency, duration);
+        }
+    }
+}
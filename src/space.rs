@@ -0,0 +1,254 @@
+//! Geometry for the turtle's 3D mode.
+//!
+//! In 3D mode the turtle has a position in space and three axes: the heading
+//! (where `forward` goes), the left axis and the up axis. Turning rotates
+//! around the up axis, pitching around the left axis and rolling around the
+//! heading. The drawn segments are kept in 3D and projected onto the screen
+//! by a perspective `Camera` which can be orbited around the origin.
+//!
+//! The x axis points right and the y axis up, just like in 2D mode. The z
+//! axis points towards the viewer, so without orbiting the camera the 3D mode
+//! looks exactly like the 2D mode as long as the turtle doesn't pitch or roll.
+use super::graphic::color::Color;
+use std::io::{self, Write};
+
+/// A point or a direction in space
+pub type Vector = (f32, f32, f32);
+
+fn add(a: Vector, b: Vector) -> Vector {
+    (a.0 + b.0, a.1 + b.1, a.2 + b.2)
+}
+
+fn scale(a: Vector, factor: f32) -> Vector {
+    (a.0 * factor, a.1 * factor, a.2 * factor)
+}
+
+fn dot(a: Vector, b: Vector) -> f32 {
+    a.0 * b.0 + a.1 * b.1 + a.2 * b.2
+}
+
+fn cross(a: Vector, b: Vector) -> Vector {
+    (a.1 * b.2 - a.2 * b.1, a.2 * b.0 - a.0 * b.2, a.0 * b.1 - a.1 * b.0)
+}
+
+/// Rotate `v` around the (unit length) `axis` by `deg` degrees,
+/// counter-clockwise when looking against the axis
+fn rotate(v: Vector, axis: Vector, deg: f32) -> Vector {
+    let (sin, cos) = deg.to_radians().sin_cos();
+    add(add(scale(v, cos), scale(cross(axis, v), sin)),
+        scale(axis, dot(axis, v) * (1. - cos)))
+}
+
+/// The turtle's axes, always unit length and perpendicular to each other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub heading: Vector,
+    pub left: Vector,
+    pub up: Vector,
+}
+
+impl Frame {
+    /// Return the frame of a turtle in the x-y plane with the given
+    /// orientation (as in 2D mode: 0 is north, counting counter-clockwise)
+    pub fn flat(orientation: f32) -> Frame {
+        let up = (0., 0., 1.);
+        Frame {
+            heading: rotate((0., 1., 0.), up, orientation),
+            left: rotate((-1., 0., 0.), up, orientation),
+            up: up,
+        }
+    }
+}
+
+/// A perspective camera that looks at the origin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    /// Rotation around the vertical screen axis in degrees
+    pub yaw: f32,
+    /// Rotation around the horizontal screen axis in degrees
+    pub pitch: f32,
+    /// Distance from the origin
+    pub distance: f32,
+}
+
+impl Camera {
+    /// Return the screen position of the point or `None` if the point is
+    /// behind the camera
+    pub fn project(&self, point: Vector) -> Option<(f32, f32)> {
+        let point = rotate(point, (0., 1., 0.), self.yaw);
+        let (x, y, z) = rotate(point, (1., 0., 0.), self.pitch);
+        let depth = self.distance - z;
+        if depth < 1. {
+            return None;
+        }
+        let factor = self.distance / depth;
+        Some((x * factor, y * factor))
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera {
+            yaw: 0.,
+            pitch: 0.,
+            distance: 1000.,
+        }
+    }
+}
+
+/// A drawn line in space
+pub type Segment = (Vector, Vector, Color);
+
+/// The state of the turtle in 3D mode together with the drawn segments
+#[derive(Debug, Clone)]
+pub struct Space {
+    pub position: Vector,
+    pub frame: Frame,
+    pub camera: Camera,
+    segments: Vec<Segment>,
+}
+
+impl Space {
+    /// Create a new space with the turtle at the given position in the x-y
+    /// plane
+    pub fn new(position: (f32, f32), orientation: f32) -> Space {
+        Space {
+            position: (position.0, position.1, 0.),
+            frame: Frame::flat(orientation),
+            camera: Camera::default(),
+            segments: Vec::new(),
+        }
+    }
+
+    /// Move the turtle to `destination`, adding a segment if `color` is given.
+    /// Returns the segment's projection if it is visible.
+    pub fn goto(&mut self, destination: Vector, color: Option<Color>)
+                -> Option<((f32, f32), (f32, f32))>
+    {
+        let start = self.position;
+        self.position = destination;
+        color.and_then(|color| {
+            self.segments.push((start, destination, color));
+            self.project_segment(start, destination)
+        })
+    }
+
+    /// Return the point `length` units ahead of the turtle
+    pub fn ahead(&self, length: f32) -> Vector {
+        add(self.position, scale(self.frame.heading, length))
+    }
+
+    /// Turn left around the up axis
+    pub fn turn(&mut self, deg: f32) {
+        let up = self.frame.up;
+        self.frame.heading = rotate(self.frame.heading, up, deg);
+        self.frame.left = rotate(self.frame.left, up, deg);
+    }
+
+    /// Turn the nose up around the left axis
+    pub fn pitch(&mut self, deg: f32) {
+        let left = self.frame.left;
+        self.frame.heading = rotate(self.frame.heading, left, -deg);
+        self.frame.up = rotate(self.frame.up, left, -deg);
+    }
+
+    /// Roll to the right around the heading
+    pub fn roll(&mut self, deg: f32) {
+        let heading = self.frame.heading;
+        self.frame.left = rotate(self.frame.left, heading, deg);
+        self.frame.up = rotate(self.frame.up, heading, deg);
+    }
+
+    /// Return the segments drawn so far
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Remove all segments
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    /// Return the projection of the segment or `None` if it is (partly) behind
+    /// the camera
+    pub fn project_segment(&self, start: Vector, end: Vector) -> Option<((f32, f32), (f32, f32))> {
+        match (self.camera.project(start), self.camera.project(end)) {
+            (Some(a), Some(b)) => Some((a, b)),
+            _ => None,
+        }
+    }
+
+    /// Return the turtle's position and orientation on the screen, or `None`
+    /// if the turtle is behind the camera
+    pub fn projected_turtle(&self) -> Option<((f32, f32), f32)> {
+        let position = match self.camera.project(self.position) {
+            Some(p) => p,
+            None => return None,
+        };
+        let ahead = self.camera.project(self.ahead(1.)).unwrap_or(position);
+        let (dx, dy) = (ahead.0 - position.0, ahead.1 - position.1);
+        let orientation = if dx == 0. && dy == 0. { 0. } else { (-dx).atan2(dy).to_degrees() };
+        Some((position, orientation))
+    }
+
+    /// Return the segments joined to polylines: consecutive segments that
+    /// continue where the last one ended (in the same color) belong to the
+    /// same polyline.
+    fn polylines(&self) -> Vec<(Vec<Vector>, Color)> {
+        let mut lines: Vec<(Vec<Vector>, Color)> = Vec::new();
+        for &(start, end, color) in &self.segments {
+            if let Some(&mut (ref mut points, line_color)) = lines.last_mut() {
+                if line_color == color && points.last() == Some(&start) {
+                    points.push(end);
+                    continue;
+                }
+            }
+            lines.push((vec![start, end], color));
+        }
+        lines
+    }
+
+    /// Write the segments as Wavefront OBJ file. Each polyline becomes an `l`
+    /// element, the colors are given as vertex colors after the coordinates.
+    pub fn write_obj<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        try!(writeln!(out, "# Exported from Rurtle"));
+        let polylines = self.polylines();
+        for &(ref points, (r, g, b, _)) in &polylines {
+            for &(x, y, z) in points {
+                try!(writeln!(out, "v {} {} {} {} {} {}", x, y, z, r, g, b));
+            }
+        }
+        // Vertex indices in OBJ files start with 1
+        let mut index = 1;
+        for &(ref points, _) in &polylines {
+            let indices: Vec<String> = (index..index + points.len()).map(|i| i.to_string())
+                .collect();
+            try!(writeln!(out, "l {}", indices.join(" ")));
+            index += points.len();
+        }
+        Ok(())
+    }
+
+    /// Write the segments as ASCII PLY file with colored vertices and one
+    /// edge per segment
+    pub fn write_ply<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let count = self.segments.len();
+        try!(write!(out, "ply\nformat ascii 1.0\ncomment Exported from Rurtle\n\
+                          element vertex {}\n\
+                          property float x\nproperty float y\nproperty float z\n\
+                          property uchar red\nproperty uchar green\nproperty uchar blue\n\
+                          element edge {}\nproperty int vertex1\nproperty int vertex2\n\
+                          end_header\n",
+                    count * 2, count));
+        let byte = |c: f32| (c.max(0.).min(1.) * 255.).round() as u8;
+        for &(start, end, (r, g, b, _)) in &self.segments {
+            for &(x, y, z) in &[start, end] {
+                try!(writeln!(out, "{} {} {} {} {} {}", x, y, z, byte(r), byte(g), byte(b)));
+            }
+        }
+        for i in 0..count {
+            try!(writeln!(out, "{} {}", 2 * i, 2 * i + 1));
+        }
+        Ok(())
+    }
+}
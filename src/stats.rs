@@ -0,0 +1,52 @@
+//! Counters to measure the performance of the interpreter and the renderer.
+//!
+//! The `Environment` counts the executed statements and the time spent
+//! lexing, parsing and evaluating, the `Turtle` counts the drawn segments and
+//! frames. `Environment::stats` combines both. The counters are available to
+//! Rurtle code via the `STATS` builtin.
+use std::time::Duration;
+
+/// `std::time::Instant` panics in the browser, there the `instant` crate asks
+/// JavaScript for the time instead. Use this one to measure time.
+#[cfg(not(feature = "web"))]
+pub use std::time::Instant;
+#[cfg(feature = "web")]
+pub use instant::Instant;
+
+/// Counters collected while running Rurtle code
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    /// Number of executed statements. The statements of a loop or function
+    /// body count each time the body runs, but the expressions inside of a
+    /// statement (e.g. function calls used as arguments) don't count.
+    pub statements: u64,
+    /// Number of line segments drawn by the turtle. Each plotted point counts
+    /// as a segment.
+    pub segments: u64,
+    /// Number of frames rendered by the turtle
+    pub frames: u64,
+    pub lex_time: Duration,
+    pub parse_time: Duration,
+    pub eval_time: Duration,
+    pub render_time: Duration,
+}
+
+impl Stats {
+    /// Return the sum of both counters
+    pub fn merge(&self, other: &Stats) -> Stats {
+        Stats {
+            statements: self.statements + other.statements,
+            segments: self.segments + other.segments,
+            frames: self.frames + other.frames,
+            lex_time: self.lex_time + other.lex_time,
+            parse_time: self.parse_time + other.parse_time,
+            eval_time: self.eval_time + other.eval_time,
+            render_time: self.render_time + other.render_time,
+        }
+    }
+}
+
+/// Return the duration in milliseconds
+pub fn millis(duration: Duration) -> f32 {
+    duration.as_secs() as f32 * 1000. + duration.subsec_nanos() as f32 / 1_000_000.
+}
@@ -0,0 +1,243 @@
+//! A `RenderBackend` that draws into the terminal.
+//!
+//! `TerminalBackend` rasterizes the drawing with a `SoftwareBackend` and prints
+//! it using Unicode braille characters, each character showing 2x4 pixels. This
+//! is only an approximation of the drawing (without colors), but it is enough
+//! for quick experiments over SSH.
+use super::backend::{Annotation, Bounds, Cursor, Overlay, RenderBackend, RenderResult, Symmetry,
+                     View};
+use super::filter::Filter;
+use super::graphic::color::Color;
+use super::software::SoftwareBackend;
+use image::{DynamicImage, GenericImage};
+use std::io::{self, Write};
+
+/// First braille character, the one without any dots
+const BRAILLE_BASE: u32 = 0x2800;
+/// Bit for the dot at position (x, y) of a braille character
+const BRAILLE_DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+/// A backend that prints the drawing to stdout
+pub struct TerminalBackend {
+    inner: SoftwareBackend,
+    size: (u32, u32),
+    columns: u32,
+    rows: u32,
+    background_color: Color,
+    last_frame: String,
+}
+
+impl TerminalBackend {
+    /// Create a new backend whose canvas has the given size (in turtle
+    /// units). The drawing is scaled to fit into `columns` x `rows`
+    /// characters.
+    pub fn new(size: (u32, u32), columns: u32, rows: u32) -> TerminalBackend {
+        TerminalBackend {
+            inner: SoftwareBackend::new(size),
+            size: size,
+            columns: columns,
+            rows: rows,
+            background_color: super::graphic::color::WHITE,
+            last_frame: String::new(),
+        }
+    }
+
+    /// Return the drawing as lines of braille characters
+    fn frame(&self) -> String {
+        let (width, height) = (self.size.0 as f32, self.size.1 as f32);
+        let scale = (self.columns as f32 * 2. / width).min(self.rows as f32 * 4. / height);
+        // The software backend doesn't fail, and the image is small
+        let image = match self.inner.render_image(scale) {
+            Ok(image) => image,
+            Err(_) => return String::new(),
+        };
+        let (r, g, b, _) = self.background_color;
+        let background = [(r * 255.) as u8, (g * 255.) as u8, (b * 255.) as u8];
+        let (image_width, image_height) = image.dimensions();
+        let mut frame = String::new();
+        for row in 0..(image_height + 3) / 4 {
+            for column in 0..(image_width + 1) / 2 {
+                let mut code = BRAILLE_BASE;
+                for dx in 0..2 {
+                    for dy in 0..4 {
+                        let (x, y) = (column * 2 + dx, row * 4 + dy);
+                        if x < image_width && y < image_height &&
+                            is_set(&image, x, y, background)
+                        {
+                            code |= BRAILLE_DOTS[dx as usize][dy as usize];
+                        }
+                    }
+                }
+                frame.push(::std::char::from_u32(code).unwrap());
+            }
+            frame.push('\n');
+        }
+        frame
+    }
+}
+
+/// Returns true if the pixel differs noticeably from the background
+fn is_set(image: &DynamicImage, x: u32, y: u32, background: [u8; 3]) -> bool {
+    let pixel = image.get_pixel(x, y).data;
+    let difference: i32 = (0..3).map(|i| (pixel[i] as i32 - background[i] as i32).abs()).sum();
+    difference > 96
+}
+
+impl RenderBackend for TerminalBackend {
+    fn add_line(&mut self, start: (f32, f32), end: (f32, f32), color: Color) {
+        self.inner.add_line(start, end, color)
+    }
+
+    fn plot_points(&mut self, points: &[(f32, f32)], color: Color) {
+        self.inner.plot_points(points, color)
+    }
+
+    fn add_text(&mut self, anchor: (f32, f32), angle: f32, color: Color, text: &str)
+                -> RenderResult<()>
+    {
+        self.inner.add_text(anchor, angle, color, text)
+    }
+
+    fn add_fallback_font(&mut self, path: &str) -> io::Result<()> {
+        self.inner.add_fallback_font(path)
+    }
+
+    fn fill(&mut self, point: (f32, f32), color: Color) -> RenderResult<()> {
+        self.inner.fill(point, color)
+    }
+
+    fn stamp_image(&mut self, path: &str, position: (f32, f32), rotation: f32, scale: f32)
+                   -> RenderResult<()>
+    {
+        self.inner.stamp_image(path, position, rotation, scale)
+    }
+
+    fn clear(&mut self) {
+        self.inner.clear()
+    }
+
+    fn begin_foreground(&mut self) {
+        self.inner.begin_foreground()
+    }
+
+    fn set_visible_lines(&mut self, count: Option<usize>) {
+        self.inner.set_visible_lines(count)
+    }
+
+    fn line_count(&self) -> usize {
+        self.inner.line_count()
+    }
+
+    fn drawing_bounds(&self) -> Option<Bounds> {
+        self.inner.drawing_bounds()
+    }
+
+    fn line_segments(&self) -> Vec<((f32, f32), (f32, f32))> {
+        self.inner.line_segments()
+    }
+
+    fn clear_foreground(&mut self) {
+        self.inner.clear_foreground()
+    }
+
+    fn set_owner(&mut self, owner: usize) {
+        self.inner.set_owner(owner)
+    }
+
+    fn clear_owner(&mut self, owner: usize) {
+        self.inner.clear_owner(owner)
+    }
+
+    fn undo_owner(&mut self, owner: usize) -> bool {
+        self.inner.undo_owner(owner)
+    }
+
+    fn add_annotation(&mut self, annotation: Annotation) -> RenderResult<()> {
+        self.inner.add_annotation(annotation)
+    }
+
+    fn clear_annotations(&mut self) {
+        self.inner.clear_annotations()
+    }
+
+    fn apply_filter(&mut self, filter: Filter) -> RenderResult<()> {
+        self.inner.apply_filter(filter)
+    }
+
+    fn set_cursor(&mut self, cursor: Cursor) {
+        self.inner.set_cursor(cursor)
+    }
+
+    fn set_background_color(&mut self, color: Color) {
+        self.background_color = color;
+        self.inner.set_background_color(color)
+    }
+
+    fn set_smooth_lines(&mut self, smooth: bool) {
+        self.inner.set_smooth_lines(smooth)
+    }
+
+    fn smooth_lines(&self) -> bool {
+        self.inner.smooth_lines()
+    }
+
+    fn set_pen_size(&mut self, size: f32) {
+        self.inner.set_pen_size(size)
+    }
+
+    /// Print the drawing if it changed since the last time. The cursor is
+    /// moved to the upper left corner first, so the drawing stays in place.
+    fn draw_and_update(&mut self) {
+        let frame = self.frame();
+        if frame == self.last_frame {
+            return;
+        }
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        // Saving and restoring the cursor position keeps the prompt usable
+        write!(out, "\x1b7\x1b[H{}\x1b8", frame).and_then(|_| out.flush()).unwrap_or(());
+        self.last_frame = frame;
+    }
+
+    fn pump_events(&mut self) {}
+
+    fn is_closed(&self) -> bool {
+        false
+    }
+
+    fn canvas_size(&self) -> (f32, f32) {
+        self.inner.canvas_size()
+    }
+
+    fn visible_size(&self) -> (f32, f32) {
+        self.inner.visible_size()
+    }
+
+    fn set_overlay(&mut self, overlay: Overlay) {
+        self.inner.set_overlay(overlay)
+    }
+
+    fn set_view(&mut self, view: View) {
+        self.inner.set_view(view)
+    }
+
+    fn view(&self) -> View {
+        self.inner.view()
+    }
+
+    fn set_symmetry(&mut self, symmetry: Symmetry) {
+        self.inner.set_symmetry(symmetry)
+    }
+
+    fn set_trail(&mut self, length: Option<usize>) {
+        self.inner.set_trail(length)
+    }
+
+    fn screenshot(&self) -> RenderResult<DynamicImage> {
+        self.inner.screenshot()
+    }
+
+    fn render_image(&self, scale: f32) -> RenderResult<DynamicImage> {
+        self.inner.render_image(scale)
+    }
+}
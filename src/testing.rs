@@ -0,0 +1,93 @@
+//! Helpers for regression tests of the visual output.
+//!
+//! `assert_drawing_matches` runs a script with the `SoftwareBackend` and
+//! compares the resulting drawing with a stored "golden" image:
+//!
+//! ```no_run
+//! rurtle::testing::assert_drawing_matches("repeat 4 do forward 100 right 90 end",
+//!                                         "tests/golden/square.png", 0.001);
+//! ```
+//!
+//! If the environment variable `RURTLE_REGENERATE_GOLDENS` is set, the golden
+//! images are (re)written instead of compared. Run the tests once in that mode
+//! after an intended change of the output and check the new images before
+//! committing them.
+use super::diff;
+use super::embed::{self, Rurtle};
+use super::software::SoftwareBackend;
+use image::{self, DynamicImage, GenericImage};
+use std::fs;
+use std::env;
+
+/// Size of the canvas the scripts are drawn on
+pub const CANVAS_SIZE: (u32, u32) = (640, 640);
+
+/// Name of the environment variable that switches to regenerating goldens
+pub const REGENERATE_VARIABLE: &'static str = "RURTLE_REGENERATE_GOLDENS";
+
+/// Run the script on a fresh `SoftwareBackend` and return the drawing,
+/// without the turtle
+pub fn render_script(script: &str) -> Result<DynamicImage, embed::Error> {
+    let mut rurtle = Rurtle::with_screen(SoftwareBackend::new(CANVAS_SIZE));
+    try!(rurtle.eval(script));
+    Ok(try!(rurtle.turtle().render_image(1.0)))
+}
+
+/// Return the fraction of pixels that differ between the two images, a number
+/// between 0 (identical) and 1. Images of different size differ completely.
+/// Pixels are compared like in `diff::compare`.
+pub fn difference(a: &DynamicImage, b: &DynamicImage) -> f32 {
+    if a.dimensions() != b.dimensions() {
+        return 1.;
+    }
+    let (width, height) = a.dimensions();
+    let mut differing = 0;
+    for y in 0..height {
+        for x in 0..width {
+            if diff::differ(&a.get_pixel(x, y), &b.get_pixel(x, y)) {
+                differing += 1;
+            }
+        }
+    }
+    differing as f32 / (width * height) as f32
+}
+
+/// Returns true if golden images should be written instead of compared
+pub fn regenerate_mode() -> bool {
+    env::var_os(REGENERATE_VARIABLE).is_some()
+}
+
+fn save(image: &DynamicImage, path: &str) {
+    let mut file = fs::File::create(path)
+        .unwrap_or_else(|e| panic!("can't create {}: {}", path, e));
+    image.save(&mut file, image::ImageFormat::PNG)
+        .unwrap_or_else(|e| panic!("can't save {}: {}", path, e));
+}
+
+/// Run the script and compare the drawing with the image at `golden`. At most
+/// `tolerance` (between 0 and 1) of the pixels may differ.
+///
+/// # Panics
+///
+/// Panics if the script fails, if the golden image can't be read or if the
+/// images differ too much. In the latter case the actual drawing is saved next
+/// to the golden image with the suffix `.actual.png` for inspection.
+pub fn assert_drawing_matches(script: &str, golden: &str, tolerance: f32) {
+    let actual = render_script(script).unwrap_or_else(|e| panic!("script failed: {}", e));
+    if regenerate_mode() {
+        save(&actual, golden);
+        return;
+    }
+    let expected = image::open(golden).unwrap_or_else(|e| {
+        panic!("can't read golden image {}: {} (set {} to create it)",
+               golden, e, REGENERATE_VARIABLE)
+    });
+    let difference = difference(&actual, &expected);
+    if difference > tolerance {
+        let actual_path = format!("{}.actual.png", golden);
+        save(&actual, &actual_path);
+        panic!("drawing differs from {} in {:.2}% of the pixels (tolerance {:.2}%), \
+                actual drawing saved as {}",
+               golden, difference * 100., tolerance * 100., actual_path);
+    }
+}
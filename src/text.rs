@@ -0,0 +1,171 @@
+//! Fonts and the order of characters for texts in any script.
+//!
+//! The bundled DejaVu Sans Mono covers Latin, Greek, Cyrillic, Hebrew and
+//! basic Arabic, but no CJK characters. A `FontChain` takes every character
+//! from the first font that has it, so fallback fonts fill the gaps.
+//!
+//! Texts in right-to-left scripts like Hebrew and Arabic are stored in
+//! logical order, the order in which they are typed. `visual_order` turns
+//! them into the order in which the characters are drawn from left to right.
+//! This is a simplified version of the Unicode bidirectional algorithm that
+//! handles a single paragraph with embedded numbers and words of the other
+//! direction. Arabic letters are not joined, each one keeps its isolated
+//! form.
+use super::graphic::FONT_DATA;
+use fontdue;
+use image::{self, RgbaImage};
+use std::fs;
+use std::io::{self, Read};
+
+/// Fonts that are tried one after another for each character
+pub struct FontChain {
+    fonts: Vec<fontdue::Font>,
+}
+
+impl FontChain {
+    /// Create a chain that only contains the bundled font
+    pub fn new() -> FontChain {
+        let font = fontdue::Font::from_bytes(FONT_DATA, fontdue::FontSettings::default())
+            .expect("Font loading failed");
+        FontChain { fonts: vec![font] }
+    }
+
+    /// Add the TrueType or OpenType font in the file at `path` to the end of
+    /// the chain
+    pub fn add_fallback(&mut self, path: &str) -> io::Result<()> {
+        let mut data = Vec::new();
+        try!(try!(fs::File::open(path)).read_to_end(&mut data));
+        match fontdue::Font::from_bytes(data, fontdue::FontSettings::default()) {
+            Ok(font) => {
+                self.fonts.push(font);
+                Ok(())
+            },
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                         format!("{}: {}", path, e))),
+        }
+    }
+
+    /// Return the first font that has a glyph for the character. If none of
+    /// them has one, the bundled font draws its replacement box.
+    pub fn font_for(&self, c: char) -> &fontdue::Font {
+        self.fonts.iter().find(|font| font.lookup_glyph_index(c) != 0).unwrap_or(&self.fonts[0])
+    }
+
+    /// Rasterize the text with `size` pixels per em into an image of the
+    /// given color with a transparent background. Returns the image and the
+    /// distance between its bottom edge and the baseline. The text has to be
+    /// in visual order.
+    pub fn rasterize(&self, text: &str, size: f32, color: [u8; 4]) -> (RgbaImage, f32) {
+        let glyphs: Vec<_> = text.chars().map(|c| self.font_for(c).rasterize(c, size)).collect();
+        let width = glyphs.iter().map(|&(ref m, _)| m.advance_width).sum::<f32>().ceil();
+        let top = glyphs.iter().map(|&(ref m, _)| m.ymin + m.height as i32)
+            .max().unwrap_or(0).max(size.ceil() as i32);
+        let bottom = glyphs.iter().map(|&(ref m, _)| m.ymin).min().unwrap_or(0).min(0);
+        let (width, height) = ((width as u32).max(1), ((top - bottom) as u32).max(1));
+        let mut image = RgbaImage::new(width, height);
+        let mut pen = 0.;
+        for (metrics, bitmap) in glyphs {
+            for row in 0..metrics.height {
+                for column in 0..metrics.width {
+                    let value = bitmap[row * metrics.width + column];
+                    let x = pen as i32 + metrics.xmin + column as i32;
+                    // The glyph's rows go down, the metrics' y-axis goes up
+                    let y = top - 1 - (metrics.ymin + (metrics.height - 1 - row) as i32);
+                    if value == 0 || x < 0 || y < 0 || x as u32 >= width || y as u32 >= height {
+                        continue;
+                    }
+                    let alpha = (color[3] as u32 * value as u32 / 255) as u8;
+                    let pixel = image::Rgba { data: [color[0], color[1], color[2], alpha] };
+                    image.put_pixel(x as u32, y as u32, pixel);
+                }
+            }
+            pen += metrics.advance_width;
+        }
+        (image, -bottom as f32)
+    }
+}
+
+/// Returns true if the character belongs to a right-to-left script
+fn is_rtl(c: char) -> bool {
+    let c = c as u32;
+    // Hebrew, Arabic, Syriac, Thaana, NKo and their supplements
+    (c >= 0x0590 && c <= 0x08FF) ||
+        // Hebrew and Arabic presentation forms
+        (c >= 0xFB1D && c <= 0xFDFF) || (c >= 0xFE70 && c <= 0xFEFF)
+}
+
+/// Direction of a character: `Some(true)` for right-to-left, `Some(false)`
+/// for left-to-right and `None` for neutral characters like spaces and
+/// punctuation, which take the direction of their surroundings
+fn direction(c: char) -> Option<bool> {
+    if is_rtl(c) {
+        Some(true)
+    } else if c.is_alphanumeric() {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Return the mirror image of brackets, which point the other way in
+/// right-to-left text
+fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        _ => c,
+    }
+}
+
+/// Return the characters of the text in the order in which they are drawn
+/// from left to right. The direction of the whole text is that of its first
+/// letter.
+pub fn visual_order(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if !chars.iter().any(|&c| is_rtl(c)) {
+        return text.to_owned();
+    }
+    let base = chars.iter().filter_map(|&c| direction(c)).next().unwrap_or(false);
+    // Neutral characters between two characters of the same direction get
+    // that direction, all others get the base direction
+    let directions: Vec<bool> = (0..chars.len()).map(|i| {
+        direction(chars[i]).unwrap_or_else(|| {
+            let before = chars[..i].iter().rev().filter_map(|&c| direction(c)).next();
+            let after = chars[i + 1..].iter().filter_map(|&c| direction(c)).next();
+            if before.is_some() && before == after { before.unwrap() } else { base }
+        })
+    }).collect();
+    let mut result: Vec<char> = chars.iter().zip(&directions)
+        .map(|(&c, &rtl)| if rtl { mirror(c) } else { c })
+        .collect();
+    if base {
+        // Reverse everything, then turn the left-to-right runs back around
+        result.reverse();
+        let mut directions = directions;
+        directions.reverse();
+        reverse_runs(&mut result, &directions, false);
+    } else {
+        reverse_runs(&mut result, &directions, true);
+    }
+    result.into_iter().collect()
+}
+
+/// Reverse every run of characters whose direction is `rtl`
+fn reverse_runs(chars: &mut [char], directions: &[bool], rtl: bool) {
+    let mut start = 0;
+    while start < chars.len() {
+        if directions[start] != rtl {
+            start += 1;
+            continue;
+        }
+        let end = (start..chars.len()).find(|&i| directions[i] != rtl).unwrap_or(chars.len());
+        chars[start..end].reverse();
+        start = end;
+    }
+}
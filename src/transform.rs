@@ -0,0 +1,81 @@
+//! Affine transformations of the drawing.
+//!
+//! A `Transform` maps the turtle's coordinates to the coordinates on the
+//! canvas. The turtle keeps a stack of them (see `Turtle::push_transform`), so
+//! a procedure can be drawn scaled, rotated or moved without changing a single
+//! length in it. The turtle itself still moves in its own coordinates, only
+//! the drawn shapes and the cursor are transformed.
+
+/// An affine transformation `(x, y) -> (a*x + c*y + e, b*x + d*y + f)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+    f: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform { a: 1., b: 0., c: 0., d: 1., e: 0., f: 0. }
+    }
+}
+
+impl Transform {
+    /// Return the transformation that first applies `inner` and then `self`
+    fn then(&self, inner: &Transform) -> Transform {
+        Transform {
+            a: self.a * inner.a + self.c * inner.b,
+            b: self.b * inner.a + self.d * inner.b,
+            c: self.a * inner.c + self.c * inner.d,
+            d: self.b * inner.c + self.d * inner.d,
+            e: self.a * inner.e + self.c * inner.f + self.e,
+            f: self.b * inner.e + self.d * inner.f + self.f,
+        }
+    }
+
+    /// Scale everything drawn afterwards by `sx` horizontally and `sy`
+    /// vertically
+    pub fn scaled(&self, sx: f32, sy: f32) -> Transform {
+        self.then(&Transform { a: sx, d: sy, .. Transform::default() })
+    }
+
+    /// Rotate everything drawn afterwards by `deg` degrees counter-clockwise
+    /// around the origin
+    pub fn rotated(&self, deg: f32) -> Transform {
+        let (sin, cos) = deg.to_radians().sin_cos();
+        self.then(&Transform { a: cos, b: sin, c: -sin, d: cos, .. Transform::default() })
+    }
+
+    /// Move everything drawn afterwards by `(dx, dy)`
+    pub fn translated(&self, dx: f32, dy: f32) -> Transform {
+        self.then(&Transform { e: dx, f: dy, .. Transform::default() })
+    }
+
+    /// Transform the point
+    pub fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (self.a * x + self.c * y + self.e, self.b * x + self.d * y + self.f)
+    }
+
+    /// Transform a heading in degrees (0 is north, counter-clockwise)
+    pub fn apply_angle(&self, deg: f32) -> f32 {
+        let (sin, cos) = deg.to_radians().sin_cos();
+        // The heading as vector, see Turtle::length_to_vector
+        let (x, y) = (-sin, cos);
+        let (tx, ty) = (self.a * x + self.c * y, self.b * x + self.d * y);
+        (-tx).atan2(ty).to_degrees()
+    }
+
+    /// Returns true if no coefficient is NaN or infinite
+    pub fn is_finite(&self) -> bool {
+        [self.a, self.b, self.c, self.d, self.e, self.f].iter().all(|x| x.is_finite())
+    }
+
+    /// Return the factor by which areas are scaled, as length. Used to scale
+    /// stamped images.
+    pub fn scale_factor(&self) -> f32 {
+        (self.a * self.d - self.b * self.c).abs().sqrt()
+    }
+}
@@ -5,8 +5,8 @@
 //! turtle draws its path on the canvas. Based on this primitive movements, you
 //! can build more complex commands and draw nice patterns.
 //!
-//! A `Turtle` always has a `TurtleScreen` owned. This `TurtleScreen` must be
-//! given to `Turtle::new()`
+//! A `Turtle` always owns the `RenderBackend` it draws on, usually a
+//! `TurtleScreen`. The backend must be given to `Turtle::new()`
 //!
 //! # Example
 //!
@@ -16,12 +16,174 @@
 //! let screen = TurtleScreen::new((640, 480), "Turtle Demo");
 //! let mut turtle = Turtle::new(screen);
 //! for _ in (0..4) {
-//!     turtle.forward(100.0);
-//!     turtle.right(90.0);
+//!     turtle.forward(100.0).unwrap();
+//!     turtle.right(90.0).unwrap();
 //! }
 //! ```
-use super::graphic::TurtleScreen;
+//!
+//! # Errors
+//!
+//! Methods that take numbers check them before anything is drawn or recorded
+//! and return a `TurtleError` for NaN or infinite lengths and coordinates,
+//! color components outside of [0; 1] or screenshots without any pixels.
+//! Invalid input therefore never reaches the graphics backend.
+//!
+//! # 3D mode
+//!
+//! With `set_3d(true)` the turtle moves in space, see the `space` module.
+//! `pitch_up`, `roll_right` etc. then rotate the turtle around its other axes
+//! and the drawing is shown through a perspective camera that can be orbited
+//! with `orbit` or by dragging with the mouse.
+//!
+//! # Transforms
+//!
+//! `scale`, `rotate` and `translate` change how the turtle's coordinates are
+//! mapped to the canvas, see the `transform` module. `push_transform` and
+//! `pop_transform` save and restore the current transform, so a procedure can
+//! be drawn at different sizes and orientations:
+//!
+//! ```
+//! # use rurtle::backend::NullBackend;
+//! # use rurtle::turtle::Turtle;
+//! # let mut turtle = Turtle::new(NullBackend::new((640, 480)));
+//! turtle.push_transform();
+//! turtle.scale(2.0, 2.0).unwrap();
+//! turtle.forward(50.0).unwrap(); // draws a line of length 100
+//! turtle.pop_transform();
+//! ```
+//!
+//! # Command log
+//!
+//! Every drawing command is recorded in the turtle's command log together with
+//! the owner that gave it (see `set_owner`), see `get_log()` and the `replay`
+//! module.
+use super::backend::{Annotation, Bounds, Cursor, Overlay, RenderBackend, Symmetry, View,
+                     GLYPH_WIDTH};
+use super::filter::Filter;
 use super::graphic::color;
+use super::physics::Physics;
+use super::plotter::{self, PlotterOptions};
+use super::replay::Command;
+use super::robot::Robot;
+use super::space::{Frame, Space, Vector};
+use super::stats::{millis, Instant, Stats};
+use super::transform::Transform;
+use image::DynamicImage;
+use std::collections::HashMap;
+use std::{error, fmt, thread, time};
+
+/// How far the turtle moves right and up per step along the ground axis
+/// it faces at heading 0 in isometric mode. Rising one unit every two units
+/// gives the common 2:1 pixel grid, a step keeps its length on the screen.
+const ISO_X: f32 = 0.894_427_2;
+const ISO_Y: f32 = 0.447_213_6;
+
+/// Invalid input given to a `Turtle`
+#[derive(Debug, Clone, PartialEq)]
+pub enum TurtleError {
+    /// A length, angle or coordinate is NaN or infinite. The first field
+    /// names the parameter.
+    InvalidNumber(&'static str, f32),
+    /// A color component is not in the range [0; 1]
+    InvalidColor(f32, f32, f32),
+    /// The pen size is not a positive number
+    InvalidPenSize(f32),
+    /// A screenshot would have the given size without any pixels
+    EmptyImage(u32, u32),
+    /// A screenshot would have the given size, more than the backend
+    /// supports in width or height (the third field)
+    ImageTooLarge(u32, u32, u32),
+    /// The backend couldn't draw, e.g. because an image is too large for the
+    /// graphics card
+    Render(String),
+    /// The turtle would move to a position that is NaN or infinite, e.g.
+    /// because a huge length overflowed
+    NonFiniteCoordinate,
+    /// No checkpoint with the given name has been saved
+    UnknownCheckpoint(String),
+    /// Sending a move to the robot failed, see `Turtle::set_robot`. The
+    /// turtle still moved.
+    Robot(String),
+}
+
+impl fmt::Display for TurtleError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TurtleError::InvalidNumber(name, value) => write!(fmt, "invalid {}: {}", name, value),
+            TurtleError::InvalidColor(r, g, b) => {
+                write!(fmt, "invalid color: [{} {} {}], the components must be between 0 and 1",
+                       r, g, b)
+            },
+            TurtleError::InvalidPenSize(size) => write!(fmt, "invalid pen size: {}", size),
+            TurtleError::EmptyImage(width, height) => {
+                write!(fmt, "can't take a screenshot of size {}x{}", width, height)
+            },
+            TurtleError::ImageTooLarge(width, height, max) => {
+                write!(fmt, "can't take a screenshot of size {}x{}, the maximum is {}x{}",
+                       width, height, max, max)
+            },
+            TurtleError::Render(ref error) => fmt.pad(error),
+            TurtleError::NonFiniteCoordinate => fmt.pad("non-finite coordinate"),
+            TurtleError::UnknownCheckpoint(ref name) => {
+                write!(fmt, "no checkpoint named {}", name)
+            },
+            TurtleError::Robot(ref error) => {
+                write!(fmt, "the robot can't be controlled anymore: {}", error)
+            },
+        }
+    }
+}
+
+impl error::Error for TurtleError {
+    fn description(&self) -> &str {
+        "invalid turtle input"
+    }
+}
+
+/// The type returned by the turtle's drawing methods
+pub type TurtleResult = Result<(), TurtleError>;
+
+/// Return an error if the number is NaN or infinite
+fn check_number(name: &'static str, value: f32) -> TurtleResult {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(TurtleError::InvalidNumber(name, value))
+    }
+}
+
+/// Return the angle in degrees wrapped into [0; 360)
+fn normalize_angle(deg: f32) -> f32 {
+    let deg = deg % 360.0;
+    let deg = if deg < 0.0 { deg + 360.0 } else { deg };
+    // Tiny negative angles round up to 360 when adding
+    if deg >= 360.0 { 0.0 } else { deg }
+}
+
+/// Return `value` rounded to the nearest multiple of `step`
+fn snap(value: f32, step: f32) -> f32 {
+    (value / step).round() * step
+}
+
+/// Return an error if a component is outside of [0; 1]
+fn check_color(red: f32, green: f32, blue: f32) -> TurtleResult {
+    // NaN fails both comparisons
+    let valid = |c: f32| c >= 0. && c <= 1.;
+    if valid(red) && valid(green) && valid(blue) {
+        Ok(())
+    } else {
+        Err(TurtleError::InvalidColor(red, green, blue))
+    }
+}
+
+/// Return an error unless the pen size is positive and finite
+fn check_pen_size(size: f32) -> TurtleResult {
+    if size > 0. && size.is_finite() {
+        Ok(())
+    } else {
+        Err(TurtleError::InvalidPenSize(size))
+    }
+}
 
 #[derive(Debug)]
 enum PenState {
@@ -29,52 +191,488 @@ enum PenState {
     PenDown,
 }
 
+/// Everything that describes the turtle itself (but not the drawing), see
+/// `Turtle::state` and `Turtle::set_state`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TurtleState {
+    pub position: (f32, f32),
+    pub orientation: f32,
+    pub color: color::Color,
+    pub pen_down: bool,
+    pub pen_size: f32,
+    pub hidden: bool,
+}
+
+/// Reveals the drawing line by line at a fixed rate, see
+/// `Turtle::set_playback`
+#[derive(Debug)]
+struct Playback {
+    /// Lines per second
+    rate: f32,
+    /// Number of lines shown so far
+    shown: f32,
+    last_update: Instant,
+}
+
+/// Text that is written along the turtle's way, see `Turtle::write_along`
+#[derive(Debug)]
+struct PathText {
+    /// The glyphs that are still to be written, in reverse order
+    glyphs: Vec<char>,
+    /// Distance to walk until the next glyph
+    gap: f32,
+}
+
+/// A saved point of the drawing that can be restored, see
+/// `Turtle::checkpoint`
+#[derive(Debug, Clone, Copy)]
+struct Checkpoint {
+    /// Number of commands of the checkpoint's owner in the log at the
+    /// checkpoint
+    commands: usize,
+    state: TurtleState,
+}
+
+/// A function that is called when the turtle is clicked
+pub type ClickHandler = Box<FnMut(&mut Turtle)>;
+
 /// The `Turtle` struct is the thing that actually provides the methods to walk
 /// on the screen
 pub struct Turtle {
-    screen: TurtleScreen,
+    screen: Box<RenderBackend>,
     orientation: f32,
     position: (f32, f32),
     color: color::Color,
     pen: PenState,
+    pen_size: f32,
+    background_color: color::Color,
+    /// Whether the screen anti-aliased lines when it was given to the turtle,
+    /// `reset` returns to it
+    initial_smooth_lines: bool,
+    hidden: bool,
+    auto_update: bool,
+    click_handler: Option<ClickHandler>,
+    /// The commands and the owners that gave them
+    log: Vec<(usize, Command)>,
+    stats: Stats,
+    space: Option<Space>,
+    physics: Physics,
+    overlay: Overlay,
+    transform: Transform,
+    /// Transforms saved by `push_transform`
+    transforms: Vec<Transform>,
+    playback: Option<Playback>,
+    /// Last mouse position while orbiting the camera by dragging
+    drag: Option<(f32, f32)>,
+    path_text: Option<PathText>,
+    /// Write texts horizontally instead of along the heading
+    horizontal_text: bool,
+    /// The position and orientation that `home` returns to
+    home: ((f32, f32), f32),
+    /// The number of the turtle in the display list, see `set_owner`
+    owner: usize,
+    /// The checkpoints by owner and name
+    checkpoints: HashMap<(usize, String), Checkpoint>,
+    /// The positions visited since `begin_path`
+    path: Option<Vec<(f32, f32)>>,
+    /// Follows every move, see `set_robot`
+    robot: Option<Robot>,
+    /// Turns end on multiples of this angle, see `set_snap`
+    snap_angle: Option<f32>,
+    /// Moves end on the points of a grid of this size, see `set_snap_grid`
+    snap_grid: Option<f32>,
+    /// Whether forward and backward move on the isometric grid, see
+    /// `set_isometric`
+    isometric: bool,
 }
 
 impl Turtle {
-    /// Construct a new Turtle. Moves the TurtleScreen.
-    pub fn new(screen: TurtleScreen) -> Turtle {
+    /// Construct a new Turtle. Moves the backend, usually a `TurtleScreen`.
+    pub fn new<B: RenderBackend + 'static>(screen: B) -> Turtle {
+        let initial_smooth_lines = screen.smooth_lines();
         Turtle {
-            screen: screen,
+            screen: Box::new(screen),
             orientation: 0.0,
             position: (0.0, 0.0),
             color: color::BLACK,
             pen: PenState::PenDown,
+            pen_size: 1.0,
+            background_color: color::WHITE,
+            initial_smooth_lines: initial_smooth_lines,
+            hidden: false,
+            auto_update: true,
+            click_handler: None,
+            log: Vec::new(),
+            stats: Stats::default(),
+            space: None,
+            physics: Physics::default(),
+            overlay: Overlay::default(),
+            transform: Transform::default(),
+            transforms: Vec::new(),
+            playback: None,
+            drag: None,
+            path_text: None,
+            horizontal_text: false,
+            home: ((0.0, 0.0), 0.0),
+            owner: 0,
+            checkpoints: HashMap::new(),
+            path: None,
+            robot: None,
+            snap_angle: None,
+            snap_grid: None,
+            isometric: false,
         }
     }
 
     /// Move the turtle to the given position. Depending on whether the pen is
     /// up or down, also draw the line. This function is used internally to
-    /// implement everything else
-    fn goto(&mut self, x: f32, y: f32) {
-        let start_position = self.position;
+    /// implement everything else. Returns an error without moving if the
+    /// position isn't finite, in turtle or in canvas coordinates, and an
+    /// error after moving if the robot couldn't follow.
+    fn goto(&mut self, x: f32, y: f32) -> TurtleResult {
+        let (start, end) = (self.to_canvas(self.position), self.to_canvas((x, y)));
+        if ![x, y, end.0, end.1].iter().all(|c| c.is_finite()) {
+            return Err(TurtleError::NonFiniteCoordinate);
+        }
         if let PenState::PenDown = self.pen {
-            self.screen.add_line(start_position, (x, y), self.color);
+            self.screen.add_line(start, end, self.color);
+            self.stats.segments += 1;
         }
         self.position = (x, y);
-        self.screen.turtle_position = self.position;
+        if let Some(ref mut path) = self.path {
+            if path.last() != Some(&(x, y)) {
+                path.push((x, y));
+            }
+        }
+        self.update_cursor();
+        self.move_robot(start, end)
+    }
+
+    /// Let the robot follow a move from `start` to `end` on the canvas
+    fn move_robot(&mut self, start: (f32, f32), end: (f32, f32)) -> TurtleResult {
+        let pen_down = self.is_pen_down();
+        if let Some(ref mut robot) = self.robot {
+            let result = match robot.move_to(start, false) {
+                Ok(()) => robot.move_to(end, pen_down),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                return Err(TurtleError::Robot(e.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the turtle to the given point in 3D mode, drawing the line if the
+    /// pen is down
+    fn goto_3d(&mut self, destination: Vector) -> TurtleResult {
+        let (x, y, z) = destination;
+        if ![x, y, z].iter().all(|c| c.is_finite()) {
+            return Err(TurtleError::NonFiniteCoordinate);
+        }
+        let color = match self.pen {
+            PenState::PenDown => Some(self.color),
+            PenState::PenUp => None,
+        };
+        if let Some(ref mut space) = self.space {
+            if let Some((start, end)) = space.goto(destination, color) {
+                self.screen.add_line(start, end, self.color);
+            }
+        }
+        if color.is_some() {
+            self.stats.segments += 1;
+        }
+        self.sync_with_space();
+        Ok(())
+    }
+
+    /// In 3D mode, set the 2D position and orientation to the turtle's
+    /// projection and update the cursor. Texts, images and fills use the
+    /// projected position.
+    fn sync_with_space(&mut self) {
+        if let Some(((x, y), orientation)) = self.space.as_ref().and_then(|s| s.projected_turtle()) {
+            self.position = (x, y);
+            self.orientation = normalize_angle(orientation);
+        }
+        self.update_cursor();
+    }
+
+    /// Project all segments again, e.g. after the camera moved. Only lines
+    /// are kept, texts, fills and images drawn in 3D mode are lost.
+    fn redraw_space(&mut self) {
+        self.screen.clear();
+        if let Some(ref space) = self.space {
+            for &(start, end, color) in space.segments() {
+                if let Some((start, end)) = space.project_segment(start, end) {
+                    self.screen.add_line(start, end, color);
+                }
+            }
+        }
+        self.sync_with_space();
+    }
+
+    /// Map a point from the turtle's coordinates to the canvas by applying
+    /// the current transform. Transforms are ignored in 3D mode.
+    fn to_canvas(&self, point: (f32, f32)) -> (f32, f32) {
+        if self.space.is_some() { point } else { self.transform.apply(point) }
+    }
+
+    /// Map a heading to the canvas, see `to_canvas`
+    fn angle_to_canvas(&self, deg: f32) -> f32 {
+        if self.space.is_some() { deg } else { self.transform.apply_angle(deg) }
+    }
+
+    /// Tell the backend about the turtle's current state and redraw
+    fn update_cursor(&mut self) {
+        let cursor = Cursor {
+            position: self.to_canvas(self.position),
+            orientation: self.angle_to_canvas(self.screen_orientation()),
+            color: self.color,
+            hidden: self.hidden,
+        };
+        self.screen.set_cursor(cursor);
+        self.redraw();
+    }
+
+    /// Redraw the screen, unless automatic updates are disabled. In playback
+    /// mode, the screen is only redrawn by `advance_playback`.
+    fn redraw(&mut self) {
+        if self.auto_update && self.playback.is_none() {
+            self.render();
+        }
+    }
+
+    /// Redraw the screen now, even if automatic updates are disabled
+    pub fn render(&mut self) {
+        let start = Instant::now();
         self.screen.draw_and_update();
+        self.stats.render_time += start.elapsed();
+        self.stats.frames += 1;
     }
 
-    /// Return a reference to the underlaying `TurtleScreen` object
-    pub fn get_screen(&mut self) -> &mut TurtleScreen {
-        &mut self.screen
+    /// Return the drawing counters, see the `stats` module
+    pub fn stats(&mut self) -> &mut Stats {
+        &mut self.stats
+    }
+
+    /// Enable or disable redrawing the screen after each command. If automatic
+    /// updates are disabled, the owner of the turtle has to call
+    /// `draw_and_update` on the screen itself, e.g. once per frame. This is
+    /// much faster when many commands are given at once and necessary in
+    /// environments where drawing must not block, like the browser.
+    pub fn set_auto_update(&mut self, auto_update: bool) {
+        self.auto_update = auto_update;
+    }
+
+    /// Returns true if the screen is redrawn after each command
+    pub fn get_auto_update(&self) -> bool {
+        self.auto_update
+    }
+
+    /// Enable playback mode: the drawing commands are executed as fast as
+    /// possible, but the lines are revealed at `rate` lines per second by
+    /// `advance_playback`. A rate of 0 shows everything again.
+    pub fn set_playback(&mut self, rate: f32) {
+        if rate > 0. {
+            let shown = self.screen.line_count();
+            self.playback = Some(Playback {
+                rate: rate,
+                shown: shown as f32,
+                last_update: Instant::now(),
+            });
+            self.screen.set_visible_lines(Some(shown));
+        } else {
+            self.playback = None;
+            self.screen.set_visible_lines(None);
+        }
+        self.redraw();
+    }
+
+    /// In playback mode, reveal the lines that are due since the last call
+    /// and redraw the screen. This should be called regularly, e.g. once per
+    /// frame.
+    pub fn advance_playback(&mut self) {
+        let total = self.screen.line_count() as f32;
+        let shown = match self.playback {
+            Some(ref mut playback) => {
+                let now = Instant::now();
+                let seconds = millis(now - playback.last_update) / 1000.;
+                playback.last_update = now;
+                playback.shown = (playback.shown + seconds * playback.rate).min(total);
+                playback.shown as usize
+            },
+            None => return,
+        };
+        self.screen.set_visible_lines(Some(shown));
+        self.render();
+    }
+
+    /// Append the command to the command log
+    fn record(&mut self, command: Command) {
+        self.log.push((self.owner, command));
+    }
+
+    /// Return the commands that have been given to the turtle so far, by all
+    /// owners
+    pub fn get_log(&self) -> Vec<Command> {
+        self.log.iter().map(|&(_, ref command)| command.clone()).collect()
+    }
+
+    /// Return the number of commands the current owner has given so far
+    fn own_commands(&self) -> usize {
+        self.log.iter().filter(|&&(owner, _)| owner == self.owner).count()
+    }
+
+    /// Execute a single command as if the corresponding method was called.
+    /// Returns an error if the command's arguments are invalid.
+    pub fn execute(&mut self, command: &Command) -> TurtleResult {
+        use super::replay::Command::*;
+        match *command {
+            Forward(x) => try!(self.forward(x)),
+            Backward(x) => try!(self.backward(x)),
+            Left(x) => try!(self.left(x)),
+            Right(x) => try!(self.right(x)),
+            PenUp => self.pen_up(),
+            PenDown => self.pen_down(),
+            Color(r, g, b) => try!(self.set_color(r, g, b)),
+            BackgroundColor(r, g, b) => try!(self.set_background_color(r, g, b)),
+            SmoothLines(flag) => self.set_smooth_lines(flag),
+            PenSize(x) => try!(self.set_pen_size(x)),
+            Teleport(x, y) => try!(self.teleport(x, y)),
+            Realign(x) => try!(self.set_orientation(x)),
+            Home => self.home(),
+            SetHome(x, y, orientation) => try!(self.set_home(x, y, orientation)),
+            Hide => self.hide(),
+            Show => self.show(),
+            Write(ref text) => try!(self.write(text)),
+            WriteRotated(ref text, heading) => try!(self.write_rotated(text, heading)),
+            HorizontalText(flag) => self.set_horizontal_text(flag),
+            WriteAlong(ref text) => self.write_along(text),
+            WriteArc(ref text, radius) => try!(self.write_arc(text, radius)),
+            // A missing image shouldn't abort the whole replay
+            StampImage(ref path, scale) => self.stamp_image(path, scale).unwrap_or(()),
+            Flood => try!(self.flood()),
+            Clear => self.clean(),
+            Mode3D(flag) => self.set_3d(flag),
+            PitchUp(x) => try!(self.pitch_up(x)),
+            PitchDown(x) => try!(self.pitch_down(x)),
+            RollLeft(x) => try!(self.roll_left(x)),
+            RollRight(x) => try!(self.roll_right(x)),
+            PushTransform => self.push_transform(),
+            PopTransform => { self.pop_transform(); },
+            Scale(x, y) => try!(self.scale(x, y)),
+            Rotate(x) => try!(self.rotate(x)),
+            Translate(x, y) => try!(self.translate(x, y)),
+            Mirror(count, reflect) => self.set_mirror(count, reflect),
+            Trail(length) => self.set_trail(length),
+            Snap(angle) => try!(self.set_snap(angle)),
+            SnapGrid(size) => try!(self.set_snap_grid(size)),
+            Isometric(flag) => self.set_isometric(flag),
+            BeginForeground => self.begin_foreground(),
+            ClearForeground => self.clear_foreground(),
+            Points(ref points) => try!(self.plot_points(points)),
+            ApplyFilter(filter) => try!(self.apply_filter(filter)),
+        }
+        Ok(())
+    }
+
+    /// Execute the given commands one after another, waiting `delay` between
+    /// two commands. The replayed commands are recorded in the log again. The
+    /// replay stops at the first invalid command.
+    pub fn replay(&mut self, commands: &[Command], delay: time::Duration) -> TurtleResult {
+        for command in commands {
+            try!(self.execute(command));
+            if delay != time::Duration::from_millis(0) {
+                self.screen.pump_events();
+                thread::sleep(delay);
+            }
+        }
+        Ok(())
+    }
+
+    /// Let `robot` follow every move the turtle makes from now on in 2D mode,
+    /// see the `robot` module. If sending a move fails, the move returns an
+    /// error and the robot isn't controlled anymore. `None` disconnects the
+    /// robot.
+    pub fn set_robot(&mut self, robot: Option<Robot>) {
+        self.robot = robot;
+    }
+
+    /// Save the drawing and the turtle's state under the given name, see
+    /// `rollback`. A checkpoint with the same name is replaced. Each owner
+    /// (see `set_owner`) has its own checkpoints.
+    pub fn checkpoint(&mut self, name: &str) {
+        let checkpoint = Checkpoint {
+            commands: self.own_commands(),
+            state: self.state(),
+        };
+        self.checkpoints.insert((self.owner, name.to_owned()), checkpoint);
+    }
+
+    /// Restore the drawing to the checkpoint with the given name by replaying
+    /// the owner's commands up to it. The turtle returns to its state at the
+    /// checkpoint, unless `keep_state` is set. Checkpoints made after this
+    /// one are dropped, the view is kept. The drawings and commands of other
+    /// owners stay as they are.
+    pub fn rollback(&mut self, name: &str, keep_state: bool) -> TurtleResult {
+        let owner = self.owner;
+        let checkpoint = match self.checkpoints.get(&(owner, name.to_owned())) {
+            Some(checkpoint) => *checkpoint,
+            None => return Err(TurtleError::UnknownCheckpoint(name.to_owned())),
+        };
+        let (state, view, auto_update) = (self.state(), self.screen.view(), self.auto_update);
+        let commands: Vec<Command> = self.log.iter()
+            .filter(|&&(command_owner, _)| command_owner == owner)
+            .take(checkpoint.commands)
+            .map(|&(_, ref command)| command.clone())
+            .collect();
+        // Removes the owner's commands from the log, the replay adds them again
+        self.reset();
+        self.screen.set_view(view);
+        // Only show the result, not every replayed step. The robot already
+        // drew all of it.
+        self.auto_update = false;
+        let robot = self.robot.take();
+        let result = self.replay(&commands, time::Duration::from_millis(0));
+        self.auto_update = auto_update;
+        self.robot = robot;
+        try!(result);
+        self.checkpoints.retain(|&(saved_owner, _), saved| {
+            saved_owner != owner || saved.commands <= checkpoint.commands
+        });
+        if keep_state {
+            try!(self.set_state(&state));
+        } else {
+            try!(self.set_state(&checkpoint.state));
+        }
+        self.redraw();
+        Ok(())
+    }
+
+    /// Return a reference to the underlaying backend
+    pub fn get_screen(&mut self) -> &mut RenderBackend {
+        &mut *self.screen
     }
 
     /// Turn the turtle by the given amount. Positive means counter-clockwise,
     /// negative means clockwise. The angle is given in degrees. This function
     /// is used internally.
     fn turn(&mut self, deg: f32) {
+        if self.space.is_some() {
+            return self.rotate_in_space(|space| space.turn(deg));
+        }
         let orientation = self.orientation;
-        self.set_orientation(orientation + deg);
+        self.apply_orientation(orientation + deg);
+    }
+
+    /// Set the orientation without recording it. This function is used
+    /// internally.
+    fn apply_orientation(&mut self, deg: f32) {
+        if self.space.is_some() {
+            return self.rotate_in_space(|space| space.frame = Frame::flat(deg));
+        }
+        self.orientation = normalize_angle(deg);
+        self.update_cursor();
     }
 
     /// Take the length of a path and return the (delta_x, delta_y) attributes
@@ -83,46 +681,261 @@ impl Turtle {
         let orientation_rad = ::std::f32::consts::PI * self.orientation / 180.0;
         let delta_x = orientation_rad.sin() * length;
         let delta_y = orientation_rad.cos() * length;
+        if self.isometric {
+            // The orientation is a direction on the ground, which is seen
+            // from above at an angle
+            let (ground_x, ground_y) = (-delta_x, delta_y);
+            return ((ground_x + ground_y) * ISO_X, (ground_y - ground_x) * ISO_Y);
+        }
         (-delta_x, delta_y)
     }
 
+    /// Return the direction the turtle moves in on the screen. This is its
+    /// orientation, unless it is in isometric mode.
+    fn screen_orientation(&self) -> f32 {
+        if !self.isometric {
+            return self.orientation;
+        }
+        let (dx, dy) = self.length_to_vector(1.);
+        normalize_angle((-dx).atan2(dy).to_degrees())
+    }
+
+    /// Everything drawn from now on belongs to the foreground and can be
+    /// removed with `clear_foreground`, while the drawing so far stays. This
+    /// is used for animations.
+    pub fn begin_foreground(&mut self) {
+        self.record(Command::BeginForeground);
+        self.screen.begin_foreground();
+    }
+
+    /// Remove everything drawn since `begin_foreground`. The commands given
+    /// since are removed from the log as well, so an animation doesn't make
+    /// it grow with every frame.
+    pub fn clear_foreground(&mut self) {
+        let start = self.log.iter()
+            .rposition(|&(_, ref command)| *command == Command::BeginForeground);
+        match start {
+            Some(start) => self.log.truncate(start + 1),
+            None => self.record(Command::ClearForeground),
+        }
+        self.screen.clear_foreground();
+        self.redraw();
+    }
+
+    /// Show the text in a box next to the turtle with a pointer to the
+    /// turtle's position, like a speech bubble. Annotations are not part of
+    /// the drawing and are not recorded, see `backend::Annotation`.
+    pub fn annotate(&mut self, text: &str) -> TurtleResult {
+        let annotation = Annotation {
+            target: self.to_canvas(self.position),
+            text: text.to_owned(),
+        };
+        try!(self.screen.add_annotation(annotation).map_err(TurtleError::Render));
+        self.redraw();
+        Ok(())
+    }
+
+    /// Remove all annotations
+    pub fn clear_annotations(&mut self) {
+        self.screen.clear_annotations();
+        self.redraw();
+    }
+
+    /// Set the number under which the shapes drawn from now on are kept in
+    /// the display list. The local turtle is 0, the server gives each client
+    /// its own number so that `clear_mine` and `undo` only affect the shapes
+    /// of that client.
+    pub fn set_owner(&mut self, owner: usize) {
+        self.owner = owner;
+        self.screen.set_owner(owner);
+    }
+
+    /// Remove everything this turtle has drawn, the shapes of the other
+    /// turtles stay. Like annotations, this is not recorded.
+    pub fn clear_mine(&mut self) {
+        self.screen.clear_owner(self.owner);
+        self.redraw();
+    }
+
+    /// Remove the last shape this turtle has drawn, e.g. the last line or
+    /// text. Returns false if there is nothing left to undo.
+    pub fn undo(&mut self) -> bool {
+        let undone = self.screen.undo_owner(self.owner);
+        self.redraw();
+        undone
+    }
+
     /// Clear the screen. Note that this only removes the drawn lines, it does
-    /// not change the turtle's position or orientation.
-    pub fn clear(&mut self) {
+    /// not change the turtle's position or orientation, see `clear_screen`.
+    pub fn clean(&mut self) {
+        self.record(Command::Clear);
         self.screen.clear();
+        if let Some(ref mut space) = self.space {
+            space.clear();
+        }
+        if let Some(ref mut playback) = self.playback {
+            playback.shown = 0.;
+        }
+    }
+
+    /// Clear the screen like `clean`, then move the turtle home and reset the
+    /// pen: down, black and 1 unit wide
+    pub fn clear_screen(&mut self) {
+        // Going home with the pen down would draw a line on the empty canvas
+        self.pen_up();
+        self.home();
+        self.clean();
+        self.pen_down();
+        self.set_color(0.0, 0.0, 0.0).unwrap();
+        self.set_pen_size(1.0).unwrap();
+    }
+
+    /// Reset the turtle to its initial state: remove what it drew, move it
+    /// home with the pen down, black color, pen size 1, white background, the
+    /// initial line smoothing, no transform, symmetry, trail or playback, the
+    /// default view and visible. The owner's commands are removed from the
+    /// command log as well. Shapes and commands of other owners (see
+    /// `set_owner`) stay.
+    pub fn reset(&mut self) {
+        self.screen.clear_owner(self.owner);
+        self.screen.clear_annotations();
+        self.position = (0.0, 0.0);
+        self.orientation = 0.0;
+        self.color = color::BLACK;
+        self.pen = PenState::PenDown;
+        self.pen_size = 1.0;
+        self.screen.set_pen_size(1.0);
+        self.background_color = color::WHITE;
+        self.screen.set_background_color(color::WHITE);
+        self.screen.set_smooth_lines(self.initial_smooth_lines);
+        self.hidden = false;
+        self.space = None;
+        self.physics = Physics::default();
+        self.transform = Transform::default();
+        self.transforms.clear();
+        self.screen.set_symmetry(Symmetry::default());
+        self.screen.set_trail(None);
+        self.playback = None;
+        self.path_text = None;
+        self.horizontal_text = false;
+        self.home = ((0.0, 0.0), 0.0);
+        self.path = None;
+        self.snap_angle = None;
+        self.snap_grid = None;
+        self.isometric = false;
+        self.screen.set_visible_lines(None);
+        self.screen.set_view(View::default());
+        let owner = self.owner;
+        self.log.retain(|&(command_owner, _)| command_owner != owner);
+        self.update_cursor();
     }
 
     /// Move the turtle forward by the given length
-    pub fn forward(&mut self, length: f32) {
-        let (x, y) = self.position;
-        let (dx, dy) = self.length_to_vector(length);
-        self.goto(x + dx, y + dy);
+    pub fn forward(&mut self, length: f32) -> TurtleResult {
+        try!(self.walk(length));
+        self.record(Command::Forward(length));
+        Ok(())
     }
 
     /// Move the turtle backward by the given length
-    pub fn backward(&mut self, length: f32) {
+    pub fn backward(&mut self, length: f32) -> TurtleResult {
+        try!(self.walk(-length));
+        self.record(Command::Backward(length));
+        Ok(())
+    }
+
+    /// Move the turtle along its heading without recording it. This
+    /// function is used internally.
+    fn walk(&mut self, length: f32) -> TurtleResult {
+        try!(check_number("length", length));
+        if let Some(destination) = self.space.as_ref().map(|s| s.ahead(length)) {
+            return self.goto_3d(destination);
+        }
+        try!(self.write_path_glyphs(length));
         let (x, y) = self.position;
         let (dx, dy) = self.length_to_vector(length);
-        self.goto(x - dx, y - dy);
+        let (x, y) = self.snap_point((x + dx, y + dy));
+        self.goto(x, y)
+    }
+
+    /// Return the nearest grid point in grid mode, otherwise the point itself
+    fn snap_point(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        match self.snap_grid {
+            Some(size) => (snap(x, size), snap(y, size)),
+            None => (x, y),
+        }
+    }
+
+    /// Return the angle to turn left by instead of `deg` so that the turtle
+    /// ends up on a multiple of the snap angle
+    fn snap_turn(&self, deg: f32) -> f32 {
+        match self.snap_angle {
+            Some(step) if self.space.is_none() => {
+                snap(self.orientation + deg, step) - self.orientation
+            },
+            _ => deg,
+        }
+    }
+
+    /// Write the glyphs of the path text that lie on the next `length` units
+    /// ahead of the turtle, see `write_along`
+    fn write_path_glyphs(&mut self, length: f32) -> TurtleResult {
+        // Walking backwards would write the text upside down
+        if length <= 0. {
+            return Ok(());
+        }
+        let mut text = match self.path_text.take() {
+            Some(text) => text,
+            None => return Ok(()),
+        };
+        let (x, y) = self.position;
+        let (dx, dy) = self.length_to_vector(1.);
+        // Texts are written to the right of the turtle's heading
+        let angle = self.angle_to_canvas(self.screen_orientation() + 90.);
+        while text.gap <= length {
+            let glyph = match text.glyphs.pop() {
+                Some(glyph) => glyph,
+                None => break,
+            };
+            let anchor = self.to_canvas((x + dx * text.gap, y + dy * text.gap));
+            try!(self.screen.add_text(anchor, angle, self.color, &glyph.to_string())
+                 .map_err(TurtleError::Render));
+            text.gap += GLYPH_WIDTH;
+        }
+        text.gap -= length;
+        if !text.glyphs.is_empty() {
+            self.path_text = Some(text);
+        }
+        Ok(())
     }
 
     /// Turn the turtle left
-    pub fn left(&mut self, deg: f32) {
+    pub fn left(&mut self, deg: f32) -> TurtleResult {
+        try!(check_number("angle", deg));
+        self.record(Command::Left(deg));
+        let deg = self.snap_turn(deg);
         self.turn(deg);
+        Ok(())
     }
 
     /// Turn the turtle right
-    pub fn right(&mut self, deg: f32) {
-        self.turn(-deg);
+    pub fn right(&mut self, deg: f32) -> TurtleResult {
+        try!(check_number("angle", deg));
+        self.record(Command::Right(deg));
+        let deg = self.snap_turn(-deg);
+        self.turn(deg);
+        Ok(())
     }
 
     /// "Lifts" the pen so that no lines are drawn anymore
     pub fn pen_up(&mut self) {
+        self.record(Command::PenUp);
         self.pen = PenState::PenUp;
     }
 
     /// Sinks the pen again so that lines are drawn
     pub fn pen_down(&mut self) {
+        self.record(Command::PenDown);
         self.pen = PenState::PenDown;
     }
 
@@ -130,70 +943,707 @@ impl Turtle {
     /// existing lines will remain in their color. `red`, `green` and `blue` are
     /// given as floats in the range [0; 1], where 0 means nothing and 1 full
     /// (like #FF in HTML).
-    pub fn set_color(&mut self, red: f32, green: f32, blue: f32) {
+    pub fn set_color(&mut self, red: f32, green: f32, blue: f32) -> TurtleResult {
+        try!(check_color(red, green, blue));
+        self.record(Command::Color(red, green, blue));
         self.color = (red, green, blue, 1.0);
-        self.screen.turtle_color = self.color;
-        self.screen.draw_and_update();
+        self.update_cursor();
+        Ok(())
     }
 
-    /// Set the background color of the screen.
-    pub fn set_background_color(&mut self, red: f32, green: f32, blue: f32) {
-        self.screen.background_color = (red, green, blue, 1.);
-        self.screen.draw_and_update();
+    /// Set the width of the lines drawn from now on. Existing lines keep
+    /// their width. The size must be positive.
+    pub fn set_pen_size(&mut self, size: f32) -> TurtleResult {
+        try!(check_pen_size(size));
+        self.record(Command::PenSize(size));
+        self.pen_size = size;
+        self.screen.set_pen_size(size);
+        Ok(())
+    }
+
+    /// Set the background color of the screen, see `set_color`
+    pub fn set_background_color(&mut self, red: f32, green: f32, blue: f32) -> TurtleResult {
+        try!(check_color(red, green, blue));
+        self.record(Command::BackgroundColor(red, green, blue));
+        self.background_color = (red, green, blue, 1.);
+        self.screen.set_background_color((red, green, blue, 1.));
+        self.redraw();
+        Ok(())
+    }
+
+    /// Enable or disable anti-aliasing of the drawn lines
+    pub fn set_smooth_lines(&mut self, smooth: bool) {
+        self.record(Command::SmoothLines(smooth));
+        self.screen.set_smooth_lines(smooth);
+        self.redraw();
+    }
+
+    /// Show a coordinate grid with the given spacing beneath the drawing. A
+    /// spacing of 0 hides the grid.
+    pub fn show_grid(&mut self, spacing: f32) {
+        self.overlay.grid = if spacing > 0. { Some(spacing) } else { None };
+        self.screen.set_overlay(self.overlay);
+        self.redraw();
+    }
+
+    /// Show or hide the x and y axes beneath the drawing
+    pub fn show_axes(&mut self, show: bool) {
+        self.overlay.axes = show;
+        self.screen.set_overlay(self.overlay);
+        self.redraw();
+    }
+
+    /// Show or hide the minimap in the corner of the window
+    pub fn show_minimap(&mut self, show: bool) {
+        self.overlay.minimap = show;
+        self.screen.set_overlay(self.overlay);
+        self.redraw();
+    }
+
+    /// Switch between fullscreen and windowed mode
+    pub fn set_fullscreen(&mut self, fullscreen: bool) -> TurtleResult {
+        self.screen.set_fullscreen(fullscreen).map_err(TurtleError::Render)
     }
 
     /// Directly move the turtle to the given point without changing the
     /// direction. Draws a line if the pen is down. Note that the origin (0, 0)
     /// is in the center of the screen with positive coordinates being right/top
     /// and negative ones left/down.
-    pub fn teleport(&mut self, x: f32, y: f32) {
-        self.goto(x, y)
+    pub fn teleport(&mut self, x: f32, y: f32) -> TurtleResult {
+        try!(check_number("x coordinate", x));
+        try!(check_number("y coordinate", y));
+        if let Some(z) = self.space.as_ref().map(|s| s.position.2) {
+            try!(self.goto_3d((x, y, z)));
+        } else {
+            let (snapped_x, snapped_y) = self.snap_point((x, y));
+            try!(self.goto(snapped_x, snapped_y));
+        }
+        self.record(Command::Teleport(x, y));
+        Ok(())
+    }
+
+    /// Move the turtle to the point `radius` units away from the origin in the
+    /// direction `angle`, which counts like the turtle's orientation: 0° is
+    /// north and positive angles count counter-clockwise. The turtle doesn't
+    /// turn.
+    pub fn polar_goto(&mut self, radius: f32, angle: f32) -> TurtleResult {
+        try!(check_number("radius", radius));
+        try!(check_number("angle", angle));
+        let angle = angle.to_radians();
+        self.teleport(-angle.sin() * radius, angle.cos() * radius)
     }
 
     /// Set the turtle's orientation in degrees with 0 being faced north and
     /// positive degrees counting counter-clockwise.
-    pub fn set_orientation(&mut self, deg: f32) {
-        self.orientation = deg % 360.0;
-        self.screen.turtle_orientation = self.orientation;
-        self.screen.draw_and_update();
+    pub fn set_orientation(&mut self, deg: f32) -> TurtleResult {
+        try!(check_number("angle", deg));
+        self.record(Command::Realign(deg));
+        self.apply_orientation(deg);
+        Ok(())
+    }
+
+    /// Turn the turtle so that it faces the point (`x`, `y`). If the turtle
+    /// is already there, its orientation doesn't change.
+    pub fn set_heading_towards(&mut self, x: f32, y: f32) -> TurtleResult {
+        try!(check_number("x coordinate", x));
+        try!(check_number("y coordinate", y));
+        let (dx, dy) = (x - self.position.0, y - self.position.1);
+        if dx == 0. && dy == 0. {
+            return Ok(());
+        }
+        // See length_to_vector: 0° is north and angles count counter-clockwise
+        self.set_orientation((-dx).atan2(dy).to_degrees())
     }
 
-    /// Move the turtle to the origin and set its orientation to 0
+    /// Move the turtle home and turn it to the home orientation, which are the
+    /// origin and 0 unless they were changed with `set_home`
     pub fn home(&mut self) {
-        self.teleport(0.0, 0.0);
-        self.set_orientation(0.0);
+        self.record(Command::Home);
+        let ((x, y), orientation) = self.home;
+        // The origin is finite on the canvas too, see `set_transform`. Other
+        // homes may not be, then the turtle only turns.
+        if self.space.is_some() {
+            self.goto_3d((x, y, 0.0)).unwrap_or(());
+        } else {
+            self.goto(x, y).unwrap_or(());
+        }
+        self.apply_orientation(orientation);
+    }
+
+    /// Set the position and orientation that `home` returns to. The turtle
+    /// doesn't move.
+    pub fn set_home(&mut self, x: f32, y: f32, orientation: f32) -> TurtleResult {
+        try!(check_number("x coordinate", x));
+        try!(check_number("y coordinate", y));
+        try!(check_number("angle", orientation));
+        self.record(Command::SetHome(x, y, orientation));
+        self.home = ((x, y), normalize_angle(orientation));
+        Ok(())
+    }
+
+    /// Save the current transform, so that it can be restored with
+    /// `pop_transform`
+    pub fn push_transform(&mut self) {
+        self.record(Command::PushTransform);
+        self.transforms.push(self.transform);
+    }
+
+    /// Restore the transform saved by the last `push_transform`. Returns
+    /// false if there was none.
+    pub fn pop_transform(&mut self) -> bool {
+        self.record(Command::PopTransform);
+        match self.transforms.pop() {
+            Some(transform) => {
+                self.transform = transform;
+                self.update_cursor();
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Replace the current transform. Returns an error if it overflowed, so
+    /// the canvas never gets non-finite coordinates.
+    fn set_transform(&mut self, transform: Transform) -> TurtleResult {
+        if !transform.is_finite() {
+            return Err(TurtleError::NonFiniteCoordinate);
+        }
+        self.transform = transform;
+        self.update_cursor();
+        Ok(())
+    }
+
+    /// Scale everything drawn from now on by `sx` horizontally and `sy`
+    /// vertically, relative to the origin
+    pub fn scale(&mut self, sx: f32, sy: f32) -> TurtleResult {
+        try!(check_number("scale", sx));
+        try!(check_number("scale", sy));
+        let transform = self.transform.scaled(sx, sy);
+        try!(self.set_transform(transform));
+        self.record(Command::Scale(sx, sy));
+        Ok(())
+    }
+
+    /// Rotate everything drawn from now on by `deg` degrees counter-clockwise
+    /// around the origin
+    pub fn rotate(&mut self, deg: f32) -> TurtleResult {
+        try!(check_number("angle", deg));
+        let transform = self.transform.rotated(deg);
+        try!(self.set_transform(transform));
+        self.record(Command::Rotate(deg));
+        Ok(())
+    }
+
+    /// Move everything drawn from now on by `(dx, dy)`
+    pub fn translate(&mut self, dx: f32, dy: f32) -> TurtleResult {
+        try!(check_number("distance", dx));
+        try!(check_number("distance", dy));
+        let transform = self.transform.translated(dx, dy);
+        try!(self.set_transform(transform));
+        self.record(Command::Translate(dx, dy));
+        Ok(())
+    }
+
+    /// Repeat every line drawn from now on `count` times rotated around the
+    /// origin. If `reflect` is set, each copy is mirrored too, like in a
+    /// kaleidoscope. A count of 1 without reflection turns the symmetry off.
+    pub fn set_mirror(&mut self, count: u32, reflect: bool) {
+        self.record(Command::Mirror(count, reflect));
+        self.screen.set_symmetry(Symmetry { count: count, reflect: reflect });
+    }
+
+    /// Only keep the last `length` lines, older lines fade out and disappear.
+    /// A length of 0 keeps all lines again.
+    pub fn set_trail(&mut self, length: u32) {
+        self.record(Command::Trail(length));
+        self.screen.set_trail(if length > 0 { Some(length as usize) } else { None });
+        self.redraw();
+    }
+
+    /// Round the turtle's orientation after every `left` and `right` to the
+    /// nearest multiple of `angle`, so that repeated turns don't drift. An
+    /// angle of 0 turns snapping off. Has no effect in 3D mode.
+    pub fn set_snap(&mut self, angle: f32) -> TurtleResult {
+        if angle < 0. || !angle.is_finite() {
+            return Err(TurtleError::InvalidNumber("snap angle", angle));
+        }
+        self.record(Command::Snap(angle));
+        self.snap_angle = if angle > 0. { Some(angle) } else { None };
+        Ok(())
+    }
+
+    /// Round the position after every `forward`, `backward` and `teleport`
+    /// to the nearest point of a grid with the given spacing. Moves shorter
+    /// than half the spacing may leave the turtle where it is. A size of 0
+    /// turns the grid off. Has no effect in 3D mode.
+    pub fn set_snap_grid(&mut self, size: f32) -> TurtleResult {
+        if size < 0. || !size.is_finite() {
+            return Err(TurtleError::InvalidNumber("grid size", size));
+        }
+        self.record(Command::SnapGrid(size));
+        self.snap_grid = if size > 0. { Some(size) } else { None };
+        Ok(())
+    }
+
+    /// Switch isometric mode on or off. In isometric mode, the turtle walks
+    /// on a ground plane that is seen from above at an angle: at heading 0 it
+    /// moves up and to the right, rising one unit every two units, after
+    /// `left 90` up and to the left. Turns by 90 degrees thus follow the axes
+    /// of the usual 2:1 isometric grid. Has no effect in 3D mode.
+    pub fn set_isometric(&mut self, isometric: bool) {
+        self.record(Command::Isometric(isometric));
+        self.isometric = isometric;
+        self.update_cursor();
+    }
+
+    /// Return the turtle's current state
+    pub fn state(&self) -> TurtleState {
+        TurtleState {
+            position: self.position,
+            orientation: self.orientation,
+            color: self.color,
+            pen_down: self.is_pen_down(),
+            pen_size: self.pen_size,
+            hidden: self.hidden,
+        }
+    }
+
+    /// Move the turtle to the given state without drawing anything. The
+    /// changes are recorded in the log as commands, so that replaying the log
+    /// gives the same drawing. Returns an error if the state is invalid, e.g.
+    /// contains a NaN coordinate.
+    pub fn set_state(&mut self, state: &TurtleState) -> TurtleResult {
+        let (r, g, b, _) = state.color;
+        try!(check_number("x coordinate", state.position.0));
+        try!(check_number("y coordinate", state.position.1));
+        try!(check_number("angle", state.orientation));
+        try!(check_color(r, g, b));
+        try!(check_pen_size(state.pen_size));
+        if self.position != state.position {
+            self.record(Command::PenUp);
+            self.record(Command::Teleport(state.position.0, state.position.1));
+            self.pen = PenState::PenUp;
+        }
+        if self.orientation != state.orientation {
+            self.record(Command::Realign(state.orientation));
+        }
+        if self.color != state.color {
+            self.record(Command::Color(r, g, b));
+        }
+        if self.state().pen_down != state.pen_down || self.position != state.position {
+            self.record(if state.pen_down { Command::PenDown } else { Command::PenUp });
+        }
+        if self.pen_size != state.pen_size {
+            self.record(Command::PenSize(state.pen_size));
+        }
+        if self.hidden != state.hidden {
+            self.record(if state.hidden { Command::Hide } else { Command::Show });
+        }
+        self.position = state.position;
+        self.orientation = normalize_angle(state.orientation);
+        self.color = state.color;
+        self.pen = if state.pen_down { PenState::PenDown } else { PenState::PenUp };
+        self.pen_size = state.pen_size;
+        self.screen.set_pen_size(state.pen_size);
+        self.hidden = state.hidden;
+        self.update_cursor();
+        Ok(())
+    }
+
+    /// Switch between 2D and 3D mode. The screen is cleared, the turtle keeps
+    /// its position on the screen.
+    pub fn set_3d(&mut self, enabled: bool) {
+        self.record(Command::Mode3D(enabled));
+        self.screen.clear();
+        self.space = if enabled {
+            Some(Space::new(self.position, self.orientation))
+        } else {
+            None
+        };
+        self.update_cursor();
+    }
+
+    /// Returns true if the turtle is in 3D mode
+    pub fn is_3d(&self) -> bool {
+        self.space.is_some()
+    }
+
+    /// Return the turtle's state and the segments drawn in 3D mode
+    pub fn get_space(&self) -> Option<&Space> {
+        self.space.as_ref()
+    }
+
+    /// Raise the turtle's nose by the given degrees (3D mode only)
+    pub fn pitch_up(&mut self, deg: f32) -> TurtleResult {
+        try!(check_number("angle", deg));
+        self.record(Command::PitchUp(deg));
+        self.rotate_in_space(|space| space.pitch(deg));
+        Ok(())
     }
 
-    /// Return the turtle's orientation
+    /// Lower the turtle's nose by the given degrees (3D mode only)
+    pub fn pitch_down(&mut self, deg: f32) -> TurtleResult {
+        try!(check_number("angle", deg));
+        self.record(Command::PitchDown(deg));
+        self.rotate_in_space(|space| space.pitch(-deg));
+        Ok(())
+    }
+
+    /// Roll the turtle to the left around its heading (3D mode only)
+    pub fn roll_left(&mut self, deg: f32) -> TurtleResult {
+        try!(check_number("angle", deg));
+        self.record(Command::RollLeft(deg));
+        self.rotate_in_space(|space| space.roll(-deg));
+        Ok(())
+    }
+
+    /// Roll the turtle to the right around its heading (3D mode only)
+    pub fn roll_right(&mut self, deg: f32) -> TurtleResult {
+        try!(check_number("angle", deg));
+        self.record(Command::RollRight(deg));
+        self.rotate_in_space(|space| space.roll(deg));
+        Ok(())
+    }
+
+    /// Apply the rotation to the turtle in 3D mode and update the cursor
+    fn rotate_in_space<F: FnOnce(&mut Space)>(&mut self, rotation: F) {
+        if let Some(ref mut space) = self.space {
+            rotation(space);
+        }
+        self.sync_with_space();
+    }
+
+    /// Rotate the camera around the origin by the given degrees (3D mode
+    /// only). `yaw` turns around the vertical axis, `pitch` around the
+    /// horizontal axis of the screen.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) -> TurtleResult {
+        try!(check_number("angle", yaw));
+        try!(check_number("angle", pitch));
+        if let Some(ref mut space) = self.space {
+            space.camera.yaw += yaw;
+            space.camera.pitch += pitch;
+        }
+        if self.space.is_some() {
+            self.redraw_space();
+        }
+        Ok(())
+    }
+
+    /// Orbit the camera while the mouse is dragged (3D mode only). This should
+    /// be called regularly, like `handle_clicks`.
+    pub fn handle_orbit(&mut self) {
+        if self.space.is_none() || !self.screen.is_mouse_down() {
+            self.drag = None;
+            return;
+        }
+        let position = self.screen.mouse_position();
+        if let Some(last) = self.drag {
+            if last != position {
+                // Mouse positions are always finite
+                self.orbit((position.0 - last.0) / 2., (last.1 - position.1) / 2.).unwrap_or(());
+            }
+        }
+        self.drag = Some(position);
+    }
+
+    /// Return the simulation state of the turtle, e.g. to set its velocity
+    pub fn physics(&mut self) -> &mut Physics {
+        &mut self.physics
+    }
+
+    /// Advance the physics simulation by `time` seconds. The turtle moves
+    /// according to its velocity and draws a line if the pen is down. The
+    /// movement is recorded as `Teleport` in the log.
+    pub fn step(&mut self, time: f32) -> TurtleResult {
+        try!(check_number("time", time));
+        let size = self.screen.canvas_size();
+        let (x, y) = self.physics.step(self.position, time, size);
+        self.teleport(x, y)
+    }
+
+    /// Return the turtle's orientation in degrees, in the range [0; 360)
     pub fn get_orientation(&self) -> f32 { self.orientation }
     /// Return the turtle's position
     pub fn get_position(&self) -> (f32, f32) { self.position }
+    /// Return the color used for new lines
+    pub fn get_color(&self) -> color::Color { self.color }
+    /// Return the width of new lines
+    pub fn get_pen_size(&self) -> f32 { self.pen_size }
+    /// Return the background color of the screen
+    pub fn get_background_color(&self) -> color::Color { self.background_color }
+
+    /// Returns true if the pen is down, i.e. moving draws lines
+    pub fn is_pen_down(&self) -> bool {
+        match self.pen {
+            PenState::PenDown => true,
+            PenState::PenUp => false,
+        }
+    }
 
     /// Hide the turtle so it won't be drawn on the screen
     pub fn hide(&mut self) {
-        self.screen.turtle_hidden = true;
-        self.screen.draw_and_update();
+        self.record(Command::Hide);
+        self.hidden = true;
+        self.update_cursor();
     }
 
     /// Show the turtle again after it has been hidden
     pub fn show(&mut self) {
-        self.screen.turtle_hidden = false;
-        self.screen.draw_and_update();
+        self.record(Command::Show);
+        self.hidden = false;
+        self.update_cursor();
     }
 
     /// Returns true if the turtle is currently hidden
     pub fn is_hidden(&self) -> bool {
-        self.screen.turtle_hidden
+        self.hidden
     }
 
     /// Write the text on the screen. The lower-left corner of the Text starts
-    /// where the turtle is.
-    pub fn write(&mut self, text: &str) {
-        self.screen.add_text(self.position, self.orientation, self.color, text);
+    /// where the turtle is. The text follows the turtle's heading, unless
+    /// horizontal texts are switched on. Returns an error if the backend
+    /// can't draw the text, e.g. because it's too long.
+    pub fn write(&mut self, text: &str) -> TurtleResult {
+        let heading = if self.horizontal_text { 0. } else { self.orientation };
+        try!(self.add_text(text, heading));
+        self.record(Command::Write(text.to_owned()));
+        Ok(())
+    }
+
+    /// Write the text like `write`, but rotated as if the turtle had the given
+    /// heading. A heading of 0 writes the text horizontally.
+    pub fn write_rotated(&mut self, text: &str, heading: f32) -> TurtleResult {
+        try!(self.add_text(text, heading));
+        self.record(Command::WriteRotated(text.to_owned(), heading));
+        Ok(())
+    }
+
+    fn add_text(&mut self, text: &str, heading: f32) -> TurtleResult {
+        let anchor = self.to_canvas(self.position);
+        let angle = self.angle_to_canvas(heading);
+        self.screen.add_text(anchor, angle, self.color, text).map_err(TurtleError::Render)
+    }
+
+    /// If `horizontal` is true, `write` keeps texts horizontal no matter
+    /// which way the turtle points
+    pub fn set_horizontal_text(&mut self, horizontal: bool) {
+        self.record(Command::HorizontalText(horizontal));
+        self.horizontal_text = horizontal;
+    }
+
+    /// Use the font in the file at `path` for characters that the bundled
+    /// font lacks, see `RenderBackend::add_fallback_font`
+    pub fn add_fallback_font(&mut self, path: &str) -> ::std::io::Result<()> {
+        self.screen.add_fallback_font(path)
+    }
+
+    /// Write the text along the way the turtle walks from now on, one glyph
+    /// after another. The glyphs follow the turtle's heading, so the text
+    /// bends with the path. Each glyph is written when the turtle passes its
+    /// place, no matter if the pen is up or down. A new text replaces the
+    /// rest of the old one.
+    pub fn write_along(&mut self, text: &str) {
+        self.record(Command::WriteAlong(text.to_owned()));
+        self.path_text = Some(PathText { glyphs: text.chars().rev().collect(), gap: 0. });
+    }
+
+    /// Write the text along an arc with the given radius, starting at the
+    /// turtle's position and heading. A positive radius bends the text to the
+    /// left, a negative one to the right. The turtle moves to the end of the
+    /// text without drawing.
+    pub fn write_arc(&mut self, text: &str, radius: f32) -> TurtleResult {
+        try!(check_number("radius", radius));
+        if radius == 0. {
+            return Err(TurtleError::InvalidNumber("radius", radius));
+        }
+        self.record(Command::WriteArc(text.to_owned(), radius));
+        // Each glyph is written on the chord of its part of the arc
+        let step = (GLYPH_WIDTH / radius).to_degrees();
+        let chord = 2. * radius.abs() * (step / 2.).to_radians().abs().sin();
+        let pen = ::std::mem::replace(&mut self.pen, PenState::PenUp);
+        let path_text = self.path_text.take();
+        let mut result = Ok(());
+        for glyph in text.chars() {
+            self.turn(step / 2.);
+            let anchor = self.to_canvas(self.position);
+            let angle = self.angle_to_canvas(self.orientation + 90.);
+            result = self.screen.add_text(anchor, angle, self.color, &glyph.to_string())
+                .map_err(TurtleError::Render)
+                .and_then(|_| self.walk(chord));
+            if result.is_err() {
+                break;
+            }
+            self.turn(step / 2.);
+        }
+        self.pen = pen;
+        self.path_text = path_text;
+        result
+    }
+
+    /// Set the function that should be called when the turtle is clicked. The
+    /// handler is run by `handle_clicks()`.
+    pub fn on_click<F: FnMut(&mut Turtle) + 'static>(&mut self, handler: F) {
+        self.click_handler = Some(Box::new(handler));
+    }
+
+    /// Check the clicks that happened since the last call and return how many
+    /// of them hit the turtle. The click handler (if any) is called once for
+    /// each hit. Note that the window's events have to be handled before, see
+    /// `TurtleScreen::handle_events()`.
+    pub fn handle_clicks(&mut self) -> usize {
+        let hits = self.screen.take_clicks().into_iter()
+            .filter(|&point| self.screen.hits_turtle(point))
+            .count();
+        // Take the handler out so it can borrow the turtle mutably
+        if let Some(mut handler) = self.click_handler.take() {
+            for _ in 0..hits {
+                handler(self);
+            }
+            if self.click_handler.is_none() {
+                self.click_handler = Some(handler);
+            }
+        }
+        hits
+    }
+
+    /// Return the bounding box of everything drawn so far in canvas
+    /// coordinates, `None` if nothing has been drawn
+    pub fn drawing_bounds(&self) -> Option<Bounds> {
+        self.screen.drawing_bounds()
+    }
+
+    /// Zoom and move the view so that the whole drawing is visible, leaving
+    /// `margin` units of the window free at each side. Without a drawing, the
+    /// default view is restored. The drawing itself and the turtle's
+    /// coordinates don't change.
+    pub fn fit_view(&mut self, margin: f32) -> TurtleResult {
+        try!(check_number("margin", margin));
+        let view = match self.screen.drawing_bounds() {
+            Some(bounds) => View::fit(bounds, self.screen.visible_size(), margin),
+            None => View::default(),
+        };
+        self.screen.set_view(view);
+        self.redraw();
+        Ok(())
+    }
+
+    /// Draw single points in the turtle's color and pen size without moving
+    /// the turtle, e.g. the points of a fractal. The points are transformed
+    /// like lines and are drawn even if the pen is up. Returns an error
+    /// without drawing anything if a point isn't finite.
+    pub fn plot_points(&mut self, points: &[(f32, f32)]) -> TurtleResult {
+        let canvas: Vec<(f32, f32)> = points.iter().map(|&point| self.to_canvas(point)).collect();
+        if !canvas.iter().all(|&(x, y)| x.is_finite() && y.is_finite()) {
+            return Err(TurtleError::NonFiniteCoordinate);
+        }
+        self.screen.plot_points(&canvas, self.color);
+        self.stats.segments += points.len() as u64;
+        self.record(Command::Points(points.to_vec()));
+        self.redraw();
+        Ok(())
+    }
+
+    /// Stamp the image found at `path` onto the screen. The image is centered
+    /// at the turtle's position and rotated according to its heading. Returns
+    /// an error if the image can't be loaded or is too large.
+    pub fn stamp_image(&mut self, path: &str, scale: f32) -> TurtleResult {
+        self.record(Command::StampImage(path.to_owned(), scale));
+        let position = self.to_canvas(self.position);
+        let angle = self.angle_to_canvas(self.orientation);
+        let scale = if self.space.is_some() {
+            scale
+        } else {
+            scale * self.transform.scale_factor()
+        };
+        try!(self.screen.stamp_image(path, position, angle, scale).map_err(TurtleError::Render));
+        self.redraw();
+        Ok(())
+    }
+
+    /// Return the current drawing including the turtle, see
+    /// `RenderBackend::screenshot`. Returns an error if the canvas has no
+    /// pixels, e.g. because the window is minimized.
+    pub fn screenshot(&self) -> Result<DynamicImage, TurtleError> {
+        try!(self.check_image_size(1.));
+        self.screen.screenshot().map_err(TurtleError::Render)
+    }
+
+    /// Return the current drawing at the device's native resolution, see
+    /// `screenshot`
+    pub fn native_screenshot(&self) -> Result<DynamicImage, TurtleError> {
+        try!(self.check_image_size(1.));
+        self.screen.native_screenshot().map_err(TurtleError::Render)
+    }
+
+    /// Render the drawing without the turtle with `scale` pixels per unit.
+    /// Returns an error if the image would have no pixels, e.g. because the
+    /// scale isn't positive, or more than the backend supports.
+    pub fn render_image(&self, scale: f32) -> Result<DynamicImage, TurtleError> {
+        try!(check_number("scale", scale));
+        try!(self.check_image_size(scale));
+        self.screen.render_image(scale).map_err(TurtleError::Render)
+    }
+
+    /// Return an error if an image of the canvas at the given scale would
+    /// have no pixels or be larger than `RenderBackend::max_image_size`
+    fn check_image_size(&self, scale: f32) -> TurtleResult {
+        let (width, height) = self.screen.canvas_size();
+        // Negative sizes saturate to 0, huge ones to the maximum
+        let (width, height) = ((width * scale) as u32, (height * scale) as u32);
+        let max = self.screen.max_image_size();
+        if width == 0 || height == 0 {
+            Err(TurtleError::EmptyImage(width, height))
+        } else if width > max || height > max {
+            Err(TurtleError::ImageTooLarge(width, height, max))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Export the drawing as PostScript file, see `RenderBackend::export_ps`
+    pub fn export_ps(&self, path: &str) -> ::std::io::Result<()> {
+        self.screen.export_ps(path)
+    }
+
+    /// Export the drawn lines for a pen plotter, see the `plotter` module
+    pub fn export_plotter(&self, path: &str, options: &PlotterOptions) -> ::std::io::Result<()> {
+        plotter::export(path, &self.screen.line_segments(), options)
+    }
+
+    /// Perform a floodfill at the current turtle position. Returns an error if
+    /// the backend can't capture the drawing.
+    pub fn flood(&mut self) -> TurtleResult {
+        let point = self.to_canvas(self.position);
+        try!(self.screen.fill(point, self.color).map_err(TurtleError::Render));
+        self.record(Command::Flood);
+        Ok(())
+    }
+
+    /// Start recording the positions the turtle visits, beginning with the
+    /// current one. Every move adds a vertex, whether the pen is down or not.
+    /// A path recorded before is discarded. Moves in 3D mode are not
+    /// recorded.
+    pub fn begin_path(&mut self) {
+        self.path = Some(vec![self.position]);
+    }
+
+    /// Return the vertices recorded since `begin_path`, empty if no path has
+    /// been started
+    pub fn path(&self) -> &[(f32, f32)] {
+        match self.path {
+            Some(ref path) => path,
+            None => &[],
+        }
     }
 
-    /// Perform a floodfill at the current turtle position
-    pub fn flood(&mut self) {
-        self.screen.floodfill(self.position, self.color);
+    /// Run the finished drawing through the filter, e.g. to turn it gray. The
+    /// filtered image replaces the drawing, so the lines can't be removed one
+    /// by one afterwards and the background color can't be changed anymore.
+    /// Returns an error if the drawing is too large for the backend.
+    pub fn apply_filter(&mut self, filter: Filter) -> TurtleResult {
+        try!(self.screen.apply_filter(filter).map_err(TurtleError::Render));
+        self.record(Command::ApplyFilter(filter));
+        self.redraw();
+        Ok(())
     }
 }
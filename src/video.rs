@@ -0,0 +1,107 @@
+//! Recording videos of the drawing.
+//!
+//! Encoding videos is far too big a task to do it here, so this module leaves
+//! it to `ffmpeg`: the frames are piped into it as raw RGBA pixels, and it
+//! picks the codec from the file extension, e.g. H.264 for `.mp4` and VP9 for
+//! `.webm`. If ffmpeg isn't installed, starting a recording fails.
+use image::{self, DynamicImage, GenericImage};
+use std::io::{self, Write};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+fn other_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, message)
+}
+
+/// A running recording. Frames are taken at a fixed rate, so the video plays
+/// at the same speed as the drawing was made.
+pub struct Recorder {
+    encoder: Child,
+    size: (u32, u32),
+    frame_time: Duration,
+    /// When the next frame is due
+    next_frame: Instant,
+}
+
+impl Recorder {
+    /// Start recording into the file at `path` with `fps` frames per second.
+    /// All frames are scaled to `size`.
+    pub fn start(path: &str, fps: f32, size: (u32, u32)) -> io::Result<Recorder> {
+        if !(fps > 0. && fps <= 240.) {
+            return Err(other_error(format!("invalid frame rate: {}", fps)));
+        }
+        // Most players need the yuv420p pixel format, which only works with
+        // even sizes
+        let size = (size.0 + size.0 % 2, size.1 + size.1 % 2);
+        let encoder = try!(Command::new("ffmpeg")
+                           .args(&["-y", "-loglevel", "error", "-f", "rawvideo"])
+                           .args(&["-pix_fmt", "rgba", "-s"])
+                           .arg(format!("{}x{}", size.0, size.1))
+                           .arg("-r")
+                           .arg(fps.to_string())
+                           .args(&["-i", "-", "-pix_fmt", "yuv420p"])
+                           // Without the protocol, names starting with - or
+                           // containing : would be read as options or URLs
+                           .arg(format!("file:{}", path))
+                           .stdin(Stdio::piped())
+                           .stdout(Stdio::null())
+                           .stderr(Stdio::null())
+                           .spawn()
+                           .map_err(|e| other_error(format!("could not start ffmpeg: {}", e))));
+        Ok(Recorder {
+            encoder: encoder,
+            size: size,
+            frame_time: Duration::from_millis((1000. / fps) as u64),
+            next_frame: Instant::now(),
+        })
+    }
+
+    /// Returns true if it's time for the next frame
+    pub fn is_due(&self) -> bool {
+        Instant::now() >= self.next_frame
+    }
+
+    /// Add the image as frame if one is due. If more than one frame is due
+    /// because nothing happened for a while, the image is repeated.
+    pub fn add_frame(&mut self, image: &DynamicImage) -> io::Result<()> {
+        let (width, height) = self.size;
+        let pixels = if image.dimensions() == self.size {
+            image.to_rgba().into_raw()
+        } else {
+            image.resize_exact(width, height, image::FilterType::Triangle).to_rgba().into_raw()
+        };
+        let now = Instant::now();
+        let stdin = self.encoder.stdin.as_mut().unwrap();
+        while self.next_frame <= now {
+            try!(stdin.write_all(&pixels));
+            self.next_frame += self.frame_time;
+        }
+        Ok(())
+    }
+
+    /// Finish the video and wait until ffmpeg has written it
+    pub fn finish(mut self) -> io::Result<()> {
+        self.close()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        // Closing the pipe tells ffmpeg that there are no more frames
+        drop(self.encoder.stdin.take());
+        let status = try!(self.encoder.wait());
+        if status.success() {
+            Ok(())
+        } else {
+            Err(other_error("ffmpeg failed to encode the video".to_owned()))
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Don't leave a half written file behind if the script ends while
+        // recording
+        if self.encoder.stdin.is_some() {
+            let _ = self.close();
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Bindings to run Rurtle in the browser.
+//!
+//! This module is only available with the `web` feature. Build it without the
+//! window, sound and readline support of the default `native` feature:
+//!
+//! ```text
+//! cargo build --lib --no-default-features --features web --target wasm32-unknown-unknown
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/debug/rurtle.wasm
+//! ```
+//!
+//! `Playground` runs Rurtle code on a `SoftwareBackend` and draws the result
+//! onto a canvas once per animation frame:
+//!
+//! ```text
+//! const context = document.getElementById("canvas").getContext("2d");
+//! const playground = new Playground(640, 640);
+//! playground.eval("repeat 4 do forward 100 right 90 end");
+//! function frame() {
+//!     playground.draw(context);
+//!     requestAnimationFrame(frame);
+//! }
+//! requestAnimationFrame(frame);
+//! ```
+//!
+//! Automatic redrawing is disabled, so evaluating code never blocks on
+//! drawing. Functions that need the operating system, like `toot` or
+//! `screenshot`, throw an error in the browser.
+use super::environ::Environment;
+use super::software::SoftwareBackend;
+use super::turtle::Turtle;
+use std::error::Error;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use web_sys::{CanvasRenderingContext2d, ImageData};
+
+/// A Rurtle interpreter drawing into an image
+#[wasm_bindgen]
+pub struct Playground {
+    environment: Environment,
+    size: (u32, u32),
+}
+
+#[wasm_bindgen]
+impl Playground {
+    /// Create a new playground whose canvas has the given size in pixels
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: u32, height: u32) -> Playground {
+        let mut turtle = Turtle::new(SoftwareBackend::new((width, height)));
+        turtle.set_auto_update(false);
+        Playground {
+            environment: Environment::new(turtle),
+            size: (width, height),
+        }
+    }
+
+    /// Run the given source code. Errors are returned as message strings.
+    pub fn eval(&mut self, source: &str) -> Result<(), JsValue> {
+        match self.environment.eval_source(source) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(JsValue::from_str(&format!("{}: {}", e.description(), e))),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.size.0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.size.1
+    }
+
+    /// Return the current drawing as RGBA pixels, row by row from the top.
+    /// Returns no pixels if the canvas has no pixels.
+    pub fn pixels(&mut self) -> Vec<u8> {
+        match self.environment.get_turtle().screenshot() {
+            Ok(image) => image.to_rgba().into_raw(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Draw the current drawing onto the canvas of `context`, with the upper
+    /// left corner at the canvas' origin
+    pub fn draw(&mut self, context: &CanvasRenderingContext2d) -> Result<(), JsValue> {
+        let pixels = self.pixels();
+        let (width, height) = self.size;
+        let image = try!(ImageData::new_with_u8_clamped_array_and_sh(Clamped(&pixels[..]), width,
+                                                                     height));
+        context.put_image_data(&image, 0., 0.)
+    }
+}
@@ -0,0 +1,40 @@
+//! Compares drawings with the golden images in `tests/golden`. Set
+//! `RURTLE_REGENERATE_GOLDENS` to rewrite them after an intended change of the
+//! output, see the `testing` module.
+extern crate image;
+extern crate rurtle;
+
+use rurtle::testing;
+use std::{env, fs, panic};
+
+const SQUARE: &'static str = "repeat 4 do forward 100 right 90 end";
+const SQUARE_GOLDEN: &'static str = "tests/golden/square.png";
+
+#[test]
+fn square_matches_golden() {
+    testing::assert_drawing_matches(SQUARE, SQUARE_GOLDEN, 0.001);
+}
+
+#[test]
+fn other_drawing_differs_from_golden() {
+    let triangle = testing::render_script("repeat 3 do forward 100 right 120 end").unwrap();
+    let golden = image::open(SQUARE_GOLDEN).unwrap();
+    assert!(testing::difference(&triangle, &golden) > 0.001);
+    assert_eq!(testing::difference(&golden, &golden), 0.);
+}
+
+#[test]
+fn missing_golden() {
+    let path = env::temp_dir().join("rurtle-missing-golden.png");
+    let path = path.to_str().unwrap().to_owned();
+    fs::remove_file(&path).unwrap_or(());
+    let result = panic::catch_unwind(|| testing::assert_drawing_matches(SQUARE, &path, 0.001));
+    if testing::regenerate_mode() {
+        // The golden image is written instead of compared
+        assert!(result.is_ok());
+        assert!(fs::metadata(&path).is_ok());
+        fs::remove_file(&path).unwrap();
+    } else {
+        assert!(result.is_err());
+    }
+}